@@ -0,0 +1,43 @@
+#import global_bindings::camera
+#import area_light::AreaLight
+#import area_light
+
+@group(1) @binding(1) var<storage, read> area_lights: array<AreaLight>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+}
+
+/// A triangle fan around the light center, one triangle per polygon edge.
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    let light = area_lights[instance_index];
+    let count = area_light::vertex_count(light);
+    let edge = vertex_index / 3u;
+    let corner = vertex_index % 3u;
+
+    var position = light.position;
+    if corner != 0u && edge < count {
+        position = area_light::vertex(light, (edge + corner - 1u) % count);
+    }
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+    if light.visible == 0u {
+        // Degenerate, nothing is rasterized
+        out.clip_position = vec4<f32>(0.0);
+    }
+    // The back of one sided lights doesn't emit
+    let facing_camera = dot(camera.position - light.position, cross(light.right, light.up)) < 0.0;
+    out.color = select(vec3<f32>(0.0), light.color.xyz, facing_camera || light.two_sided != 0u);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color * camera.exposure, 1.0);
+}
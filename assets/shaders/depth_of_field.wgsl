@@ -0,0 +1,71 @@
+#import vertex::{FullscreenV2F}
+
+struct DepthOfFieldParams {
+    focal_distance: f32,
+    focal_length: f32,
+    aperture_diameter: f32,
+    sensor_height: f32,
+    znear: f32,
+    zfar: f32,
+    max_coc_radius: f32,
+    radius_scale: f32,
+}
+
+@group(0) @binding(0) var main_tex: texture_2d<f32>;
+@group(0) @binding(1) var main_sampler: sampler;
+
+@group(1) @binding(0) var<uniform> params: DepthOfFieldParams;
+
+@group(2) @binding(0) var depth_tex: texture_depth_2d;
+@group(2) @binding(1) var depth_sampler: sampler;
+
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+fn linear_depth(uv: vec2<f32>) -> f32 {
+    let size = vec2<f32>(textureDimensions(depth_tex));
+    let coords = vec2<i32>(clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)) * (size - 1.0));
+    let depth = textureLoad(depth_tex, coords, 0);
+    let n = params.znear;
+    let f = params.zfar;
+    return n * f / (f - depth * (f - n));
+}
+
+/// Signed circle of confusion radius in pixels, negative in front of the focal plane.
+fn circle_of_confusion(distance: f32) -> f32 {
+    let f = params.focal_length;
+    let s1 = max(params.focal_distance, f + 0.001);
+    let coc = params.aperture_diameter * f * (distance - s1) / (distance * (s1 - f));
+    let pixels_per_meter = f32(textureDimensions(main_tex).y) / params.sensor_height;
+    return clamp(coc * pixels_per_meter * 0.5, -params.max_coc_radius, params.max_coc_radius);
+}
+
+/// Single pass scatter-as-gather along a golden angle spiral.
+@fragment
+fn fs_main(in: FullscreenV2F) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(main_tex));
+    let center_depth = linear_depth(in.uv);
+    let center_coc = abs(circle_of_confusion(center_depth));
+
+    var color = textureSampleLevel(main_tex, main_sampler, in.uv, 0.0).xyz;
+    var total = 1.0;
+    var radius = params.radius_scale;
+    var angle = 0.0;
+
+    for (var i = 0; i < 256 && radius < params.max_coc_radius; i++) {
+        let uv = in.uv + vec2<f32>(cos(angle), sin(angle)) * texel * radius;
+        let sample_color = textureSampleLevel(main_tex, main_sampler, uv, 0.0).xyz;
+        let sample_depth = linear_depth(uv);
+        var sample_coc = abs(circle_of_confusion(sample_depth));
+        // Sharp background must not bleed over the foreground
+        if sample_depth > center_depth {
+            sample_coc = clamp(sample_coc, 0.0, center_coc * 2.0);
+        }
+        let m = smoothstep(radius - 0.5, radius + 0.5, sample_coc);
+        color += mix(color / total, sample_color, m);
+        total += 1.0;
+        radius += params.radius_scale / radius;
+        angle += GOLDEN_ANGLE;
+    }
+
+    return vec4<f32>(color / total, 1.0);
+}
@@ -0,0 +1,58 @@
+struct IrradianceBake {
+    texel: vec3<u32>,
+    slab_depth: u32,
+}
+
+@group(0) @binding(0) var<uniform> bake: IrradianceBake;
+@group(0) @binding(1) var capture: texture_2d_array<f32>;
+@group(0) @binding(2) var volume: texture_storage_3d<rgba16float, write>;
+
+const PI: f32 = radians(180.0);
+
+/// Unnormalized direction through the texel at `uv` in [-1, 1] of a cubemap face
+fn face_direction(face: u32, uv: vec2<f32>) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -uv.y, -uv.x); }
+        case 1u: { return vec3<f32>(-1.0, -uv.y, uv.x); }
+        case 2u: { return vec3<f32>(uv.x, 1.0, uv.y); }
+        case 3u: { return vec3<f32>(uv.x, -1.0, -uv.y); }
+        case 4u: { return vec3<f32>(uv.x, -uv.y, 1.0); }
+        default: { return vec3<f32>(-uv.x, -uv.y, -1.0); }
+    }
+}
+
+/// +x, -x, +y, -y, +z, -z
+fn axis_direction(axis: u32) -> vec3<f32> {
+    let sign = select(1.0, -1.0, axis % 2u == 1u);
+    var ret = vec3<f32>(0.0);
+    ret[axis / 2u] = sign;
+    return ret;
+}
+
+/// One invocation per axis, each integrates the cosine weighted radiance of the whole capture.
+/// Stored divided by PI, so shading only multiplies by the diffuse color.
+@compute @workgroup_size(6)
+fn cs_main(@builtin(local_invocation_index) axis: u32) {
+    let size = textureDimensions(capture);
+    let axis_dir = axis_direction(axis);
+    // Texel area in [-1, 1] face coordinates
+    let texel_area = 4.0 / f32(size.x * size.y);
+
+    var irradiance = vec3<f32>(0.0);
+    for (var face = 0u; face < 6u; face += 1u) {
+        for (var y = 0u; y < size.y; y += 1u) {
+            for (var x = 0u; x < size.x; x += 1u) {
+                let uv = (vec2<f32>(f32(x), f32(y)) + 0.5) / vec2<f32>(size) * 2.0 - 1.0;
+                let dir = face_direction(face, uv);
+                let len2 = dot(dir, dir);
+                let solid_angle = texel_area / (len2 * sqrt(len2));
+                let cos_theta = max(dot(dir, axis_dir) * inverseSqrt(len2), 0.0);
+                let radiance = textureLoad(capture, vec2<u32>(x, y), face, 0).xyz;
+                irradiance += radiance * cos_theta * solid_angle;
+            }
+        }
+    }
+
+    let texel = bake.texel + vec3<u32>(0u, 0u, axis * bake.slab_depth);
+    textureStore(volume, texel, vec4<f32>(irradiance / PI, 1.0));
+}
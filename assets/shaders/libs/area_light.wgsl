@@ -0,0 +1,109 @@
+#define_import_path area_light
+
+const AREA_LIGHT_RECTANGLE: u32 = 0u;
+const AREA_LIGHT_DISK: u32 = 1u;
+
+// Disks are integrated as octagons with the same area
+const DISK_VERTICES: u32 = 8u;
+const DISK_RADIUS_SCALE: f32 = 1.0539;
+
+const LTC_LUT_SIZE: f32 = 64.0;
+
+struct AreaLight {
+    position: vec3<f32>,
+    shape: u32,
+    right: vec3<f32>,
+    two_sided: u32,
+    up: vec3<f32>,
+    visible: u32,
+    color: vec4<f32>,
+}
+
+fn vertex_count(light: AreaLight) -> u32 {
+    return select(4u, DISK_VERTICES, light.shape == AREA_LIGHT_DISK);
+}
+
+/// Counter-clockwise around `right x up`, so the front face (along `-(right x up)`) integrates positive.
+fn vertex(light: AreaLight, i: u32) -> vec3<f32> {
+    if light.shape == AREA_LIGHT_DISK {
+        let angle = f32(i) * radians(360.0) / f32(DISK_VERTICES);
+        return light.position
+            + (light.right * cos(angle) + light.up * sin(angle)) * DISK_RADIUS_SCALE;
+    }
+    let x = select(-1.0, 1.0, i == 1u || i == 2u);
+    let y = select(-1.0, 1.0, i >= 2u);
+    return light.position + light.right * x + light.up * y;
+}
+
+/// Texture coordinates of the LTC tables, x: perceptual roughness, y: sqrt(1 - n.v).
+fn ltc_uv(perceptual_roughness: f32, n_dot_v: f32) -> vec2<f32> {
+    let uv = vec2<f32>(perceptual_roughness, sqrt(1.0 - clamp(n_dot_v, 0.0, 1.0)));
+    return uv * (LTC_LUT_SIZE - 1.0) / LTC_LUT_SIZE + 0.5 / LTC_LUT_SIZE;
+}
+
+fn ltc_inverse_matrix(t: vec4<f32>) -> mat3x3<f32> {
+    return mat3x3<f32>(
+        vec3<f32>(t.x, 0.0, t.y),
+        vec3<f32>(0.0, 1.0, 0.0),
+        vec3<f32>(t.z, 0.0, t.w),
+    );
+}
+
+/// Form factor of the arc between two unit vectors, z component only.
+fn integrate_edge(v1: vec3<f32>, v2: vec3<f32>) -> f32 {
+    let x = dot(v1, v2);
+    let y = abs(x);
+    let a = 0.8543985 + (0.4965155 + 0.0145206 * y) * y;
+    let b = 3.4175940 + (4.1616724 + y) * y;
+    let v = a / b;
+    let theta_sintheta = select(0.5 * inverseSqrt(max(1.0 - x * x, 1e-7)) - v, v, x > 0.0);
+    return cross(v1, v2).z * theta_sintheta;
+}
+
+fn horizon_intersection(a: vec3<f32>, b: vec3<f32>) -> vec3<f32> {
+    return mix(a, b, a.z / (a.z - b.z));
+}
+
+/// Integral of the cosine distribution transformed by `minv` over the light polygon,
+/// clipped to the upper hemisphere of the shading frame. Divided by 2 pi.
+fn ltc_evaluate(
+    normal: vec3<f32>,
+    view: vec3<f32>,
+    world_pos: vec3<f32>,
+    minv: mat3x3<f32>,
+    light: AreaLight,
+) -> f32 {
+    // Tangent frame with the view vector in the xz plane
+    let t1 = normalize(view - normal * dot(view, normal));
+    let t2 = cross(normal, t1);
+    let m = minv * transpose(mat3x3<f32>(t1, t2, normal));
+
+    let count = vertex_count(light);
+    var sum = 0.0;
+    // Clipping a convex polygon by the horizon leaves at most one exit and one entry,
+    // joined by an edge on the horizon
+    var exit_point = vec3<f32>(0.0);
+    var entry_point = vec3<f32>(0.0);
+    var clipped = false;
+    for (var i = 0u; i < count; i += 1u) {
+        let a = m * (vertex(light, i) - world_pos);
+        let b = m * (vertex(light, (i + 1u) % count) - world_pos);
+        if a.z >= 0.0 && b.z >= 0.0 {
+            sum += integrate_edge(normalize(a), normalize(b));
+        } else if a.z >= 0.0 {
+            exit_point = horizon_intersection(a, b);
+            sum += integrate_edge(normalize(a), normalize(exit_point));
+            clipped = true;
+        } else if b.z >= 0.0 {
+            entry_point = horizon_intersection(a, b);
+            sum += integrate_edge(normalize(entry_point), normalize(b));
+            clipped = true;
+        }
+    }
+    if clipped {
+        sum += integrate_edge(normalize(exit_point), normalize(entry_point));
+    }
+
+    sum = select(max(sum, 0.0), abs(sum), light.two_sided != 0u);
+    return sum / radians(360.0);
+}
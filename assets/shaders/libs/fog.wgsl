@@ -0,0 +1,56 @@
+#define_import_path fog
+
+struct FogUniform {
+    inv_view_proj: mat4x4<f32>,
+    camera_position: vec4<f32>,
+    color: vec4<f32>,
+    density: f32,
+    height_falloff: f32,
+    base_height: f32,
+    anisotropy: f32,
+    max_distance: f32,
+    mode: u32,
+    ambient: f32,
+    padding: f32,
+}
+
+const FOG_MODE_OFF: u32 = 0u;
+const FOG_MODE_EXPONENTIAL: u32 = 1u;
+const FOG_MODE_VOLUMETRIC: u32 = 2u;
+
+const PI: f32 = radians(180.0);
+
+/// Extinction coefficient at `height`
+fn density_at(fog: FogUniform, height: f32) -> f32 {
+    return fog.density * exp(-fog.height_falloff * (height - fog.base_height));
+}
+
+/// Henyey-Greenstein phase function
+fn phase_hg(cos_theta: f32, g: f32) -> f32 {
+    let g2 = g * g;
+    let denom = max(1.0 + g2 - 2.0 * g * cos_theta, 0.0001);
+    return (1.0 - g2) / (4.0 * PI * pow(denom, 1.5));
+}
+
+/// Froxels are distributed quadratically along the view ray, `w` in [0, 1].
+fn slice_distance(fog: FogUniform, w: f32) -> f32 {
+    return w * w * fog.max_distance;
+}
+
+fn distance_to_slice(fog: FogUniform, distance: f32) -> f32 {
+    return sqrt(clamp(distance / fog.max_distance, 0.0, 1.0));
+}
+
+/// Transmittance of the analytic height fog between the camera and `world_pos`.
+fn height_fog_transmittance(fog: FogUniform, world_pos: vec3<f32>) -> f32 {
+    let ray = world_pos - fog.camera_position.xyz;
+    let dist = length(ray);
+    let k = fog.height_falloff;
+    let start = density_at(fog, fog.camera_position.y);
+    var optical_depth = start * dist;
+    let dy = k * ray.y;
+    if abs(dy) > 0.0001 {
+        optical_depth *= (1.0 - exp(-dy)) / dy;
+    }
+    return exp(-optical_depth);
+}
@@ -5,6 +5,8 @@ struct CameraUniform {
     view_proj: mat4x4<f32>,
     position: vec3<f32>,
     direction: vec3<f32>,
+    exposure: f32,
+    prev_view_proj: mat4x4<f32>,
 }
 
 struct LightUniform {
@@ -13,6 +15,44 @@ struct LightUniform {
     view_proj: mat4x4<f32>,
     intensity: f32,
     lights_nums: vec4<u32>,
+    /// See `ParallelLight::shadow_params`
+    shadow: vec4<f32>,
+    shadow_filter: vec4<u32>,
+    shadow_moments: vec4<f32>,
+}
+
+// Must match `MAX_REFLECTION_PROBES`
+const MAX_REFLECTION_PROBES: u32 = 8u;
+
+struct ReflectionProbe {
+    position: vec3<f32>,
+    blend_distance: f32,
+    box_min: vec3<f32>,
+    layer: u32,
+    box_max: vec3<f32>,
+    padding: f32,
+}
+
+struct ReflectionProbes {
+    probes: array<ReflectionProbe, MAX_REFLECTION_PROBES>,
+    count: vec4<u32>,
+}
+
+// Must match `MAX_IRRADIANCE_VOLUMES` and the z of `MAX_VOLUME_RESOLUTION`
+const MAX_IRRADIANCE_VOLUMES: u32 = 4u;
+const IRRADIANCE_SLAB_DEPTH: u32 = 16u;
+
+struct IrradianceVolume {
+    box_min: vec3<f32>,
+    blend_distance: f32,
+    box_max: vec3<f32>,
+    slot: u32,
+    resolution: vec4<u32>,
+}
+
+struct IrradianceVolumes {
+    volumes: array<IrradianceVolume, MAX_IRRADIANCE_VOLUMES>,
+    count: vec4<u32>,
 }
 
 @group(0) @binding(0) var<uniform> camera: CameraUniform;
@@ -23,3 +63,9 @@ struct LightUniform {
 @group(0) @binding(4) var dfg_lut: texture_2d<f32>;
 @group(0) @binding(5) var env_cubemap: texture_cube<f32>;
 @group(0) @binding(6) var env_cubemap_sampler: sampler;
+@group(0) @binding(7) var reflection_probe_cubemaps: texture_cube_array<f32>;
+@group(0) @binding(8) var<uniform> reflection_probes: ReflectionProbes;
+@group(0) @binding(9) var irradiance_volume_texture: texture_3d<f32>;
+@group(0) @binding(10) var<uniform> irradiance_volumes: IrradianceVolumes;
+@group(0) @binding(11) var directional_shadow_moments: texture_2d<f32>;
+@group(0) @binding(12) var directional_shadow_moments_sampler: sampler;
@@ -1,7 +1,10 @@
 #define_import_path ibl_functions
 
 #import global_bindings::{
-    env_cubemap, env_cubemap_sampler, dfg_lut
+    env_cubemap, env_cubemap_sampler, dfg_lut,
+    reflection_probe_cubemaps, reflection_probes, ReflectionProbe, MAX_REFLECTION_PROBES,
+    irradiance_volume_texture, irradiance_volumes, IrradianceVolume,
+    MAX_IRRADIANCE_VOLUMES, IRRADIANCE_SLAB_DEPTH,
 }
 #import pbr_type::PBRSurface
 
@@ -14,9 +17,77 @@ fn prefiltered_dfg_lut(perceptual_roughness: f32, nDotV: f32) -> vec2<f32> {
     return textureSample(dfg_lut, env_cubemap_sampler, vec2(nDotV, perceptual_roughness)).xy;
 }
 
-fn evaluate_ibl_spectular(reflect: vec3<f32>, perceptual_roughness: f32) -> vec3<f32>{
+/// 1 inside the box, fading to 0 over `blend_distance` towards its faces
+fn probe_influence(probe: ReflectionProbe, world_pos: vec3<f32>) -> f32 {
+    let inner = min(world_pos - probe.box_min, probe.box_max - world_pos);
+    let distance = min(min(inner.x, inner.y), inner.z);
+    return clamp(distance / max(probe.blend_distance, 0.0001), 0.0, 1.0);
+}
+
+/// Parallax correction: hit the box with the reflection ray and look up the
+/// probe towards the hit point instead of along the ray.
+fn box_project(probe: ReflectionProbe, world_pos: vec3<f32>, dir: vec3<f32>) -> vec3<f32> {
+    let to_max = (probe.box_max - world_pos) / dir;
+    let to_min = (probe.box_min - world_pos) / dir;
+    let furthest = max(to_max, to_min);
+    let t = min(min(furthest.x, furthest.y), furthest.z);
+    return world_pos + dir * t - probe.position;
+}
+
+/// Reflection probes in priority order, whatever they do not cover falls back to the sky.
+fn evaluate_ibl_spectular(world_pos: vec3<f32>, reflect: vec3<f32>, perceptual_roughness: f32) -> vec3<f32>{
     let level = 5.0 * perceptual_roughness;
-    return textureSampleLevel(env_cubemap, env_cubemap_sampler, reflect, level).xyz;
+    var color = vec3<f32>(0.0);
+    var remaining = 1.0;
+    for (var i = 0u; i < min(reflection_probes.count.x, MAX_REFLECTION_PROBES); i += 1u) {
+        let probe = reflection_probes.probes[i];
+        let weight = probe_influence(probe, world_pos) * remaining;
+        if weight <= 0.0 { continue; }
+        let dir = box_project(probe, world_pos, reflect);
+        color += weight * textureSampleLevel(
+            reflection_probe_cubemaps, env_cubemap_sampler, dir, probe.layer, level
+        ).xyz;
+        remaining -= weight;
+        if remaining <= 0.0 { break; }
+    }
+    return color + remaining * textureSampleLevel(env_cubemap, env_cubemap_sampler, reflect, level).xyz;
+}
+
+fn volume_influence(volume: IrradianceVolume, world_pos: vec3<f32>) -> f32 {
+    let inner = min(world_pos - volume.box_min, volume.box_max - world_pos);
+    let distance = min(min(inner.x, inner.y), inner.z);
+    return clamp(distance / max(volume.blend_distance, 0.0001), 0.0, 1.0);
+}
+
+/// Trilinear lookup of the ambient cube of `axis` (+x, -x, +y, -y, +z, -z)
+fn sample_ambient_cube(volume: IrradianceVolume, world_pos: vec3<f32>, axis: u32) -> vec3<f32> {
+    let resolution = vec3<f32>(volume.resolution.xyz);
+    let t = clamp((world_pos - volume.box_min) / (volume.box_max - volume.box_min), vec3(0.0), vec3(1.0));
+    // Probes sit on the texel centers and span the whole box
+    var texel = t * max(resolution - 1.0, vec3(0.0)) + 0.5;
+    texel.z += f32((volume.slot * 6u + axis) * IRRADIANCE_SLAB_DEPTH);
+    let size = vec3<f32>(textureDimensions(irradiance_volume_texture));
+    return textureSampleLevel(irradiance_volume_texture, env_cubemap_sampler, texel / size, 0.0).xyz;
+}
+
+/// Irradiance over PI from the volumes around `world_pos`, `fallback` where none covers it.
+fn evaluate_irradiance_volumes(world_pos: vec3<f32>, normal: vec3<f32>, fallback: vec3<f32>) -> vec3<f32> {
+    let n2 = normal * normal;
+    let positive = normal >= vec3(0.0);
+    var color = vec3<f32>(0.0);
+    var remaining = 1.0;
+    for (var i = 0u; i < min(irradiance_volumes.count.x, MAX_IRRADIANCE_VOLUMES); i += 1u) {
+        let volume = irradiance_volumes.volumes[i];
+        let weight = volume_influence(volume, world_pos) * remaining;
+        if weight <= 0.0 { continue; }
+        let irradiance = n2.x * sample_ambient_cube(volume, world_pos, select(1u, 0u, positive.x))
+            + n2.y * sample_ambient_cube(volume, world_pos, select(3u, 2u, positive.y))
+            + n2.z * sample_ambient_cube(volume, world_pos, select(5u, 4u, positive.z));
+        color += weight * irradiance;
+        remaining -= weight;
+        if remaining <= 0.0 { break; }
+    }
+    return color + remaining * fallback;
 }
 
 /// IBL 仍然由 Specular + Diffuse 构成
@@ -27,17 +98,17 @@ fn evaluate_ibl_spectular(reflect: vec3<f32>, perceptual_roughness: f32) -> vec3
 /// ## Diffuse = Diffuse Color * Indirect Diffuse
 /// - Diffuse Color: abldo
 /// - Indirect Diffuse: 通过 Spherical Harmonics 获得，只取决于法线
-fn evaluate_ibl(normal: vec3<f32>, world2camera: vec3<f32>, diffuse_color: vec3<f32>, f0: vec3<f32>, f90: vec3<f32>, perceptual_roughness: f32)
+fn evaluate_ibl(world_pos: vec3<f32>, normal: vec3<f32>, world2camera: vec3<f32>, diffuse_color: vec3<f32>, f0: vec3<f32>, f90: vec3<f32>, perceptual_roughness: f32)
     -> vec3<f32>
 {
     let nDotV = max(dot(normal, world2camera), 0.0); // Check neg pos
     let reflect = reflect(-world2camera, normal);
 
-    let indirect_specular: vec3<f32> = evaluate_ibl_spectular(reflect, perceptual_roughness);
+    let indirect_specular: vec3<f32> = evaluate_ibl_spectular(world_pos, reflect, perceptual_roughness);
     let dfg: vec2<f32> = prefiltered_dfg_lut(perceptual_roughness, nDotV);
     let specular_color: vec3<f32> = f0 * dfg.x + f90 * dfg.y;
 
-    let indirect_diffuse: vec3<f32> = vec3<f32>(0.0); //todo
+    let indirect_diffuse: vec3<f32> = evaluate_irradiance_volumes(world_pos, normal, vec3<f32>(0.0)); //todo global irradiance
 
     return diffuse_color * indirect_diffuse + specular_color * indirect_specular;
 }
@@ -0,0 +1,38 @@
+#define_import_path light_cluster
+
+// Must match `MAX_LIGHTS_PER_CLUSTER`
+const MAX_LIGHTS_PER_CLUSTER: u32 = 64u;
+
+struct ClusterUniform {
+    view: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    // xyz: grid, w: point lights
+    grid: vec4<u32>,
+    // near, far, slice scale, slice bias
+    depth: vec4<f32>,
+}
+
+fn slice_of_depth(clusters: ClusterUniform, view_depth: f32) -> u32 {
+    let slice = log(max(view_depth, clusters.depth.x)) * clusters.depth.z + clusters.depth.w;
+    return min(u32(max(slice, 0.0)), clusters.grid.z - 1u);
+}
+
+fn depth_of_slice(clusters: ClusterUniform, slice: u32) -> f32 {
+    return clusters.depth.x * pow(clusters.depth.y / clusters.depth.x, f32(slice) / f32(clusters.grid.z));
+}
+
+fn cluster_index(clusters: ClusterUniform, id: vec3<u32>) -> u32 {
+    return (id.z * clusters.grid.y + id.y) * clusters.grid.x + id.x;
+}
+
+/// Offset into the cluster light list, the count is followed by the light indices.
+fn cluster_offset(index: u32) -> u32 {
+    return index * (MAX_LIGHTS_PER_CLUSTER + 1u);
+}
+
+/// `uv` is the screen uv, top left is (0, 0).
+fn cluster_at(clusters: ClusterUniform, uv: vec2<f32>, world_pos: vec3<f32>) -> u32 {
+    let view_depth = -(clusters.view * vec4<f32>(world_pos, 1.0)).z;
+    let tile = min(vec2<u32>(clamp(uv, vec2<f32>(0.0), vec2<f32>(1.0)) * vec2<f32>(clusters.grid.xy)), clusters.grid.xy - 1u);
+    return cluster_index(clusters, vec3<u32>(tile, slice_of_depth(clusters, view_depth)));
+}
@@ -1,5 +1,36 @@
 #define_import_path pbr_type
 
+/// UV set and affine `KHR_texture_transform` of a texture slot, must match `RawTextureTransform`
+struct TextureTransform {
+    u_row: vec3<f32>,
+    tex_coord: u32,
+    v_row: vec3<f32>,
+}
+
+fn transform_uv(transform: TextureTransform, uv: vec2<f32>, second_uv: vec2<f32>) -> vec2<f32> {
+    let source = vec3<f32>(select(uv, second_uv, transform.tex_coord == 1u), 1.0);
+    return vec2<f32>(dot(transform.u_row, source), dot(transform.v_row, source));
+}
+
+/// Material uniform, must match `RawPBRMaterial`
+struct PBRMaterial {
+    metallic: f32,
+    roughness: f32,
+    reflectance: f32,
+    emissive_strength: f32,
+    emissive: vec4<f32>,
+    // rgb: color, a: roughness
+    sheen: vec4<f32>,
+    // rgb: tint of the dielectric F0, a: specular factor, the F90 of dielectrics
+    specular: vec4<f32>,
+    // x: transmission, y: thickness, z: attenuation distance, w: ior
+    transmission: vec4<f32>,
+    attenuation_color: vec4<f32>,
+    base_color_transform: TextureTransform,
+    normal_transform: TextureTransform,
+    emissive_transform: TextureTransform,
+}
+
 struct StandardMaterial {
     base_color: vec3<f32>,
     emissive: vec4<f32>,
@@ -8,6 +39,10 @@ struct StandardMaterial {
     reflectance: f32,
     clear_coat: f32,
     clear_coat_perceptual_roughness: f32,
+    sheen_color: vec3<f32>,
+    sheen_perceptual_roughness: f32,
+    specular_tint: vec3<f32>,
+    specular_factor: f32,
 }
 
 fn standard_material_new() -> StandardMaterial{
@@ -20,6 +55,10 @@ fn standard_material_new() -> StandardMaterial{
     material.reflectance = 0.5;
     material.clear_coat = 0.0;
     material.clear_coat_perceptual_roughness = 0.5;
+    material.sheen_color = vec3<f32>(0.0);
+    material.sheen_perceptual_roughness = 0.0;
+    material.specular_tint = vec3<f32>(1.0);
+    material.specular_factor = 1.0;
 
     return material;
 }
@@ -48,7 +87,34 @@ fn perceptual_roughness_to_roughness(perceptual_roughness: f32) -> f32 {
 // x: mapped_normal (3),
 // y: metallic, reflectance, clear_coat_perceptual_roughness, clear_coat,
 // z: base_color (3), perceptual_roughness,
-// w: emissive (3),
+// w: emissive (3) in RGB9E5,
+// extra x: sheen_color (3), sheen_perceptual_roughness
+// extra y: specular_tint (3), specular_factor
+
+const RGB9E5_EXPONENT_BIAS: i32 = 15;
+const RGB9E5_MANTISSA_BITS: i32 = 9;
+const RGB9E5_MAX: f32 = 65408.0;
+
+/// Shared exponent HDR color, 9 bits of mantissa per channel and 5 bits of exponent.
+fn pack_rgb9e5(color: vec3<f32>) -> u32 {
+    let c = clamp(color, vec3<f32>(0.0), vec3<f32>(RGB9E5_MAX));
+    let max_channel = max(max(c.r, c.g), max(c.b, 1e-30));
+    var exponent = max(-RGB9E5_EXPONENT_BIAS - 1, i32(floor(log2(max_channel)))) + 1 + RGB9E5_EXPONENT_BIAS;
+    var scale = exp2(f32(exponent - RGB9E5_EXPONENT_BIAS - RGB9E5_MANTISSA_BITS));
+    // Rounding may overflow the mantissa
+    if u32(floor(max_channel / scale + 0.5)) == 512u {
+        scale *= 2.0;
+        exponent += 1;
+    }
+    let m = vec3<u32>(floor(c / scale + 0.5));
+    return (u32(exponent) << 27u) | (m.b << 18u) | (m.g << 9u) | m.r;
+}
+
+fn unpack_rgb9e5(packed: u32) -> vec3<f32> {
+    let exponent = i32(packed >> 27u) - RGB9E5_EXPONENT_BIAS - RGB9E5_MANTISSA_BITS;
+    let m = vec3<u32>(packed & 0x1ffu, (packed >> 9u) & 0x1ffu, (packed >> 18u) & 0x1ffu);
+    return vec3<f32>(m) * exp2(f32(exponent));
+}
 
 fn pack_g_buffer(in: PBRSurface) -> vec4<u32> {
     return vec4<u32>(
@@ -59,11 +125,18 @@ fn pack_g_buffer(in: PBRSurface) -> vec4<u32> {
             in.material.clear_coat_perceptual_roughness,
             in.material.clear_coat)),
         pack4x8unorm(vec4<f32>(in.material.base_color, in.material.perceptual_roughness)),
-        pack4x8unorm(in.material.emissive),
+        pack_rgb9e5(in.material.emissive.xyz),
+    );
+}
+
+fn pack_g_buffer_extra(in: PBRSurface) -> vec2<u32> {
+    return vec2<u32>(
+        pack4x8unorm(vec4<f32>(in.material.sheen_color, in.material.sheen_perceptual_roughness)),
+        pack4x8unorm(vec4<f32>(in.material.specular_tint, in.material.specular_factor)),
     );
 }
 
-fn unpack_g_buffer(in: vec4<u32>) -> PBRSurface {
+fn unpack_g_buffer(in: vec4<u32>, extra: vec2<u32>) -> PBRSurface {
     var material = standard_material_new();
     var ret: PBRSurface;
     let raw_normal = unpack4x8unorm(in.x).xyz;
@@ -75,6 +148,13 @@ fn unpack_g_buffer(in: vec4<u32>) -> PBRSurface {
     let color_rou = unpack4x8unorm(in.z);
     material.base_color = color_rou.xyz;
     material.perceptual_roughness = color_rou.w;
+    material.emissive = vec4<f32>(unpack_rgb9e5(in.w), 1.0);
+    let sheen = unpack4x8unorm(extra.x);
+    material.sheen_color = sheen.xyz;
+    material.sheen_perceptual_roughness = sheen.w;
+    let specular = unpack4x8unorm(extra.y);
+    material.specular_tint = specular.xyz;
+    material.specular_factor = specular.w;
 
     ret.material = material;
     if(all(raw_normal == vec3f(0.0))) {
@@ -0,0 +1,175 @@
+#define_import_path shadow
+
+#import global_bindings::{
+    light, directional_shadow_map, directional_shadow_map_comparison_sampler,
+    directional_shadow_moments, directional_shadow_moments_sampler,
+}
+
+// Must match `ShadowFilter::mode`
+const SHADOW_FILTER_HARD: u32 = 0u;
+const SHADOW_FILTER_PCF: u32 = 1u;
+const SHADOW_FILTER_POISSON: u32 = 2u;
+const SHADOW_FILTER_PCSS: u32 = 3u;
+const SHADOW_FILTER_VSM: u32 = 4u;
+const SHADOW_FILTER_EVSM: u32 = 5u;
+
+const POISSON_SAMPLES: u32 = 16u;
+// Keeps the blocker search of PCSS from sampling half the map
+const PCSS_MAX_SEARCH_UV: f32 = 0.05;
+
+var<private> POISSON_DISK: array<vec2<f32>, 16> = array<vec2<f32>, 16>(
+    vec2<f32>(-0.94201624, -0.39906216),
+    vec2<f32>(0.94558609, -0.76890725),
+    vec2<f32>(-0.09418410, -0.92938870),
+    vec2<f32>(0.34495938, 0.29387760),
+    vec2<f32>(-0.91588581, 0.45771432),
+    vec2<f32>(-0.81544232, -0.87912464),
+    vec2<f32>(-0.38277543, 0.27676845),
+    vec2<f32>(0.97484398, 0.75648379),
+    vec2<f32>(0.44323325, -0.97511554),
+    vec2<f32>(0.53742981, -0.47373420),
+    vec2<f32>(-0.26496911, -0.41893023),
+    vec2<f32>(0.79197514, 0.19090188),
+    vec2<f32>(-0.24188840, 0.99706507),
+    vec2<f32>(-0.81409955, 0.91437590),
+    vec2<f32>(0.19984126, 0.78641367),
+    vec2<f32>(0.14383161, -0.14100790),
+);
+
+fn interleaved_gradient_noise(pixel: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+fn compare(coords: vec2<f32>, depth: f32) -> f32 {
+    return textureSampleCompareLevel(
+        directional_shadow_map,
+        directional_shadow_map_comparison_sampler,
+        coords,
+        depth,
+    );
+}
+
+fn poisson_offset(i: u32, rotation: mat2x2<f32>, radius: f32) -> vec2<f32> {
+    return rotation * POISSON_DISK[i] * radius;
+}
+
+fn pcf(coords: vec2<f32>, depth: f32, radius: u32) -> f32 {
+    let r = i32(radius);
+    var sum = 0.0;
+    for (var i = -r; i <= r; i++) {
+        for (var j = -r; j <= r; j++) {
+            sum += compare(coords + vec2<f32>(f32(i), f32(j)) * light.shadow.x, depth);
+        }
+    }
+    let width = f32(2 * r + 1);
+    return sum / (width * width);
+}
+
+fn poisson_pcf(coords: vec2<f32>, depth: f32, rotation: mat2x2<f32>, radius: f32) -> f32 {
+    var sum = 0.0;
+    for (var i = 0u; i < POISSON_SAMPLES; i++) {
+        sum += compare(coords + poisson_offset(i, rotation, radius), depth);
+    }
+    return sum / f32(POISSON_SAMPLES);
+}
+
+/// Average depth of the occluders within `radius`, negative if there are none.
+fn average_blocker_depth(coords: vec2<f32>, depth: f32, rotation: mat2x2<f32>, radius: f32) -> f32 {
+    let dims = vec2<i32>(textureDimensions(directional_shadow_map));
+    var sum = 0.0;
+    var count = 0.0;
+    for (var i = 0u; i < POISSON_SAMPLES; i++) {
+        let uv = coords + poisson_offset(i, rotation, radius);
+        let texel = clamp(vec2<i32>(uv * vec2<f32>(dims)), vec2<i32>(0), dims - 1);
+        let blocker = textureLoad(directional_shadow_map, texel, 0);
+        if blocker < depth {
+            sum += blocker;
+            count += 1.0;
+        }
+    }
+    return select(-1.0, sum / max(count, 1.0), count > 0.0);
+}
+
+fn pcss(coords: vec2<f32>, depth: f32, rotation: mat2x2<f32>) -> f32 {
+    // Blockers lie between the near plane and the receiver
+    let search = clamp(depth * light.shadow.w, light.shadow.x, PCSS_MAX_SEARCH_UV);
+    let blocker = average_blocker_depth(coords, depth, rotation, search);
+    if blocker < 0.0 {
+        return 1.0;
+    }
+    let penumbra = max((depth - blocker) * light.shadow.w, light.shadow.x);
+    return poisson_pcf(coords, depth, rotation, penumbra);
+}
+
+/// Upper bound of the visibility from the mean and variance, with light bleeding reduction.
+fn chebyshev(moments: vec2<f32>, depth: f32, min_variance: f32) -> f32 {
+    if depth <= moments.x {
+        return 1.0;
+    }
+    let variance = max(moments.y - moments.x * moments.x, min_variance);
+    let d = depth - moments.x;
+    let p_max = variance / (variance + d * d);
+    let bleeding = light.shadow_moments.z;
+    return clamp((p_max - bleeding) / (1.0 - bleeding), 0.0, 1.0);
+}
+
+fn moment_shadow(coords: vec2<f32>, depth: f32, ddx: vec2<f32>, ddy: vec2<f32>) -> f32 {
+    let moments = textureSampleGrad(
+        directional_shadow_moments,
+        directional_shadow_moments_sampler,
+        coords,
+        ddx,
+        ddy,
+    );
+    let min_variance = light.shadow_moments.w;
+    if light.shadow_filter.x == SHADOW_FILTER_EVSM {
+        // Same warp as `shadow_moments.wgsl`, the min variance scales with its derivative
+        let d = depth * 2.0 - 1.0;
+        let positive = exp(light.shadow_moments.x * d);
+        let negative = -exp(-light.shadow_moments.y * d);
+        let positive_scale = light.shadow_moments.x * positive;
+        let negative_scale = light.shadow_moments.y * negative;
+        return min(
+            chebyshev(moments.xy, positive, min_variance * positive_scale * positive_scale),
+            chebyshev(moments.zw, negative, min_variance * negative_scale * negative_scale),
+        );
+    }
+    return chebyshev(moments.xy, depth, min_variance);
+}
+
+/// Visibility of the parallel light, `pixel` rotates the Poisson disk per pixel.
+fn directional_shadow(world_pos: vec3<f32>, normal: vec3<f32>, pixel: vec2<f32>) -> f32 {
+    let n_dot_l = clamp(dot(normal, -light.direction), 0.0, 1.0);
+    let offset_pos = world_pos + normal * light.shadow.z * (1.0 - n_dot_l);
+    let pos = light.view_proj * vec4<f32>(offset_pos, 1.0);
+    let ndc = pos.xyz / pos.w;
+    let coords = ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5);
+    // Mip selection of the moments, taken while the control flow is still uniform
+    let ddx = dpdx(coords);
+    let ddy = dpdy(coords);
+    if any(coords < vec2<f32>(0.0)) || any(coords > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    let depth = ndc.z - light.shadow.y;
+
+    let angle = interleaved_gradient_noise(pixel) * radians(360.0);
+    let rotation = mat2x2<f32>(cos(angle), sin(angle), -sin(angle), cos(angle));
+
+    switch light.shadow_filter.x {
+        case SHADOW_FILTER_PCF: {
+            return pcf(coords, depth, light.shadow_filter.y);
+        }
+        case SHADOW_FILTER_POISSON: {
+            return poisson_pcf(coords, depth, rotation, light.shadow.w);
+        }
+        case SHADOW_FILTER_PCSS: {
+            return pcss(coords, depth, rotation);
+        }
+        case SHADOW_FILTER_VSM, SHADOW_FILTER_EVSM: {
+            return moment_shadow(coords, depth, ddx, ddy);
+        }
+        default: {
+            return compare(coords, depth);
+        }
+    }
+}
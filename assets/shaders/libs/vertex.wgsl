@@ -6,6 +6,7 @@ struct VertexInput {
     @location(2) tangent: vec3<f32>,
     @location(3) color: vec4<f32>,
     @location(4) tex_coord: vec2<f32>,
+    @location(5) second_tex_coord: vec2<f32>,
 };
 
 struct CubeVertexInput {
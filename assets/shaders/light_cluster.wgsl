@@ -0,0 +1,64 @@
+#import light_cluster::{ClusterUniform, MAX_LIGHTS_PER_CLUSTER}
+#import light_cluster
+
+struct PointLight {
+    color: vec4<f32>,
+    position: vec4<f32>,
+    intensity: f32,
+    distance: f32,
+    decay: f32,
+}
+
+@group(0) @binding(0) var<uniform> clusters: ClusterUniform;
+@group(0) @binding(1) var<storage, read> point_lights: array<PointLight>;
+@group(0) @binding(2) var<storage, read_write> cluster_lights: array<u32>;
+
+/// View space point on the view ray through `ndc`, at `view_depth` in front of the camera.
+fn view_point(ndc: vec2<f32>, view_depth: f32) -> vec3<f32> {
+    let far = clusters.inverse_projection * vec4<f32>(ndc, 1.0, 1.0);
+    let dir = far.xyz / far.w;
+    return dir * (view_depth / -dir.z);
+}
+
+/// One invocation per cluster, tests every light sphere against the cluster bounds.
+@compute @workgroup_size(4, 3, 4)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id >= clusters.grid.xyz) {
+        return;
+    }
+
+    let uv_min = vec2<f32>(id.xy) / vec2<f32>(clusters.grid.xy);
+    let uv_max = vec2<f32>(id.xy + 1u) / vec2<f32>(clusters.grid.xy);
+    // uv y points down
+    let ndc_min = vec2<f32>(uv_min.x * 2.0 - 1.0, 1.0 - uv_max.y * 2.0);
+    let ndc_max = vec2<f32>(uv_max.x * 2.0 - 1.0, 1.0 - uv_min.y * 2.0);
+    let near = light_cluster::depth_of_slice(clusters, id.z);
+    let far = light_cluster::depth_of_slice(clusters, id.z + 1u);
+
+    var aabb_min = vec3<f32>(1e30);
+    var aabb_max = vec3<f32>(-1e30);
+    for (var i = 0u; i < 4u; i += 1u) {
+        let ndc = vec2<f32>(
+            select(ndc_min.x, ndc_max.x, (i & 1u) != 0u),
+            select(ndc_min.y, ndc_max.y, (i & 2u) != 0u),
+        );
+        let p_near = view_point(ndc, near);
+        let p_far = view_point(ndc, far);
+        aabb_min = min(aabb_min, min(p_near, p_far));
+        aabb_max = max(aabb_max, max(p_near, p_far));
+    }
+
+    let offset = light_cluster::cluster_offset(light_cluster::cluster_index(clusters, id));
+    var count = 0u;
+    for (var i = 0u; i < clusters.grid.w && count < MAX_LIGHTS_PER_CLUSTER; i += 1u) {
+        let li = point_lights[i];
+        let center = (clusters.view * vec4<f32>(li.position.xyz, 1.0)).xyz;
+        let closest = clamp(center, aabb_min, aabb_max);
+        let d = closest - center;
+        if dot(d, d) <= li.distance * li.distance {
+            cluster_lights[offset + 1u + count] = i;
+            count += 1u;
+        }
+    }
+    cluster_lights[offset] = count;
+}
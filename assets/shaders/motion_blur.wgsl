@@ -0,0 +1,113 @@
+#import vertex::{FullscreenV2F}
+
+struct MotionBlurParams {
+    shutter_fraction: f32,
+    sample_count: u32,
+    znear: f32,
+    zfar: f32,
+}
+
+@group(0) @binding(0) var main_tex: texture_2d<f32>;
+@group(0) @binding(1) var main_sampler: sampler;
+
+@group(1) @binding(0) var<uniform> params: MotionBlurParams;
+@group(1) @binding(1) var neighbor_max_tex: texture_2d<f32>;
+
+@group(2) @binding(0) var depth_tex: texture_depth_2d;
+@group(2) @binding(1) var depth_sampler: sampler;
+
+@group(3) @binding(0) var g_samp: sampler;
+@group(3) @binding(1) var world_pos_tex: texture_2d<f32>;
+@group(3) @binding(2) var g_buffer_tex: texture_2d<u32>;
+@group(3) @binding(3) var velocity_tex: texture_2d<f32>;
+
+const TILE_SIZE: f32 = 16.0;
+/// Depth range in meters over which two samples are considered at the same depth
+const SOFT_Z_EXTENT: f32 = 0.05;
+
+fn linear_depth(coords: vec2<i32>) -> f32 {
+    let depth = textureLoad(depth_tex, coords, 0);
+    let n = params.znear;
+    let f = params.zfar;
+    return n * f / (f - depth * (f - n));
+}
+
+/// Velocity in pixels over the exposure, no longer than a tile.
+fn exposure_velocity(velocity: vec2<f32>) -> vec2<f32> {
+    let v = velocity * params.shutter_fraction;
+    let len = length(v);
+    if len > TILE_SIZE {
+        return v * (TILE_SIZE / len);
+    }
+    return v;
+}
+
+fn pixel_velocity(coords: vec2<i32>) -> vec2<f32> {
+    let size = vec2<f32>(textureDimensions(velocity_tex));
+    return exposure_velocity(textureLoad(velocity_tex, coords, 0).xy * size);
+}
+
+fn cone(dist: f32, velocity_length: f32) -> f32 {
+    return clamp(1.0 - dist / velocity_length, 0.0, 1.0);
+}
+
+fn cylinder(dist: f32, velocity_length: f32) -> f32 {
+    return 1.0 - smoothstep(0.95 * velocity_length, 1.05 * velocity_length, dist);
+}
+
+/// 1 when `za` is in front of `zb`
+fn soft_depth_compare(za: f32, zb: f32) -> f32 {
+    return clamp(1.0 - (za - zb) / SOFT_Z_EXTENT, 0.0, 1.0);
+}
+
+fn interleaved_gradient_noise(p: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(p, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+@fragment
+fn fs_main(in: FullscreenV2F) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(main_tex));
+    let max_coords = vec2<i32>(size) - 1;
+    let center = vec2<i32>(in.clip_position.xy);
+    let color = textureSampleLevel(main_tex, main_sampler, in.uv, 0.0);
+
+    let tile = center / i32(TILE_SIZE);
+    let neighbor_max = exposure_velocity(textureLoad(neighbor_max_tex, tile, 0).xy);
+    if length(neighbor_max) < 0.5 {
+        return color;
+    }
+
+    let center_velocity_length = max(length(pixel_velocity(center)), 0.5);
+    let center_depth = linear_depth(center);
+
+    var total_weight = 1.0 / center_velocity_length;
+    var sum = color.xyz * total_weight;
+    let jitter = interleaved_gradient_noise(in.clip_position.xy) - 0.5;
+    let count = max(params.sample_count, 3u);
+
+    for (var i = 0u; i < count; i++) {
+        if i == (count - 1u) / 2u {
+            continue;
+        }
+        let t = mix(-1.0, 1.0, (f32(i) + jitter + 1.0) / (f32(count) + 1.0));
+        let offset = neighbor_max * t;
+        let coords = clamp(center + vec2<i32>(round(offset)), vec2<i32>(0), max_coords);
+
+        let sample_depth = linear_depth(coords);
+        let sample_velocity_length = max(length(pixel_velocity(coords)), 0.5);
+        let dist = length(offset);
+
+        let sample_in_front = soft_depth_compare(sample_depth, center_depth);
+        let center_in_front = soft_depth_compare(center_depth, sample_depth);
+        // Blurry sample in front, center blurring over background, both blurry
+        let weight = sample_in_front * cone(dist, sample_velocity_length)
+            + center_in_front * cone(dist, center_velocity_length)
+            + cylinder(dist, sample_velocity_length) * cylinder(dist, center_velocity_length) * 2.0;
+
+        let uv = (vec2<f32>(coords) + 0.5) / size;
+        sum += textureSampleLevel(main_tex, main_sampler, uv, 0.0).xyz * weight;
+        total_weight += weight;
+    }
+
+    return vec4<f32>(sum / total_weight, color.w);
+}
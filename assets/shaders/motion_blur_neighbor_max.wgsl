@@ -0,0 +1,25 @@
+#import vertex::{FullscreenV2F}
+
+@group(0) @binding(0) var tile_max_tex: texture_2d<f32>;
+
+/// Dilate the tile max velocity over the 3x3 neighborhood so blur can leak past tile edges.
+@fragment
+fn fs_main(in: FullscreenV2F) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(tile_max_tex));
+    let center = vec2<i32>(in.clip_position.xy);
+
+    var max_velocity = vec2<f32>(0.0);
+    var max_length = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let coords = clamp(center + vec2<i32>(x, y), vec2<i32>(0), size - 1);
+            let velocity = textureLoad(tile_max_tex, coords, 0).xy;
+            let len = dot(velocity, velocity);
+            if len > max_length {
+                max_length = len;
+                max_velocity = velocity;
+            }
+        }
+    }
+    return vec4<f32>(max_velocity, 0.0, 1.0);
+}
@@ -0,0 +1,30 @@
+#import vertex::{FullscreenV2F}
+
+@group(0) @binding(0) var g_samp: sampler;
+@group(0) @binding(1) var world_pos_tex: texture_2d<f32>;
+@group(0) @binding(2) var g_buffer_tex: texture_2d<u32>;
+@group(0) @binding(3) var velocity_tex: texture_2d<f32>;
+
+const TILE_SIZE: i32 = 16;
+
+/// Longest velocity in pixels of the tile covered by this texel.
+@fragment
+fn fs_main(in: FullscreenV2F) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(velocity_tex));
+    let base = vec2<i32>(in.clip_position.xy) * TILE_SIZE;
+
+    var max_velocity = vec2<f32>(0.0);
+    var max_length = 0.0;
+    for (var y = 0; y < TILE_SIZE; y++) {
+        for (var x = 0; x < TILE_SIZE; x++) {
+            let coords = min(base + vec2<i32>(x, y), size - 1);
+            let velocity = textureLoad(velocity_tex, coords, 0).xy * vec2<f32>(size);
+            let len = dot(velocity, velocity);
+            if len > max_length {
+                max_length = len;
+                max_velocity = velocity;
+            }
+        }
+    }
+    return vec4<f32>(max_velocity, 0.0, 1.0);
+}
@@ -2,10 +2,16 @@
 #import pbr_type
 #import pbr_type::{ PBRSurface }
 #import global_bindings::{
-    camera, light, directional_shadow_map, directional_shadow_map_comparison_sampler,
-    env_cubemap, env_cubemap_sampler,
+    camera, light, env_cubemap, env_cubemap_sampler,
 }
 #import ibl_functions
+#import fog::{FogUniform, FOG_MODE_EXPONENTIAL, FOG_MODE_VOLUMETRIC}
+#import fog
+#import area_light::AreaLight
+#import area_light
+#import light_cluster::ClusterUniform
+#import light_cluster
+#import shadow
 
 struct PointLight {
     color: vec4<f32>,
@@ -18,8 +24,19 @@ struct PointLight {
 @group(1) @binding(0) var g_samp: sampler;
 @group(1) @binding(1) var world_pos_tex: texture_2d<f32>;
 @group(1) @binding(2) var g_buffer_tex: texture_2d<u32>;
+@group(1) @binding(4) var g_buffer_extra_tex: texture_2d<u32>;
 
 @group(2) @binding(0) var<storage, read> point_lights: array<PointLight>;
+@group(2) @binding(1) var<storage, read> area_lights: array<AreaLight>;
+@group(2) @binding(2) var ltc_1: texture_2d<f32>;
+@group(2) @binding(3) var ltc_2: texture_2d<f32>;
+@group(2) @binding(4) var ltc_sampler: sampler;
+@group(2) @binding(5) var<uniform> clusters: ClusterUniform;
+@group(2) @binding(6) var<storage, read> cluster_lights: array<u32>;
+
+@group(3) @binding(0) var<uniform> fog_uniform: FogUniform;
+@group(3) @binding(1) var fog_volume: texture_3d<f32>;
+@group(3) @binding(2) var fog_sampler: sampler;
 
 const PI: f32 = radians(180.0);
 
@@ -38,6 +55,17 @@ fn V_smith_ggx_correlated_fast(nDotV: f32, nDotL: f32, roughness: f32) -> f32 {
     return 0.5 / (0.001 + GGXL + GGXV);
 }
 
+/// Charlie distribution of the sheen lobe, "Production Friendly Microfacet Sheen BRDF"
+fn D_charlie(roughness: f32, nDotH: f32) -> f32 {
+    let inv_alpha = 1.0 / roughness;
+    let sin2h = max(1.0 - nDotH * nDotH, 0.0078125);
+    return (2.0 + inv_alpha) * pow(sin2h, inv_alpha * 0.5) / (2.0 * PI);
+}
+
+fn V_neubelt(nDotV: f32, nDotL: f32) -> f32 {
+    return 1.0 / (4.0 * (nDotL + nDotV - nDotL * nDotV) + 0.001);
+}
+
 fn calculate_light(
     light_color: vec3<f32>,
     light_diffuse_intensity: f32,
@@ -80,38 +108,72 @@ fn calculate_light(
     // final specular BRDF
     let specular_brdf = fresnel * (D_GGX * V_SmithGGX);
 
+    // ! Sheen BRDF, layered on top and dimming the base by its rough albedo
+    let sheen_color = surface.material.sheen_color;
+    let sheen_roughness = pbr_type::perceptual_roughness_to_roughness(surface.material.sheen_perceptual_roughness);
+    let sheen_brdf = sheen_color * D_charlie(sheen_roughness, nDotH) * V_neubelt(nDotV, nDotL);
+    let sheen_scaling = 1.0 - 0.157 * max(sheen_color.r, max(sheen_color.g, sheen_color.b));
+
     let light_intensity = light_color * light_diffuse_intensity;
 
-    let ret = (specular_brdf + diffuse_brdf) * light_intensity * nDotL;
+    let ret = ((specular_brdf + diffuse_brdf) * sheen_scaling + sheen_brdf) * light_intensity * nDotL;
 
     return ret;
 }
 
-fn sample_directional_shadow(world_pos: vec3<f32>) -> f32{
-    let pos = light.view_proj * vec4<f32>(world_pos, 1.0);
-    let light_space_clip_pos = pos.xyz / pos.w;
-    let coords = light_space_clip_pos.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5);
-    let current_depth = light_space_clip_pos.z;
-    var sample: f32 = 0.0;
-    for (var i = -1; i <= 1; i++) {
-        for (var j = -1; j <= 1; j++) {
-            sample += textureSampleCompare(
-                directional_shadow_map,
-                directional_shadow_map_comparison_sampler,
-                coords + vec2f(vec2(i, j)) / 2048.0,
-                current_depth
-            );
-        }
+fn calculate_area_lights(
+    surface: PBRSurface,
+    world_pos: vec3<f32>,
+    world2camera: vec3<f32>,
+    f0: vec3<f32>,
+) -> vec3<f32> {
+    let normal = surface.normal;
+    let n_dot_v = dot(normal, world2camera);
+    let uv = area_light::ltc_uv(surface.material.perceptual_roughness, n_dot_v);
+    let minv = area_light::ltc_inverse_matrix(textureSampleLevel(ltc_1, ltc_sampler, uv, 0.0));
+    let magnitude_fresnel = textureSampleLevel(ltc_2, ltc_sampler, uv, 0.0).xy;
+    // Split sum of Schlick Fresnel over the fitted lobe
+    let specular_scale = f0 * magnitude_fresnel.x + (1.0 - f0) * magnitude_fresnel.y;
+    let diffuse_color = (1.0 - surface.material.metallic) * surface.material.base_color;
+    let identity = mat3x3<f32>(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(0.0, 0.0, 1.0));
+
+    var ret = vec3<f32>(0.0);
+    for (var i = 0u; i < light.lights_nums.y; i += 1u) {
+        let li = area_lights[i];
+        let specular = area_light::ltc_evaluate(normal, world2camera, world_pos, minv, li);
+        let diffuse = area_light::ltc_evaluate(normal, world2camera, world_pos, identity, li);
+        ret += li.color.xyz * (specular_scale * specular + diffuse_color * diffuse);
     }
-    return sample / 9.;
+    return ret;
+}
+
+fn apply_fog(color: vec3<f32>, world_pos: vec3<f32>, uv: vec2<f32>) -> vec3<f32> {
+    if fog_uniform.mode == FOG_MODE_EXPONENTIAL {
+        let transmittance = fog::height_fog_transmittance(fog_uniform, world_pos);
+        let view_dir = normalize(world_pos - fog_uniform.camera_position.xyz);
+        let sun = light.color.xyz * light.intensity
+            * fog::phase_hg(dot(view_dir, -light.direction), fog_uniform.anisotropy);
+        let in_scattering = fog_uniform.color.xyz * (vec3<f32>(fog_uniform.ambient) + sun);
+        return mix(in_scattering, color, transmittance);
+    }
+    if fog_uniform.mode == FOG_MODE_VOLUMETRIC {
+        let depth = f32(textureDimensions(fog_volume).z);
+        let distance = length(world_pos - fog_uniform.camera_position.xyz);
+        // Each froxel stores the integral up to its far side
+        let w = fog::distance_to_slice(fog_uniform, distance) - 0.5 / depth;
+        let volume = textureSampleLevel(fog_volume, fog_sampler, vec3<f32>(uv, w), 0.0);
+        return color * volume.a + volume.xyz;
+    }
+    return color;
 }
 
 @fragment
 fn fs_main(in: FullscreenV2F) -> @location(0) vec4<f32> {
     let world_pos: vec3<f32> = textureSample(world_pos_tex, g_samp, in.uv).xyz;
     let g_buffer: vec4<u32> = textureLoad(g_buffer_tex, vec2<i32>(in.clip_position.xy), 0);
+    let g_buffer_extra: vec2<u32> = textureLoad(g_buffer_extra_tex, vec2<i32>(in.clip_position.xy), 0).xy;
 
-    let surface: PBRSurface = pbr_type::unpack_g_buffer(g_buffer);
+    let surface: PBRSurface = pbr_type::unpack_g_buffer(g_buffer, g_buffer_extra);
 
     if(all(surface.normal == vec3f(0.0))) {
         discard;
@@ -120,10 +182,12 @@ fn fs_main(in: FullscreenV2F) -> @location(0) vec4<f32> {
     let metallic = surface.material.metallic;
     let base_color = surface.material.base_color;
 
+    // KHR_materials_specular tints the dielectric part
     let f0: vec3<f32> =
-        vec3<f32>(0.16 * pow2(surface.material.reflectance) * (1.0 - metallic))
+        0.16 * pow2(surface.material.reflectance) * (1.0 - metallic) * surface.material.specular_tint
          + base_color * metallic;
-    let f90 = vec3<f32>(1.0);
+    // and its specular factor scales the F90 of the dielectric part
+    let f90 = mix(vec3<f32>(surface.material.specular_factor), vec3<f32>(1.0), metallic);
 
     var surface_color = vec3<f32>(0.0);
 
@@ -139,11 +203,12 @@ fn fs_main(in: FullscreenV2F) -> @location(0) vec4<f32> {
         f90,
     );
 
-    // + Point Lighting
-    let point_lights_num = light.lights_nums.x;
+    // + Point Lighting, only the lights binned into this pixel's cluster
+    let cluster_offset = light_cluster::cluster_offset(light_cluster::cluster_at(clusters, in.uv, world_pos));
+    let cluster_light_count = cluster_lights[cluster_offset];
 
-    for (var i = 0u; i < point_lights_num; i += 1u) {
-        let li = point_lights[i];
+    for (var i = 0u; i < cluster_light_count; i += 1u) {
+        let li = point_lights[cluster_lights[cluster_offset + 1u + i]];
         let world2light_unnorm = li.position.xyz - world_pos;
         let dist = length(world2light_unnorm);
         if dist > li.distance { continue; }
@@ -161,8 +226,12 @@ fn fs_main(in: FullscreenV2F) -> @location(0) vec4<f32> {
         );
     }
 
+    // + Area Lighting
+    surface_color += calculate_area_lights(surface, world_pos, world2camera, f0);
+
     /// + Image based Lighting
     let ibl = ibl_functions::evaluate_ibl(
+                        world_pos,
                         surface.normal,
                         world2camera,
                         base_color,
@@ -175,9 +244,16 @@ fn fs_main(in: FullscreenV2F) -> @location(0) vec4<f32> {
     surface_color += vec3<f32>(0.1);
 
     /// -- Shadowing --
-    let shadow = sample_directional_shadow(world_pos);
+    let shadow = shadow::directional_shadow(world_pos, surface.normal, in.clip_position.xy);
     surface_color *= mix(vec3<f32>(0.5), vec3<f32>(1.0), shadow);
 
+    // + Emission, unaffected by shadows
+    surface_color += surface.material.emissive.xyz;
+
+    surface_color = apply_fog(surface_color, world_pos, in.uv);
+
+    surface_color *= camera.exposure;
+
     return vec4<f32>(surface_color, 1.0);
     // return vec4<f32>(ibl, 1.0);
     // return vec4<f32>(surface.material.base_color, 1.0);
@@ -0,0 +1,62 @@
+#import vertex::CubemapVertexOutput
+
+struct SkyUniform {
+    sun_direction: vec4<f32>,
+    zenith: vec4<f32>,
+    perez: array<vec4<f32>, 5>,
+    sun_color: vec4<f32>,
+}
+
+@group(1) @binding(0) var<uniform> sky: SkyUniform;
+
+/// Perez luminance distribution of Y, x and y at once.
+fn perez(cos_theta: f32, gamma: f32, cos_gamma: f32) -> vec3<f32> {
+    let a = sky.perez[0].xyz;
+    let b = sky.perez[1].xyz;
+    let c = sky.perez[2].xyz;
+    let d = sky.perez[3].xyz;
+    let e = sky.perez[4].xyz;
+    return (1.0 + a * exp(b / max(cos_theta, 0.01)))
+        * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+fn xyY_to_linear_srgb(c: vec3<f32>) -> vec3<f32> {
+    let big_y = c.x;
+    let x = c.y;
+    let y = max(c.z, 0.0001);
+    let xyz = vec3<f32>(x * big_y / y, big_y, (1.0 - x - y) * big_y / y);
+    return vec3<f32>(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    );
+}
+
+@fragment
+fn fs_main(in: CubemapVertexOutput) -> @location(0) vec4<f32> {
+    var dir = normalize(in.local_position);
+    let sun_dir = sky.sun_direction.xyz;
+    let intensity = sky.sun_direction.w;
+    let fade = sky.sun_color.w;
+
+    // The ground reflects a dim horizon
+    let ground = select(1.0, 0.3, dir.y < 0.0);
+    dir = normalize(vec3<f32>(dir.x, max(dir.y, 0.001), dir.z));
+
+    let sun_cos_theta = max(sun_dir.y, 0.01);
+    let cos_gamma = clamp(dot(dir, normalize(vec3<f32>(sun_dir.x, sun_cos_theta, sun_dir.z))), -1.0, 1.0);
+    let gamma = acos(cos_gamma);
+    let sun_theta = acos(sun_cos_theta);
+
+    let distribution = perez(dir.y, gamma, cos_gamma) / perez(1.0, sun_theta, sun_cos_theta);
+    var color = xyY_to_linear_srgb(sky.zenith.xyz * distribution);
+    color = max(color, vec3<f32>(0.0)) * intensity * ground;
+
+    // Far dimmer than the real disk, the parallel light already adds its light,
+    // and a texel that bright would leave fireflies in the prefiltered mips
+    if ground == 1.0 && dot(dir, sun_dir) > sky.zenith.w {
+        color += sky.sun_color.xyz * intensity * 100.0;
+    }
+
+    return vec4<f32>(color * fade, 1.0);
+}
@@ -0,0 +1,81 @@
+#import vertex::{ VertexInput }
+#import pbr_type::{ PBRMaterial }
+#import pbr_type
+
+struct ProbeCapture {
+    position: vec3<f32>,
+    depth_scale: f32,
+}
+
+struct LightUniform {
+    direction: vec3<f32>,
+    color: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    intensity: f32,
+    lights_nums: vec4<u32>,
+}
+
+struct TransformUniform {
+    model: mat4x4<f32>,
+    normal: mat3x3<f32>,
+    prev_model: mat4x4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) tex_coord: vec2<f32>,
+    @location(2) second_tex_coord: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> probe: ProbeCapture;
+@group(0) @binding(1) var<uniform> light: LightUniform;
+@group(0) @binding(2) var env_cubemap: texture_cube<f32>;
+@group(0) @binding(3) var env_sampler: sampler;
+
+@group(1) @binding(0) var<uniform> pbr_mat: PBRMaterial;
+@group(1) @binding(1) var tex_0: texture_2d<f32>;
+@group(1) @binding(2) var samp_0: sampler;
+@group(1) @binding(5) var emissive_tex: texture_2d<f32>;
+@group(1) @binding(6) var emissive_samp: sampler;
+
+@group(2) @binding(0) var<uniform> transform: TransformUniform;
+
+@group(3) @binding(0) var<uniform> face_view_proj: mat4x4<f32>;
+
+const PI: f32 = radians(180.0);
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let world_pos = (transform.model * vec4<f32>(in.position, 1.0)).xyz;
+    // Same y flip as `render_cubemap_vert`, so the faces line up with the sky
+    var local = world_pos - probe.position;
+    local.y = -local.y;
+
+    var out: VertexOutput;
+    out.clip_position = face_view_proj * vec4<f32>(local * probe.depth_scale, 1.0);
+    out.normal = transform.normal * in.normal;
+    out.tex_coord = in.tex_coord;
+    out.second_tex_coord = in.second_tex_coord;
+    return out;
+}
+
+/// Diffuse and emission, lit by the parallel light without shadows and the blurriest level of the sky.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let base_color_uv = pbr_type::transform_uv(pbr_mat.base_color_transform, in.tex_coord, in.second_tex_coord);
+    let emissive_uv = pbr_type::transform_uv(pbr_mat.emissive_transform, in.tex_coord, in.second_tex_coord);
+    let base_color = textureSample(tex_0, samp_0, base_color_uv).xyz;
+    let normal = normalize(in.normal);
+    let diffuse_color = (1.0 - pbr_mat.metallic) * base_color;
+
+    let n_dot_l = max(dot(normal, -light.direction), 0.0);
+    let direct = diffuse_color / PI * light.color.xyz * light.intensity * n_dot_l;
+    let ambient = base_color * textureSampleLevel(env_cubemap, env_sampler, normal, 4.0).xyz;
+
+    let emissive = pbr_mat.emissive.xyz * pbr_mat.emissive_strength
+        * textureSample(emissive_tex, emissive_samp, emissive_uv).xyz;
+
+    return vec4<f32>(direct + ambient + vec3<f32>(0.1) * base_color + emissive, 1.0);
+}
+
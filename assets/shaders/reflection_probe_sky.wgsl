@@ -0,0 +1,10 @@
+#import vertex::{ CubemapVertexOutput }
+
+// Group 0 is the face matrix of `render_cubemap_vert`, group 1 the probe capture bindings
+@group(1) @binding(2) var env_cubemap: texture_cube<f32>;
+@group(1) @binding(3) var env_sampler: sampler;
+
+@fragment
+fn fs_main(in: CubemapVertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(env_cubemap, env_sampler, normalize(in.local_position), 0.0);
+}
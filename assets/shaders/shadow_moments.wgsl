@@ -0,0 +1,90 @@
+struct LightUniform {
+    direction: vec3<f32>,
+    color: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    intensity: f32,
+    lights_nums: vec4<u32>,
+    shadow: vec4<f32>,
+    shadow_filter: vec4<u32>,
+    shadow_moments: vec4<f32>,
+}
+
+// Must match `ShadowFilter::mode`
+const SHADOW_FILTER_EVSM: u32 = 5u;
+
+@group(0) @binding(0) var<uniform> light: LightUniform;
+@group(0) @binding(1) var shadow_map: texture_depth_2d;
+@group(0) @binding(2) var moments_out: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(3) var moments_in: texture_2d<f32>;
+
+fn encode_moments(depth: f32) -> vec4<f32> {
+    if light.shadow_filter.x == SHADOW_FILTER_EVSM {
+        let d = depth * 2.0 - 1.0;
+        let positive = exp(light.shadow_moments.x * d);
+        let negative = -exp(-light.shadow_moments.y * d);
+        return vec4<f32>(positive, positive * positive, negative, negative * negative);
+    }
+    return vec4<f32>(depth, depth * depth, 0.0, 0.0);
+}
+
+/// Gaussian with a standard deviation of half the radius
+fn blur_weight(offset: i32, radius: i32) -> f32 {
+    let sigma = max(f32(radius) * 0.5, 0.5);
+    return exp(-f32(offset * offset) / (2.0 * sigma * sigma));
+}
+
+/// Converts the depth map to moments and blurs them along x.
+@compute @workgroup_size(8, 8, 1)
+fn cs_blur_horizontal(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dims = textureDimensions(moments_out);
+    if any(id.xy >= dims) {
+        return;
+    }
+    let radius = i32(light.shadow_filter.z);
+    let last = vec2<i32>(dims) - 1;
+    var sum = vec4<f32>(0.0);
+    var total = 0.0;
+    for (var i = -radius; i <= radius; i++) {
+        let texel = clamp(vec2<i32>(id.xy) + vec2<i32>(i, 0), vec2<i32>(0), last);
+        let weight = blur_weight(i, radius);
+        sum += encode_moments(textureLoad(shadow_map, texel, 0)) * weight;
+        total += weight;
+    }
+    textureStore(moments_out, id.xy, sum / total);
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn cs_blur_vertical(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dims = textureDimensions(moments_out);
+    if any(id.xy >= dims) {
+        return;
+    }
+    let radius = i32(light.shadow_filter.z);
+    let last = vec2<i32>(dims) - 1;
+    var sum = vec4<f32>(0.0);
+    var total = 0.0;
+    for (var i = -radius; i <= radius; i++) {
+        let texel = clamp(vec2<i32>(id.xy) + vec2<i32>(0, i), vec2<i32>(0), last);
+        let weight = blur_weight(i, radius);
+        sum += textureLoad(moments_in, texel, 0) * weight;
+        total += weight;
+    }
+    textureStore(moments_out, id.xy, sum / total);
+}
+
+/// Averages 2x2 texels of the previous mip, moments stay valid under linear filtering.
+@compute @workgroup_size(8, 8, 1)
+fn cs_downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dims = textureDimensions(moments_out);
+    if any(id.xy >= dims) {
+        return;
+    }
+    let last = vec2<i32>(textureDimensions(moments_in)) - 1;
+    let base = vec2<i32>(id.xy) * 2;
+    var sum = vec4<f32>(0.0);
+    for (var i = 0; i < 4; i++) {
+        let texel = min(base + vec2<i32>(i & 1, i >> 1), last);
+        sum += textureLoad(moments_in, texel, 0);
+    }
+    textureStore(moments_out, id.xy, sum * 0.25);
+}
@@ -0,0 +1,149 @@
+#import vertex::{VertexInput}
+#import pbr_type::{PBRMaterial}
+#import pbr_type
+#import global_bindings::{camera, light}
+#import ibl_functions
+#import shadow
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) tangent: vec3<f32>,
+    @location(2) tex_coord: vec2<f32>,
+    @location(3) world_pos: vec3<f32>,
+    @location(4) second_tex_coord: vec2<f32>,
+};
+
+struct TransformUniform {
+    model: mat4x4<f32>,
+    normal: mat3x3<f32>,
+    prev_model: mat4x4<f32>,
+}
+
+// Material -----
+@group(1) @binding(0) var<uniform> pbr_mat: PBRMaterial;
+@group(1) @binding(1) var tex_0: texture_2d<f32>;
+@group(1) @binding(2) var samp_0: sampler;
+@group(1) @binding(3) var normal_tex: texture_2d<f32>;
+@group(1) @binding(4) var normal_samp: sampler;
+@group(1) @binding(5) var emissive_tex: texture_2d<f32>;
+@group(1) @binding(6) var emissive_samp: sampler;
+
+// Object -----
+@group(2) @binding(0) var<uniform> transform: TransformUniform;
+
+// Opaque scene color with a blurred mip chain -----
+@group(3) @binding(0) var scene_color: texture_2d<f32>;
+@group(3) @binding(1) var scene_sampler: sampler;
+
+const PI: f32 = radians(180.0);
+
+fn pow2(a: f32) -> f32 {
+    return a * a;
+}
+
+fn pow5(a: f32) -> f32 {
+    let a2 = a * a;
+    return a2 * a2 * a;
+}
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.world_pos = (transform.model * vec4<f32>(model.position, 1.0)).xyz;
+    out.normal = transform.normal * model.normal;
+    out.tangent = transform.normal * model.tangent;
+    out.tex_coord = model.tex_coord;
+    out.second_tex_coord = model.second_tex_coord;
+    out.clip_position = camera.view_proj * vec4<f32>(out.world_pos, 1.0);
+    return out;
+}
+
+fn world_to_uv(world_pos: vec3<f32>) -> vec2<f32> {
+    let clip = camera.view_proj * vec4<f32>(world_pos, 1.0);
+    return clip.xy / clip.w * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5);
+}
+
+/// Parallel light and IBL on the reflected part, the refracted part samples the scene behind
+/// through a volume of `thickness`. Point, area lights and fog are not applied.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let base_color_uv = pbr_type::transform_uv(pbr_mat.base_color_transform, in.tex_coord, in.second_tex_coord);
+    let normal_uv = pbr_type::transform_uv(pbr_mat.normal_transform, in.tex_coord, in.second_tex_coord);
+    let emissive_uv = pbr_type::transform_uv(pbr_mat.emissive_transform, in.tex_coord, in.second_tex_coord);
+    let base_color = textureSample(tex_0, samp_0, base_color_uv).xyz;
+    let emissive = pbr_mat.emissive.xyz * pbr_mat.emissive_strength
+        * textureSample(emissive_tex, emissive_samp, emissive_uv).xyz;
+
+    let n_normal = normalize(in.normal);
+    let n_tangent = normalize(in.tangent);
+    let bitangent = cross(n_normal, n_tangent);
+    let tbn = mat3x3<f32>(n_tangent, bitangent, n_normal);
+    let tangent_space_normal = textureSample(normal_tex, normal_samp, normal_uv).xyz * 2.0 - 1.0;
+    let normal = normalize(tbn * tangent_space_normal);
+
+    let shadow = shadow::directional_shadow(in.world_pos, normal, in.clip_position.xy);
+
+    let metallic = pbr_mat.metallic;
+    let perceptual_roughness = pbr_mat.roughness;
+    let roughness = pbr_type::perceptual_roughness_to_roughness(perceptual_roughness);
+    let transmission = pbr_mat.transmission.x * (1.0 - metallic);
+    let ior = pbr_mat.transmission.w;
+
+    let specular_tint = clamp(pbr_mat.specular.xyz, vec3<f32>(0.0), vec3<f32>(1.0));
+    let f0 = 0.16 * pow2(pbr_mat.reflectance) * (1.0 - metallic) * specular_tint + base_color * metallic;
+    let f90 = mix(vec3<f32>(clamp(pbr_mat.specular.w, 0.0, 1.0)), vec3<f32>(1.0), metallic);
+    // Light that is not transmitted is scattered diffusely
+    let diffuse_color = (1.0 - metallic) * (1.0 - transmission) * base_color;
+
+    let world2camera = normalize(camera.position - in.world_pos);
+    let world2light = -light.direction;
+    let half = normalize(world2light + world2camera);
+    let n_dot_l = max(dot(normal, world2light), 0.0);
+    let n_dot_v = max(dot(normal, world2camera), 0.0);
+    let n_dot_h = max(dot(normal, half), 0.0);
+    let h_dot_v = max(dot(half, world2camera), 0.0);
+
+    // + Parallel Lighting
+    let roughness2 = pow2(roughness);
+    let d_ggx = roughness2 / (PI * pow2(pow2(n_dot_h) * (roughness2 - 1.0) + 1.0));
+    let ggx_v = n_dot_l * (n_dot_v * (1.0 - roughness) + roughness);
+    let ggx_l = n_dot_v * (n_dot_l * (1.0 - roughness) + roughness);
+    let v_smith = 0.5 / (0.001 + ggx_l + ggx_v);
+    let fresnel_h = f0 + (f90 - f0) * pow5(1.0 - h_dot_v);
+    let brdf = fresnel_h * d_ggx * v_smith + diffuse_color / PI;
+    var color = brdf * light.color.xyz * light.intensity * n_dot_l;
+
+    // + Image based Lighting
+    color += ibl_functions::evaluate_ibl(
+        in.world_pos,
+        normal,
+        world2camera,
+        diffuse_color,
+        f0,
+        f90,
+        perceptual_roughness,
+    );
+
+    color *= mix(vec3<f32>(0.5), vec3<f32>(1.0), shadow);
+    color += emissive;
+    color *= camera.exposure;
+
+    // + Transmission, the scene color is already exposed
+    let scale = length(transform.model[0].xyz);
+    let thickness = pbr_mat.transmission.y * scale;
+    let refracted = refract(-world2camera, normal, 1.0 / ior);
+    let uv = world_to_uv(in.world_pos + refracted * thickness);
+    // Rougher surfaces and denser media blur the background more
+    let max_lod = f32(textureNumLevels(scene_color) - 1u);
+    let lod = max_lod * perceptual_roughness * clamp(ior * 2.0 - 2.0, 0.0, 1.0);
+    let background = textureSampleLevel(scene_color, scene_sampler, uv, lod).xyz;
+
+    // Beer-Lambert, `attenuation_color` remains after `attenuation_distance`
+    let attenuation_color = max(pbr_mat.attenuation_color.xyz, vec3<f32>(0.0001));
+    let attenuation = pow(attenuation_color, vec3<f32>(thickness / pbr_mat.transmission.z));
+    let fresnel = f0 + (f90 - f0) * pow5(1.0 - n_dot_v);
+    color += transmission * (1.0 - fresnel) * base_color * attenuation * background;
+
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,70 @@
+#import fog::{FogUniform, density_at, phase_hg, slice_distance}
+
+struct LightUniform {
+    direction: vec3<f32>,
+    color: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    intensity: f32,
+    lights_nums: vec4<u32>,
+}
+
+struct PointLight {
+    color: vec4<f32>,
+    position: vec4<f32>,
+    intensity: f32,
+    distance: f32,
+    decay: f32,
+}
+
+@group(0) @binding(0) var<uniform> fog: FogUniform;
+@group(0) @binding(1) var<uniform> light: LightUniform;
+@group(0) @binding(2) var<storage, read> point_lights: array<PointLight>;
+@group(0) @binding(3) var shadow_map: texture_depth_2d;
+@group(0) @binding(4) var shadow_sampler: sampler_comparison;
+@group(0) @binding(5) var scattering_out: texture_storage_3d<rgba16float, write>;
+
+fn sun_visibility(world_pos: vec3<f32>) -> f32 {
+    let pos = light.view_proj * vec4<f32>(world_pos, 1.0);
+    let ndc = pos.xyz / pos.w;
+    let coords = ndc.xy * vec2<f32>(0.5, -0.5) + vec2<f32>(0.5);
+    if any(coords < vec2<f32>(0.0)) || any(coords > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    return textureSampleCompareLevel(shadow_map, shadow_sampler, coords, ndc.z);
+}
+
+/// Scattered light (rgb) and extinction (a) at the center of every froxel.
+@compute @workgroup_size(8, 8, 1)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dims = textureDimensions(scattering_out);
+    if any(id >= dims) {
+        return;
+    }
+
+    let uv = (vec2<f32>(id.xy) + 0.5) / vec2<f32>(dims.xy);
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let far = fog.inv_view_proj * vec4<f32>(ndc, 1.0, 1.0);
+    let view_dir = normalize(far.xyz / far.w - fog.camera_position.xyz);
+    let w = (f32(id.z) + 0.5) / f32(dims.z);
+    let world_pos = fog.camera_position.xyz + view_dir * slice_distance(fog, w);
+
+    let density = density_at(fog, world_pos.y);
+
+    var radiance = vec3<f32>(fog.ambient);
+    radiance += light.color.xyz * light.intensity * sun_visibility(world_pos)
+        * phase_hg(dot(view_dir, -light.direction), fog.anisotropy);
+
+    for (var i = 0u; i < light.lights_nums.x; i += 1u) {
+        let li = point_lights[i];
+        let to_light = li.position.xyz - world_pos;
+        let dist = length(to_light);
+        if dist > li.distance {
+            continue;
+        }
+        let attenuation = li.intensity / ((li.decay * dist * dist) + 0.001);
+        radiance += li.color.xyz * attenuation
+            * phase_hg(dot(view_dir, -to_light / dist), fog.anisotropy);
+    }
+
+    textureStore(scattering_out, id, vec4<f32>(radiance * fog.color.xyz * density, density));
+}
@@ -0,0 +1,34 @@
+#import fog::{FogUniform, slice_distance}
+
+@group(0) @binding(0) var<uniform> fog: FogUniform;
+@group(0) @binding(1) var scattering_tex: texture_3d<f32>;
+@group(0) @binding(2) var integrated_out: texture_storage_3d<rgba16float, write>;
+
+/// March every froxel column front to back, storing in-scattered light (rgb) and
+/// transmittance (a) from the camera to the far side of each froxel.
+/// See Hillaire, "Physically Based and Unified Volumetric Rendering in Frostbite".
+@compute @workgroup_size(8, 8, 1)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let dims = textureDimensions(integrated_out);
+    if any(id.xy >= dims.xy) {
+        return;
+    }
+
+    var in_scattering = vec3<f32>(0.0);
+    var transmittance = 1.0;
+    var prev_distance = 0.0;
+    for (var z = 0u; z < dims.z; z += 1u) {
+        let distance = slice_distance(fog, f32(z + 1u) / f32(dims.z));
+        let step = distance - prev_distance;
+        prev_distance = distance;
+
+        let froxel = textureLoad(scattering_tex, vec3<u32>(id.xy, z), 0);
+        let extinction = max(froxel.a, 0.00001);
+        let slice_transmittance = exp(-extinction * step);
+        // Energy conserving integration of the scattering over the froxel
+        in_scattering += transmittance * (froxel.xyz - froxel.xyz * slice_transmittance) / extinction;
+        transmittance *= slice_transmittance;
+
+        textureStore(integrated_out, vec3<u32>(id.xy, z), vec4<f32>(in_scattering, transmittance));
+    }
+}
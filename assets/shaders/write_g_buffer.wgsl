@@ -1,5 +1,5 @@
 #import vertex::{VertexInput}
-#import pbr_type::{ StandardMaterial, PBRSurface }
+#import pbr_type::{ StandardMaterial, PBRSurface, PBRMaterial }
 #import pbr_type
 #import global_bindings::{
     camera, light
@@ -12,22 +12,22 @@ struct VertexOutput {
     @location(2) tangent: vec3<f32>,
     @location(3) tex_coord: vec2<f32>,
     @location(4) world_pos: vec3<f32>,
+    @location(5) current_clip: vec4<f32>,
+    @location(6) prev_clip: vec4<f32>,
+    @location(7) second_tex_coord: vec2<f32>,
 };
 
 struct FragmentOutput {
     @location(0) world_pos: vec4<f32>,
     @location(1) g_buffer: vec4<u32>,
+    @location(2) velocity: vec2<f32>,
+    @location(3) g_buffer_extra: vec2<u32>,
 }
 
 struct TransformUniform {
     model: mat4x4<f32>,
     normal: mat3x3<f32>,
-}
-
-struct PBRMaterial {
-    metallic: f32,
-    roughness: f32,
-    reflectance: f32,
+    prev_model: mat4x4<f32>,
 }
 
 // Material -----
@@ -36,6 +36,8 @@ struct PBRMaterial {
 @group(1) @binding(2) var samp_0: sampler;
 @group(1) @binding(3) var normal_tex: texture_2d<f32>;
 @group(1) @binding(4) var normal_samp: sampler;
+@group(1) @binding(5) var emissive_tex: texture_2d<f32>;
+@group(1) @binding(6) var emissive_samp: sampler;
 
 // Object -----
 @group(2) @binding(0)
@@ -53,19 +55,25 @@ fn vs_main(
     out.normal = transform.normal * model.normal;
     out.tangent = transform.normal * model.tangent;
     out.tex_coord = model.tex_coord;
+    out.second_tex_coord = model.second_tex_coord;
     out.clip_position = camera.view_proj * vec4<f32>(out.world_pos, 1.0);
+    out.current_clip = out.clip_position;
+    out.prev_clip = camera.prev_view_proj * transform.prev_model * vec4<f32>(model.position, 1.0);
     return out;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> FragmentOutput {
-    let base_color = textureSample(tex_0, samp_0, in.tex_coord);
+    let base_color_uv = pbr_type::transform_uv(pbr_mat.base_color_transform, in.tex_coord, in.second_tex_coord);
+    let normal_uv = pbr_type::transform_uv(pbr_mat.normal_transform, in.tex_coord, in.second_tex_coord);
+    let emissive_uv = pbr_type::transform_uv(pbr_mat.emissive_transform, in.tex_coord, in.second_tex_coord);
+    let base_color = textureSample(tex_0, samp_0, base_color_uv);
 
     let n_normal = normalize(in.normal);
     let n_tangent = normalize(in.tangent);
     let bitangent = cross(n_normal, n_tangent);
     let tbn = mat3x3<f32>(n_tangent, bitangent, n_normal);
-    let tangent_space_normal = textureSample(normal_tex, normal_samp, in.tex_coord).xyz * 2.0 - 1.0;
+    let tangent_space_normal = textureSample(normal_tex, normal_samp, normal_uv).xyz * 2.0 - 1.0;
     let normal = normalize(tbn * tangent_space_normal);
 
     var surface: PBRSurface = pbr_type::pbr_surface_new();
@@ -75,11 +83,22 @@ fn fs_main(in: VertexOutput) -> FragmentOutput {
     material.metallic = pbr_mat.metallic;
     material.perceptual_roughness = pbr_mat.roughness;
     material.reflectance = pbr_mat.reflectance;
+    let emissive = textureSample(emissive_tex, emissive_samp, emissive_uv).xyz;
+    material.emissive = vec4<f32>(pbr_mat.emissive.xyz * pbr_mat.emissive_strength * emissive, 1.0);
+    material.sheen_color = pbr_mat.sheen.xyz;
+    material.sheen_perceptual_roughness = pbr_mat.sheen.w;
+    material.specular_tint = clamp(pbr_mat.specular.xyz, vec3<f32>(0.0), vec3<f32>(1.0));
+    material.specular_factor = clamp(pbr_mat.specular.w, 0.0, 1.0);
     surface.material = material;
 
     var o: FragmentOutput;
     o.world_pos = vec4<f32>(in.world_pos, 1.0);
     o.g_buffer = pbr_type::pack_g_buffer(surface);
+    o.g_buffer_extra = pbr_type::pack_g_buffer_extra(surface);
+    // Screen space motion since last frame in uv units
+    let current_ndc = in.current_clip.xy / in.current_clip.w;
+    let prev_ndc = in.prev_clip.xy / in.prev_clip.w;
+    o.velocity = (current_ndc - prev_ndc) * vec2<f32>(0.5, -0.5);
 
     return o;
 }
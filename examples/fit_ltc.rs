@@ -0,0 +1,363 @@
+//! Fits the linearly transformed cosine tables used by area lights.
+//!
+//! Port of the fitting code from "Real-Time Polygonal-Light Shading with Linearly
+//! Transformed Cosines" (Heitz et al. 2016). Writes two 64x64 Rgba16Float tables into
+//! `assets/textures/ltc/`:
+//! - `ltc_1.bin`: the four varying entries of the inverse matrix
+//! - `ltc_2.bin`: GGX magnitude and Schlick Fresnel terms
+//!
+//! Run with `cargo run --release --example fit_ltc`.
+
+use std::{f64::consts::PI, fs, path::Path};
+
+use cgmath::{InnerSpace, Matrix3, SquareMatrix, Vector3};
+
+type Vec3 = Vector3<f64>;
+type Mat3 = Matrix3<f64>;
+
+const N: usize = 64;
+const SAMPLES: usize = 32;
+const MIN_ALPHA: f64 = 0.00001;
+
+fn lambda(alpha: f64, cos_theta: f64) -> f64 {
+    if cos_theta >= 1.0 {
+        return 0.0;
+    }
+    let tan2 = (1.0 - cos_theta * cos_theta) / (cos_theta * cos_theta);
+    0.5 * (-1.0 + (1.0 + alpha * alpha * tan2).sqrt())
+}
+
+/// GGX with height correlated masking-shadowing, cosine included. Returns `(value, pdf)`.
+fn ggx_eval(v: Vec3, l: Vec3, alpha: f64) -> (f64, f64) {
+    if v.z <= 0.0 {
+        return (0.0, 0.0);
+    }
+    let lambda_v = lambda(alpha, v.z);
+    let g2 = if l.z <= 0.0 {
+        0.0
+    } else {
+        1.0 / (1.0 + lambda_v + lambda(alpha, l.z))
+    };
+
+    let h = (v + l).normalize();
+    let slope_x = h.x / h.z;
+    let slope_y = h.y / h.z;
+    let d = 1.0 / (1.0 + (slope_x * slope_x + slope_y * slope_y) / alpha / alpha);
+    let d = d * d / (PI * alpha * alpha * h.z.powi(4));
+
+    let pdf = (d * h.z / 4.0 / v.dot(h)).abs();
+    (d * g2 / 4.0 / v.z, pdf)
+}
+
+fn ggx_sample(v: Vec3, alpha: f64, u1: f64, u2: f64) -> Vec3 {
+    let phi = 2.0 * PI * u1;
+    let r = alpha * (u2 / (1.0 - u2)).sqrt();
+    let n = Vec3::new(r * phi.cos(), r * phi.sin(), 1.0).normalize();
+    -v + n * 2.0 * n.dot(v)
+}
+
+#[derive(Clone, Copy)]
+struct Ltc {
+    magnitude: f64,
+    fresnel: f64,
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+    m11: f64,
+    m22: f64,
+    m13: f64,
+    m: Mat3,
+    inv_m: Mat3,
+    det_m: f64,
+}
+
+impl Ltc {
+    fn new() -> Self {
+        let mut ltc = Self {
+            magnitude: 1.0,
+            fresnel: 1.0,
+            x: Vec3::unit_x(),
+            y: Vec3::unit_y(),
+            z: Vec3::unit_z(),
+            m11: 1.0,
+            m22: 1.0,
+            m13: 0.0,
+            m: Mat3::identity(),
+            inv_m: Mat3::identity(),
+            det_m: 1.0,
+        };
+        ltc.update();
+        ltc
+    }
+
+    fn update(&mut self) {
+        let frame = Mat3::from_cols(self.x, self.y, self.z);
+        let scale = Mat3::from_cols(
+            Vec3::new(self.m11, 0.0, 0.0),
+            Vec3::new(0.0, self.m22, 0.0),
+            Vec3::new(self.m13, 0.0, 1.0),
+        );
+        self.m = frame * scale;
+        self.inv_m = self.m.invert().unwrap_or(Mat3::identity());
+        self.det_m = self.m.determinant().abs();
+    }
+
+    fn eval(&self, l: Vec3) -> f64 {
+        let original = (self.inv_m * l).normalize();
+        let transformed = self.m * original;
+        let len = transformed.magnitude();
+        let jacobian = self.det_m / (len * len * len);
+        let d = original.z.max(0.0) / PI;
+        self.magnitude * d / jacobian
+    }
+
+    fn sample(&self, u1: f64, u2: f64) -> Vec3 {
+        let theta = u1.sqrt().acos();
+        let phi = 2.0 * PI * u2;
+        (self.m
+            * Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            ))
+        .normalize()
+    }
+}
+
+/// Returns `(norm, fresnel, average direction)` of the BRDF lobe.
+fn average_terms(v: Vec3, alpha: f64) -> (f64, f64, Vec3) {
+    let mut norm = 0.0;
+    let mut fresnel = 0.0;
+    let mut dir = Vec3::new(0.0, 0.0, 0.0);
+    for j in 0..SAMPLES {
+        for i in 0..SAMPLES {
+            let u1 = (i as f64 + 0.5) / SAMPLES as f64;
+            let u2 = (j as f64 + 0.5) / SAMPLES as f64;
+            let l = ggx_sample(v, alpha, u1, u2);
+            let (value, pdf) = ggx_eval(v, l, alpha);
+            if pdf > 0.0 {
+                let weight = value / pdf;
+                let h = (v + l).normalize();
+                norm += weight;
+                fresnel += weight * (1.0 - v.dot(h).max(0.0)).powi(5);
+                dir += l * weight;
+            }
+        }
+    }
+    let count = (SAMPLES * SAMPLES) as f64;
+    dir.y = 0.0;
+    (norm / count, fresnel / count, dir.normalize())
+}
+
+/// Multiple importance sampled cubic error between the BRDF and the LTC.
+fn fit_error(ltc: &Ltc, v: Vec3, alpha: f64) -> f64 {
+    let mut error = 0.0;
+    for j in 0..SAMPLES {
+        for i in 0..SAMPLES {
+            let u1 = (i as f64 + 0.5) / SAMPLES as f64;
+            let u2 = (j as f64 + 0.5) / SAMPLES as f64;
+
+            for l in [ltc.sample(u1, u2), ggx_sample(v, alpha, u1, u2)] {
+                let (eval_brdf, pdf_brdf) = ggx_eval(v, l, alpha);
+                let eval_ltc = ltc.eval(l);
+                let pdf_ltc = eval_ltc / ltc.magnitude;
+                let e = (eval_brdf - eval_ltc).abs().powi(3);
+                let pdf = pdf_ltc + pdf_brdf;
+                if pdf > 0.0 {
+                    error += e / pdf;
+                }
+            }
+        }
+    }
+    error / (SAMPLES * SAMPLES) as f64
+}
+
+fn apply_params(ltc: &mut Ltc, params: [f64; 3], isotropic: bool) {
+    let m11 = params[0].max(1e-7);
+    let m22 = params[1].max(1e-7);
+    if isotropic {
+        ltc.m11 = m11;
+        ltc.m22 = m11;
+        ltc.m13 = 0.0;
+    } else {
+        ltc.m11 = m11;
+        ltc.m22 = m22;
+        ltc.m13 = params[2];
+    }
+    ltc.update();
+}
+
+fn nelder_mead(
+    start: [f64; 3],
+    delta: f64,
+    tolerance: f64,
+    max_iters: usize,
+    mut f: impl FnMut([f64; 3]) -> f64,
+) -> [f64; 3] {
+    let mut s = [start; 4];
+    for (i, point) in s.iter_mut().enumerate().skip(1) {
+        point[i - 1] += delta;
+    }
+    let mut fs = s.map(&mut f);
+
+    let mut lo = 0;
+    for _ in 0..max_iters {
+        lo = 0;
+        let mut hi = 0;
+        let mut nh = 0;
+        for i in 1..4 {
+            if fs[i] < fs[lo] {
+                lo = i;
+            }
+            if fs[i] > fs[hi] {
+                nh = hi;
+                hi = i;
+            } else if fs[i] > fs[nh] {
+                nh = i;
+            }
+        }
+
+        let a = fs[lo].abs();
+        let b = fs[hi].abs();
+        if 2.0 * (a - b).abs() < (a + b) * tolerance {
+            break;
+        }
+
+        let mut o = [0.0; 3];
+        for (i, point) in s.iter().enumerate() {
+            if i != hi {
+                for k in 0..3 {
+                    o[k] += point[k] / 3.0;
+                }
+            }
+        }
+
+        let r = [0, 1, 2].map(|k| 2.0 * o[k] - s[hi][k]);
+        let fr = f(r);
+        if fr < fs[nh] {
+            if fr < fs[lo] {
+                let e = [0, 1, 2].map(|k| 3.0 * o[k] - 2.0 * s[hi][k]);
+                let fe = f(e);
+                if fe < fr {
+                    s[hi] = e;
+                    fs[hi] = fe;
+                    continue;
+                }
+            }
+            s[hi] = r;
+            fs[hi] = fr;
+            continue;
+        }
+
+        let c = [0, 1, 2].map(|k| 0.5 * o[k] + 0.5 * s[hi][k]);
+        let fc = f(c);
+        if fc < fs[hi] {
+            s[hi] = c;
+            fs[hi] = fc;
+            continue;
+        }
+
+        for k in 0..4 {
+            if k != lo {
+                s[k] = [0, 1, 2].map(|i| 0.5 * s[k][i] + 0.5 * s[lo][i]);
+                fs[k] = f(s[k]);
+            }
+        }
+    }
+    s[lo]
+}
+
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    if exp <= 0 {
+        if exp < -10 {
+            return sign;
+        }
+        let m = (mantissa | 0x80_0000) >> (1 - exp);
+        return sign | ((m + 0x1000) >> 13) as u16;
+    }
+    if exp >= 31 {
+        return sign | 0x7c00;
+    }
+    let half = sign as u32 | ((exp as u32) << 10) | (mantissa >> 13);
+    // Round to nearest, a carry into the exponent is still correct
+    (half + ((mantissa >> 12) & 1)) as u16
+}
+
+fn write_table(path: &Path, table: &[[f32; 4]]) {
+    let bytes = table
+        .iter()
+        .flatten()
+        .flat_map(|v| f32_to_f16(*v).to_le_bytes())
+        .collect::<Vec<_>>();
+    fs::write(path, bytes).unwrap();
+}
+
+fn main() {
+    let mut params = vec![[0.0f64; 3]; N * N];
+    let mut ltc_1 = vec![[0.0f32; 4]; N * N];
+    let mut ltc_2 = vec![[0.0f32; 4]; N * N];
+
+    let mut ltc = Ltc::new();
+    for a in (0..N).rev() {
+        for t in 0..N {
+            // x: roughness, y: sqrt(1 - cos(theta))
+            let x = t as f64 / (N - 1) as f64;
+            let theta = (1.0 - x * x).acos().min(1.57);
+            let v = Vec3::new(theta.sin(), 0.0, theta.cos());
+
+            let roughness = a as f64 / (N - 1) as f64;
+            let alpha = (roughness * roughness).max(MIN_ALPHA);
+
+            let (norm, fresnel, average_dir) = average_terms(v, alpha);
+            ltc.magnitude = norm;
+            ltc.fresnel = fresnel;
+
+            let isotropic = t == 0;
+            if isotropic {
+                ltc.x = Vec3::unit_x();
+                ltc.y = Vec3::unit_y();
+                ltc.z = Vec3::unit_z();
+                if a == N - 1 {
+                    ltc.m11 = 1.0;
+                    ltc.m22 = 1.0;
+                } else {
+                    let previous = params[a + 1];
+                    ltc.m11 = previous[0];
+                    ltc.m22 = previous[1];
+                }
+                ltc.m13 = 0.0;
+            } else {
+                ltc.x = Vec3::new(average_dir.z, 0.0, -average_dir.x);
+                ltc.y = Vec3::unit_y();
+                ltc.z = average_dir;
+            }
+            ltc.update();
+
+            let start = [ltc.m11, ltc.m22, ltc.m13];
+            let result = nelder_mead(start, 0.05, 1e-5, 100, |p| {
+                let mut candidate = ltc;
+                apply_params(&mut candidate, p, isotropic);
+                fit_error(&candidate, v, alpha)
+            });
+            apply_params(&mut ltc, result, isotropic);
+
+            let index = a + t * N;
+            params[index] = [ltc.m11, ltc.m22, ltc.m13];
+
+            let inv = ltc.inv_m / ltc.inv_m[1][1];
+            // Column major, the shader rebuilds the matrix from these columns
+            ltc_1[index] = [inv[0][0], inv[0][2], inv[2][0], inv[2][2]].map(|v| v as f32);
+            ltc_2[index] = [ltc.magnitude as f32, ltc.fresnel as f32, 0.0, 0.0];
+        }
+        println!("roughness {}/{}", N - a, N);
+    }
+
+    let dir = Path::new("assets/textures/ltc");
+    fs::create_dir_all(dir).unwrap();
+    write_table(&dir.join("ltc_1.bin"), &ltc_1);
+    write_table(&dir.join("ltc_2.bin"), &ltc_2);
+}
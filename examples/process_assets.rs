@@ -0,0 +1,16 @@
+//! Processes the models under the asset root into the asset cache, so that the first launch
+//! doesn't parse glTF or decode images either.
+//!
+//! Run with `cargo run --release --example process_assets`. The asset root and the cache
+//! follow `$WGPU_PBR_ASSETS` and `$WGPU_PBR_ASSET_CACHE`, the root and the packs can also be
+//! given with `--assets <dir>` and `--pack <file.zip>`.
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    if let Err(e) = wgpu_pbr::AssetSources::apply_args(std::env::args().skip(1)) {
+        eprintln!("{:#}", e);
+        std::process::exit(2);
+    }
+    let processed = wgpu_pbr::process_assets();
+    println!("Processed {} models", processed);
+}
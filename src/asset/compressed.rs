@@ -0,0 +1,968 @@
+use anyhow::*;
+use basis_universal::{TranscodeParameters, Transcoder, TranscoderTextureFormat};
+use std::{borrow::Cow, io::Read};
+use wgpu::{
+    util::{DeviceExt, TextureDataOrder},
+    AstcBlock, AstcChannel, Features, TextureFormat,
+};
+
+use crate::{render::ColorSpace, OPTIONAL_DEVICE_FEATURES};
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+/// GPU ready texel data read from a `.ktx2` or `.dds` container, mip levels are pre-baked.
+pub struct CompressedImage {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Faces times layers, faces vary fastest
+    pub layers: u32,
+    pub mip_level_count: u32,
+    pub is_cubemap: bool,
+    /// The container doesn't say whether the texels are sRGB, the loader's hint is used instead
+    pub unknown_color_space: bool,
+    pub order: TextureDataOrder,
+    pub data: Vec<u8>,
+}
+
+impl CompressedImage {
+    pub fn is_compressed_container(bytes: &[u8]) -> bool {
+        bytes.starts_with(&KTX2_IDENTIFIER) || bytes.starts_with(&DDS_MAGIC)
+    }
+
+    /// Basis Universal payloads are transcoded to a block compression of
+    /// [`OPTIONAL_DEVICE_FEATURES`] the device `features` have, RGBA8 without any.
+    pub fn parse(bytes: &[u8], features: Features) -> Result<Self> {
+        if bytes.starts_with(&KTX2_IDENTIFIER) {
+            parse_ktx2(bytes, features)
+        } else if bytes.starts_with(&DDS_MAGIC) {
+            parse_dds(bytes)
+        } else {
+            Err(anyhow!("Neither a KTX2 nor a DDS file"))
+        }
+    }
+
+    /// Fails when the format needs a device feature the adapter doesn't have.
+    pub fn upload(
+        &self,
+        label: Option<&str>,
+        color_space: ColorSpace,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<wgpu::Texture> {
+        let format = match (self.unknown_color_space, color_space) {
+            (false, _) => self.format,
+            (true, ColorSpace::Srgb) => self.format.add_srgb_suffix(),
+            (true, ColorSpace::Linear) => self.format.remove_srgb_suffix(),
+        };
+        let missing = format.required_features() - device.features();
+        if !missing.is_empty() {
+            bail!("{:?} needs {:?}, which this adapter lacks", format, missing);
+        }
+        let (block_width, block_height) = format.block_dimensions();
+        if !self.width.is_multiple_of(block_width) || !self.height.is_multiple_of(block_height) {
+            bail!(
+                "{}x{} is not a multiple of the {}x{} blocks of {:?}",
+                self.width,
+                self.height,
+                block_width,
+                block_height,
+                format
+            );
+        }
+
+        Ok(device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label,
+                size: wgpu::Extent3d {
+                    width: self.width,
+                    height: self.height,
+                    depth_or_array_layers: self.layers,
+                },
+                mip_level_count: self.mip_level_count,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            self.order,
+            &self.data,
+        ))
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|it| u32::from_le_bytes(it.try_into().unwrap()))
+        .ok_or_else(|| anyhow!("Unexpected end of file at {}", offset))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|it| u64::from_le_bytes(it.try_into().unwrap()))
+        .ok_or_else(|| anyhow!("Unexpected end of file at {}", offset))
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|it| u16::from_le_bytes(it.try_into().unwrap()))
+        .ok_or_else(|| anyhow!("Unexpected end of file at {}", offset))
+}
+
+const KTX_SS_NONE: u32 = 0;
+const KTX_SS_BASIS_LZ: u32 = 1;
+const KTX_SS_ZSTD: u32 = 2;
+const KTX_SS_ZLIB: u32 = 3;
+const KHR_DF_MODEL_ETC1S: u8 = 163;
+const KHR_DF_MODEL_UASTC: u8 = 166;
+const KHR_DF_TRANSFER_SRGB: u8 = 2;
+const KHR_DF_CHANNEL_UASTC_RGBA: u8 = 3;
+const KHR_DF_CHANNEL_UASTC_RRRG: u8 = 5;
+
+/// See the KTX 2.0 specification, Zstandard and zlib supercompression are inflated.
+fn parse_ktx2(bytes: &[u8], features: Features) -> Result<CompressedImage> {
+    let vk_format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?.max(1);
+    let depth = read_u32(bytes, 28)?;
+    let layer_count = read_u32(bytes, 32)?.max(1);
+    let face_count = read_u32(bytes, 36)?;
+    let mip_level_count = read_u32(bytes, 40)?.max(1);
+    let supercompression = read_u32(bytes, 44)?;
+    let dfd_offset = read_u32(bytes, 48)? as usize;
+
+    if depth > 1 {
+        bail!("3D KTX2 textures are not supported");
+    }
+
+    // The level index starts with the base level, the data itself is stored smallest first
+    let mut levels = Vec::new();
+    for level in 0..mip_level_count as usize {
+        let entry = 80 + level * 24;
+        let offset = read_u64(bytes, entry)? as usize;
+        let length = read_u64(bytes, entry + 8)? as usize;
+        let uncompressed_length = read_u64(bytes, entry + 16)? as usize;
+        let level_data = bytes
+            .get(offset..offset + length)
+            .ok_or_else(|| anyhow!("KTX2 level {} is out of bounds", level))?;
+        let level_data = match supercompression {
+            KTX_SS_NONE => Cow::Borrowed(level_data),
+            // The slices are only inflated by the ETC1S transcoder
+            KTX_SS_BASIS_LZ if vk_format == 0 => Cow::Borrowed(level_data),
+            KTX_SS_ZSTD => {
+                let mut inflated = Vec::with_capacity(uncompressed_length);
+                ruzstd::decoding::StreamingDecoder::new(level_data)
+                    .map_err(|err| anyhow!("KTX2 level {}: {}", level, err))?
+                    .read_to_end(&mut inflated)
+                    .with_context(|| format!("KTX2 level {}", level))?;
+                Cow::Owned(inflated)
+            }
+            KTX_SS_ZLIB => Cow::Owned(
+                miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(
+                    level_data,
+                    uncompressed_length,
+                )
+                .map_err(|err| anyhow!("KTX2 level {}: {:?}", level, err.status))?,
+            ),
+            _ => bail!(
+                "KTX2 supercompression scheme {} is not supported",
+                supercompression
+            ),
+        };
+        if matches!(supercompression, KTX_SS_ZSTD | KTX_SS_ZLIB)
+            && level_data.len() != uncompressed_length
+        {
+            bail!(
+                "KTX2 level {} inflated to {} bytes instead of {}",
+                level,
+                level_data.len(),
+                uncompressed_length
+            );
+        }
+        levels.push(level_data);
+    }
+
+    let (format, data) = if vk_format == 0 {
+        // The color model of the basic data format descriptor tells ETC1S and UASTC apart
+        let color_model = bytes.get(dfd_offset + 12).copied().unwrap_or(0);
+        let srgb = bytes.get(dfd_offset + 14) == Some(&KHR_DF_TRANSFER_SRGB);
+        let target = BasisTarget::new(features, width, height);
+        let extent = Ktx2Extent {
+            width,
+            height,
+            images: layer_count * face_count,
+        };
+        let data = match (color_model, supercompression) {
+            (KHR_DF_MODEL_ETC1S, KTX_SS_BASIS_LZ) => {
+                transcode_etc1s(bytes, &levels, extent, &target)?
+            }
+            (KHR_DF_MODEL_UASTC, KTX_SS_NONE | KTX_SS_ZSTD | KTX_SS_ZLIB) => {
+                // The channel id of the first sample
+                let channel = bytes.get(dfd_offset + 31).map_or(0, |it| it & 0xF);
+                let has_alpha = matches!(
+                    channel,
+                    KHR_DF_CHANNEL_UASTC_RGBA | KHR_DF_CHANNEL_UASTC_RRRG
+                );
+                transcode_uastc(&levels, extent, has_alpha, &target)?
+            }
+            (KHR_DF_MODEL_ETC1S | KHR_DF_MODEL_UASTC, _) => bail!(
+                "Basis Universal payload with supercompression scheme {}",
+                supercompression
+            ),
+            _ => bail!("KTX2 without a Vulkan format"),
+        };
+        let format = if srgb {
+            target.format.add_srgb_suffix()
+        } else {
+            target.format
+        };
+        (format, data)
+    } else {
+        let format = vk_format_to_wgpu(vk_format)
+            .ok_or_else(|| anyhow!("Unsupported KTX2 vkFormat {}", vk_format))?;
+        (format, levels.concat())
+    };
+
+    Ok(CompressedImage {
+        format,
+        width,
+        height,
+        layers: layer_count * face_count,
+        mip_level_count,
+        is_cubemap: face_count == 6,
+        unknown_color_space: false,
+        order: TextureDataOrder::MipMajor,
+        data,
+    })
+}
+
+/// What Basis Universal payloads are transcoded to.
+struct BasisTarget {
+    format: TextureFormat,
+    texture: TranscoderTextureFormat,
+}
+
+impl BasisTarget {
+    /// The first block compression of [`OPTIONAL_DEVICE_FEATURES`] the device has, RGBA8 without
+    /// any or when the base level isn't made of whole 4x4 blocks.
+    fn new(features: Features, width: u32, height: u32) -> Self {
+        let aligned = width.is_multiple_of(4) && height.is_multiple_of(4);
+        OPTIONAL_DEVICE_FEATURES
+            .iter()
+            .filter(|&&feature| aligned && features.contains(feature))
+            .find_map(|&feature| {
+                if feature == Features::TEXTURE_COMPRESSION_BC {
+                    Some(Self {
+                        format: TextureFormat::Bc7RgbaUnorm,
+                        texture: TranscoderTextureFormat::BC7_RGBA,
+                    })
+                } else if feature == Features::TEXTURE_COMPRESSION_ETC2 {
+                    Some(Self {
+                        format: TextureFormat::Etc2Rgba8Unorm,
+                        texture: TranscoderTextureFormat::ETC2_RGBA,
+                    })
+                } else if feature == Features::TEXTURE_COMPRESSION_ASTC {
+                    Some(Self {
+                        format: TextureFormat::Astc {
+                            block: AstcBlock::B4x4,
+                            channel: AstcChannel::Unorm,
+                        },
+                        texture: TranscoderTextureFormat::ASTC_4x4_RGBA,
+                    })
+                } else {
+                    None
+                }
+            })
+            .unwrap_or(Self {
+                format: TextureFormat::Rgba8Unorm,
+                texture: TranscoderTextureFormat::RGBA32,
+            })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Ktx2Extent {
+    width: u32,
+    height: u32,
+    /// Faces times layers
+    images: u32,
+}
+
+impl Ktx2Extent {
+    fn level(&self, level: usize) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+}
+
+/// Part of a level, the alpha slice of an ETC1S image follows its color slice.
+struct BasisSlice {
+    image: u32,
+    level: usize,
+    alpha: bool,
+    offset: usize,
+    length: usize,
+}
+
+/// The endpoint and selector codebooks and Huffman tables ETC1S slices refer to.
+#[derive(Default)]
+struct Etc1sCodebooks<'a> {
+    endpoint_count: u16,
+    selector_count: u16,
+    endpoints_length: usize,
+    selectors_length: usize,
+    data: &'a [u8],
+}
+
+/// UASTC levels are plain 16 byte 4x4 blocks, one slice per image.
+fn transcode_uastc(
+    levels: &[Cow<[u8]>],
+    extent: Ktx2Extent,
+    has_alpha: bool,
+    target: &BasisTarget,
+) -> Result<Vec<u8>> {
+    let mut slice_lengths = Vec::with_capacity(levels.len());
+    for (level, level_data) in levels.iter().enumerate() {
+        let (width, height) = extent.level(level);
+        let length = (width.div_ceil(4) * height.div_ceil(4) * 16) as usize;
+        if level_data.len() != length * extent.images as usize {
+            bail!(
+                "UASTC level {} has {} bytes instead of {}",
+                level,
+                level_data.len(),
+                length * extent.images as usize
+            );
+        }
+        slice_lengths.push(length);
+    }
+    let mut slices = Vec::new();
+    for image in 0..extent.images {
+        for (level, &length) in slice_lengths.iter().enumerate() {
+            slices.push(BasisSlice {
+                image,
+                level,
+                alpha: false,
+                offset: image as usize * length,
+                length,
+            });
+        }
+    }
+    transcode_basis(levels, extent, &slices, None, has_alpha, target)
+}
+
+/// BasisLZ keeps the codebooks in the supercompression global data, followed by where the
+/// slices of every image are.
+fn transcode_etc1s(
+    bytes: &[u8],
+    levels: &[Cow<[u8]>],
+    extent: Ktx2Extent,
+    target: &BasisTarget,
+) -> Result<Vec<u8>> {
+    let sgd_offset = read_u64(bytes, 64)? as usize;
+    let sgd_length = read_u64(bytes, 72)? as usize;
+    let sgd = bytes
+        .get(sgd_offset..sgd_offset + sgd_length)
+        .ok_or_else(|| anyhow!("KTX2 supercompression global data is out of bounds"))?;
+    let endpoints_length = read_u32(sgd, 4)? as usize;
+    let selectors_length = read_u32(sgd, 8)? as usize;
+    let tables_length = read_u32(sgd, 12)? as usize;
+
+    // Image descriptions are ordered by level, then layer and face
+    let image_count = levels.len() * extent.images as usize;
+    let codebooks_offset = 20 + image_count * 20;
+    let codebooks = Etc1sCodebooks {
+        endpoint_count: read_u16(sgd, 0)?,
+        selector_count: read_u16(sgd, 2)?,
+        endpoints_length,
+        selectors_length,
+        data: sgd
+            .get(
+                codebooks_offset
+                    ..codebooks_offset + endpoints_length + selectors_length + tables_length,
+            )
+            .ok_or_else(|| anyhow!("ETC1S codebooks are out of bounds"))?,
+    };
+    let has_alpha =
+        (0..image_count).any(|index| read_u32(sgd, 20 + index * 20 + 16).is_ok_and(|it| it != 0));
+
+    let mut slices = Vec::new();
+    for image in 0..extent.images {
+        for level in 0..levels.len() {
+            let desc = 20 + (level * extent.images as usize + image as usize) * 20;
+            slices.push(BasisSlice {
+                image,
+                level,
+                alpha: false,
+                offset: read_u32(sgd, desc + 4)? as usize,
+                length: read_u32(sgd, desc + 8)? as usize,
+            });
+            if has_alpha {
+                slices.push(BasisSlice {
+                    image,
+                    level,
+                    alpha: true,
+                    offset: read_u32(sgd, desc + 12)? as usize,
+                    length: read_u32(sgd, desc + 16)? as usize,
+                });
+            }
+        }
+    }
+    transcode_basis(levels, extent, &slices, Some(codebooks), has_alpha, target)
+}
+
+const BASIS_HEADER_SIZE: usize = 77;
+const BASIS_SLICE_DESC_SIZE: usize = 23;
+const BASIS_TEX_FORMAT_ETC1S: u32 = 0;
+const BASIS_TEX_FORMAT_UASTC: u32 = 1;
+const BASIS_HEADER_FLAG_ETC1S: u32 = 1;
+const BASIS_HEADER_FLAG_HAS_ALPHA_SLICES: u32 = 4;
+const BASIS_SLICE_DESC_FLAG_HAS_ALPHA: u32 = 1;
+
+/// The transcoder only reads `.basis` files, so the slices are rewrapped into one. ETC1S slices
+/// come with their `codebooks`, UASTC ones without.
+fn transcode_basis(
+    levels: &[Cow<[u8]>],
+    extent: Ktx2Extent,
+    slices: &[BasisSlice],
+    codebooks: Option<Etc1sCodebooks>,
+    has_alpha: bool,
+    target: &BasisTarget,
+) -> Result<Vec<u8>> {
+    let (tex_format, codebooks) = match codebooks {
+        Some(codebooks) => (BASIS_TEX_FORMAT_ETC1S, codebooks),
+        None => (BASIS_TEX_FORMAT_UASTC, Etc1sCodebooks::default()),
+    };
+    let codebooks_offset = BASIS_HEADER_SIZE + slices.len() * BASIS_SLICE_DESC_SIZE;
+    let mut file = vec![0; codebooks_offset];
+    file.extend_from_slice(codebooks.data);
+    let mut level_offsets = Vec::with_capacity(levels.len());
+    for level_data in levels {
+        level_offsets.push(file.len());
+        file.extend_from_slice(level_data);
+    }
+
+    for (index, slice) in slices.iter().enumerate() {
+        let (width, height) = extent.level(slice.level);
+        let offset = level_offsets[slice.level] + slice.offset;
+        let data = file
+            .get(offset..offset + slice.length)
+            .ok_or_else(|| anyhow!("Slice of level {} is out of bounds", slice.level))?;
+        let crc = basis_crc16(data);
+        let fields = [
+            (slice.image, 3),
+            (slice.level as u32, 1),
+            (slice.alpha as u32 * BASIS_SLICE_DESC_FLAG_HAS_ALPHA, 1),
+            (width, 2),
+            (height, 2),
+            (width.div_ceil(4), 2),
+            (height.div_ceil(4), 2),
+            (offset as u32, 4),
+            (slice.length as u32, 4),
+            (crc as u32, 2),
+        ];
+        write_packed(
+            &mut file,
+            BASIS_HEADER_SIZE + index * BASIS_SLICE_DESC_SIZE,
+            &fields,
+        );
+    }
+
+    let mut flags = 0;
+    if tex_format == BASIS_TEX_FORMAT_ETC1S {
+        flags |= BASIS_HEADER_FLAG_ETC1S;
+    }
+    if has_alpha {
+        flags |= BASIS_HEADER_FLAG_HAS_ALPHA_SLICES;
+    }
+    let endpoints_offset = codebooks_offset;
+    let selectors_offset = endpoints_offset + codebooks.endpoints_length;
+    let tables_offset = selectors_offset + codebooks.selectors_length;
+    let data_crc = basis_crc16(&file[BASIS_HEADER_SIZE..]);
+    let fields = [
+        // Signature, version, header size and its CRC16
+        (u16::from_le_bytes(*b"sB") as u32, 2),
+        (0x13, 2),
+        (BASIS_HEADER_SIZE as u32, 2),
+        (0, 2),
+        ((file.len() - BASIS_HEADER_SIZE) as u32, 4),
+        (data_crc as u32, 2),
+        (slices.len() as u32, 3),
+        (extent.images, 3),
+        (tex_format, 1),
+        (flags, 2),
+        // A 2D texture, the frame duration, reserved and user data
+        (0, 1),
+        (0, 3),
+        (0, 4),
+        (0, 4),
+        (0, 4),
+        (codebooks.endpoint_count as u32, 2),
+        (endpoints_offset as u32, 4),
+        (codebooks.endpoints_length as u32, 3),
+        (codebooks.selector_count as u32, 2),
+        (selectors_offset as u32, 4),
+        (codebooks.selectors_length as u32, 3),
+        (tables_offset as u32, 4),
+        (
+            (codebooks_offset + codebooks.data.len() - tables_offset) as u32,
+            4,
+        ),
+        (BASIS_HEADER_SIZE as u32, 4),
+        // No extended data
+        (0, 4),
+        (0, 4),
+    ];
+    write_packed(&mut file, 0, &fields);
+    let header_crc = basis_crc16(&file[8..BASIS_HEADER_SIZE]);
+    file[6..8].copy_from_slice(&header_crc.to_le_bytes());
+
+    let mut transcoder = Transcoder::new();
+    transcoder
+        .prepare_transcoding(&file)
+        .map_err(|_| anyhow!("Invalid Basis Universal data"))?;
+    let mut data = Vec::new();
+    for level in 0..levels.len() {
+        for image in 0..extent.images {
+            let transcoded = transcoder
+                .transcode_image_level(
+                    &file,
+                    target.texture,
+                    TranscodeParameters {
+                        image_index: image,
+                        level_index: level as u32,
+                        ..Default::default()
+                    },
+                )
+                .map_err(|err| anyhow!("Transcoding level {}: {:?}", level, err))?;
+            data.extend_from_slice(&transcoded);
+        }
+    }
+    Ok(data)
+}
+
+/// Little endian fields of the given byte sizes.
+fn write_packed(bytes: &mut [u8], mut offset: usize, fields: &[(u32, usize)]) {
+    for &(value, size) in fields {
+        bytes[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+        offset += size;
+    }
+}
+
+/// The CRC16 of `.basis` headers and slices.
+fn basis_crc16(bytes: &[u8]) -> u16 {
+    let mut crc = !0u16;
+    for &byte in bytes {
+        let q = byte as u16 ^ (crc >> 8);
+        let k = (q >> 4) ^ q;
+        crc = (crc << 8) ^ k ^ (k << 5) ^ (k << 12);
+    }
+    !crc
+}
+
+fn vk_format_to_wgpu(vk_format: u32) -> Option<TextureFormat> {
+    use TextureFormat as F;
+    let astc = |block, srgb: bool| F::Astc {
+        block,
+        channel: if srgb {
+            AstcChannel::UnormSrgb
+        } else {
+            AstcChannel::Unorm
+        },
+    };
+    Some(match vk_format {
+        9 => F::R8Unorm,
+        16 => F::Rg8Unorm,
+        37 => F::Rgba8Unorm,
+        43 => F::Rgba8UnormSrgb,
+        44 => F::Bgra8Unorm,
+        50 => F::Bgra8UnormSrgb,
+        76 => F::R16Float,
+        83 => F::Rg16Float,
+        97 => F::Rgba16Float,
+        109 => F::Rgba32Float,
+        122 => F::Rg11b10Ufloat,
+        123 => F::Rgb9e5Ufloat,
+        131 | 133 => F::Bc1RgbaUnorm,
+        132 | 134 => F::Bc1RgbaUnormSrgb,
+        135 => F::Bc2RgbaUnorm,
+        136 => F::Bc2RgbaUnormSrgb,
+        137 => F::Bc3RgbaUnorm,
+        138 => F::Bc3RgbaUnormSrgb,
+        139 => F::Bc4RUnorm,
+        140 => F::Bc4RSnorm,
+        141 => F::Bc5RgUnorm,
+        142 => F::Bc5RgSnorm,
+        143 => F::Bc6hRgbUfloat,
+        144 => F::Bc6hRgbFloat,
+        145 => F::Bc7RgbaUnorm,
+        146 => F::Bc7RgbaUnormSrgb,
+        147 => F::Etc2Rgb8Unorm,
+        148 => F::Etc2Rgb8UnormSrgb,
+        149 => F::Etc2Rgb8A1Unorm,
+        150 => F::Etc2Rgb8A1UnormSrgb,
+        151 => F::Etc2Rgba8Unorm,
+        152 => F::Etc2Rgba8UnormSrgb,
+        153 => F::EacR11Unorm,
+        154 => F::EacR11Snorm,
+        155 => F::EacRg11Unorm,
+        156 => F::EacRg11Snorm,
+        157..=184 => {
+            let block = [
+                AstcBlock::B4x4,
+                AstcBlock::B5x4,
+                AstcBlock::B5x5,
+                AstcBlock::B6x5,
+                AstcBlock::B6x6,
+                AstcBlock::B8x5,
+                AstcBlock::B8x6,
+                AstcBlock::B8x8,
+                AstcBlock::B10x5,
+                AstcBlock::B10x6,
+                AstcBlock::B10x8,
+                AstcBlock::B10x10,
+                AstcBlock::B12x10,
+                AstcBlock::B12x12,
+            ][(vk_format - 157) as usize / 2];
+            astc(block, vk_format.is_multiple_of(2))
+        }
+        _ => return None,
+    })
+}
+
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDPF_FOURCC: u32 = 0x4;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+
+/// Legacy FourCC and DX10 extended headers, volume textures are not handled.
+fn parse_dds(bytes: &[u8]) -> Result<CompressedImage> {
+    let flags = read_u32(bytes, 8)?;
+    let height = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 16)?;
+    let mip_level_count = if flags & DDSD_MIPMAPCOUNT != 0 {
+        read_u32(bytes, 28)?.max(1)
+    } else {
+        1
+    };
+    let pixel_flags = read_u32(bytes, 80)?;
+    let four_cc = bytes.get(84..88).unwrap_or_default();
+    let bit_count = read_u32(bytes, 88)?;
+    let red_mask = read_u32(bytes, 92)?;
+    let caps2 = read_u32(bytes, 112)?;
+
+    let (format, layers, is_cubemap, unknown_color_space, data_offset) =
+        if pixel_flags & DDPF_FOURCC != 0 && four_cc == b"DX10" {
+            let dxgi_format = read_u32(bytes, 128)?;
+            let misc_flag = read_u32(bytes, 136)?;
+            let array_size = read_u32(bytes, 140)?.max(1);
+            let format = dxgi_format_to_wgpu(dxgi_format)
+                .ok_or_else(|| anyhow!("Unsupported DXGI format {}", dxgi_format))?;
+            let is_cubemap = misc_flag & DDS_RESOURCE_MISC_TEXTURECUBE != 0;
+            let faces = if is_cubemap { 6 } else { 1 };
+            (format, array_size * faces, is_cubemap, false, 148)
+        } else {
+            let format = if pixel_flags & DDPF_FOURCC != 0 {
+                four_cc_to_wgpu(four_cc)
+            } else if bit_count == 32 {
+                match red_mask {
+                    0x0000_00ff => Some(TextureFormat::Rgba8Unorm),
+                    0x00ff_0000 => Some(TextureFormat::Bgra8Unorm),
+                    _ => None,
+                }
+            } else {
+                None
+            }
+            .ok_or_else(|| anyhow!("Unsupported DDS pixel format"))?;
+            let is_cubemap = caps2 & DDSCAPS2_CUBEMAP != 0;
+            let layers = if is_cubemap { 6 } else { 1 };
+            (format, layers, is_cubemap, true, 128)
+        };
+
+    Ok(CompressedImage {
+        format,
+        width,
+        height,
+        layers,
+        mip_level_count,
+        is_cubemap,
+        unknown_color_space,
+        order: TextureDataOrder::LayerMajor,
+        data: bytes
+            .get(data_offset..)
+            .ok_or_else(|| anyhow!("DDS without data"))?
+            .to_vec(),
+    })
+}
+
+fn four_cc_to_wgpu(four_cc: &[u8]) -> Option<TextureFormat> {
+    use TextureFormat as F;
+    Some(match four_cc {
+        b"DXT1" => F::Bc1RgbaUnorm,
+        b"DXT2" | b"DXT3" => F::Bc2RgbaUnorm,
+        b"DXT4" | b"DXT5" => F::Bc3RgbaUnorm,
+        b"ATI1" | b"BC4U" => F::Bc4RUnorm,
+        b"BC4S" => F::Bc4RSnorm,
+        b"ATI2" | b"BC5U" => F::Bc5RgUnorm,
+        b"BC5S" => F::Bc5RgSnorm,
+        // D3DFMT_A16B16G16R16F and D3DFMT_A32B32G32R32F
+        [113, 0, 0, 0] => F::Rgba16Float,
+        [116, 0, 0, 0] => F::Rgba32Float,
+        _ => return None,
+    })
+}
+
+fn dxgi_format_to_wgpu(dxgi_format: u32) -> Option<TextureFormat> {
+    use TextureFormat as F;
+    Some(match dxgi_format {
+        2 => F::Rgba32Float,
+        10 => F::Rgba16Float,
+        26 => F::Rg11b10Ufloat,
+        28 => F::Rgba8Unorm,
+        29 => F::Rgba8UnormSrgb,
+        49 => F::Rg8Unorm,
+        61 => F::R8Unorm,
+        67 => F::Rgb9e5Ufloat,
+        71 => F::Bc1RgbaUnorm,
+        72 => F::Bc1RgbaUnormSrgb,
+        74 => F::Bc2RgbaUnorm,
+        75 => F::Bc2RgbaUnormSrgb,
+        77 => F::Bc3RgbaUnorm,
+        78 => F::Bc3RgbaUnormSrgb,
+        80 => F::Bc4RUnorm,
+        81 => F::Bc4RSnorm,
+        83 => F::Bc5RgUnorm,
+        84 => F::Bc5RgSnorm,
+        87 => F::Bgra8Unorm,
+        91 => F::Bgra8UnormSrgb,
+        95 => F::Bc6hRgbUfloat,
+        96 => F::Bc6hRgbFloat,
+        98 => F::Bc7RgbaUnorm,
+        99 => F::Bc7RgbaUnormSrgb,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use basis_universal::{BasisTextureFormat, Compressor, CompressorParams};
+    use ruzstd::encoding::CompressionLevel;
+
+    fn ktx2_header(vk_format: u32, width: u32, height: u32, faces: u32, levels: u32) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for value in [vk_format, 1, width, height, 0, 0, faces, levels, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        // Empty data format descriptor, key/value and supercompression data
+        bytes.extend_from_slice(&[0; 32]);
+        bytes
+    }
+
+    #[test]
+    fn test_ktx2_levels() {
+        // BC7 8x8 with 2 levels, 4 and 1 blocks of 16 bytes
+        let mut bytes = ktx2_header(145, 8, 8, 1, 2);
+        let data_start = (bytes.len() + 2 * 24) as u64;
+        for (offset, length) in [(data_start + 16, 64u64), (data_start, 16)] {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+        }
+        bytes.extend_from_slice(&[1; 16]);
+        bytes.extend_from_slice(&[0; 64]);
+
+        let image = CompressedImage::parse(&bytes, Features::empty()).unwrap();
+        assert_eq!(image.format, TextureFormat::Bc7RgbaUnorm);
+        assert_eq!(image.mip_level_count, 2);
+        assert_eq!(image.layers, 1);
+        assert_eq!(image.data.len(), 80);
+        // Base level first
+        assert_eq!(image.data[0], 0);
+        assert_eq!(image.data[64], 1);
+    }
+
+    #[test]
+    fn test_ktx2_basis_rejected() {
+        let bytes = ktx2_header(0, 4, 4, 1, 1);
+        assert!(CompressedImage::parse(&bytes, Features::empty()).is_err());
+    }
+
+    /// An 8x8 gray gradient and its `.basis` file
+    fn encode_basis(format: BasisTextureFormat) -> (Vec<u8>, Vec<u8>) {
+        let pixels: Vec<u8> = (0..64u8)
+            .flat_map(|texel| {
+                let gray = 64 + texel % 8 * 8 + texel / 8 * 4;
+                [gray, gray, gray, 255]
+            })
+            .collect();
+        let mut params = CompressorParams::new();
+        params.set_basis_format(format);
+        params.set_print_status_to_stdout(false);
+        params.source_image_mut(0).init(&pixels, 8, 8, 4);
+        let mut compressor = Compressor::default();
+        unsafe {
+            assert!(compressor.init(&params));
+            compressor.process().unwrap();
+        }
+        (pixels, compressor.basis_file().to_vec())
+    }
+
+    fn basis_field(basis: &[u8], offset: usize, size: usize) -> usize {
+        let mut value = [0; 4];
+        value[..size].copy_from_slice(&basis[offset..offset + size]);
+        u32::from_le_bytes(value) as usize
+    }
+
+    /// Single 8x8 image of the given Basis Universal color model
+    fn basis_ktx2(
+        color_model: u8,
+        supercompression: u32,
+        level: &[u8],
+        uncompressed_length: usize,
+        sgd: &[u8],
+    ) -> Vec<u8> {
+        let mut bytes = ktx2_header(0, 8, 8, 1, 1);
+        let dfd_offset = bytes.len() + 24;
+        let sgd_offset = dfd_offset + 44;
+        let level_offset = sgd_offset + sgd.len();
+        bytes[44..48].copy_from_slice(&supercompression.to_le_bytes());
+        bytes[48..52].copy_from_slice(&(dfd_offset as u32).to_le_bytes());
+        bytes[64..72].copy_from_slice(&(sgd_offset as u64).to_le_bytes());
+        bytes[72..80].copy_from_slice(&(sgd.len() as u64).to_le_bytes());
+        for value in [level_offset, level.len(), uncompressed_length] {
+            bytes.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+        // Basic descriptor block with an sRGB transfer and an RGB sample
+        let mut dfd = [0; 44];
+        dfd[0] = 44;
+        dfd[12] = color_model;
+        dfd[14] = KHR_DF_TRANSFER_SRGB;
+        bytes.extend_from_slice(&dfd);
+        bytes.extend_from_slice(sgd);
+        bytes.extend_from_slice(level);
+        bytes
+    }
+
+    fn assert_close(pixels: &[u8], transcoded: &[u8], tolerance: u8) {
+        assert_eq!(pixels.len(), transcoded.len());
+        for (expected, actual) in pixels.iter().zip(transcoded) {
+            assert!(
+                expected.abs_diff(*actual) <= tolerance,
+                "{} vs {}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn test_ktx2_uastc() {
+        let (pixels, basis) = encode_basis(BasisTextureFormat::UASTC4x4);
+        // The only slice of the file is the UASTC level
+        let slice_desc = basis_field(&basis, 65, 4);
+        let offset = basis_field(&basis, slice_desc + 13, 4);
+        let length = basis_field(&basis, slice_desc + 17, 4);
+        let blocks = &basis[offset..offset + length];
+        assert_eq!(blocks.len(), 4 * 16);
+        let level = ruzstd::encoding::compress_to_vec(blocks, CompressionLevel::Fastest);
+        let bytes = basis_ktx2(KHR_DF_MODEL_UASTC, KTX_SS_ZSTD, &level, blocks.len(), &[]);
+
+        let image = CompressedImage::parse(&bytes, Features::empty()).unwrap();
+        assert_eq!(image.format, TextureFormat::Rgba8UnormSrgb);
+        assert_close(&pixels, &image.data, 8);
+
+        let level = miniz_oxide::deflate::compress_to_vec_zlib(blocks, 6);
+        let zlib = basis_ktx2(KHR_DF_MODEL_UASTC, KTX_SS_ZLIB, &level, blocks.len(), &[]);
+        assert_eq!(
+            CompressedImage::parse(&zlib, Features::empty())
+                .unwrap()
+                .data,
+            image.data
+        );
+
+        let features = Features::TEXTURE_COMPRESSION_ASTC | Features::TEXTURE_COMPRESSION_BC;
+        let image = CompressedImage::parse(&bytes, features).unwrap();
+        assert_eq!(image.format, TextureFormat::Bc7RgbaUnormSrgb);
+        assert_eq!(image.data.len(), 4 * 16);
+    }
+
+    #[test]
+    fn test_ktx2_etc1s() {
+        let (pixels, basis) = encode_basis(BasisTextureFormat::ETC1S);
+        let slice_desc = basis_field(&basis, 65, 4);
+        let offset = basis_field(&basis, slice_desc + 13, 4);
+        let length = basis_field(&basis, slice_desc + 17, 4);
+        assert_eq!(basis_field(&basis, 14, 3), 1, "no alpha slice");
+
+        // Global data header, the image and the endpoint, selector and table codebooks
+        let mut sgd = Vec::new();
+        sgd.extend_from_slice(&basis[39..41]);
+        sgd.extend_from_slice(&basis[48..50]);
+        let mut codebooks = Vec::new();
+        for (offset, size) in [(41, 3), (50, 3), (57, 4)] {
+            let length = basis_field(&basis, offset + 4, size);
+            sgd.extend_from_slice(&(length as u32).to_le_bytes());
+            let offset = basis_field(&basis, offset, 4);
+            codebooks.extend_from_slice(&basis[offset..offset + length]);
+        }
+        sgd.extend_from_slice(&0u32.to_le_bytes());
+        for value in [0, 0, length as u32, 0, 0] {
+            sgd.extend_from_slice(&value.to_le_bytes());
+        }
+        sgd.extend_from_slice(&codebooks);
+        let bytes = basis_ktx2(
+            KHR_DF_MODEL_ETC1S,
+            KTX_SS_BASIS_LZ,
+            &basis[offset..offset + length],
+            0,
+            &sgd,
+        );
+
+        // Rewrapping the slices transcodes them as the original file does
+        let mut transcoder = Transcoder::new();
+        transcoder.prepare_transcoding(&basis).unwrap();
+        let expected = transcoder
+            .transcode_image_level(
+                &basis,
+                TranscoderTextureFormat::RGBA32,
+                TranscodeParameters::default(),
+            )
+            .unwrap();
+        let image = CompressedImage::parse(&bytes, Features::empty()).unwrap();
+        assert_eq!(image.format, TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(image.data, expected);
+        assert_close(&pixels, &image.data, 24);
+
+        let image = CompressedImage::parse(&bytes, Features::TEXTURE_COMPRESSION_ETC2).unwrap();
+        assert_eq!(image.format, TextureFormat::Etc2Rgba8UnormSrgb);
+        assert_eq!(image.data.len(), 4 * 16);
+    }
+
+    #[test]
+    fn test_dds_cubemap() {
+        let mut bytes = DDS_MAGIC.to_vec();
+        bytes.resize(128, 0);
+        bytes[8..12].copy_from_slice(&DDSD_MIPMAPCOUNT.to_le_bytes());
+        bytes[12..16].copy_from_slice(&4u32.to_le_bytes());
+        bytes[16..20].copy_from_slice(&4u32.to_le_bytes());
+        bytes[28..32].copy_from_slice(&1u32.to_le_bytes());
+        bytes[80..84].copy_from_slice(&DDPF_FOURCC.to_le_bytes());
+        bytes[84..88].copy_from_slice(b"DXT1");
+        bytes[112..116].copy_from_slice(&DDSCAPS2_CUBEMAP.to_le_bytes());
+        bytes.extend_from_slice(&[0; 6 * 8]);
+
+        let image = CompressedImage::parse(&bytes, Features::empty()).unwrap();
+        assert_eq!(image.format, TextureFormat::Bc1RgbaUnorm);
+        assert!(image.is_cubemap);
+        assert!(image.unknown_color_space);
+        assert_eq!(image.layers, 6);
+        assert_eq!(image.data.len(), 48);
+    }
+}
@@ -1,10 +1,8 @@
-use std::{fs::File, io::Read};
-
 use wgpu::TextureViewDescriptor;
 
-use crate::render::UploadedImage;
+use crate::render::{ColorSpace, UploadedImage};
 
-use super::AssetPath;
+use super::{compressed::CompressedImage, AssetPath};
 
 /// Order of paths is +x, -x, +y, -y, +z, -z
 pub fn load_cubemap_sliced(
@@ -14,10 +12,7 @@ pub fn load_cubemap_sliced(
 ) -> anyhow::Result<UploadedImage> {
     let mut byte_images = Vec::with_capacity(6);
     for path in paths {
-        let mut file = File::open(path.final_path())?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
-        byte_images.push(image::load_from_memory(&buffer)?.to_rgba8());
+        byte_images.push(image::load_from_memory(&path.read()?)?.to_rgba8());
     }
 
     let dimensions = byte_images[0].dimensions();
@@ -75,3 +70,23 @@ pub fn load_cubemap_sliced(
 
     Ok(UploadedImage { texture, view })
 }
+
+/// A `.ktx2` or `.dds` cubemap, uploaded with the mip levels it ships with.
+pub fn load_cubemap_compressed(
+    path: &AssetPath,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> anyhow::Result<UploadedImage> {
+    let image = CompressedImage::parse(&path.read()?, device.features())?;
+    if !image.is_cubemap || image.layers != 6 {
+        anyhow::bail!("{} is not a single cubemap", path.final_path());
+    }
+    let texture = image.upload(None, ColorSpace::Srgb, device, queue)?;
+
+    let view = texture.create_view(&TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    });
+
+    Ok(UploadedImage { texture, view })
+}
@@ -0,0 +1,830 @@
+//! Writes the ECS scene as a binary glTF (`.glb`).
+//!
+//! Nodes keep their local transforms and hierarchy. Meshes are written from the
+//! CPU copy kept by their [`Model`], with [`PBRMaterial`] overrides merged the
+//! same way the renderer does. Point and parallel lights use
+//! `KHR_lights_punctual`, rect lights have no glTF equivalent and go to the
+//! node's `extras`.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    io::Cursor,
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::*;
+use bevy_ecs::prelude::*;
+use cgmath::InnerSpace;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    asset::{Assets, Handle},
+    cgmath_ext::{Vec3, Vec4},
+    engine_lifetime::Name,
+    render::{
+        camera::Camera,
+        gizmos::Gizmos,
+        light::{
+            area_light::{AreaLightShape, RectLight},
+            parallel_light::ParallelLight,
+            point_light::PointLight,
+        },
+        material::pbr::{GltfMaterial, PBRMaterial, TextureTransform},
+        transform::Transform,
+        Mesh, MeshHandle, Model, UploadedImageWithSampler, UploadedMesh,
+    },
+    RenderState,
+};
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const NEAREST: u32 = 9728;
+const LINEAR: u32 = 9729;
+const NEAREST_MIPMAP_NEAREST: u32 = 9984;
+const LINEAR_MIPMAP_NEAREST: u32 = 9985;
+const NEAREST_MIPMAP_LINEAR: u32 = 9986;
+const LINEAR_MIPMAP_LINEAR: u32 = 9987;
+const CLAMP_TO_EDGE: u32 = 33071;
+const MIRRORED_REPEAT: u32 = 33648;
+const REPEAT: u32 = 10497;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExportStats {
+    pub nodes: usize,
+    pub meshes: usize,
+    pub materials: usize,
+    pub images: usize,
+}
+
+/// Writes every entity with a [`Transform`] to `path`, gizmos are left out.
+pub fn export_scene(world: &mut World, path: &Path) -> Result<ExportStats> {
+    let mut entities = world
+        .query::<(Entity, &Transform)>()
+        .iter(world)
+        .map(|(entity, transform)| (entity, transform.parent))
+        .collect::<Vec<_>>();
+    entities.sort_by_key(|(entity, _)| *entity);
+    let world = &*world;
+
+    let mut cpu_meshes = HashMap::<Handle<UploadedMesh>, (Arc<Model>, usize)>::new();
+    if let Some(models) = world.get_resource::<Assets<Model>>() {
+        for model in models.values() {
+            for (index, handle) in model.uploaded_meshes.iter().enumerate() {
+                cpu_meshes.insert(handle.clone(), (Arc::clone(model), index));
+            }
+        }
+    }
+    let read_image = |image: &UploadedImageWithSampler| {
+        let rs = world
+            .get_resource::<RenderState>()
+            .ok_or(anyhow!("No render state"))?;
+        image.read_rgba8(&rs.device, &rs.queue)
+    };
+    let mut builder = GlbBuilder::new(&read_image);
+
+    let exported = entities
+        .iter()
+        .map(|(entity, _)| *entity)
+        .collect::<BTreeSet<_>>();
+    let mut children = HashMap::<Entity, Vec<Entity>>::new();
+    let mut roots = Vec::new();
+    for (entity, parent) in &entities {
+        match parent.filter(|it| exported.contains(it)) {
+            Some(parent) => children.entry(parent).or_default().push(*entity),
+            None => roots.push(*entity),
+        }
+    }
+
+    let mut scene_nodes = Vec::new();
+    for root in roots {
+        if let Some(node) = export_node(world, root, &children, &cpu_meshes, &mut builder) {
+            scene_nodes.push(node);
+        }
+    }
+
+    let (glb, stats) = builder.finish(&scene_nodes);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, glb)?;
+    Ok(stats)
+}
+
+fn export_node(
+    world: &World,
+    entity: Entity,
+    children: &HashMap<Entity, Vec<Entity>>,
+    cpu_meshes: &HashMap<Handle<UploadedMesh>, (Arc<Model>, usize)>,
+    builder: &mut GlbBuilder,
+) -> Option<usize> {
+    let entity_ref = world.entity(entity);
+    if entity_ref.contains::<Gizmos>() {
+        return None;
+    }
+    let transform = entity_ref.get::<Transform>()?;
+    let rotation = transform.rotation.normalize();
+    let mut node = json!({
+        "translation": vec3(transform.position),
+        "rotation": [rotation.v.x, rotation.v.y, rotation.v.z, rotation.s],
+        "scale": vec3(transform.scale),
+    });
+    if let Some(name) = entity_ref.get::<Name>() {
+        node["name"] = json!(name.0);
+    }
+
+    let cpu_mesh = entity_ref
+        .get::<MeshHandle>()
+        .and_then(|it| cpu_meshes.get(&it.0));
+    if let Some((model, index)) = cpu_mesh {
+        let mesh = &model.meshes[*index];
+        let materials = match entity_ref.get::<PBRMaterial>() {
+            Some(overrides) => {
+                let base = mesh.primitives.first().and_then(|it| it.material.as_ref());
+                let merged = overrides.merged(base, world.get_resource());
+                vec![Some(merged); mesh.primitives.len()]
+            }
+            None => mesh
+                .primitives
+                .iter()
+                .map(|it| it.material.clone())
+                .collect(),
+        };
+        let key = (Arc::as_ptr(model) as usize, *index);
+        if let Some(mesh) = builder.mesh(key, mesh, materials) {
+            node["mesh"] = json!(mesh);
+        }
+    }
+
+    if let Some(camera) = entity_ref.get::<Camera>() {
+        node["camera"] = json!(builder.cameras.len());
+        builder.cameras.push(json!({
+            "type": "perspective",
+            "perspective": {
+                // `Camera::aspect` is height / width
+                "aspectRatio": 1.0 / camera.aspect,
+                "yfov": camera.fovy.to_radians(),
+                "znear": camera.znear,
+                "zfar": camera.zfar,
+            },
+        }));
+    }
+
+    let light = if let Some(light) = entity_ref.get::<PointLight>() {
+        let mut json = json!({
+            "type": "point",
+            "color": color(light.color),
+            "intensity": light.intensity.max(0.0),
+        });
+        if let Some(distance) = light.distance.filter(|it| *it > 0.0) {
+            json["range"] = json!(distance);
+        }
+        Some(json)
+    } else {
+        entity_ref.get::<ParallelLight>().map(|light| {
+            json!({
+                "type": "directional",
+                "color": color(light.color),
+                "intensity": light.intensity.max(0.0),
+            })
+        })
+    };
+    if let Some(light) = light {
+        builder.use_extension("KHR_lights_punctual");
+        node["extensions"] = json!({ "KHR_lights_punctual": { "light": builder.lights.len() } });
+        builder.lights.push(light);
+    }
+
+    if let Some(light) = entity_ref.get::<RectLight>() {
+        let shape = match light.shape {
+            AreaLightShape::Rectangle => "rectangle",
+            AreaLightShape::Disk => "disk",
+        };
+        node["extras"] = json!({
+            "rectLight": {
+                "color": color(light.color),
+                "intensity": light.intensity,
+                "width": light.width,
+                "height": light.height,
+                "shape": shape,
+                "twoSided": light.two_sided,
+            },
+        });
+    }
+
+    let index = builder.nodes.len();
+    builder.nodes.push(Value::Null);
+    let child_nodes = children
+        .get(&entity)
+        .into_iter()
+        .flatten()
+        .filter_map(|child| export_node(world, *child, children, cpu_meshes, builder))
+        .collect::<Vec<_>>();
+    if !child_nodes.is_empty() {
+        node["children"] = json!(child_nodes);
+    }
+    builder.nodes[index] = node;
+    Some(index)
+}
+
+fn vec3(v: Vec3) -> [f32; 3] {
+    [v.x, v.y, v.z]
+}
+
+/// Linear RGB clamped to the `[0, 1]` range glTF allows
+fn color(v: Vec4) -> [f32; 3] {
+    [v.x, v.y, v.z].map(|it| it.clamp(0.0, 1.0))
+}
+
+struct GlbBuilder<'a> {
+    read_image: &'a dyn Fn(&UploadedImageWithSampler) -> Result<image::RgbaImage>,
+    bin: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    images: Vec<Value>,
+    samplers: Vec<Value>,
+    textures: Vec<Value>,
+    materials: Vec<Value>,
+    meshes: Vec<Value>,
+    nodes: Vec<Value>,
+    cameras: Vec<Value>,
+    lights: Vec<Value>,
+    extensions_used: BTreeSet<String>,
+    /// None when the image couldn't be read back
+    textures_by_image: HashMap<*const UploadedImageWithSampler, Option<usize>>,
+    /// Keyed by model address and mesh index
+    attributes_by_mesh: HashMap<(usize, usize), Value>,
+    meshes_by_key: HashMap<(usize, usize, Vec<Option<usize>>), usize>,
+}
+
+impl<'a> GlbBuilder<'a> {
+    fn new(read_image: &'a dyn Fn(&UploadedImageWithSampler) -> Result<image::RgbaImage>) -> Self {
+        Self {
+            read_image,
+            bin: Vec::new(),
+            buffer_views: Vec::new(),
+            accessors: Vec::new(),
+            images: Vec::new(),
+            samplers: Vec::new(),
+            textures: Vec::new(),
+            materials: Vec::new(),
+            meshes: Vec::new(),
+            nodes: Vec::new(),
+            cameras: Vec::new(),
+            lights: Vec::new(),
+            extensions_used: BTreeSet::new(),
+            textures_by_image: HashMap::new(),
+            attributes_by_mesh: HashMap::new(),
+            meshes_by_key: HashMap::new(),
+        }
+    }
+
+    fn buffer_view(&mut self, bytes: &[u8], target: Option<u32>) -> usize {
+        self.bin.resize(self.bin.len().next_multiple_of(4), 0);
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = json!(target);
+        }
+        self.bin.extend_from_slice(bytes);
+        self.buffer_views.push(view);
+        self.buffer_views.len() - 1
+    }
+
+    fn float_accessor<const N: usize>(&mut self, data: &[[f32; N]], with_bounds: bool) -> usize {
+        let view = self.buffer_view(bytemuck::cast_slice(data), Some(ARRAY_BUFFER));
+        let kind = ["SCALAR", "VEC2", "VEC3", "VEC4"][N - 1];
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": FLOAT,
+            "count": data.len(),
+            "type": kind,
+        });
+        if with_bounds {
+            let (min, max) = data
+                .iter()
+                .fold(([f32::MAX; N], [f32::MIN; N]), |(min, max), v| {
+                    (
+                        std::array::from_fn(|i| min[i].min(v[i])),
+                        std::array::from_fn(|i| max[i].max(v[i])),
+                    )
+                });
+            accessor["min"] = json!(min.to_vec());
+            accessor["max"] = json!(max.to_vec());
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn vertex_attributes(&mut self, key: (usize, usize), mesh: &Mesh) -> Value {
+        if let Some(attributes) = self.attributes_by_mesh.get(&key) {
+            return attributes.clone();
+        }
+        let mut mesh = mesh.clone();
+        mesh.generate_normals();
+        let vertices = &mesh.vertices;
+        let positions = vertices.iter().map(|it| it.position).collect::<Vec<_>>();
+        let normals = vertices
+            .iter()
+            .map(|it| {
+                let normal = Vec3::from(it.normal);
+                if normal.magnitude2() > 0.0 {
+                    normal.normalize().into()
+                } else {
+                    [0.0, 0.0, 1.0]
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut attributes = json!({
+            "POSITION": self.float_accessor(&positions, true),
+            "NORMAL": self.float_accessor(&normals, false),
+        });
+        let tex_coords = vertices.iter().map(|it| it.tex_coord).collect::<Vec<_>>();
+        attributes["TEXCOORD_0"] = json!(self.float_accessor(&tex_coords, false));
+        if vertices.iter().any(|it| it.tex_coord1 != [0.0; 2]) {
+            let tex_coords1 = vertices.iter().map(|it| it.tex_coord1).collect::<Vec<_>>();
+            attributes["TEXCOORD_1"] = json!(self.float_accessor(&tex_coords1, false));
+        }
+        if vertices.iter().any(|it| it.color != [0.0; 4]) {
+            let colors = vertices
+                .iter()
+                .map(|it| it.color.map(|c| c.clamp(0.0, 1.0)))
+                .collect::<Vec<_>>();
+            attributes["COLOR_0"] = json!(self.float_accessor(&colors, false));
+        }
+        self.attributes_by_mesh.insert(key, attributes.clone());
+        attributes
+    }
+
+    /// `materials` is parallel to the primitives, None for an empty mesh.
+    fn mesh(
+        &mut self,
+        key: (usize, usize),
+        mesh: &Mesh,
+        materials: Vec<Option<GltfMaterial>>,
+    ) -> Option<usize> {
+        if mesh.vertices.is_empty() {
+            return None;
+        }
+        let primitives = mesh
+            .primitives
+            .iter()
+            .zip(materials)
+            .filter(|(primitive, _)| primitive.indices_num > 0)
+            .collect::<Vec<_>>();
+        if primitives.is_empty() {
+            return None;
+        }
+        let materials = primitives
+            .iter()
+            .map(|(_, material)| material.as_ref().map(|it| self.material(it)))
+            .collect::<Vec<_>>();
+        let mesh_key = (key.0, key.1, materials.clone());
+        if let Some(index) = self.meshes_by_key.get(&mesh_key) {
+            return Some(*index);
+        }
+
+        let attributes = self.vertex_attributes(key, mesh);
+        let primitives = primitives
+            .iter()
+            .zip(materials)
+            .map(|((primitive, _), material)| {
+                let start = primitive.indices_start as usize;
+                let indices = &mesh.indices[start..start + primitive.indices_num as usize];
+                let view =
+                    self.buffer_view(bytemuck::cast_slice(indices), Some(ELEMENT_ARRAY_BUFFER));
+                self.accessors.push(json!({
+                    "bufferView": view,
+                    "componentType": UNSIGNED_INT,
+                    "count": indices.len(),
+                    "type": "SCALAR",
+                }));
+                let mut json = json!({
+                    "attributes": attributes,
+                    "indices": self.accessors.len() - 1,
+                });
+                if let Some(material) = material {
+                    json["material"] = json!(material);
+                }
+                json
+            })
+            .collect::<Vec<_>>();
+        self.meshes.push(json!({ "primitives": primitives }));
+        self.meshes_by_key.insert(mesh_key, self.meshes.len() - 1);
+        Some(self.meshes.len() - 1)
+    }
+
+    /// Index of an equal material if one was written already.
+    fn material(&mut self, material: &GltfMaterial) -> usize {
+        let mut pbr = json!({
+            "metallicFactor": material.metallic.clamp(0.0, 1.0),
+            "roughnessFactor": material.roughness.clamp(0.0, 1.0),
+        });
+        if let Some(info) =
+            self.texture_info(&material.base_color_texture, &material.base_color_transform)
+        {
+            pbr["baseColorTexture"] = info;
+        }
+        let mut json = json!({ "pbrMetallicRoughness": pbr });
+        if let Some(info) = self.texture_info(&material.normal_texture, &material.normal_transform)
+        {
+            json["normalTexture"] = info;
+        }
+        if let Some(info) =
+            self.texture_info(&material.emissive_texture, &material.emissive_transform)
+        {
+            json["emissiveTexture"] = info;
+        }
+
+        let mut extensions = Map::new();
+        let emissive = material.emissive * material.emissive_strength.max(0.0);
+        let strength = emissive.x.max(emissive.y).max(emissive.z).max(1.0);
+        if emissive != Vec3::new(0.0, 0.0, 0.0) {
+            json["emissiveFactor"] = json!(vec3(emissive / strength).map(|it| it.max(0.0)));
+        }
+        if strength > 1.0 {
+            extensions.insert(
+                "KHR_materials_emissive_strength".into(),
+                json!({ "emissiveStrength": strength }),
+            );
+        }
+
+        // Inverse of `MaterialExtensions::reflectance`
+        let f0_sqrt = 0.4 * material.reflectance.clamp(0.0, 1.0);
+        let ior = (1.0 + f0_sqrt) / (1.0 - f0_sqrt);
+        if (ior - 1.5).abs() > 1e-4 {
+            extensions.insert("KHR_materials_ior".into(), json!({ "ior": ior }));
+        }
+        let ext = &material.extensions;
+        if ext.specular != 1.0 || ext.specular_color != Vec3::new(1.0, 1.0, 1.0) {
+            extensions.insert(
+                "KHR_materials_specular".into(),
+                json!({
+                    "specularFactor": ext.specular.clamp(0.0, 1.0),
+                    "specularColorFactor": vec3(ext.specular_color).map(|it| it.max(0.0)),
+                }),
+            );
+        }
+        if ext.sheen_color != Vec3::new(0.0, 0.0, 0.0) {
+            extensions.insert(
+                "KHR_materials_sheen".into(),
+                json!({
+                    "sheenColorFactor": vec3(ext.sheen_color).map(|it| it.clamp(0.0, 1.0)),
+                    "sheenRoughnessFactor": ext.sheen_roughness.clamp(0.0, 1.0),
+                }),
+            );
+        }
+        if ext.is_transmissive() {
+            extensions.insert(
+                "KHR_materials_transmission".into(),
+                json!({ "transmissionFactor": ext.transmission.min(1.0) }),
+            );
+            let mut volume = json!({
+                "thicknessFactor": ext.thickness.max(0.0),
+                "attenuationColor": vec3(ext.attenuation_color).map(|it| it.clamp(0.0, 1.0)),
+            });
+            if ext.attenuation_distance > 0.0 && ext.attenuation_distance < f32::MAX {
+                volume["attenuationDistance"] = json!(ext.attenuation_distance);
+            }
+            extensions.insert("KHR_materials_volume".into(), volume);
+        }
+        if !extensions.is_empty() {
+            for name in extensions.keys() {
+                self.use_extension(name);
+            }
+            json["extensions"] = Value::Object(extensions);
+        }
+
+        match self.materials.iter().position(|it| *it == json) {
+            Some(index) => index,
+            None => {
+                self.materials.push(json);
+                self.materials.len() - 1
+            }
+        }
+    }
+
+    fn texture_info(
+        &mut self,
+        image: &Option<Arc<UploadedImageWithSampler>>,
+        transform: &TextureTransform,
+    ) -> Option<Value> {
+        let index = self.texture(image.as_ref()?)?;
+        let mut info = json!({ "index": index });
+        if transform.tex_coord != 0 {
+            info["texCoord"] = json!(transform.tex_coord);
+        }
+        let identity = TextureTransform::default();
+        if transform.offset != identity.offset
+            || transform.rotation != identity.rotation
+            || transform.scale != identity.scale
+        {
+            self.use_extension("KHR_texture_transform");
+            info["extensions"] = json!({
+                "KHR_texture_transform": {
+                    "offset": [transform.offset.x, transform.offset.y],
+                    "rotation": transform.rotation,
+                    "scale": [transform.scale.x, transform.scale.y],
+                },
+            });
+        }
+        Some(info)
+    }
+
+    /// Reads the image back from the GPU and stores it as PNG.
+    fn texture(&mut self, image: &Arc<UploadedImageWithSampler>) -> Option<usize> {
+        let key = Arc::as_ptr(image);
+        if let Some(texture) = self.textures_by_image.get(&key) {
+            return *texture;
+        }
+        let png = (self.read_image)(image).and_then(|rgba| {
+            let mut png = Vec::new();
+            rgba.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)?;
+            Ok(png)
+        });
+        let texture = match png {
+            Result::Ok(png) => {
+                let view = self.buffer_view(&png, None);
+                self.images
+                    .push(json!({ "bufferView": view, "mimeType": "image/png" }));
+                let sampler = self.sampler(&image.sampler_desc);
+                self.textures.push(json!({
+                    "source": self.images.len() - 1,
+                    "sampler": sampler,
+                }));
+                Some(self.textures.len() - 1)
+            }
+            Err(err) => {
+                log::warn!("Exporting without texture: {:#}", err);
+                None
+            }
+        };
+        self.textures_by_image.insert(key, texture);
+        texture
+    }
+
+    /// Inverse of [`crate::render::gltf_sampler_desc`], equal samplers are shared.
+    fn sampler(&mut self, desc: &wgpu::SamplerDescriptor) -> usize {
+        use wgpu::{AddressMode, FilterMode};
+
+        let wrap = |mode: AddressMode| match mode {
+            AddressMode::Repeat => REPEAT,
+            AddressMode::MirrorRepeat => MIRRORED_REPEAT,
+            _ => CLAMP_TO_EDGE,
+        };
+        let filter = |mode: FilterMode| match mode {
+            FilterMode::Nearest => NEAREST,
+            FilterMode::Linear => LINEAR,
+        };
+        let min_filter = match (desc.min_filter, desc.mipmap_filter) {
+            (FilterMode::Nearest, FilterMode::Nearest) => NEAREST_MIPMAP_NEAREST,
+            (FilterMode::Linear, FilterMode::Nearest) => LINEAR_MIPMAP_NEAREST,
+            (FilterMode::Nearest, FilterMode::Linear) => NEAREST_MIPMAP_LINEAR,
+            (FilterMode::Linear, FilterMode::Linear) => LINEAR_MIPMAP_LINEAR,
+        };
+        let json = json!({
+            "magFilter": filter(desc.mag_filter),
+            "minFilter": min_filter,
+            "wrapS": wrap(desc.address_mode_u),
+            "wrapT": wrap(desc.address_mode_v),
+        });
+        match self.samplers.iter().position(|it| *it == json) {
+            Some(index) => index,
+            None => {
+                self.samplers.push(json);
+                self.samplers.len() - 1
+            }
+        }
+    }
+
+    fn use_extension(&mut self, name: &str) {
+        self.extensions_used.insert(name.to_string());
+    }
+
+    fn finish(self, scene_nodes: &[usize]) -> (Vec<u8>, ExportStats) {
+        let stats = ExportStats {
+            nodes: self.nodes.len(),
+            meshes: self.meshes.len(),
+            materials: self.materials.len(),
+            images: self.images.len(),
+        };
+        let mut root = json!({
+            "asset": { "version": "2.0", "generator": "wgpu_pbr" },
+            "scene": 0,
+            "scenes": [{ "nodes": scene_nodes }],
+        });
+        let arrays = [
+            ("nodes", self.nodes),
+            ("meshes", self.meshes),
+            ("materials", self.materials),
+            ("textures", self.textures),
+            ("samplers", self.samplers),
+            ("images", self.images),
+            ("accessors", self.accessors),
+            ("bufferViews", self.buffer_views),
+            ("cameras", self.cameras),
+        ];
+        for (name, values) in arrays {
+            if !values.is_empty() {
+                root[name] = Value::Array(values);
+            }
+        }
+        if !self.lights.is_empty() {
+            root["extensions"] = json!({ "KHR_lights_punctual": { "lights": self.lights } });
+        }
+        if !self.extensions_used.is_empty() {
+            root["extensionsUsed"] = json!(self.extensions_used);
+        }
+        if !self.bin.is_empty() {
+            root["buffers"] = json!([{ "byteLength": self.bin.len().next_multiple_of(4) }]);
+        }
+        let json = serde_json::to_vec(&root).expect("Serializing JSON");
+        (write_glb(&json, &self.bin), stats)
+    }
+}
+
+/// GLB container with a JSON chunk and, if `bin` isn't empty, a BIN chunk.
+fn write_glb(json: &[u8], bin: &[u8]) -> Vec<u8> {
+    let mut chunks = Vec::new();
+    let mut chunk = |kind: &[u8; 4], data: &[u8], padding: u8| {
+        let padded = data.len().next_multiple_of(4);
+        chunks.extend_from_slice(&(padded as u32).to_le_bytes());
+        chunks.extend_from_slice(kind);
+        chunks.extend_from_slice(data);
+        chunks.resize(chunks.len() + padded - data.len(), padding);
+    };
+    chunk(b"JSON", json, b' ');
+    if !bin.is_empty() {
+        chunk(b"BIN\0", bin, 0);
+    }
+    let mut glb = Vec::with_capacity(12 + chunks.len());
+    glb.extend_from_slice(b"glTF");
+    glb.extend_from_slice(&2u32.to_le_bytes());
+    glb.extend_from_slice(&(12 + chunks.len() as u32).to_le_bytes());
+    glb.extend_from_slice(&chunks);
+    glb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{Primitive, Vertex};
+
+    fn json_chunk(glb: &[u8]) -> Value {
+        let length = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        serde_json::from_slice(&glb[20..20 + length]).unwrap()
+    }
+
+    #[test]
+    fn test_mesh_round_trip() {
+        let vertex = |position: [f32; 3], tex_coord: [f32; 2]| Vertex {
+            position,
+            tex_coord,
+            ..Default::default()
+        };
+        let mesh = Mesh {
+            vertices: vec![
+                vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+                vertex([2.0, 0.0, 0.0], [1.0, 0.0]),
+                vertex([0.0, 1.0, -1.0], [0.0, 1.0]),
+            ],
+            indices: vec![0, 1, 2],
+            primitives: vec![Primitive {
+                indices_start: 0,
+                indices_num: 3,
+                material: Some(GltfMaterial {
+                    emissive: Vec3::new(1.0, 0.5, 0.0),
+                    emissive_strength: 4.0,
+                    ..Default::default()
+                }),
+            }],
+        };
+        let no_images = |_: &UploadedImageWithSampler| Err(anyhow!("No images"));
+        let mut builder = GlbBuilder::new(&no_images);
+        let materials = mesh.primitives.iter().map(|it| it.material.clone());
+        let index = builder.mesh((0, 0), &mesh, materials.clone().collect());
+        assert_eq!(index, Some(0));
+        assert_eq!(builder.mesh((0, 0), &mesh, materials.collect()), Some(0));
+        builder.nodes.push(json!({ "mesh": 0 }));
+        let (glb, stats) = builder.finish(&[0]);
+        assert_eq!(stats.meshes, 1);
+        assert_eq!(glb.len() % 4, 0);
+
+        let root = json_chunk(&glb);
+        assert_eq!(root["accessors"][0]["min"], json!([0.0, 0.0, -1.0]));
+        assert_eq!(root["accessors"][0]["max"], json!([2.0, 1.0, 0.0]));
+        assert_eq!(
+            root["extensionsUsed"],
+            json!(["KHR_materials_emissive_strength"])
+        );
+        let material = &root["materials"][0];
+        assert_eq!(material["emissiveFactor"], json!([1.0, 0.5, 0.0]));
+        assert_eq!(
+            material["extensions"]["KHR_materials_emissive_strength"]["emissiveStrength"],
+            json!(4.0)
+        );
+
+        let (document, buffers, _) = gltf::import_slice(&glb).unwrap();
+        let primitive = document
+            .meshes()
+            .next()
+            .unwrap()
+            .primitives()
+            .next()
+            .unwrap();
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let positions = reader.read_positions().unwrap().collect::<Vec<_>>();
+        assert_eq!(positions[1], [2.0, 0.0, 0.0]);
+        let normals = reader.read_normals().unwrap().collect::<Vec<_>>();
+        assert!(normals.iter().all(|it| Vec3::from(*it).magnitude() > 0.999));
+        let indices = reader
+            .read_indices()
+            .unwrap()
+            .into_u32()
+            .collect::<Vec<_>>();
+        assert_eq!(indices, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_samplers() {
+        let no_images = |_: &UploadedImageWithSampler| Err(anyhow!("No images"));
+        let mut builder = GlbBuilder::new(&no_images);
+        let gltf = gltf::Gltf::from_slice(
+            br#"{"asset":{"version":"2.0"},"samplers":[{"magFilter":9728,"minFilter":9986,"wrapS":33648,"wrapT":33071}]}"#,
+        )
+        .unwrap();
+        let desc = crate::render::gltf_sampler_desc(&gltf.samplers().next().unwrap());
+        assert_eq!(builder.sampler(&desc), 0);
+        assert_eq!(
+            builder.samplers[0],
+            json!({ "magFilter": 9728, "minFilter": 9986, "wrapS": 33648, "wrapT": 33071 })
+        );
+        assert_eq!(builder.sampler(&desc), 0);
+        assert_eq!(builder.sampler(&wgpu::SamplerDescriptor::default()), 1);
+    }
+
+    #[test]
+    fn test_scene_nodes() {
+        let mut world = World::new();
+        let parent = world
+            .spawn((
+                Name("Rig".to_string()),
+                Transform {
+                    parent: None,
+                    children: vec![],
+                    position: Vec3::new(1.0, 2.0, 3.0),
+                    rotation: cgmath::Quaternion::new(2.0, 0.0, 0.0, 0.0),
+                    scale: Vec3::new(1.0, 1.0, 1.0),
+                },
+                PointLight::default(),
+            ))
+            .id();
+        let mut child = Transform {
+            parent: Some(parent),
+            children: vec![],
+            position: Vec3::new(0.0, 0.0, 0.0),
+            rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        };
+        world.spawn((child.clone(), Camera::new(1.5)));
+        child.parent = None;
+        world.spawn((child, RectLight::default()));
+
+        let path =
+            std::env::temp_dir().join(format!("wgpu_pbr_export_{}.glb", uuid::Uuid::new_v4()));
+        let stats = export_scene(&mut world, &path).unwrap();
+        let glb = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(stats.nodes, 3);
+
+        let root = json_chunk(&glb);
+        assert_eq!(root["scenes"][0]["nodes"], json!([0, 2]));
+        let rig = &root["nodes"][0];
+        assert_eq!(rig["name"], json!("Rig"));
+        assert_eq!(rig["rotation"], json!([0.0, 0.0, 0.0, 1.0]));
+        assert_eq!(rig["children"], json!([1]));
+        assert_eq!(rig["extensions"]["KHR_lights_punctual"]["light"], json!(0));
+        assert_eq!(
+            root["extensions"]["KHR_lights_punctual"]["lights"][0]["type"],
+            json!("point")
+        );
+        assert_eq!(root["nodes"][1]["camera"], json!(0));
+        assert_eq!(
+            root["cameras"][0]["perspective"]["aspectRatio"],
+            json!(1.0 / 1.5f32)
+        );
+        assert_eq!(
+            root["nodes"][2]["extras"]["rectLight"]["shape"],
+            json!("rectangle")
+        );
+        assert_eq!(root["extensionsUsed"], json!(["KHR_lights_punctual"]));
+        assert!(root.get("buffers").is_none());
+        gltf::Gltf::from_slice(&glb).unwrap();
+    }
+}
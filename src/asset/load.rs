@@ -1,182 +1,630 @@
-use std::fs;
-use std::{fs::File, io::Read, sync::Arc};
+use std::path::Path;
+use std::sync::Arc;
 
-use crate::render::material::pbr::GltfMaterial;
-use crate::render::{self, Model, Primitive, UploadedImageWithSampler, Vertex};
+use crate::asset::compressed::CompressedImage;
+use crate::cgmath_ext::{Vec2, Vec3, VectorExt};
+use crate::render::material::pbr::{GltfMaterial, MaterialExtensions, TextureTransform};
+use crate::render::mipmap::MipmapGenerator;
+use crate::render::{
+    self, ColorSpace, ImageSettings, Model, Primitive, UploadedImageWithSampler, Vertex,
+};
 use crate::RenderState;
 use anyhow::*;
-use bevy_ecs::world::World;
+use bevy_ecs::world::{Mut, World};
 use wgpu::ShaderModule;
 
-use super::AssetPath;
+use super::{obj, ply, processed, AssetPath};
 
 pub trait Loadable: Sized {
     fn load(path: AssetPath, world: &mut World) -> Result<Self>;
 }
 
+/// Loading split in two, so that [`AssetServer`](super::server::AssetServer) can run
+/// `decode` on a worker thread and `upload` on the main thread.
+pub trait AsyncLoadable: Sized + Send + Sync + 'static {
+    type Settings: Clone + Default + Send + Sync + 'static;
+    type Decoded: Send + 'static;
+
+    /// File IO and CPU work only
+    fn decode(path: &AssetPath, settings: &Self::Settings) -> Result<Self::Decoded>;
+    /// GPU uploads
+    fn upload(decoded: Self::Decoded, settings: &Self::Settings, world: &mut World)
+        -> Result<Self>;
+
+    /// Final paths of other files read by `decode`, changing them reloads the asset
+    fn dependencies(_decoded: &Self::Decoded) -> Vec<String> {
+        Vec::new()
+    }
+}
+
 impl Loadable for UploadedImageWithSampler {
     fn load(path: AssetPath, world: &mut World) -> Result<Self> {
-        let mut file = File::open(path.final_path())?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
-        let image = image::load_from_memory(&buffer)?.to_rgba8();
-        let render_state = world.resource::<RenderState>();
-
-        let dimensions = image.dimensions();
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
-        };
+        Self::load_with_settings(path, world, ImageSettings::default())
+    }
+}
 
-        let texture = render_state
-            .device
-            .create_texture(&wgpu::TextureDescriptor {
-                size,
-                mip_level_count: 1,
-                label: None,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                view_formats: &[],
-            });
-
-        render_state.queue.write_texture(
-            wgpu::TexelCopyTextureInfoBase {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &image,
-            wgpu::TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
-        );
-
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = render_state
-            .device
-            .create_sampler(&UploadedImageWithSampler::default_sampler_desc());
-
-        Ok(UploadedImageWithSampler {
-            size,
-            texture,
-            view,
-            sampler,
-        })
+impl UploadedImageWithSampler {
+    pub fn load_with_settings(
+        path: AssetPath,
+        world: &mut World,
+        settings: ImageSettings,
+    ) -> Result<Self> {
+        Self::upload(Self::decode(&path, &settings)?, &settings, world)
+    }
+}
+
+pub enum DecodedImage {
+    Rgba8(image::RgbaImage),
+    /// `.ktx2` or `.dds` container, parsed once the device features are known
+    Compressed(Vec<u8>),
+}
+
+impl AsyncLoadable for UploadedImageWithSampler {
+    type Settings = ImageSettings;
+    type Decoded = DecodedImage;
+
+    fn decode(path: &AssetPath, _settings: &ImageSettings) -> Result<DecodedImage> {
+        let buffer = path.read()?;
+
+        if CompressedImage::is_compressed_container(&buffer) {
+            Ok(DecodedImage::Compressed(buffer))
+        } else {
+            Ok(DecodedImage::Rgba8(
+                image::load_from_memory(&buffer)?.to_rgba8(),
+            ))
+        }
+    }
+
+    fn upload(decoded: DecodedImage, settings: &ImageSettings, world: &mut World) -> Result<Self> {
+        let mipmapped_sampler_desc = wgpu::SamplerDescriptor {
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..UploadedImageWithSampler::default_sampler_desc()
+        };
+        match decoded {
+            DecodedImage::Compressed(container) => {
+                let render_state = world.resource::<RenderState>();
+                let image = CompressedImage::parse(&container, render_state.device.features())?;
+                UploadedImageWithSampler::from_compressed(
+                    &image,
+                    settings.color_space,
+                    &mipmapped_sampler_desc,
+                    &render_state.device,
+                    &render_state.queue,
+                )
+            }
+            DecodedImage::Rgba8(image) => {
+                let sampler_desc = if settings.mipmaps {
+                    mipmapped_sampler_desc
+                } else {
+                    UploadedImageWithSampler::default_sampler_desc()
+                };
+                Ok(
+                    world.resource_scope(|world, mut mipmaps: Mut<MipmapGenerator>| {
+                        let render_state = world.resource::<RenderState>();
+                        UploadedImageWithSampler::from_rgba8(
+                            &image,
+                            settings.color_space,
+                            &sampler_desc,
+                            &render_state.device,
+                            &render_state.queue,
+                            settings.mipmaps.then_some(mipmaps.as_mut()),
+                        )
+                    }),
+                )
+            }
+        }
     }
 }
 
 impl Loadable for Model {
     fn load(path: AssetPath, world: &mut World) -> Result<Self> {
-        let path = path.final_path();
-        let (document, buffers, images) = gltf::import(path)?;
-        let render_state = world.resource::<RenderState>();
-
-        let meshes = document
-            .meshes()
-            .map(|mesh| {
-                let mut vertices = Vec::<Vertex>::new();
-                let mut indices = Vec::<u32>::new();
-                let mut primitives = Vec::<render::Primitive>::new();
-                for primitive in mesh.primitives() {
-                    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-
-                    let positions = reader
-                        .read_positions()
-                        .map(|v| {
-                            // v.map(|raw_pos| (rotate_90 * Vector3::from(raw_pos)).into())
-                            v.collect::<Vec<_>>()
-                        })
-                        .unwrap_or_default();
-                    let normals = reader
-                        .read_normals()
-                        .map(|v| v.collect::<Vec<_>>())
-                        .unwrap_or_default();
-                    let tangents = reader
-                        .read_normals()
-                        .map(|v| v.collect::<Vec<_>>())
-                        .unwrap_or_default();
-                    let tex_coords = reader
-                        .read_tex_coords(0)
-                        .map(|v| v.into_f32().collect::<Vec<_>>())
-                        .unwrap_or_default();
-                    let colors = reader
-                        .read_colors(0)
-                        .map(|v| v.into_rgba_f32().collect::<Vec<_>>())
-                        .unwrap_or_default();
-                    let mut primitive_indices = reader
-                        .read_indices()
-                        .map(|v| v.into_u32().collect::<Vec<_>>())
-                        .unwrap_or_default();
-
-                    for i in 0..positions.len() {
-                        let v = Vertex {
-                            position: *positions.get(i).unwrap_or(&[0.0; 3]),
-                            normal: *normals.get(i).unwrap_or(&[0.0; 3]),
-                            tangent: *tangents.get(i).unwrap_or(&[0.0; 3]),
-                            color: *colors.get(i).unwrap_or(&[0.0; 4]),
-                            tex_coord: *tex_coords.get(i).unwrap_or(&[0.0; 2]),
-                        };
-                        vertices.push(v);
-                    }
+        Model::upload(Model::decode(&path, &())?, &(), world)
+    }
+}
 
-                    let material_instance: Option<GltfMaterial> = {
-                        let mat = primitive.material();
-                        let pbr_mr = mat.pbr_metallic_roughness();
-                        let base_color = primitive
-                            .material()
-                            .pbr_metallic_roughness()
-                            .base_color_texture();
-                        base_color.map(|tex_info| {
-                            let uploaded_image = Arc::new(UploadedImageWithSampler::from_glb_data(
-                                images.get(tex_info.texture().index()).unwrap(),
-                                &tex_info.texture().sampler(),
+/// Texture slot of a glTF material, resolved to images when uploading.
+pub(super) struct TextureSlot {
+    /// Image indices to try in order
+    pub(super) sources: Vec<usize>,
+    pub(super) sampler: wgpu::SamplerDescriptor<'static>,
+}
+
+impl TextureSlot {
+    fn new(texture: gltf::Texture) -> Self {
+        Self {
+            sources: texture_sources(&texture),
+            sampler: render::gltf_sampler_desc(&texture.sampler()),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(super) struct PrimitiveTextures {
+    pub(super) base_color: Option<TextureSlot>,
+    pub(super) normal: Option<TextureSlot>,
+    pub(super) emissive: Option<TextureSlot>,
+}
+
+/// A glTF model with its images decoded, the materials don't have textures yet.
+pub struct DecodedModel {
+    pub(super) meshes: Vec<render::Mesh>,
+    /// Per mesh and primitive, parallel to `meshes`
+    pub(super) textures: Vec<Vec<PrimitiveTextures>>,
+    pub(super) images: Vec<Result<GltfImage>>,
+    /// URIs of the external buffers and images, relative to the model
+    pub(super) uris: Vec<String>,
+    /// `uris` as final paths
+    pub(super) dependencies: Vec<String>,
+}
+
+impl AsyncLoadable for Model {
+    type Settings = ();
+    type Decoded = DecodedModel;
+
+    /// glTF, OBJ or PLY by extension. Prefers the processed model, see [`processed`](super::processed).
+    fn decode(path: &AssetPath, settings: &()) -> Result<DecodedModel> {
+        let source = path.read()?;
+        match processed::read_model(path, &source, settings) {
+            Result::Ok(Some(decoded)) => return Ok(decoded),
+            Result::Ok(None) => {}
+            Err(err) => log::warn!("Reprocessing {}: {:#}", path.final_path(), err),
+        }
+        let final_path = path.final_path();
+        let extension = Path::new(&final_path)
+            .extension()
+            .map(|it| it.to_string_lossy().to_lowercase());
+        let decoded = match extension.as_deref() {
+            Some("obj") => obj::decode(path, &source)?,
+            Some("ply") => ply::decode(path, &source)?,
+            _ => decode_gltf(path, &source)?,
+        };
+        if let Err(err) = processed::write_model(path, &source, settings, &decoded) {
+            log::warn!("Failed to cache {}: {:#}", path.final_path(), err);
+        }
+        Ok(decoded)
+    }
+
+    fn upload(decoded: DecodedModel, _settings: &(), world: &mut World) -> Result<Self> {
+        let DecodedModel {
+            mut meshes,
+            textures,
+            images,
+            ..
+        } = decoded;
+        world.resource_scope(|world, mut mipmaps: Mut<MipmapGenerator>| {
+            let render_state = world.resource::<RenderState>();
+            let mut upload = |slot: &TextureSlot, color_space: ColorSpace| {
+                for &index in slot.sources.iter() {
+                    let uploaded = match &images[index] {
+                        Result::Ok(GltfImage::Decoded(image)) => {
+                            Ok(UploadedImageWithSampler::from_rgba8(
+                                image,
+                                color_space,
+                                &slot.sampler,
                                 &render_state.device,
                                 &render_state.queue,
-                            ));
-                            GltfMaterial {
-                                base_color_texture: Some(uploaded_image),
-                                roughness: pbr_mr.roughness_factor(),
-                                metallic: pbr_mr.metallic_factor(),
-                                ..Default::default()
-                            }
-                        })
+                                Some(&mut mipmaps),
+                            ))
+                        }
+                        Result::Ok(GltfImage::Compressed(container)) => {
+                            CompressedImage::parse(container, render_state.device.features())
+                                .and_then(|image| {
+                                    UploadedImageWithSampler::from_compressed(
+                                        &image,
+                                        color_space,
+                                        &slot.sampler,
+                                        &render_state.device,
+                                        &render_state.queue,
+                                    )
+                                })
+                        }
+                        Err(err) => Err(anyhow!("{}", err)),
                     };
+                    match uploaded {
+                        Result::Ok(it) => return Some(Arc::new(it)),
+                        Err(err) => log::warn!("Skipping glTF image {}: {}", index, err),
+                    }
+                }
+                None
+            };
 
-                    let indices_start = indices.len() as u32;
-                    let indices_num = primitive_indices.len() as u32;
+            for (mesh, mesh_textures) in meshes.iter_mut().zip(textures.iter()) {
+                for (primitive, slots) in mesh.primitives.iter_mut().zip(mesh_textures.iter()) {
+                    let Some(material) = primitive.material.as_mut() else {
+                        continue;
+                    };
+                    material.base_color_texture = slots
+                        .base_color
+                        .as_ref()
+                        .and_then(|it| upload(it, ColorSpace::Srgb));
+                    material.normal_texture = slots
+                        .normal
+                        .as_ref()
+                        .and_then(|it| upload(it, ColorSpace::Linear));
+                    material.emissive_texture = slots
+                        .emissive
+                        .as_ref()
+                        .and_then(|it| upload(it, ColorSpace::Srgb));
+                }
+            }
+        });
+
+        Ok(Model::new(meshes, world))
+    }
+
+    fn dependencies(decoded: &DecodedModel) -> Vec<String> {
+        decoded.dependencies.clone()
+    }
+}
 
-                    indices.append(&mut primitive_indices);
-                    primitives.push(Primitive {
-                        indices_start,
-                        indices_num,
-                        material: material_instance,
-                    });
+fn decode_gltf(path: &AssetPath, source: &[u8]) -> Result<DecodedModel> {
+    let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(source)?;
+    let buffers = load_gltf_buffers(&document, path, blob)?;
+    let images = document
+        .images()
+        .map(|image| load_gltf_image(image, path, &buffers))
+        .collect::<Vec<_>>();
+    let buffer_uris = document
+        .buffers()
+        .filter_map(|buffer| match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => Some(uri),
+            gltf::buffer::Source::Bin => None,
+        });
+    let image_uris = document.images().filter_map(|image| match image.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+    let uris = buffer_uris
+        .chain(image_uris)
+        .filter(|uri| !uri.starts_with("data:"))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let dependencies = uris
+        .iter()
+        .map(|uri| path.sibling(uri).final_path())
+        .collect();
+
+    for mat in document.materials() {
+        let ignored = unsupported_extension_textures(&mat);
+        if !ignored.is_empty() {
+            log::warn!(
+                "{}: material {} has unsupported textures, only their factors are used: {}",
+                path.final_path(),
+                mat.name().unwrap_or("<unnamed>"),
+                ignored.join(", ")
+            );
+        }
+    }
+
+    let mut textures = Vec::new();
+    let meshes = document
+        .meshes()
+        .map(|mesh| {
+            let mut vertices = Vec::<Vertex>::new();
+            let mut indices = Vec::<u32>::new();
+            let mut primitives = Vec::<render::Primitive>::new();
+            let mut mesh_textures = Vec::new();
+            for primitive in mesh.primitives() {
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+                let positions = reader
+                    .read_positions()
+                    .map(|v| {
+                        // v.map(|raw_pos| (rotate_90 * Vector3::from(raw_pos)).into())
+                        v.collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                let normals = reader
+                    .read_normals()
+                    .map(|v| v.collect::<Vec<_>>())
+                    .unwrap_or_default();
+                let tangents = reader
+                    .read_normals()
+                    .map(|v| v.collect::<Vec<_>>())
+                    .unwrap_or_default();
+                let tex_coords = reader
+                    .read_tex_coords(0)
+                    .map(|v| v.into_f32().collect::<Vec<_>>())
+                    .unwrap_or_default();
+                let tex_coords1 = reader
+                    .read_tex_coords(1)
+                    .map(|v| v.into_f32().collect::<Vec<_>>())
+                    .unwrap_or_default();
+                let colors = reader
+                    .read_colors(0)
+                    .map(|v| v.into_rgba_f32().collect::<Vec<_>>())
+                    .unwrap_or_default();
+                let mut primitive_indices = reader
+                    .read_indices()
+                    .map(|v| v.into_u32().collect::<Vec<_>>())
+                    .unwrap_or_default();
+
+                for i in 0..positions.len() {
+                    let v = Vertex {
+                        position: *positions.get(i).unwrap_or(&[0.0; 3]),
+                        normal: *normals.get(i).unwrap_or(&[0.0; 3]),
+                        tangent: *tangents.get(i).unwrap_or(&[0.0; 3]),
+                        color: *colors.get(i).unwrap_or(&[0.0; 4]),
+                        tex_coord: *tex_coords.get(i).unwrap_or(&[0.0; 2]),
+                        tex_coord1: *tex_coords1.get(i).unwrap_or(&[0.0; 2]),
+                    };
+                    vertices.push(v);
                 }
-                render::Mesh {
-                    vertices,
-                    indices,
-                    primitives,
+
+                let mat = primitive.material();
+                let pbr_mr = mat.pbr_metallic_roughness();
+                let base_color_info = pbr_mr.base_color_texture();
+                let emissive_info = mat.emissive_texture();
+                let normal = mat.normal_texture();
+                let slots = PrimitiveTextures {
+                    base_color: base_color_info
+                        .as_ref()
+                        .map(|it| TextureSlot::new(it.texture())),
+                    normal: normal.as_ref().map(|it| TextureSlot::new(it.texture())),
+                    emissive: emissive_info
+                        .as_ref()
+                        .map(|it| TextureSlot::new(it.texture())),
+                };
+                let emissive = Vec3::from(mat.emissive_factor());
+                let is_emissive = emissive != Vec3::zero();
+                let extensions = load_material_extensions(&mat);
+                let material_instance = (slots.base_color.is_some()
+                    || slots.normal.is_some()
+                    || is_emissive
+                    || extensions.is_some())
+                .then(|| {
+                    let extensions = extensions.unwrap_or_default();
+                    GltfMaterial {
+                        roughness: pbr_mr.roughness_factor(),
+                        metallic: pbr_mr.metallic_factor(),
+                        reflectance: extensions.reflectance(),
+                        emissive,
+                        emissive_strength: mat.emissive_strength().unwrap_or(1.0),
+                        base_color_transform: base_color_info
+                            .as_ref()
+                            .map(load_texture_transform)
+                            .unwrap_or_default(),
+                        normal_transform: normal
+                            .as_ref()
+                            .map(load_normal_texture_transform)
+                            .unwrap_or_default(),
+                        emissive_transform: emissive_info
+                            .as_ref()
+                            .map(load_texture_transform)
+                            .unwrap_or_default(),
+                        extensions,
+                        ..Default::default()
+                    }
+                });
+                mesh_textures.push(slots);
+
+                let indices_start = indices.len() as u32;
+                let indices_num = primitive_indices.len() as u32;
+
+                indices.append(&mut primitive_indices);
+                primitives.push(Primitive {
+                    indices_start,
+                    indices_num,
+                    material: material_instance,
+                });
+            }
+            textures.push(mesh_textures);
+            render::Mesh {
+                vertices,
+                indices,
+                primitives,
+            }
+        })
+        .collect::<Vec<render::Mesh>>();
+
+    Ok(DecodedModel {
+        meshes,
+        textures,
+        images,
+        uris,
+        dependencies,
+    })
+}
+
+/// A decoded glTF image, or a `.ktx2` one referenced through `KHR_texture_basisu`.
+pub(super) enum GltfImage {
+    Decoded(image::RgbaImage),
+    /// KTX2 or DDS container, parsed when uploading
+    Compressed(Vec<u8>),
+}
+
+/// Like `gltf::import_buffers`, but external buffers are read through `path`'s source.
+fn load_gltf_buffers(
+    document: &gltf::Document,
+    path: &AssetPath,
+    mut blob: Option<Vec<u8>>,
+) -> Result<Vec<gltf::buffer::Data>> {
+    document
+        .buffers()
+        .map(|buffer| {
+            let data = match buffer.source() {
+                gltf::buffer::Source::Uri(uri) if !uri.starts_with("data:") => {
+                    let mut data = path.sibling(uri).read()?;
+                    data.resize(data.len().next_multiple_of(4), 0);
+                    gltf::buffer::Data(data)
                 }
-            })
-            .collect::<Vec<render::Mesh>>();
+                source => gltf::buffer::Data::from_source_and_blob(source, None, &mut blob)?,
+            };
+            ensure!(
+                data.len() >= buffer.length(),
+                "Buffer {} is shorter than declared",
+                buffer.index()
+            );
+            Ok(data)
+        })
+        .collect()
+}
+
+fn load_gltf_image(
+    image: gltf::Image,
+    path: &AssetPath,
+    buffers: &[gltf::buffer::Data],
+) -> Result<GltfImage> {
+    let bytes = match image.source() {
+        gltf::image::Source::View { view, .. } => {
+            let buffer = &buffers[view.buffer().index()];
+            buffer[view.offset()..view.offset() + view.length()].to_vec()
+        }
+        gltf::image::Source::Uri { uri, .. } if !uri.starts_with("data:") => {
+            path.sibling(uri).read()?
+        }
+        // Data URIs are decoded by the gltf crate, the base is never read
+        source => {
+            return Ok(GltfImage::Decoded(render::gltf_image_to_rgba8(
+                gltf::image::Data::from_source(source, Some(Path::new("")), buffers)?,
+            )?))
+        }
+    };
+    decode_image(bytes)
+}
 
-        Ok(Model { meshes })
+/// PNG, JPEG and the like are decoded, KTX2 and DDS are kept for uploading.
+pub(super) fn decode_image(bytes: Vec<u8>) -> Result<GltfImage> {
+    if CompressedImage::is_compressed_container(&bytes) {
+        Ok(GltfImage::Compressed(bytes))
+    } else {
+        Ok(GltfImage::Decoded(
+            image::load_from_memory(&bytes)?.to_rgba8(),
+        ))
+    }
+}
+
+/// Images to try for a texture, the `KHR_texture_basisu` source before the fallback one.
+fn texture_sources(texture: &gltf::Texture) -> Vec<usize> {
+    let basisu = texture
+        .extension_value("KHR_texture_basisu")
+        .and_then(|it| it.get("source")?.as_u64())
+        .map(|it| it as usize);
+    basisu
+        .into_iter()
+        .chain(texture.source().map(|it| it.index()))
+        .collect()
+}
+
+/// `texCoord` of the slot, overridden by its `KHR_texture_transform`. Only two UV sets are read.
+fn load_texture_transform(info: &gltf::texture::Info) -> TextureTransform {
+    let mut ret = TextureTransform {
+        tex_coord: info.tex_coord(),
+        ..Default::default()
+    };
+    if let Some(transform) = info.texture_transform() {
+        ret.offset = transform.offset().into();
+        ret.rotation = transform.rotation();
+        ret.scale = transform.scale().into();
+        ret.tex_coord = transform.tex_coord().unwrap_or(ret.tex_coord);
+    }
+    ret.tex_coord = ret.tex_coord.min(1);
+    ret
+}
+
+/// Same as [`load_texture_transform`], the gltf crate leaves the extension of normal textures raw.
+fn load_normal_texture_transform(normal: &gltf::material::NormalTexture) -> TextureTransform {
+    let mut ret = TextureTransform {
+        tex_coord: normal.tex_coord(),
+        ..Default::default()
+    };
+    if let Some(transform) = normal.extension_value("KHR_texture_transform") {
+        let number = |value: &gltf::json::Value| value.as_f64().map(|it| it as f32);
+        let pair = |key: &str| {
+            let array = transform.get(key)?.as_array()?;
+            Some(Vec2::new(number(array.first()?)?, number(array.get(1)?)?))
+        };
+        ret.offset = pair("offset").unwrap_or(ret.offset);
+        ret.rotation = transform.get("rotation").and_then(number).unwrap_or(0.0);
+        ret.scale = pair("scale").unwrap_or(ret.scale);
+        if let Some(tex_coord) = transform.get("texCoord").and_then(|it| it.as_u64()) {
+            ret.tex_coord = tex_coord as u32;
+        }
+    }
+    ret.tex_coord = ret.tex_coord.min(1);
+    ret
+}
+
+/// Factors of the ior, specular, sheen, transmission and volume extensions, `None` if the material uses none.
+fn load_material_extensions(mat: &gltf::Material) -> Option<MaterialExtensions> {
+    let mut ext = MaterialExtensions::default();
+    let mut found = false;
+    if let Some(ior) = mat.ior() {
+        ext.ior = ior;
+        found = true;
+    }
+    if let Some(specular) = mat.specular() {
+        ext.specular = specular.specular_factor();
+        ext.specular_color = specular.specular_color_factor().into();
+        found = true;
+    }
+    // Not modeled by the gltf crate, read from the raw extension
+    if let Some(sheen) = mat.extension_value("KHR_materials_sheen") {
+        let factor = |key: &str| {
+            sheen
+                .get(key)
+                .and_then(|it| it.as_f64())
+                .map(|it| it as f32)
+        };
+        if let Some(color) = sheen.get("sheenColorFactor").and_then(|it| it.as_array()) {
+            let channel = |i: usize| color.get(i).and_then(|it| it.as_f64()).unwrap_or(0.0) as f32;
+            ext.sheen_color = Vec3::new(channel(0), channel(1), channel(2));
+        }
+        ext.sheen_roughness = factor("sheenRoughnessFactor").unwrap_or(0.0);
+        found = true;
+    }
+    if let Some(transmission) = mat.transmission() {
+        ext.transmission = transmission.transmission_factor();
+        found = true;
+    }
+    if let Some(volume) = mat.volume() {
+        ext.thickness = volume.thickness_factor();
+        ext.attenuation_distance = volume.attenuation_distance().min(f32::MAX);
+        ext.attenuation_color = volume.attenuation_color().into();
+        found = true;
+    }
+    found.then_some(ext)
+}
+
+/// Textures of the material extensions that [`load_material_extensions`] doesn't load.
+fn unsupported_extension_textures(mat: &gltf::Material) -> Vec<&'static str> {
+    let mut ignored = Vec::new();
+    if let Some(sheen) = mat.extension_value("KHR_materials_sheen") {
+        for key in ["sheenColorTexture", "sheenRoughnessTexture"] {
+            if sheen.get(key).is_some() {
+                ignored.push(key);
+            }
+        }
+    }
+    if let Some(specular) = mat.specular() {
+        if specular.specular_texture().is_some() {
+            ignored.push("specularTexture");
+        }
+        if specular.specular_color_texture().is_some() {
+            ignored.push("specularColorTexture");
+        }
+    }
+    if mat
+        .transmission()
+        .is_some_and(|it| it.transmission_texture().is_some())
+    {
+        ignored.push("transmissionTexture");
+    }
+    if mat
+        .volume()
+        .is_some_and(|it| it.thickness_texture().is_some())
+    {
+        ignored.push("thicknessTexture");
     }
+    ignored
 }
 
 impl Loadable for ShaderModule {
     fn load(path: AssetPath, world: &mut World) -> Result<Self> {
-        let path = path.final_path();
+        let wgsl_string = path.read_to_string()?;
         let rs = &world.resource::<RenderState>();
         let device = &rs.device;
-        let wgsl_string = fs::read_to_string(path)?;
         Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
             source: wgpu::ShaderSource::Wgsl(wgsl_string.into()),
@@ -1,24 +1,109 @@
+use anyhow::{anyhow, Result};
+use bevy_ecs::system::Resource;
+use source::{AssetReader, AssetSources, FileReader};
 use std::{
     collections::HashMap,
     hash::{Hash, Hasher},
     marker::PhantomData,
-    sync::Arc,
+    path::{Path, PathBuf},
+    sync::{Arc, Weak},
 };
 
+pub mod compressed;
 pub mod cubemap;
+pub mod export;
 pub mod load;
+pub mod obj;
+pub mod ply;
+pub mod processed;
+pub mod server;
+pub mod source;
+pub mod watcher;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum AssetPath {
+    /// Relative to [`AssetSources::root`], then looked up in the mounted packs
     Assets(String),
+    /// Anywhere on the filesystem
+    Absolute(PathBuf),
+    /// Compiled into the binary, see [`embed_asset!`](crate::embed_asset)
+    Embedded(String),
 }
 
 impl AssetPath {
+    /// Where the asset is, used in messages and to match changed files
     pub fn final_path(&self) -> String {
         match self {
-            AssetPath::Assets(p) => format!("assets/{}", p),
+            AssetPath::Assets(p) => normalized(&AssetSources::root().join(p)),
+            AssetPath::Absolute(p) => normalized(p),
+            AssetPath::Embedded(p) => format!("embedded://{}", p),
         }
     }
+
+    pub fn read(&self) -> Result<Vec<u8>> {
+        match self {
+            AssetPath::Assets(p) => AssetSources::with_assets(p, |reader| reader.read(p))
+                .unwrap_or_else(|| Err(anyhow!("{} is in no asset source", p))),
+            AssetPath::Absolute(p) => absolute_reader().read(&p.to_string_lossy()),
+            AssetPath::Embedded(p) => AssetSources::with_embedded(|reader| reader.read(p)),
+        }
+    }
+
+    pub fn read_to_string(&self) -> Result<String> {
+        Ok(String::from_utf8(self.read()?)?)
+    }
+
+    pub fn exists(&self) -> bool {
+        match self {
+            AssetPath::Assets(p) => AssetSources::with_assets(p, |_| ()).is_some(),
+            AssetPath::Absolute(p) => p.is_file(),
+            AssetPath::Embedded(p) => AssetSources::with_embedded(|reader| reader.exists(p)),
+        }
+    }
+
+    /// `relative` to the directory of this path, in the same source
+    pub fn sibling(&self, relative: &str) -> AssetPath {
+        match self {
+            AssetPath::Assets(p) => AssetPath::Assets(source::join(source::parent(p), relative)),
+            AssetPath::Absolute(p) => {
+                AssetPath::Absolute(p.parent().unwrap_or(Path::new("")).join(relative))
+            }
+            AssetPath::Embedded(p) => {
+                AssetPath::Embedded(source::join(source::parent(p), relative))
+            }
+        }
+    }
+
+    /// The files directly in this directory, across all sources of the variant
+    pub fn read_dir(&self) -> Result<Vec<AssetPath>> {
+        let mut paths = match self {
+            AssetPath::Assets(p) => {
+                let found = AssetSources::assets()
+                    .iter()
+                    .filter_map(|reader| reader.read_dir(p).ok())
+                    .flatten()
+                    .map(AssetPath::Assets)
+                    .collect::<Vec<_>>();
+                if found.is_empty() {
+                    return Err(anyhow!("{} is in no asset source", p));
+                }
+                found
+            }
+            AssetPath::Absolute(p) => absolute_reader()
+                .read_dir(&p.to_string_lossy())?
+                .into_iter()
+                .map(|it| AssetPath::Absolute(it.into()))
+                .collect(),
+            AssetPath::Embedded(p) => AssetSources::with_embedded(|reader| reader.read_dir(p))?
+                .into_iter()
+                .map(AssetPath::Embedded)
+                .collect(),
+        };
+        paths.sort_by_key(|it| it.final_path());
+        paths.dedup();
+        Ok(paths)
+    }
+
     /// Start with `assets/shaders/`
     pub fn new_shader_wgsl(path: &str) -> Self {
         let mut path = path.to_string();
@@ -29,86 +114,196 @@ impl AssetPath {
     }
 }
 
+fn absolute_reader() -> FileReader {
+    FileReader {
+        root: PathBuf::new(),
+    }
+}
+
+fn normalized(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+struct Entry<T> {
+    /// None until the asset is uploaded
+    value: Option<Arc<T>>,
+    path: Option<AssetPath>,
+    refs: Weak<()>,
+}
+
+/// Assets shared between entities, optionally keyed by the path they were loaded from.
+///
+/// Entries live as long as a strong [`Handle`] to them exists, after that
+/// [`Assets::free_unused`] drops them and with them their GPU resources.
 pub struct Assets<T> {
-    map: HashMap<Handle<T>, (String, Arc<T>)>,
-    name_map: HashMap<String, Handle<T>>,
+    entries: HashMap<uuid::Uuid, Entry<T>>,
+    paths: HashMap<AssetPath, uuid::Uuid>,
 }
 
 impl<T> Assets<T> {
     pub fn new() -> Self {
         Self {
-            map: HashMap::new(),
-            name_map: HashMap::new(),
+            entries: HashMap::new(),
+            paths: HashMap::new(),
         }
     }
 
     pub fn get(&self, handle: &Handle<T>) -> Option<Arc<T>> {
-        self.map.get(handle).map(|it| it.1.clone())
+        self.entries
+            .get(&handle.uuid)
+            .and_then(|it| it.value.clone())
     }
 
-    pub fn get_by_name(&self, name: &str) -> Option<Arc<T>> {
-        self.name_map
-            .get(&name.to_string())
-            .and_then(|handle| self.get(handle))
-    }
-
-    pub fn insert_with_name(&mut self, name: &str, value: Arc<T>) -> (Handle<T>, Option<Arc<T>>) {
-        let name = name.to_string();
-        let removed = self.remove_by_name(&name);
-        // insert
-
-        let uuid = uuid::Uuid::new_v4();
-        let handle = Handle {
+    /// A strong handle to the entry of `path`, if it is still referenced.
+    pub fn handle(&self, path: &AssetPath) -> Option<Handle<T>> {
+        let uuid = *self.paths.get(path)?;
+        let refs = self.entries.get(&uuid)?.refs.upgrade()?;
+        Some(Handle {
             pha: PhantomData::<T>,
             uuid,
-        };
-        self.map.insert(handle, (name.clone(), value));
-        self.name_map.insert(name, handle);
+            refs: Some(refs),
+        })
+    }
 
-        (handle, removed)
+    /// An entry for `path` without a value yet, e.g. for [`server::AssetServer::load`].
+    /// Also returns a weak handle to the unreferenced entry of `path` it replaced.
+    pub fn reserve(&mut self, path: AssetPath) -> (Handle<T>, Option<Handle<T>>) {
+        let handle = Handle::new();
+        let evicted = self.paths.remove(&path).map(|uuid| {
+            self.entries.remove(&uuid);
+            Handle {
+                pha: PhantomData::<T>,
+                uuid,
+                refs: None,
+            }
+        });
+        self.paths.insert(path.clone(), handle.uuid);
+        self.entries
+            .insert(handle.uuid, handle.entry(None, Some(path)));
+        (handle, evicted)
     }
 
-    pub fn push(&mut self, value: Arc<T>) -> Handle<T> {
-        let uuid = uuid::Uuid::new_v4();
-        let handle = Handle {
-            pha: PhantomData::<T>,
-            uuid,
-        };
-        let name = uuid.to_string();
-        self.map.insert(handle, (name.clone(), value));
-        self.name_map.insert(name, handle);
-        handle
+    /// Sets the value of an existing entry, or adds one if `handle` is strong.
+    pub fn insert(&mut self, handle: &Handle<T>, value: Arc<T>) -> Option<Arc<T>> {
+        match self.entries.get_mut(&handle.uuid) {
+            Some(entry) => entry.value.replace(value),
+            None => {
+                if handle.is_strong() {
+                    self.entries
+                        .insert(handle.uuid, handle.entry(Some(value), None));
+                }
+                None
+            }
+        }
     }
 
-    pub fn remove_by_name(&mut self, name: &String) -> Option<Arc<T>> {
-        let handle = self.name_map.remove(name);
+    pub fn push(&mut self, value: Arc<T>) -> Handle<T> {
+        let handle = Handle::new();
+        self.entries
+            .insert(handle.uuid, handle.entry(Some(value), None));
         handle
-            .and_then(|it| self.map.remove(&it).map(|(name, value)| value))
     }
 
     pub fn remove(&mut self, handle: &Handle<T>) -> Option<Arc<T>> {
-        let out = self.map.remove(handle);
-        if let Some((name, value)) = out {
-            self.name_map.remove(&name);
-            return Some(value);
-        };
-        None
+        let entry = self.entries.remove(&handle.uuid)?;
+        if let Some(path) = entry.path {
+            self.paths.remove(&path);
+        }
+        entry.value
+    }
+
+    /// Drops the entries without strong handles, returns weak handles to them.
+    pub fn free_unused(&mut self) -> Vec<Handle<T>> {
+        let unused = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.refs.strong_count() == 0)
+            .map(|(uuid, _)| Handle {
+                pha: PhantomData::<T>,
+                uuid: *uuid,
+                refs: None,
+            })
+            .collect::<Vec<_>>();
+        for handle in unused.iter() {
+            self.remove(handle);
+        }
+        unused
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The path the asset was loaded from, None for assets added with [`Assets::push`].
+    pub fn path(&self, handle: &Handle<T>) -> Option<&AssetPath> {
+        self.entries.get(&handle.uuid)?.path.as_ref()
+    }
+
+    /// Uploaded assets, in no particular order.
+    pub fn values(&self) -> impl Iterator<Item = &Arc<T>> {
+        self.entries.values().filter_map(|it| it.value.as_ref())
+    }
+}
+
+impl<T: Send + Sync + 'static> Resource for Assets<T> {}
+
+impl<T> Default for Assets<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
+/// Refers to an entry of [`Assets`], strong handles keep it alive.
 #[derive(Debug)]
 pub struct Handle<T> {
     pha: PhantomData<T>,
     uuid: uuid::Uuid,
+    refs: Option<Arc<()>>,
+}
+
+impl<T> Handle<T> {
+    /// A strong handle that no entry uses yet
+    pub fn new() -> Self {
+        Self {
+            pha: PhantomData::<T>,
+            uuid: uuid::Uuid::new_v4(),
+            refs: Some(Arc::new(())),
+        }
+    }
+
+    pub fn downgrade(&self) -> Self {
+        Self {
+            pha: PhantomData::<T>,
+            uuid: self.uuid,
+            refs: None,
+        }
+    }
+
+    pub fn is_strong(&self) -> bool {
+        self.refs.is_some()
+    }
+
+    fn entry(&self, value: Option<Arc<T>>, path: Option<AssetPath>) -> Entry<T> {
+        Entry {
+            value,
+            path,
+            refs: self.refs.as_ref().map(Arc::downgrade).unwrap_or_default(),
+        }
+    }
 }
 
-impl<T> Copy for Handle<T> {}
+impl<T> Default for Handle<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl<T> Clone for Handle<T> {
     fn clone(&self) -> Self {
         Handle {
             pha: PhantomData::<T>,
             uuid: self.uuid,
+            refs: self.refs.clone(),
         }
     }
 }
@@ -144,15 +339,49 @@ mod test {
     }
 
     #[test]
-    fn test_asset_name() {
+    fn test_asset_path() {
+        let mut assets = Assets::<String>::new();
+        let path = AssetPath::Assets("boooo1121321!".to_string());
+        let (handle, evicted) = assets.reserve(path.clone());
+        assert!(evicted.is_none());
+        assets.insert(&handle, Arc::new("Hello".to_string()));
+        assert_eq!(*assets.get(&handle).unwrap(), "Hello");
+        // Same path, same entry
+        assert!(assets.handle(&path).unwrap() == handle);
+        assert_eq!(assets.path(&handle), Some(&path));
+
+        // Reserving a path whose entry is unreferenced replaces that entry
+        let weak = handle.downgrade();
+        drop(handle);
+        assert!(assets.handle(&path).is_none());
+        let (handle, evicted) = assets.reserve(path.clone());
+        assert!(evicted.unwrap() == weak);
+        assert!(assets.get(&weak).is_none());
+        assert_eq!(assets.len(), 1);
+
+        assets.remove(&handle);
+
+        assert!(assets.handle(&path).is_none());
+    }
+
+    #[test]
+    fn test_free_unused() {
         let mut assets = Assets::<String>::new();
-        let name = "boooo1121321!";
-        assets.insert_with_name(name, Arc::new("Hello".to_string()));
+        let handle = assets.push(Arc::new("Hello".to_string()));
+        let weak = handle.downgrade();
+        let strong = handle.clone();
 
-        assert_eq!(*assets.get_by_name(name).unwrap(), "Hello");
+        drop(handle);
+        assert!(assets.free_unused().is_empty());
 
-        assets.remove_by_name(&name.to_string());
+        drop(strong);
+        let freed = assets.free_unused();
+        assert_eq!(freed.len(), 1);
+        assert!(freed[0] == weak);
+        assert!(assets.get(&weak).is_none());
 
-        assert!(assets.get_by_name(name).is_none());
+        // Weak handles don't add entries
+        assert!(assets.insert(&weak, Arc::new("Hi".to_string())).is_none());
+        assert_eq!(assets.len(), 0);
     }
 }
@@ -0,0 +1,511 @@
+//! Wavefront OBJ with its MTL materials.
+//!
+//! Every `o` becomes a [`Mesh`](render::Mesh) and every material used in it a [`Primitive`].
+//! Polygons are triangulated as fans, missing normals and tangents are generated.
+
+use std::collections::HashMap;
+
+use anyhow::*;
+
+use crate::{
+    cgmath_ext::{Vec2, Vec3, VectorExt},
+    render::{
+        self,
+        material::pbr::{GltfMaterial, MaterialExtensions, TextureTransform},
+        Primitive, Vertex,
+    },
+};
+
+use super::{
+    load::{decode_image, DecodedModel, GltfImage, PrimitiveTextures, TextureSlot},
+    source, AssetPath,
+};
+
+pub(super) fn decode(path: &AssetPath, source: &[u8]) -> Result<DecodedModel> {
+    let text = String::from_utf8_lossy(source);
+    let mut uris = Vec::new();
+    let mut materials = HashMap::new();
+    for line in text.lines() {
+        if let Some(file) = line.trim().strip_prefix("mtllib ") {
+            let file = file.trim().to_string();
+            match path.sibling(&file).read_to_string() {
+                Result::Ok(mtl) => materials.extend(parse_mtl(&mtl, source::parent(&file))),
+                Err(err) => log::warn!("Skipping {}: {:#}", file, err),
+            }
+            uris.push(file);
+        }
+    }
+    let (meshes, used) = parse_obj(&text)?;
+
+    let mut images = Images::default();
+    let mut textures = Vec::new();
+    let meshes = meshes
+        .into_iter()
+        .zip(used)
+        .map(|(mut mesh, names)| {
+            let mut mesh_textures = Vec::new();
+            for (primitive, name) in mesh.primitives.iter_mut().zip(names) {
+                let Some(mtl) = name.and_then(|it| materials.get(&it)) else {
+                    mesh_textures.push(PrimitiveTextures::default());
+                    continue;
+                };
+                let mut slot = |map: &Option<MtlMap>| {
+                    map.as_ref().map(|map| TextureSlot {
+                        sources: vec![images.file(path, &map.file)],
+                        sampler: sampler(),
+                    })
+                };
+                let mut slots = PrimitiveTextures {
+                    base_color: slot(&mtl.diffuse_map),
+                    normal: slot(&mtl.normal_map),
+                    emissive: slot(&mtl.emissive_map),
+                };
+                // No base color factor on the material, a plain `Kd` becomes a 1x1 texture
+                if slots.base_color.is_none() {
+                    slots.base_color = mtl.diffuse.map(|diffuse| TextureSlot {
+                        sources: vec![images.color(diffuse)],
+                        sampler: sampler(),
+                    });
+                }
+                primitive.material = Some(mtl.to_gltf());
+                mesh_textures.push(slots);
+            }
+            textures.push(mesh_textures);
+            mesh.generate_normals();
+            mesh.generate_tangents();
+            mesh
+        })
+        .collect();
+
+    uris.append(&mut images.uris);
+    let dependencies = uris
+        .iter()
+        .map(|uri| path.sibling(uri).final_path())
+        .collect();
+    Ok(DecodedModel {
+        meshes,
+        textures,
+        images: images.images,
+        uris,
+        dependencies,
+    })
+}
+
+/// Images of the materials, a file is decoded once however many maps use it
+#[derive(Default)]
+struct Images {
+    images: Vec<Result<GltfImage>>,
+    files: HashMap<String, usize>,
+    uris: Vec<String>,
+}
+
+impl Images {
+    fn file(&mut self, path: &AssetPath, uri: &str) -> usize {
+        if let Some(&index) = self.files.get(uri) {
+            return index;
+        }
+        self.uris.push(uri.to_string());
+        self.images
+            .push(path.sibling(uri).read().and_then(decode_image));
+        self.files.insert(uri.to_string(), self.images.len() - 1);
+        self.images.len() - 1
+    }
+
+    /// A 1x1 image of an sRGB color
+    fn color(&mut self, color: [f32; 3]) -> usize {
+        let [r, g, b] = color.map(|it| (it.clamp(0.0, 1.0) * 255.0).round() as u8);
+        let image = image::RgbaImage::from_pixel(1, 1, image::Rgba([r, g, b, 255]));
+        self.images.push(Result::Ok(GltfImage::Decoded(image)));
+        self.images.len() - 1
+    }
+}
+
+fn sampler() -> wgpu::SamplerDescriptor<'static> {
+    wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    }
+}
+
+/// A mesh being built, vertices are shared by faces that use the same `v/vt/vn`.
+#[derive(Default)]
+struct MeshBuilder {
+    vertices: Vec<Vertex>,
+    lookup: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+    /// Indices per material, in the order the materials are first used
+    groups: Vec<(Option<String>, Vec<u32>)>,
+}
+
+impl MeshBuilder {
+    fn finish(self) -> Option<(render::Mesh, Vec<Option<String>>)> {
+        let mut indices = Vec::new();
+        let mut primitives = Vec::new();
+        let mut names = Vec::new();
+        for (name, mut group) in self.groups {
+            if group.is_empty() {
+                continue;
+            }
+            primitives.push(Primitive {
+                indices_start: indices.len() as u32,
+                indices_num: group.len() as u32,
+                material: None,
+            });
+            names.push(name);
+            indices.append(&mut group);
+        }
+        (!primitives.is_empty()).then(|| {
+            let mesh = render::Mesh {
+                vertices: self.vertices,
+                indices,
+                primitives,
+            };
+            (mesh, names)
+        })
+    }
+}
+
+/// Meshes and the material names of their primitives
+type ParsedObj = (Vec<render::Mesh>, Vec<Vec<Option<String>>>);
+
+fn parse_obj(text: &str) -> Result<ParsedObj> {
+    let mut positions = Vec::<[f32; 3]>::new();
+    let mut colors = Vec::<[f32; 4]>::new();
+    let mut tex_coords = Vec::<[f32; 2]>::new();
+    let mut normals = Vec::<[f32; 3]>::new();
+    let mut material = None;
+    let mut builder = MeshBuilder::default();
+    let mut meshes = Vec::new();
+    let mut used = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let values = tokens.collect::<Vec<_>>();
+        let floats = || -> Result<Vec<f32>> {
+            values
+                .iter()
+                .map(|it| Ok(it.parse::<f32>()?))
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Line {}", number + 1))
+        };
+        match keyword {
+            "v" => {
+                let v = floats()?;
+                ensure!(
+                    v.len() >= 3,
+                    "Line {}: a vertex needs x, y and z",
+                    number + 1
+                );
+                positions.push([v[0], v[1], v[2]]);
+                // `v x y z r g b` is a common extension
+                colors.push(match v.len() {
+                    6.. => [v[3], v[4], v[5], 1.0],
+                    _ => [0.0; 4],
+                });
+            }
+            "vt" => {
+                let v = floats()?;
+                // OBJ has V pointing up
+                tex_coords.push([
+                    v.first().copied().unwrap_or(0.0),
+                    1.0 - v.get(1).copied().unwrap_or(0.0),
+                ]);
+            }
+            "vn" => {
+                let v = floats()?;
+                ensure!(
+                    v.len() >= 3,
+                    "Line {}: a normal needs x, y and z",
+                    number + 1
+                );
+                normals.push([v[0], v[1], v[2]]);
+            }
+            "f" => {
+                let resolve = |token: Option<&str>, len: usize| -> Result<Option<usize>> {
+                    let Some(token) = token.filter(|it| !it.is_empty()) else {
+                        return Ok(None);
+                    };
+                    let index = token.parse::<i64>()?;
+                    let resolved = if index < 0 {
+                        len as i64 + index
+                    } else {
+                        index - 1
+                    };
+                    ensure!(
+                        (0..len as i64).contains(&resolved),
+                        "Index {} out of range",
+                        index
+                    );
+                    Ok(Some(resolved as usize))
+                };
+                let mut corners = Vec::new();
+                for value in values.iter() {
+                    let mut parts = value.split('/');
+                    let corner = (|| -> Result<_> {
+                        let v = resolve(parts.next(), positions.len())?
+                            .context("A face corner needs a vertex")?;
+                        let vt = resolve(parts.next(), tex_coords.len())?;
+                        let vn = resolve(parts.next(), normals.len())?;
+                        Ok((v, vt, vn))
+                    })()
+                    .with_context(|| format!("Line {}", number + 1))?;
+                    let index = *builder.lookup.entry(corner).or_insert_with(|| {
+                        let (v, vt, vn) = corner;
+                        builder.vertices.push(Vertex {
+                            position: positions[v],
+                            normal: vn.map(|it| normals[it]).unwrap_or([0.0; 3]),
+                            tangent: [0.0; 3],
+                            color: colors[v],
+                            tex_coord: vt.map(|it| tex_coords[it]).unwrap_or([0.0; 2]),
+                            tex_coord1: [0.0; 2],
+                        });
+                        builder.vertices.len() as u32 - 1
+                    });
+                    corners.push(index);
+                }
+                let group = match builder.groups.iter().position(|(it, _)| *it == material) {
+                    Some(group) => group,
+                    None => {
+                        builder.groups.push((material.clone(), Vec::new()));
+                        builder.groups.len() - 1
+                    }
+                };
+                let indices = &mut builder.groups[group].1;
+                for i in 1..corners.len().saturating_sub(1) {
+                    indices.extend([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            "usemtl" => material = Some(values.join(" ")),
+            "o" => {
+                if let Some((mesh, names)) = std::mem::take(&mut builder).finish() {
+                    meshes.push(mesh);
+                    used.push(names);
+                }
+            }
+            // Groups, smoothing groups, lines and the like
+            _ => {}
+        }
+    }
+    if let Some((mesh, names)) = builder.finish() {
+        meshes.push(mesh);
+        used.push(names);
+    }
+    Ok((meshes, used))
+}
+
+/// A texture statement, `-o` and `-s` become the texture transform
+struct MtlMap {
+    file: String,
+    offset: Vec2,
+    scale: Vec2,
+}
+
+impl MtlMap {
+    fn transform(map: &Option<MtlMap>) -> TextureTransform {
+        let Some(map) = map else {
+            return TextureTransform::default();
+        };
+        // `vt` were flipped, so the V offset is measured from the other side
+        TextureTransform {
+            offset: Vec2::new(map.offset.x, 1.0 - map.offset.y - map.scale.y),
+            scale: map.scale,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Default)]
+struct MtlMaterial {
+    diffuse: Option<[f32; 3]>,
+    diffuse_map: Option<MtlMap>,
+    normal_map: Option<MtlMap>,
+    emissive: Option<[f32; 3]>,
+    emissive_map: Option<MtlMap>,
+    shininess: Option<f32>,
+    /// `Pr` and `Pm` of the PBR extension
+    roughness: Option<f32>,
+    metallic: Option<f32>,
+    ior: Option<f32>,
+}
+
+impl MtlMaterial {
+    fn to_gltf(&self) -> GltfMaterial {
+        // Blinn-Phong exponent to a Beckmann roughness of about the same highlight
+        let roughness = self
+            .roughness
+            .or(self
+                .shininess
+                .map(|ns| (2.0 / (ns.max(0.0) + 2.0)).sqrt().sqrt()))
+            .unwrap_or(1.0);
+        let extensions = MaterialExtensions {
+            ior: self.ior.unwrap_or(1.5),
+            ..Default::default()
+        };
+        let emissive = Vec3::from(self.emissive.unwrap_or([0.0; 3]));
+        GltfMaterial {
+            roughness,
+            metallic: self.metallic.unwrap_or(0.0),
+            reflectance: extensions.reflectance(),
+            // The emissive map is multiplied by the color, `Ke` defaults to white with a map
+            emissive: match (self.emissive, &self.emissive_map) {
+                (None, Some(_)) => Vec3::one(),
+                _ => emissive,
+            },
+            base_color_transform: MtlMap::transform(&self.diffuse_map),
+            normal_transform: MtlMap::transform(&self.normal_map),
+            emissive_transform: MtlMap::transform(&self.emissive_map),
+            extensions,
+            ..Default::default()
+        }
+    }
+}
+
+/// Texture files are made relative to the OBJ through `dir`, the directory of the MTL.
+fn parse_mtl(text: &str, dir: &str) -> HashMap<String, MtlMaterial> {
+    let mut materials = HashMap::new();
+    let mut name = None;
+    for line in text.lines() {
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let values = tokens.collect::<Vec<_>>();
+        if keyword == "newmtl" {
+            let new = values.join(" ");
+            materials.insert(new.clone(), MtlMaterial::default());
+            name = Some(new);
+            continue;
+        }
+        let Some(mtl) = name.as_ref().and_then(|it| materials.get_mut(it)) else {
+            continue;
+        };
+        let float = |i: usize| values.get(i).and_then(|it| it.parse::<f32>().ok());
+        let color = || Some([float(0)?, float(1).or(float(0))?, float(2).or(float(0))?]);
+        let map = || parse_map(&values, dir);
+        match keyword {
+            "Kd" => mtl.diffuse = color(),
+            "Ke" => mtl.emissive = color(),
+            "Ns" => mtl.shininess = float(0),
+            "Pr" => mtl.roughness = float(0),
+            "Pm" => mtl.metallic = float(0),
+            "Ni" => mtl.ior = float(0),
+            "map_Kd" => mtl.diffuse_map = map(),
+            "map_Ke" => mtl.emissive_map = map(),
+            // Tangent space normal maps are commonly exported as bump maps
+            "norm" | "map_Bump" | "map_bump" | "bump" => mtl.normal_map = map(),
+            _ => {}
+        }
+    }
+    materials
+}
+
+fn parse_map(values: &[&str], dir: &str) -> Option<MtlMap> {
+    let mut map = MtlMap {
+        file: String::new(),
+        offset: Vec2::zero(),
+        scale: Vec2::one(),
+    };
+    let mut i = 0;
+    while let Some(option) = values.get(i).and_then(|it| it.strip_prefix('-')) {
+        i += 1;
+        let numbers = values[i..]
+            .iter()
+            .take(3)
+            .map_while(|it| it.parse::<f32>().ok())
+            .collect::<Vec<_>>();
+        match option {
+            "o" | "s" | "t" => {
+                i += numbers.len();
+                let uv = |default: f32| {
+                    Vec2::new(
+                        numbers.first().copied().unwrap_or(default),
+                        numbers.get(1).copied().unwrap_or(default),
+                    )
+                };
+                match option {
+                    "o" => map.offset = uv(0.0),
+                    "s" => map.scale = uv(1.0),
+                    _ => {}
+                }
+            }
+            "mm" => i += 2,
+            // `-blendu on`, `-bm 1.0`, `-imfchan l` and the like take one value
+            _ => i += 1,
+        }
+    }
+    let file = values.get(i..)?.join(" ");
+    (!file.is_empty()).then(|| {
+        map.file = source::join(dir, &file.replace('\\', "/"));
+        map
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_obj() {
+        let obj = "
+            v 0 0 0
+            v 1 0 0
+            v 1 1 0
+            v 0 1 0
+            vt 0 0
+            vt 1 0
+            vt 1 1
+            vt 0 1
+            usemtl red
+            f 1/1 2/2 3/3 4/4
+            o second
+            usemtl blue
+            f -4 -3 -2
+            usemtl red
+            f -4 -2 -1
+        ";
+        let (meshes, used) = parse_obj(obj).unwrap();
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(meshes[0].vertices.len(), 4);
+        assert_eq!(meshes[0].vertices[2].tex_coord, [1.0, 0.0]);
+        assert_eq!(meshes[1].primitives.len(), 2);
+        assert_eq!(meshes[1].indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(used[1], vec![Some("blue".into()), Some("red".into())]);
+        assert!(parse_obj("v 0 0 0\nf 1 2 3").is_err());
+
+        let mut mesh = meshes.into_iter().next().unwrap();
+        mesh.generate_normals();
+        mesh.generate_tangents();
+        assert_eq!(mesh.vertices[0].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(mesh.vertices[0].tangent, [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_parse_mtl() {
+        let mtl = "
+            newmtl brick
+            Kd 0.5 0.25 0
+            Ns 0
+            map_Kd -s 2 2 1 -o 0.5 0 textures/brick wall.png
+            map_Bump -bm 0.5 brick_n.png
+        ";
+        let materials = parse_mtl(mtl, "models");
+        let brick = &materials["brick"];
+        assert_eq!(brick.diffuse, Some([0.5, 0.25, 0.0]));
+        let map = brick.diffuse_map.as_ref().unwrap();
+        assert_eq!(map.file, "models/textures/brick wall.png");
+        assert_eq!(map.scale, Vec2::new(2.0, 2.0));
+        assert_eq!(map.offset, Vec2::new(0.5, 0.0));
+        assert_eq!(
+            brick.normal_map.as_ref().unwrap().file,
+            "models/brick_n.png"
+        );
+        assert_eq!(brick.to_gltf().roughness, 1.0);
+    }
+}
@@ -0,0 +1,390 @@
+//! Stanford PLY, ASCII or binary, as a single mesh with one primitive.
+//!
+//! Reads positions, normals, UVs and colors of the vertices and triangulates the faces.
+//! Per corner `texcoord` lists and a `comment TextureFile` are understood as MeshLab writes them.
+
+use std::collections::HashMap;
+
+use anyhow::*;
+
+use crate::render::{self, material::pbr::GltfMaterial, Primitive, Vertex};
+
+use super::{
+    load::{decode_image, DecodedModel, PrimitiveTextures, TextureSlot},
+    AssetPath,
+};
+
+pub(super) fn decode(path: &AssetPath, source: &[u8]) -> Result<DecodedModel> {
+    let Ply { mut mesh, texture } = parse(source)?;
+    mesh.generate_normals();
+    mesh.generate_tangents();
+
+    let mut slots = PrimitiveTextures::default();
+    let mut images = Vec::new();
+    let mut uris = Vec::new();
+    if let Some(uri) = texture {
+        images.push(path.sibling(&uri).read().and_then(decode_image));
+        slots.base_color = Some(TextureSlot {
+            sources: vec![0],
+            sampler: wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::Repeat,
+                address_mode_v: wgpu::AddressMode::Repeat,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            },
+        });
+        mesh.primitives[0].material = Some(GltfMaterial::default());
+        uris.push(uri);
+    }
+    let dependencies = uris
+        .iter()
+        .map(|uri| path.sibling(uri).final_path())
+        .collect();
+    Ok(DecodedModel {
+        meshes: vec![mesh],
+        textures: vec![vec![slots]],
+        images,
+        uris,
+        dependencies,
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => bail!("Unknown PLY type {}", name),
+        })
+    }
+
+    /// Integer colors are scaled to 0..1 by the largest value of their type
+    fn max(&self) -> f64 {
+        match self {
+            Scalar::U8 => u8::MAX as f64,
+            Scalar::U16 => u16::MAX as f64,
+            _ => 1.0,
+        }
+    }
+}
+
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar),
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+/// The values after the header
+struct Body<'a> {
+    format: Format,
+    data: &'a [u8],
+    at: usize,
+}
+
+impl Body<'_> {
+    fn read(&mut self, scalar: Scalar) -> Result<f64> {
+        if self.format == Format::Ascii {
+            let rest = &self.data[self.at..];
+            let start = rest
+                .iter()
+                .position(|it| !it.is_ascii_whitespace())
+                .context("PLY body is truncated")?;
+            let len = rest[start..]
+                .iter()
+                .position(|it| it.is_ascii_whitespace())
+                .unwrap_or(rest.len() - start);
+            self.at += start + len;
+            let token = std::str::from_utf8(&rest[start..start + len])?;
+            return Ok(token.parse::<f64>()?);
+        }
+        let size = match scalar {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        };
+        let bytes = self
+            .data
+            .get(self.at..self.at + size)
+            .context("PLY body is truncated")?;
+        self.at += size;
+        let mut array = [0; 8];
+        array[..size].copy_from_slice(bytes);
+        if self.format == Format::BigEndian {
+            array[..size].reverse();
+        }
+        Ok(match scalar {
+            Scalar::I8 => array[0] as i8 as f64,
+            Scalar::U8 => array[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([array[0], array[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([array[0], array[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes(array[..4].try_into().unwrap()) as f64,
+            Scalar::U32 => u32::from_le_bytes(array[..4].try_into().unwrap()) as f64,
+            Scalar::F32 => f32::from_le_bytes(array[..4].try_into().unwrap()) as f64,
+            Scalar::F64 => f64::from_le_bytes(array),
+        })
+    }
+}
+
+struct Ply {
+    mesh: render::Mesh,
+    /// Relative to the PLY file
+    texture: Option<String>,
+}
+
+fn parse(source: &[u8]) -> Result<Ply> {
+    const END: &[u8] = b"end_header";
+    let end = source
+        .windows(END.len())
+        .position(|it| it == END)
+        .context("No PLY header")?;
+    let body_start = source[end..]
+        .iter()
+        .position(|&it| it == b'\n')
+        .map(|it| end + it + 1)
+        .unwrap_or(source.len());
+    let header = String::from_utf8_lossy(&source[..end]);
+    let mut lines = header.lines().map(str::trim);
+    ensure!(lines.next() == Some("ply"), "Not a PLY file");
+
+    let mut format = None;
+    let mut texture = None;
+    let mut elements = Vec::<Element>::new();
+    for line in lines {
+        let tokens = line.split_whitespace().collect::<Vec<_>>();
+        match tokens.as_slice() {
+            ["format", name, ..] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => bail!("Unknown PLY format {}", name),
+                })
+            }
+            ["comment", "TextureFile", file @ ..] => texture = Some(file.join(" ")),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse()?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => elements
+                .last_mut()
+                .context("PLY property outside of an element")?
+                .properties
+                .push(Property::List(
+                    name.to_string(),
+                    Scalar::parse(count)?,
+                    Scalar::parse(item)?,
+                )),
+            ["property", scalar, name] => elements
+                .last_mut()
+                .context("PLY property outside of an element")?
+                .properties
+                .push(Property::Scalar(name.to_string(), Scalar::parse(scalar)?)),
+            _ => {}
+        }
+    }
+    let mut body = Body {
+        format: format.context("PLY format is missing")?,
+        data: &source[body_start..],
+        at: 0,
+    };
+
+    let mut vertices = Vec::<Vertex>::new();
+    let mut indices = Vec::<u32>::new();
+    // Corners with their own UVs get their own vertex
+    let mut split = HashMap::<(u32, [u32; 2]), u32>::new();
+    for element in elements.iter() {
+        for _ in 0..element.count {
+            let mut scalars = HashMap::<&str, f64>::new();
+            let mut lists = HashMap::<&str, Vec<f64>>::new();
+            for property in element.properties.iter() {
+                match property {
+                    Property::Scalar(name, scalar) => {
+                        let value = body.read(*scalar)?;
+                        // Colors are the only values that get scaled
+                        let value = match name.as_str() {
+                            "red" | "green" | "blue" | "alpha" => value / scalar.max(),
+                            _ => value,
+                        };
+                        scalars.insert(name, value);
+                    }
+                    Property::List(name, count, item) => {
+                        let count = body.read(*count)? as usize;
+                        let values = (0..count)
+                            .map(|_| body.read(*item))
+                            .collect::<Result<Vec<_>>>()?;
+                        lists.insert(name, values);
+                    }
+                }
+            }
+            match element.name.as_str() {
+                "vertex" => {
+                    let get = |names: &[&str]| names.iter().find_map(|it| scalars.get(it).copied());
+                    let value = |name: &str| get(&[name]).unwrap_or(0.0) as f32;
+                    let u = get(&["u", "s", "texture_u"]).unwrap_or(0.0) as f32;
+                    let v = get(&["v", "t", "texture_v"]).unwrap_or(0.0) as f32;
+                    let color = match get(&["red"]) {
+                        Some(_) => [
+                            value("red"),
+                            value("green"),
+                            value("blue"),
+                            get(&["alpha"]).unwrap_or(1.0) as f32,
+                        ],
+                        None => [0.0; 4],
+                    };
+                    vertices.push(Vertex {
+                        position: [value("x"), value("y"), value("z")],
+                        normal: [value("nx"), value("ny"), value("nz")],
+                        tangent: [0.0; 3],
+                        color,
+                        // V points up like in OBJ
+                        tex_coord: [u, 1.0 - v],
+                        tex_coord1: [0.0; 2],
+                    });
+                }
+                "face" => {
+                    let corners = lists
+                        .get("vertex_indices")
+                        .or(lists.get("vertex_index"))
+                        .context("PLY face without vertex indices")?;
+                    let tex_coords = lists.get("texcoord");
+                    let mut face = Vec::new();
+                    for (i, &index) in corners.iter().enumerate() {
+                        let index = index as u32;
+                        ensure!((index as usize) < vertices.len(), "PLY index out of range");
+                        let corner = match tex_coords {
+                            Some(uvs) if uvs.len() >= corners.len() * 2 => {
+                                let uv = [uvs[i * 2] as f32, 1.0 - uvs[i * 2 + 1] as f32];
+                                *split
+                                    .entry((index, uv.map(f32::to_bits)))
+                                    .or_insert_with(|| {
+                                        let mut vertex = vertices[index as usize];
+                                        vertex.tex_coord = uv;
+                                        vertices.push(vertex);
+                                        vertices.len() as u32 - 1
+                                    })
+                            }
+                            _ => index,
+                        };
+                        face.push(corner);
+                    }
+                    for i in 1..face.len().saturating_sub(1) {
+                        indices.extend([face[0], face[i], face[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let indices_num = indices.len() as u32;
+    Ok(Ply {
+        mesh: render::Mesh {
+            vertices,
+            indices,
+            primitives: vec![Primitive {
+                indices_start: 0,
+                indices_num,
+                material: None,
+            }],
+        },
+        texture,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ply() {
+        let ascii = b"ply
+format ascii 1.0
+comment TextureFile quad.png
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 255 0 0
+1 0 0 255 0 0
+1 1 0 255 0 0
+0 1 0 255 0 0
+4 0 1 2 3
+";
+        let ply = parse(ascii).unwrap();
+        assert_eq!(ply.texture.as_deref(), Some("quad.png"));
+        assert_eq!(ply.mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+        assert_eq!(ply.mesh.vertices[3].color, [1.0, 0.0, 0.0, 1.0]);
+
+        let mut binary = b"ply
+format binary_big_endian 1.0
+element vertex 3
+property float x
+property float y
+property float z
+element face 1
+property list uchar uint vertex_indices
+property list uchar float texcoord
+end_header
+"
+        .to_vec();
+        for position in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+            for value in position {
+                binary.extend(value.to_be_bytes());
+            }
+        }
+        binary.push(3);
+        for index in [0u32, 1, 2] {
+            binary.extend(index.to_be_bytes());
+        }
+        binary.push(6);
+        for uv in [0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0] {
+            binary.extend(uv.to_be_bytes());
+        }
+        let mut mesh = parse(&binary).unwrap().mesh;
+        assert_eq!(mesh.indices, vec![3, 4, 5]);
+        assert_eq!(mesh.vertices[5].tex_coord, [0.0, 0.0]);
+        mesh.generate_normals();
+        assert_eq!(mesh.vertices[4].normal, [0.0, 0.0, 1.0]);
+        assert!(parse(&binary[..binary.len() - 1]).is_err());
+    }
+}
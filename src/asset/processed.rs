@@ -0,0 +1,665 @@
+//! Processed models, cached so that launches skip parsing glTF and decoding PNG/JPEG.
+//!
+//! A processed model is one file under [`cache_dir`]:
+//! - header: magic, [`VERSION`], the source key, the URIs the key covers, body length and hash
+//! - body: images, then per mesh its bounds, interleaved [`Vertex`] data, indices and primitives
+//!   with their material factors and texture slots
+//!
+//! The source key hashes the model file, its external buffers and images, and the import
+//! settings. A different key means the cache is stale, a body that doesn't match its hash or
+//! doesn't parse means it is corrupt. Either way the model is processed again.
+
+use std::{
+    env,
+    fmt::Debug,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::*;
+use wgpu::{AddressMode, FilterMode};
+
+use crate::{
+    cgmath_ext::{Vec2, Vec3},
+    render::{
+        self,
+        material::pbr::{GltfMaterial, MaterialExtensions, TextureTransform},
+        Model, Primitive, Vertex,
+    },
+};
+
+use super::{
+    load::{AsyncLoadable, DecodedModel, GltfImage, PrimitiveTextures, TextureSlot},
+    source::AssetSources,
+    AssetPath,
+};
+
+const MAGIC: &[u8; 8] = b"PBRMODEL";
+/// Bumped whenever the layout of the body or the way models are decoded changes
+const VERSION: u32 = 1;
+
+/// `$WGPU_PBR_ASSET_CACHE`, or `.asset_cache` next to the asset root.
+pub fn cache_dir() -> PathBuf {
+    match env::var_os("WGPU_PBR_ASSET_CACHE") {
+        Some(dir) => dir.into(),
+        None => AssetSources::root().with_file_name(".asset_cache"),
+    }
+}
+
+fn cache_file(path: &AssetPath) -> PathBuf {
+    let final_path = path.final_path();
+    let stem = Path::new(&final_path)
+        .file_stem()
+        .map(|it| it.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let id = blake3::hash(final_path.as_bytes()).to_hex();
+    cache_dir().join(format!("{}-{}.model", stem, &id[..16]))
+}
+
+fn source_key(
+    path: &AssetPath,
+    source: &[u8],
+    uris: &[String],
+    settings: &impl Debug,
+) -> Result<[u8; 32]> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&VERSION.to_le_bytes());
+    hasher.update(format!("{:?}", settings).as_bytes());
+    hasher.update(source);
+    for uri in uris {
+        let bytes = path.sibling(uri).read()?;
+        hasher.update(&(bytes.len() as u64).to_le_bytes());
+        hasher.update(&bytes);
+    }
+    Ok(*hasher.finalize().as_bytes())
+}
+
+/// The processed model of `path`, `None` if there is none or it is stale.
+pub(super) fn read_model(
+    path: &AssetPath,
+    source: &[u8],
+    settings: &impl Debug,
+) -> Result<Option<DecodedModel>> {
+    let Result::Ok(file) = fs::read(cache_file(path)) else {
+        return Ok(None);
+    };
+    let mut reader = Reader::new(&file);
+    ensure!(reader.take(MAGIC.len())? == MAGIC, "Not a processed model");
+    if reader.u32()? != VERSION {
+        return Ok(None);
+    }
+    let key = reader.take(32)?;
+    let uris = (0..reader.u32()?)
+        .map(|_| reader.string())
+        .collect::<Result<Vec<_>>>()?;
+    // A missing dependency is reported by decoding the model again
+    match source_key(path, source, &uris, settings) {
+        Result::Ok(current) if current == key => {}
+        _ => {
+            log::info!("Processed {} is stale", path.final_path());
+            return Ok(None);
+        }
+    }
+    let len = reader.u64()? as usize;
+    let hash = reader.take(32)?;
+    let body = reader.take(len)?;
+    ensure!(
+        blake3::hash(body).as_bytes() == hash,
+        "Processed model is corrupt"
+    );
+
+    let mut decoded = read_body(&mut Reader::new(body))?;
+    decoded.dependencies = uris
+        .iter()
+        .map(|uri| path.sibling(uri).final_path())
+        .collect();
+    decoded.uris = uris;
+    Ok(Some(decoded))
+}
+
+/// Writes the processed model of `path` through a temporary file, so readers never see half of it.
+pub(super) fn write_model(
+    path: &AssetPath,
+    source: &[u8],
+    settings: &impl Debug,
+    decoded: &DecodedModel,
+) -> Result<()> {
+    let mut body = Writer::default();
+    write_body(&mut body, decoded);
+
+    let mut file = Writer::default();
+    file.bytes(MAGIC);
+    file.u32(VERSION);
+    file.bytes(&source_key(path, source, &decoded.uris, settings)?);
+    file.u32(decoded.uris.len() as u32);
+    for uri in decoded.uris.iter() {
+        file.string(uri);
+    }
+    file.u64(body.0.len() as u64);
+    file.bytes(blake3::hash(&body.0).as_bytes());
+    file.bytes(&body.0);
+
+    let target = cache_file(path);
+    fs::create_dir_all(cache_dir())?;
+    let temporary = target.with_extension("tmp");
+    fs::write(&temporary, &file.0)?;
+    fs::rename(&temporary, &target)?;
+    Ok(())
+}
+
+/// Processes every glTF, OBJ and PLY model under the asset root ahead of time, returns how many are cached.
+pub fn process_models() -> usize {
+    let root = AssetSources::root();
+    let mut models = Vec::new();
+    find_models(&root, &root, &mut models);
+    let mut processed = 0;
+    for path in models {
+        match Model::decode(&path, &()) {
+            Result::Ok(_) => processed += 1,
+            Err(err) => log::error!("Failed to process {}: {:#}", path.final_path(), err),
+        }
+    }
+    processed
+}
+
+fn find_models(root: &Path, dir: &Path, out: &mut Vec<AssetPath>) {
+    let Result::Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_models(root, &path, out);
+        } else if matches!(
+            path.extension().and_then(|it| it.to_str()),
+            Some("gltf" | "glb" | "obj" | "ply")
+        ) {
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            out.push(AssetPath::Assets(
+                relative.to_string_lossy().replace('\\', "/"),
+            ));
+        }
+    }
+}
+
+fn write_body(w: &mut Writer, decoded: &DecodedModel) {
+    w.u32(decoded.images.len() as u32);
+    for image in decoded.images.iter() {
+        match image {
+            Result::Ok(GltfImage::Decoded(image)) => {
+                w.u8(1);
+                w.u32(image.width());
+                w.u32(image.height());
+                w.blob(image.as_raw());
+            }
+            Result::Ok(GltfImage::Compressed(container)) => {
+                w.u8(2);
+                w.blob(container);
+            }
+            Err(err) => {
+                w.u8(0);
+                w.string(&format!("{:#}", err));
+            }
+        }
+    }
+
+    w.u32(decoded.meshes.len() as u32);
+    for (mesh, textures) in decoded.meshes.iter().zip(decoded.textures.iter()) {
+        // Lets tools look at the extent of a mesh without reading its vertices
+        let (min, max) = mesh
+            .bounds()
+            .unwrap_or((Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0)));
+        w.vec3(min);
+        w.vec3(max);
+        w.u32(mesh.vertices.len() as u32);
+        w.bytes(bytemuck::cast_slice(&mesh.vertices));
+        w.u32(mesh.indices.len() as u32);
+        w.bytes(bytemuck::cast_slice(&mesh.indices));
+        w.u32(mesh.primitives.len() as u32);
+        for (primitive, slots) in mesh.primitives.iter().zip(textures.iter()) {
+            w.u32(primitive.indices_start);
+            w.u32(primitive.indices_num);
+            match &primitive.material {
+                Some(material) => {
+                    w.u8(1);
+                    write_material(w, material);
+                }
+                None => w.u8(0),
+            }
+            for slot in [&slots.base_color, &slots.normal, &slots.emissive] {
+                match slot {
+                    Some(slot) => {
+                        w.u8(1);
+                        write_slot(w, slot);
+                    }
+                    None => w.u8(0),
+                }
+            }
+        }
+    }
+}
+
+fn read_body(r: &mut Reader) -> Result<DecodedModel> {
+    let images = (0..r.u32()?)
+        .map(|_| {
+            Ok(match r.u8()? {
+                0 => Err(anyhow!("{}", r.string()?)),
+                1 => {
+                    let width = r.u32()?;
+                    let height = r.u32()?;
+                    let image = image::RgbaImage::from_raw(width, height, r.blob()?)
+                        .context("Image is shorter than its size")?;
+                    Result::Ok(GltfImage::Decoded(image))
+                }
+                2 => Result::Ok(GltfImage::Compressed(r.blob()?)),
+                tag => bail!("Unknown image tag {}", tag),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut meshes = Vec::new();
+    let mut textures = Vec::new();
+    for _ in 0..r.u32()? {
+        let _bounds = (r.vec3()?, r.vec3()?);
+        let count = r.u32()? as usize;
+        let vertices: Vec<Vertex> =
+            bytemuck::pod_collect_to_vec(r.take(count * size_of::<Vertex>())?);
+        let count = r.u32()? as usize;
+        let indices: Vec<u32> = bytemuck::pod_collect_to_vec(r.take(count * size_of::<u32>())?);
+        ensure!(
+            indices.iter().all(|&it| (it as usize) < vertices.len()),
+            "Index out of the vertices"
+        );
+        let mut primitives = Vec::new();
+        let mut mesh_textures = Vec::new();
+        for _ in 0..r.u32()? {
+            let indices_start = r.u32()?;
+            let indices_num = r.u32()?;
+            ensure!(
+                indices_start as usize + indices_num as usize <= indices.len(),
+                "Primitive out of the indices"
+            );
+            let material = match r.u8()? {
+                0 => None,
+                _ => Some(read_material(r)?),
+            };
+            let mut slot = || -> Result<Option<TextureSlot>> {
+                Ok(match r.u8()? {
+                    0 => None,
+                    _ => Some(read_slot(r, images.len())?),
+                })
+            };
+            mesh_textures.push(PrimitiveTextures {
+                base_color: slot()?,
+                normal: slot()?,
+                emissive: slot()?,
+            });
+            primitives.push(Primitive {
+                indices_start,
+                indices_num,
+                material,
+            });
+        }
+        meshes.push(render::Mesh {
+            vertices,
+            indices,
+            primitives,
+        });
+        textures.push(mesh_textures);
+    }
+    ensure!(r.is_empty(), "Trailing bytes");
+    Ok(DecodedModel {
+        meshes,
+        textures,
+        images,
+        uris: Vec::new(),
+        dependencies: Vec::new(),
+    })
+}
+
+fn write_material(w: &mut Writer, material: &GltfMaterial) {
+    w.f32(material.roughness);
+    w.f32(material.metallic);
+    w.f32(material.reflectance);
+    w.vec3(material.emissive);
+    w.f32(material.emissive_strength);
+    for transform in [
+        &material.base_color_transform,
+        &material.normal_transform,
+        &material.emissive_transform,
+    ] {
+        w.u32(transform.tex_coord);
+        w.vec2(transform.offset);
+        w.f32(transform.rotation);
+        w.vec2(transform.scale);
+    }
+    let ext = &material.extensions;
+    w.f32(ext.ior);
+    w.f32(ext.specular);
+    w.vec3(ext.specular_color);
+    w.vec3(ext.sheen_color);
+    w.f32(ext.sheen_roughness);
+    w.f32(ext.transmission);
+    w.f32(ext.thickness);
+    w.f32(ext.attenuation_distance);
+    w.vec3(ext.attenuation_color);
+}
+
+fn read_material(r: &mut Reader) -> Result<GltfMaterial> {
+    let roughness = r.f32()?;
+    let metallic = r.f32()?;
+    let reflectance = r.f32()?;
+    let emissive = r.vec3()?;
+    let emissive_strength = r.f32()?;
+    let mut transform = || -> Result<TextureTransform> {
+        Ok(TextureTransform {
+            tex_coord: r.u32()?,
+            offset: r.vec2()?,
+            rotation: r.f32()?,
+            scale: r.vec2()?,
+        })
+    };
+    let base_color_transform = transform()?;
+    let normal_transform = transform()?;
+    let emissive_transform = transform()?;
+    let extensions = MaterialExtensions {
+        ior: r.f32()?,
+        specular: r.f32()?,
+        specular_color: r.vec3()?,
+        sheen_color: r.vec3()?,
+        sheen_roughness: r.f32()?,
+        transmission: r.f32()?,
+        thickness: r.f32()?,
+        attenuation_distance: r.f32()?,
+        attenuation_color: r.vec3()?,
+    };
+    Ok(GltfMaterial {
+        roughness,
+        metallic,
+        reflectance,
+        emissive,
+        emissive_strength,
+        base_color_transform,
+        normal_transform,
+        emissive_transform,
+        extensions,
+        ..Default::default()
+    })
+}
+
+fn write_slot(w: &mut Writer, slot: &TextureSlot) {
+    w.u32(slot.sources.len() as u32);
+    for &source in slot.sources.iter() {
+        w.u32(source as u32);
+    }
+    let sampler = &slot.sampler;
+    for mode in [
+        sampler.address_mode_u,
+        sampler.address_mode_v,
+        sampler.address_mode_w,
+    ] {
+        w.u8(match mode {
+            AddressMode::ClampToEdge => 0,
+            AddressMode::Repeat => 1,
+            AddressMode::MirrorRepeat => 2,
+            AddressMode::ClampToBorder => 3,
+        });
+    }
+    for filter in [
+        sampler.mag_filter,
+        sampler.min_filter,
+        sampler.mipmap_filter,
+    ] {
+        w.u8(match filter {
+            FilterMode::Nearest => 0,
+            FilterMode::Linear => 1,
+        });
+    }
+}
+
+fn read_slot(r: &mut Reader, image_count: usize) -> Result<TextureSlot> {
+    let sources = (0..r.u32()?)
+        .map(|_| {
+            let source = r.u32()? as usize;
+            ensure!(source < image_count, "Texture source out of the images");
+            Ok(source)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut address_mode = || -> Result<AddressMode> {
+        Ok(match r.u8()? {
+            0 => AddressMode::ClampToEdge,
+            1 => AddressMode::Repeat,
+            2 => AddressMode::MirrorRepeat,
+            3 => AddressMode::ClampToBorder,
+            mode => bail!("Unknown address mode {}", mode),
+        })
+    };
+    let (address_mode_u, address_mode_v, address_mode_w) =
+        (address_mode()?, address_mode()?, address_mode()?);
+    let mut filter = || -> Result<FilterMode> {
+        Ok(match r.u8()? {
+            0 => FilterMode::Nearest,
+            1 => FilterMode::Linear,
+            filter => bail!("Unknown filter mode {}", filter),
+        })
+    };
+    let (mag_filter, min_filter, mipmap_filter) = (filter()?, filter()?, filter()?);
+    Ok(TextureSlot {
+        sources,
+        sampler: wgpu::SamplerDescriptor {
+            address_mode_u,
+            address_mode_v,
+            address_mode_w,
+            mag_filter,
+            min_filter,
+            mipmap_filter,
+            ..Default::default()
+        },
+    })
+}
+
+/// Little endian, except vertices and indices which are copied as they are in memory.
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn vec2(&mut self, value: Vec2) {
+        self.f32(value.x);
+        self.f32(value.y);
+    }
+
+    fn vec3(&mut self, value: Vec3) {
+        self.f32(value.x);
+        self.f32(value.y);
+        self.f32(value.z);
+    }
+
+    /// Length prefixed
+    fn blob(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.bytes(bytes);
+    }
+
+    fn string(&mut self, value: &str) {
+        self.blob(value.as_bytes());
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, at: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.at == self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .at
+            .checked_add(len)
+            .and_then(|end| self.data.get(self.at..end))
+            .context("Processed model is truncated")?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn vec2(&mut self) -> Result<Vec2> {
+        Ok(Vec2::new(self.f32()?, self.f32()?))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn blob(&mut self) -> Result<Vec<u8>> {
+        let len = self.u64()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn string(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.blob()?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> DecodedModel {
+        let vertex = |x: f32| Vertex {
+            position: [x, 1.0, -x],
+            normal: [0.0, 1.0, 0.0],
+            tangent: [1.0, 0.0, 0.0],
+            color: [1.0; 4],
+            tex_coord: [x, 0.0],
+            tex_coord1: [0.0, x],
+        };
+        let slot = TextureSlot {
+            sources: vec![1, 0],
+            sampler: wgpu::SamplerDescriptor {
+                address_mode_u: AddressMode::MirrorRepeat,
+                mag_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+        };
+        DecodedModel {
+            meshes: vec![render::Mesh {
+                vertices: vec![vertex(0.0), vertex(1.0), vertex(2.0)],
+                indices: vec![0, 1, 2],
+                primitives: vec![Primitive {
+                    indices_start: 0,
+                    indices_num: 3,
+                    material: Some(GltfMaterial {
+                        roughness: 0.25,
+                        emissive: Vec3::new(1.0, 2.0, 3.0),
+                        ..Default::default()
+                    }),
+                }],
+            }],
+            textures: vec![vec![PrimitiveTextures {
+                normal: Some(slot),
+                ..Default::default()
+            }]],
+            images: vec![
+                Result::Ok(GltfImage::Decoded(image::RgbaImage::new(2, 1))),
+                Err(anyhow!("Missing")),
+            ],
+            uris: Vec::new(),
+            dependencies: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_body_round_trip() {
+        let mut writer = Writer::default();
+        write_body(&mut writer, &model());
+        let decoded = read_body(&mut Reader::new(&writer.0)).unwrap();
+
+        let mesh = &decoded.meshes[0];
+        assert_eq!(mesh.vertices[2].position, [2.0, 1.0, -2.0]);
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        let material = mesh.primitives[0].material.as_ref().unwrap();
+        assert_eq!(material.roughness, 0.25);
+        assert_eq!(material.emissive, Vec3::new(1.0, 2.0, 3.0));
+        let slot = decoded.textures[0][0].normal.as_ref().unwrap();
+        assert_eq!(slot.sources, vec![1, 0]);
+        assert_eq!(slot.sampler.address_mode_u, AddressMode::MirrorRepeat);
+        assert!(decoded.textures[0][0].base_color.is_none());
+        assert!(
+            matches!(&decoded.images[0], Result::Ok(GltfImage::Decoded(it)) if it.width() == 2)
+        );
+        assert!(decoded.images[1].is_err());
+
+        // Truncated or out of range data is rejected instead of read
+        assert!(read_body(&mut Reader::new(&writer.0[..writer.0.len() - 1])).is_err());
+        let mut bad_index = model();
+        bad_index.meshes[0].indices[2] = 3;
+        let mut writer = Writer::default();
+        write_body(&mut writer, &bad_index);
+        assert!(read_body(&mut Reader::new(&writer.0)).is_err());
+    }
+
+    #[test]
+    fn test_stale_and_corrupt() {
+        let dir = env::temp_dir().join(format!("processed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        env::set_var("WGPU_PBR_ASSET_CACHE", dir.join("cache"));
+        let path = AssetPath::Absolute(dir.join("model.gltf"));
+
+        write_model(&path, b"source", &(), &model()).unwrap();
+        assert!(read_model(&path, b"source", &()).unwrap().is_some());
+        assert!(read_model(&path, b"edited", &()).unwrap().is_none());
+        assert!(read_model(&path, b"source", &"other settings")
+            .unwrap()
+            .is_none());
+
+        let file = cache_file(&path);
+        let mut bytes = fs::read(&file).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&file, bytes).unwrap();
+        assert!(read_model(&path, b"source", &()).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
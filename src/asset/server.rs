@@ -0,0 +1,281 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::*;
+use bevy_ecs::prelude::*;
+
+use super::{load::AsyncLoadable, AssetPath, Assets, Handle};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed(String),
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+type UploadFn = Box<dyn FnOnce(&mut World) -> Result<()> + Send>;
+type ReloadFn = Arc<dyn Fn(&mut AssetServer) + Send + Sync>;
+
+struct Source {
+    path: String,
+    reload: ReloadFn,
+}
+
+/// Decodes assets on worker threads and uploads them on the main thread, see
+/// [`sys_upload_decoded_assets`]. Loaded assets end up in the `Assets<T>` resource.
+#[derive(Resource)]
+pub struct AssetServer {
+    jobs: Sender<Job>,
+    decoded_sender: Sender<(uuid::Uuid, Result<UploadFn>)>,
+    decoded: Mutex<Receiver<(uuid::Uuid, Result<UploadFn>)>>,
+    states: HashMap<uuid::Uuid, LoadState>,
+    /// Bumped by every successful upload, reloads included
+    versions: HashMap<uuid::Uuid, u32>,
+    sources: HashMap<uuid::Uuid, Source>,
+    /// Final path to the assets that are reloaded when it changes
+    watched: HashMap<String, HashSet<uuid::Uuid>>,
+    /// Main thread time spent on uploads per frame, one upload always runs
+    pub upload_budget: Duration,
+}
+
+impl Default for AssetServer {
+    fn default() -> Self {
+        let (jobs, job_receiver) = channel::<Job>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let workers = thread::available_parallelism().map_or(2, |it| it.get().clamp(1, 4));
+        for index in 0..workers {
+            let job_receiver = Arc::clone(&job_receiver);
+            thread::Builder::new()
+                .name(format!("Asset Worker {}", index))
+                .spawn(move || loop {
+                    // The lock is released before the job runs
+                    let job = job_receiver.lock().unwrap().recv();
+                    match job {
+                        Result::Ok(job) => job(),
+                        // The server is gone
+                        Err(_) => break,
+                    }
+                })
+                .unwrap();
+        }
+
+        let (decoded_sender, decoded) = channel();
+        Self {
+            jobs,
+            decoded_sender,
+            decoded: Mutex::new(decoded),
+            states: HashMap::new(),
+            versions: HashMap::new(),
+            sources: HashMap::new(),
+            watched: HashMap::new(),
+            upload_budget: Duration::from_millis(4),
+        }
+    }
+}
+
+impl AssetServer {
+    /// Returns at once, the asset is in `Assets<T>` when [`AssetServer::state`] is `Loaded`.
+    /// A path that is still referenced is not loaded again, its handle is returned instead.
+    pub fn load<T: AsyncLoadable>(
+        world: &mut World,
+        path: AssetPath,
+        settings: T::Settings,
+    ) -> Handle<T> {
+        let mut assets = world.get_resource_or_insert_with(Assets::<T>::new);
+        if let Some(handle) = assets.handle(&path) {
+            return handle;
+        }
+        let (handle, evicted) = assets.reserve(path.clone());
+        let mut server = world.resource_mut::<AssetServer>();
+        // Its entry was dropped before `sys_free_unused_assets` could see it
+        if let Some(evicted) = evicted {
+            server.forget(evicted.uuid);
+        }
+        server.start(handle.downgrade(), path, settings);
+        handle
+    }
+
+    fn start<T: AsyncLoadable>(
+        &mut self,
+        handle: Handle<T>,
+        path: AssetPath,
+        settings: T::Settings,
+    ) {
+        let final_path = path.final_path();
+        let reload: ReloadFn = {
+            let handle = handle.clone();
+            let path = path.clone();
+            let settings = settings.clone();
+            Arc::new(move |server: &mut AssetServer| {
+                server.load_into(handle.clone(), path.clone(), settings.clone())
+            })
+        };
+        self.sources.insert(
+            handle.uuid,
+            Source {
+                path: final_path.clone(),
+                reload,
+            },
+        );
+        self.watch(handle.uuid, vec![final_path]);
+        self.load_into(handle, path, settings);
+    }
+
+    /// Loads `path` again into `handle`, the old asset stays until the new one is uploaded.
+    fn load_into<T: AsyncLoadable>(
+        &mut self,
+        handle: Handle<T>,
+        path: AssetPath,
+        settings: T::Settings,
+    ) {
+        self.states.insert(handle.uuid, LoadState::Loading);
+        let sender = self.decoded_sender.clone();
+        self.jobs
+            .send(Box::new(move || {
+                let id = handle.uuid;
+                let name = path.final_path();
+                let result = T::decode(&path, &settings)
+                    .with_context(|| format!("Decoding {}", name))
+                    .map(|decoded| -> UploadFn {
+                        let dependencies = T::dependencies(&decoded);
+                        Box::new(move |world: &mut World| {
+                            let asset = T::upload(decoded, &settings, world)
+                                .with_context(|| format!("Uploading {}", name))?;
+                            // Does nothing if the asset was freed in the meantime
+                            world
+                                .get_resource_or_insert_with(Assets::<T>::new)
+                                .insert(&handle, Arc::new(asset));
+                            let mut watched = dependencies;
+                            watched.push(name);
+                            world.resource_mut::<AssetServer>().watch(id, watched);
+                            Ok(())
+                        })
+                    });
+                // The receiver only goes away with the world
+                let _ = sender.send((id, result));
+            }))
+            .unwrap();
+    }
+
+    fn watch(&mut self, id: uuid::Uuid, paths: Vec<String>) {
+        if !self.sources.contains_key(&id) {
+            return;
+        }
+        for ids in self.watched.values_mut() {
+            ids.remove(&id);
+        }
+        self.watched.retain(|_, ids| !ids.is_empty());
+        for path in paths {
+            self.watched.entry(path).or_default().insert(id);
+        }
+    }
+
+    /// Drops everything kept about an asset that was freed.
+    fn forget(&mut self, id: uuid::Uuid) {
+        self.states.remove(&id);
+        self.versions.remove(&id);
+        self.sources.remove(&id);
+        for ids in self.watched.values_mut() {
+            ids.remove(&id);
+        }
+        self.watched.retain(|_, ids| !ids.is_empty());
+    }
+
+    /// Reloads the assets that depend on the file at `final_path`, returns false if there are none.
+    pub fn reload(&mut self, final_path: &str) -> bool {
+        let reloads = self
+            .watched
+            .get(final_path)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| self.sources.get(id))
+            .map(|source| Arc::clone(&source.reload))
+            .collect::<Vec<_>>();
+        for reload in reloads.iter() {
+            reload(self);
+        }
+        !reloads.is_empty()
+    }
+
+    pub fn state<T>(&self, handle: &Handle<T>) -> Option<&LoadState> {
+        self.states.get(&handle.uuid)
+    }
+
+    /// Starts at 1 with the first upload, 0 while nothing has been uploaded.
+    pub fn version<T>(&self, handle: &Handle<T>) -> u32 {
+        self.versions.get(&handle.uuid).copied().unwrap_or(0)
+    }
+
+    /// Paths and errors of the assets whose last load failed
+    pub fn failures(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.states.iter().filter_map(|(id, state)| match state {
+            LoadState::Failed(err) => Some((
+                self.sources.get(id).map_or("", |it| it.path.as_str()),
+                err.as_str(),
+            )),
+            _ => None,
+        })
+    }
+}
+
+/// Uploads the assets decoded so far, until the frame's budget is used up.
+pub fn sys_upload_decoded_assets(world: &mut World) {
+    let start = Instant::now();
+    loop {
+        let (budget, next) = {
+            let server = world.resource::<AssetServer>();
+            let next = server.decoded.lock().unwrap().try_recv();
+            (server.upload_budget, next)
+        };
+        let Result::Ok((id, decoded)) = next else {
+            break;
+        };
+        let state = match decoded.and_then(|upload| upload(world)) {
+            Result::Ok(()) => LoadState::Loaded,
+            Err(err) => {
+                log::error!("Failed to load asset: {:?}", err);
+                LoadState::Failed(format!("{:#}", err))
+            }
+        };
+        let mut server = world.resource_mut::<AssetServer>();
+        // Unless it was freed while loading
+        if server.sources.contains_key(&id) {
+            if state == LoadState::Loaded {
+                *server.versions.entry(id).or_default() += 1;
+            }
+            server.states.insert(id, state);
+        }
+        if start.elapsed() >= budget {
+            break;
+        }
+    }
+}
+
+/// Frees the assets of `Assets<T>` that are no longer referenced by a strong handle.
+pub fn sys_free_unused_assets<T: Send + Sync + 'static>(world: &mut World) {
+    let Some(mut assets) = world.get_resource_mut::<Assets<T>>() else {
+        return;
+    };
+    let freed = assets.free_unused();
+    if freed.is_empty() {
+        return;
+    }
+    log::debug!(
+        "Freed {} unused {}",
+        freed.len(),
+        std::any::type_name::<T>()
+    );
+    if let Some(mut server) = world.get_resource_mut::<AssetServer>() {
+        for handle in freed {
+            server.forget(handle.uuid);
+        }
+    }
+}
@@ -0,0 +1,452 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::*;
+
+/// Where the bytes of assets come from. Paths are relative to the source and use `/`.
+pub trait AssetReader: Send + Sync {
+    fn read(&self, path: &str) -> Result<Vec<u8>>;
+    fn exists(&self, path: &str) -> bool;
+    /// Paths of the files directly in `dir`
+    fn read_dir(&self, dir: &str) -> Result<Vec<String>>;
+}
+
+/// Files under a directory, an empty root reads paths as they are.
+pub struct FileReader {
+    pub root: PathBuf,
+}
+
+impl AssetReader for FileReader {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let full = self.root.join(path);
+        fs::read(&full).with_context(|| format!("Reading {}", full.display()))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.root.join(path).is_file()
+    }
+
+    fn read_dir(&self, dir: &str) -> Result<Vec<String>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(self.root.join(dir))? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                let path = Path::new(dir).join(entry.file_name());
+                paths.push(path.to_string_lossy().replace('\\', "/"));
+            }
+        }
+        Ok(paths)
+    }
+}
+
+/// Files compiled into the binary, see [`embed_asset!`](crate::embed_asset).
+#[derive(Default)]
+pub struct EmbeddedReader {
+    files: HashMap<String, &'static [u8]>,
+}
+
+impl EmbeddedReader {
+    pub fn insert(&mut self, path: &str, bytes: &'static [u8]) {
+        self.files.insert(path.to_string(), bytes);
+    }
+}
+
+impl AssetReader for EmbeddedReader {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        self.files
+            .get(path)
+            .map(|it| it.to_vec())
+            .with_context(|| format!("{} is not embedded", path))
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn read_dir(&self, dir: &str) -> Result<Vec<String>> {
+        Ok(self
+            .files
+            .keys()
+            .filter(|it| parent(it) == dir.trim_end_matches('/'))
+            .cloned()
+            .collect())
+    }
+}
+
+struct ZipEntry {
+    method: u16,
+    compressed_size: usize,
+    size: usize,
+    local_header: usize,
+}
+
+/// A read-only `.zip` archive, entries are either stored or deflated.
+pub struct ZipReader {
+    data: Vec<u8>,
+    entries: HashMap<String, ZipEntry>,
+}
+
+impl ZipReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::from_bytes(fs::read(path)?).with_context(|| format!("Opening {}", path.display()))
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        let u16_at = |at: usize| -> Result<u16> {
+            let bytes = data.get(at..at + 2).context("Truncated zip")?;
+            Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        let u32_at = |at: usize| -> Result<u32> {
+            let bytes = data.get(at..at + 4).context("Truncated zip")?;
+            Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+
+        // The end of central directory record is followed by a comment of up to 64KiB
+        let end = (data.len().saturating_sub(22 + 0xFFFF)..=data.len().saturating_sub(22))
+            .rev()
+            .find(|&at| u32_at(at).is_ok_and(|it| it == 0x0605_4b50))
+            .context("Not a zip archive")?;
+        let count = u16_at(end + 10)? as usize;
+        let mut at = u32_at(end + 16)? as usize;
+
+        let mut entries = HashMap::new();
+        for _ in 0..count {
+            ensure!(u32_at(at)? == 0x0201_4b50, "Broken zip central directory");
+            let name_len = u16_at(at + 28)? as usize;
+            let extra_len = u16_at(at + 30)? as usize;
+            let comment_len = u16_at(at + 32)? as usize;
+            let name = data
+                .get(at + 46..at + 46 + name_len)
+                .context("Truncated zip")?;
+            let name = String::from_utf8_lossy(name).replace('\\', "/");
+            if !name.ends_with('/') {
+                entries.insert(
+                    name,
+                    ZipEntry {
+                        method: u16_at(at + 10)?,
+                        compressed_size: u32_at(at + 20)? as usize,
+                        size: u32_at(at + 24)? as usize,
+                        local_header: u32_at(at + 42)? as usize,
+                    },
+                );
+            }
+            at += 46 + name_len + extra_len + comment_len;
+        }
+        Ok(Self { data, entries })
+    }
+}
+
+impl AssetReader for ZipReader {
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let entry = self
+            .entries
+            .get(path)
+            .with_context(|| format!("{} is not in the archive", path))?;
+        let header = entry.local_header;
+        let field = |at: usize| -> Result<usize> {
+            let bytes = self.data.get(at..at + 2).context("Truncated zip")?;
+            Ok(u16::from_le_bytes(bytes.try_into().unwrap()) as usize)
+        };
+        let start = header + 30 + field(header + 26)? + field(header + 28)?;
+        let compressed = self
+            .data
+            .get(start..start + entry.compressed_size)
+            .context("Truncated zip")?;
+        let bytes = match entry.method {
+            0 => compressed.to_vec(),
+            8 => miniz_oxide::inflate::decompress_to_vec(compressed)
+                .map_err(|err| anyhow!("Inflating {}: {:?}", path, err.status))?,
+            method => bail!("{} uses the unsupported zip method {}", path, method),
+        };
+        ensure!(bytes.len() == entry.size, "{} has the wrong size", path);
+        Ok(bytes)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        self.entries.contains_key(path)
+    }
+
+    fn read_dir(&self, dir: &str) -> Result<Vec<String>> {
+        Ok(self
+            .entries
+            .keys()
+            .filter(|it| parent(it) == dir.trim_end_matches('/'))
+            .cloned()
+            .collect())
+    }
+}
+
+/// The readers behind [`AssetPath`](super::AssetPath), shared by every thread.
+pub struct AssetSources {
+    root: PathBuf,
+    /// Searched in order for `AssetPath::Assets`, the asset root first
+    assets: Vec<Arc<dyn AssetReader>>,
+    embedded: EmbeddedReader,
+}
+
+lazy_static::lazy_static! {
+    static ref SOURCES: RwLock<AssetSources> = RwLock::new(AssetSources::new());
+}
+
+impl AssetSources {
+    fn new() -> Self {
+        let root = default_root();
+        let mut sources = Self {
+            root: PathBuf::new(),
+            assets: Vec::new(),
+            embedded: EmbeddedReader::default(),
+        };
+        sources.set_root_inner(root);
+        // A build can ship `assets.zip` next to or instead of the folder
+        let pack = sources.root.with_extension("zip");
+        if pack.is_file() {
+            match ZipReader::open(&pack) {
+                Result::Ok(reader) => sources.assets.push(Arc::new(reader)),
+                Err(err) => log::error!("{:#}", err),
+            }
+        }
+        sources.embedded.insert(
+            "textures/white.png",
+            include_bytes!("../../assets/textures/white.png"),
+        );
+        sources.embedded.insert(
+            "textures/normal_default.png",
+            include_bytes!("../../assets/textures/normal_default.png"),
+        );
+        sources.embedded.insert(
+            "textures/missing.png",
+            include_bytes!("../../assets/textures/missing.png"),
+        );
+        sources
+    }
+
+    fn set_root_inner(&mut self, root: PathBuf) {
+        let reader: Arc<dyn AssetReader> = Arc::new(FileReader { root: root.clone() });
+        match self.assets.first_mut() {
+            Some(first) => *first = reader,
+            None => self.assets.push(reader),
+        }
+        self.root = root;
+    }
+
+    /// The directory `AssetPath::Assets` are relative to. Defaults to `$WGPU_PBR_ASSETS`, or
+    /// the first `assets` folder in the working directory or above the executable.
+    pub fn root() -> PathBuf {
+        SOURCES.read().unwrap().root.clone()
+    }
+
+    pub fn set_root(root: impl Into<PathBuf>) {
+        SOURCES.write().unwrap().set_root_inner(root.into());
+    }
+
+    /// Adds a source searched after the ones before it, e.g. a [`ZipReader`].
+    pub fn mount(reader: Arc<dyn AssetReader>) {
+        SOURCES.write().unwrap().assets.push(reader);
+    }
+
+    pub fn mount_pack(path: &Path) -> Result<()> {
+        Self::mount(Arc::new(ZipReader::open(path)?));
+        Ok(())
+    }
+
+    pub fn embed(path: &str, bytes: &'static [u8]) {
+        SOURCES.write().unwrap().embedded.insert(path, bytes);
+    }
+
+    /// Applies the command line, `--assets <dir>` replaces the root and every
+    /// `--pack <file.zip>` is mounted after the sources before it.
+    pub fn apply_args(args: impl IntoIterator<Item = String>) -> Result<()> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--assets" => Self::set_root(args.next().context("--assets needs a directory")?),
+                "--pack" => {
+                    let pack = args.next().context("--pack needs a zip file")?;
+                    Self::mount_pack(Path::new(&pack))?;
+                }
+                _ => bail!("Unknown argument {}", arg),
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `f` with the first source of `AssetPath::Assets` that has `path`.
+    pub(super) fn with_assets<R>(path: &str, f: impl FnOnce(&dyn AssetReader) -> R) -> Option<R> {
+        let sources = SOURCES.read().unwrap();
+        let reader = sources.assets.iter().find(|it| it.exists(path))?;
+        Some(f(reader.as_ref()))
+    }
+
+    /// Every source of `AssetPath::Assets` in search order.
+    pub(super) fn assets() -> Vec<Arc<dyn AssetReader>> {
+        SOURCES.read().unwrap().assets.clone()
+    }
+
+    pub(super) fn with_embedded<R>(f: impl FnOnce(&EmbeddedReader) -> R) -> R {
+        f(&SOURCES.read().unwrap().embedded)
+    }
+}
+
+/// Embeds a file under `assets/` into the binary, it is then read with `AssetPath::Embedded`.
+#[macro_export]
+macro_rules! embed_asset {
+    ($path:literal) => {
+        $crate::AssetSources::embed(
+            $path,
+            include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/", $path)),
+        )
+    };
+}
+
+fn default_root() -> PathBuf {
+    if let Some(root) = env::var_os("WGPU_PBR_ASSETS") {
+        return root.into();
+    }
+    if Path::new("assets").is_dir() {
+        return PathBuf::from("assets");
+    }
+    env::current_exe()
+        .ok()
+        .and_then(|exe| {
+            exe.ancestors()
+                .map(|dir| dir.join("assets"))
+                .find(|it| it.is_dir())
+        })
+        .unwrap_or_else(|| PathBuf::from("assets"))
+}
+
+pub(super) fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |it| it.0)
+}
+
+/// `relative` resolved against `dir`, `.` and `..` are folded.
+pub(super) fn join(dir: &str, relative: &str) -> String {
+    let mut parts = dir
+        .split('/')
+        .filter(|it| !it.is_empty())
+        .collect::<Vec<_>>();
+    for part in relative.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::asset::AssetPath;
+
+    fn zip(files: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut central = Vec::new();
+        for (name, content, deflate) in files {
+            let data = if *deflate {
+                miniz_oxide::deflate::compress_to_vec(content, 6)
+            } else {
+                content.to_vec()
+            };
+            let offset = out.len() as u32;
+            let header = |sig: u32, central: bool| {
+                let mut h = sig.to_le_bytes().to_vec();
+                if central {
+                    h.extend_from_slice(&20u16.to_le_bytes());
+                }
+                h.extend_from_slice(&[20, 0, 0, 0]);
+                h.extend_from_slice(&(if *deflate { 8u16 } else { 0 }).to_le_bytes());
+                h.extend_from_slice(&[0; 8]);
+                h.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                h.extend_from_slice(&(content.len() as u32).to_le_bytes());
+                h.extend_from_slice(&(name.len() as u16).to_le_bytes());
+                h.extend_from_slice(&[0; 2]);
+                if central {
+                    h.extend_from_slice(&[0; 10]);
+                    h.extend_from_slice(&offset.to_le_bytes());
+                }
+                h.extend_from_slice(name.as_bytes());
+                h
+            };
+            central.extend(header(0x0201_4b50, true));
+            out.extend(header(0x0403_4b50, false));
+            out.extend(data);
+        }
+        let central_offset = out.len() as u32;
+        out.extend_from_slice(&central);
+        out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+        out.extend_from_slice(&central_offset.to_le_bytes());
+        out.extend_from_slice(&[0; 2]);
+        out
+    }
+
+    #[test]
+    fn test_zip_reader() {
+        let text = b"fn main() {} // fn main() {} // fn main() {}";
+        let reader = ZipReader::from_bytes(zip(&[
+            ("shaders/a.wgsl", text, false),
+            ("shaders/libs/b.wgsl", text, true),
+        ]))
+        .unwrap();
+
+        assert_eq!(reader.read("shaders/a.wgsl").unwrap(), text);
+        assert_eq!(reader.read("shaders/libs/b.wgsl").unwrap(), text);
+        assert!(reader.read("shaders/c.wgsl").is_err());
+        assert_eq!(
+            reader.read_dir("shaders/libs/").unwrap(),
+            vec!["shaders/libs/b.wgsl"]
+        );
+    }
+
+    #[test]
+    fn test_mount_pack() {
+        let text = b"// packed";
+        let pack = env::temp_dir().join(format!("wgpu_pbr_test_pack_{}.zip", std::process::id()));
+        fs::write(
+            &pack,
+            zip(&[
+                ("test_pack/shaders/a.wgsl", text, true),
+                ("test_pack/shaders/b.wgsl", text, false),
+            ]),
+        )
+        .unwrap();
+        AssetSources::apply_args(["--pack".to_string(), pack.to_string_lossy().to_string()])
+            .unwrap();
+        fs::remove_file(&pack).unwrap();
+
+        let path = AssetPath::Assets("test_pack/shaders/a.wgsl".to_string());
+        assert!(path.exists());
+        assert_eq!(path.read().unwrap(), text);
+        assert_eq!(
+            AssetPath::Assets("test_pack/shaders/".to_string())
+                .read_dir()
+                .unwrap(),
+            vec![
+                path.clone(),
+                AssetPath::Assets("test_pack/shaders/b.wgsl".to_string())
+            ]
+        );
+        assert!(AssetPath::Assets("test_pack/shaders/c.wgsl".to_string())
+            .read()
+            .is_err());
+        assert!(AssetSources::apply_args(["--pack".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(join("models", "textures/a.png"), "models/textures/a.png");
+        assert_eq!(join("models/sub", "../a.png"), "models/a.png");
+        assert_eq!(join("", "./a.png"), "a.png");
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use bevy_ecs::prelude::*;
+
+use crate::render::shader_loader::ReloadablePipelines;
+
+use super::{server::AssetServer, source::AssetSources};
+
+/// Polls the asset root on a background thread and reports the files that changed.
+///
+/// A change is reported once the modification time stayed the same for one poll,
+/// so that files are not picked up while they are still being written.
+#[derive(Resource)]
+pub struct AssetWatcher {
+    changes: Mutex<Receiver<String>>,
+}
+
+impl Default for AssetWatcher {
+    fn default() -> Self {
+        let (sender, changes) = channel();
+        let root = AssetSources::root();
+        thread::Builder::new()
+            .name("Asset Watcher".to_string())
+            .spawn(move || watch(&root, Duration::from_millis(500), sender))
+            .unwrap();
+        Self {
+            changes: Mutex::new(changes),
+        }
+    }
+}
+
+impl AssetWatcher {
+    /// Final paths of the files changed since the last call
+    pub fn changed_paths(&self) -> Vec<String> {
+        let mut paths = self.changes.lock().unwrap().try_iter().collect::<Vec<_>>();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+}
+
+fn watch(root: &Path, interval: Duration, sender: Sender<String>) {
+    let mut known = HashMap::new();
+    scan(root, &mut known);
+    let mut pending = HashMap::new();
+    loop {
+        thread::sleep(interval);
+        let mut current = HashMap::new();
+        scan(root, &mut current);
+        for (path, modified) in current.iter() {
+            if known.get(path) == Some(modified) {
+                continue;
+            }
+            if pending.get(path) == Some(modified) {
+                pending.remove(path);
+                known.insert(path.clone(), *modified);
+                // The world is gone
+                if sender.send(path.clone()).is_err() {
+                    return;
+                }
+            } else {
+                pending.insert(path.clone(), *modified);
+            }
+        }
+        known.retain(|path, _| current.contains_key(path));
+        pending.retain(|path, _| current.contains_key(path));
+    }
+}
+
+fn scan(dir: &Path, out: &mut HashMap<String, SystemTime>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            scan(&path, out);
+        } else if let Ok(modified) = metadata.modified() {
+            out.insert(path.to_string_lossy().replace('\\', "/"), modified);
+        }
+    }
+}
+
+/// Reloads assets and pipelines whose files changed on disk.
+pub fn sys_reload_changed_assets(world: &mut World) {
+    let Some(watcher) = world.get_resource::<AssetWatcher>() else {
+        return;
+    };
+    let (shaders, others): (Vec<_>, Vec<_>) = watcher
+        .changed_paths()
+        .into_iter()
+        .partition(|it| it.ends_with(".wgsl"));
+    if !shaders.is_empty() {
+        ReloadablePipelines::reload_shaders(world, &shaders);
+    }
+    let mut server = world.resource_mut::<AssetServer>();
+    for path in others {
+        if server.reload(&path) {
+            log::info!("Reloading {}", path);
+        }
+    }
+}
@@ -3,11 +3,17 @@ use egui::load::SizedTexture;
 
 use crate::{
     cgmath_ext::{Vec2, VectorExt},
-    egui_tools::{world_tree, EguiRenderer},
+    egui_tools::{assets_ui, environment_ui, post_effects_ui, world_tree, EguiRenderer},
     engine::input::{CursorButton, Input},
     render::{
-        self, camera::Camera, defered_rendering::write_g_buffer_pipeline::GBufferTexturesBindGroup,
-        gizmos::GizmosPipeline, post_processing::PostProcessingManager, transform::Transform,
+        self,
+        camera::Camera,
+        defered_rendering::write_g_buffer_pipeline::GBufferTexturesBindGroup,
+        gizmos::GizmosPipeline,
+        material::buffer_material::BufferMaterialManager,
+        post_processing::{motion_blur::MotionBlur, PostProcessingManager},
+        transform::Transform,
+        transmission::Transmission,
         ColorRenderTarget, DepthRenderTarget, RenderTargetSize,
     },
     RenderState,
@@ -16,6 +22,9 @@ use crate::{
 pub enum Pane {
     MainView,
     ControlPanel,
+    PostProcessing,
+    Environment,
+    Assets,
 }
 
 struct TreeBehavior<'a> {
@@ -53,6 +62,21 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior<'_> {
                     }
                 });
             }
+            Pane::PostProcessing => {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    post_effects_ui(ui, self.world);
+                });
+            }
+            Pane::Environment => {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    environment_ui(ui, self.world);
+                });
+            }
+            Pane::Assets => {
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    assets_ui(ui, self.world);
+                });
+            }
         };
         egui_tiles::UiResponse::None
     }
@@ -61,6 +85,9 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior<'_> {
         match pane {
             Pane::MainView => "Main View".into(),
             Pane::ControlPanel => "Control Panel".into(),
+            Pane::PostProcessing => "Post Processing".into(),
+            Pane::Environment => "Environment".into(),
+            Pane::Assets => "Assets".into(),
         }
     }
 }
@@ -117,6 +144,9 @@ pub fn sys_on_resize_render_target(
     mut camera: Single<&mut Camera>,
     mut post_processing_manager: ResMut<PostProcessingManager>,
     mut gizmos_pipeline: ResMut<GizmosPipeline>,
+    mut motion_blur: ResMut<MotionBlur>,
+    mut transmission: ResMut<Transmission>,
+    mut buffer_material_manager: ResMut<BufferMaterialManager>,
 ) {
     if target_size.is_changed() {
         let device = &render_state.device;
@@ -136,9 +166,23 @@ pub fn sys_on_resize_render_target(
         egui_tex_id.0 = Some(id);
         camera.aspect = height as f32 / width as f32;
 
-        post_processing_manager.resize(width, height, device, config);
+        post_processing_manager.resize(
+            width,
+            height,
+            device,
+            config,
+            depth_target.0.as_ref().unwrap(),
+        );
         g_buffer_textures.resize(width, height, device);
         gizmos_pipeline.resize(width, height, device);
+        motion_blur.resize(
+            width,
+            height,
+            device,
+            &mut post_processing_manager,
+            &mut buffer_material_manager,
+        );
+        transmission.resize(width, height, device);
     };
 }
 fn create_tree() -> egui_tiles::Tree<Pane> {
@@ -147,7 +191,13 @@ fn create_tree() -> egui_tiles::Tree<Pane> {
     let mut left_tabs_id_vec = vec![];
     let control_pane = tiles.insert_pane(Pane::ControlPanel);
     let main_view_pane = tiles.insert_pane(Pane::MainView);
+    let post_processing_pane = tiles.insert_pane(Pane::PostProcessing);
+    let environment_pane = tiles.insert_pane(Pane::Environment);
+    let assets_pane = tiles.insert_pane(Pane::Assets);
     left_tabs_id_vec.push(tiles.insert_vertical_tile(vec![control_pane]));
+    left_tabs_id_vec.push(tiles.insert_vertical_tile(vec![post_processing_pane]));
+    left_tabs_id_vec.push(tiles.insert_vertical_tile(vec![environment_pane]));
+    left_tabs_id_vec.push(tiles.insert_vertical_tile(vec![assets_pane]));
     left_tabs_id_vec.push(tiles.insert_vertical_tile(vec![main_view_pane]));
 
     let left_tabs = tiles.insert_tab_tile(left_tabs_id_vec);
@@ -2,7 +2,7 @@ use std::any::type_name;
 
 use bevy_ecs::entity::Entity;
 use bevy_ecs::prelude::Resource;
-use bevy_ecs::world::World;
+use bevy_ecs::world::{Mut, World};
 use cgmath::{Deg, Euler};
 use egui::{Color32, Context, DragValue, Ui, Widget};
 use egui_wgpu::wgpu::{CommandEncoder, Device, Queue, StoreOp, TextureFormat, TextureView};
@@ -11,13 +11,29 @@ use egui_winit::State;
 use winit::event::WindowEvent;
 use winit::window::Window;
 
+use crate::asset::export::export_scene;
+use crate::asset::server::AssetServer;
+use crate::asset::source::AssetSources;
+use crate::asset::{AssetPath, Assets};
 use crate::cgmath_ext::{Vec3, Vec4, Vector4Ext, VectorExt};
+use crate::engine::scene::{load_scene, save_scene};
 use crate::engine_lifetime::Name;
 use crate::render::camera::{Camera, CameraController};
-use crate::render::light::parallel_light::ParallelLight;
+use crate::render::fog::{FogMode, FogSettings};
+use crate::render::irradiance_volume::{
+    IrradianceVolume, IrradianceVolumes, MAX_IRRADIANCE_VOLUMES, MAX_VOLUME_RESOLUTION,
+};
+use crate::render::light::area_light::{AreaLightShape, RectLight};
+use crate::render::light::parallel_light::{ParallelLight, ShadowFilter};
 use crate::render::light::point_light::PointLight;
 use crate::render::material::pbr::PBRMaterial;
+use crate::render::post_processing::{PostProcessingManager, RenderStage};
+use crate::render::reflection_probe::{ReflectionProbe, ReflectionProbes, MAX_REFLECTION_PROBES};
+use crate::render::shader_loader::ReloadablePipelines;
+use crate::render::skybox::procedural::ProceduralSky;
 use crate::render::transform::Transform;
+use crate::render::{Model, UploadedImageWithSampler, UploadedMesh};
+use crate::RenderState;
 
 #[derive(Resource)]
 pub struct EguiConfig {
@@ -162,6 +178,13 @@ fn color_vec4_srgba(ui: &mut Ui, color: &mut Vec4) -> egui::Response {
     ret
 }
 
+fn color_vec3_linear(ui: &mut Ui, color: &mut Vec3) -> egui::Response {
+    let mut c: [f32; 3] = (*color).into();
+    let ret = ui.color_edit_button_rgb(&mut c);
+    *color = c.into();
+    ret
+}
+
 pub fn vec3_ui(ui: &mut Ui, label: &str, vec3: &mut Vec3, default_value: Vec3) {
     ui.horizontal(|ui| {
         ui.label(label);
@@ -241,6 +264,44 @@ pub fn world_tree(ui: &mut Ui, id: Entity, world: &mut World) {
 
         impl_component_ui!(Camera, world, id, ui, ui, camera, {
             label_value(ui, "FOV", &mut camera.fovy);
+            egui::Grid::new(format!("Physical Camera {}", id.index()))
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Aperture (f/)");
+                    ui.add(egui::Slider::new(&mut camera.aperture, 1.0..=22.0));
+                    ui.end_row();
+
+                    ui.label("Focal Distance");
+                    ui.add(
+                        egui::Slider::new(&mut camera.focal_distance, 0.1..=100.0)
+                            .logarithmic(true),
+                    );
+                    ui.end_row();
+
+                    ui.label("Sensor Height (mm)");
+                    ui.add(egui::Slider::new(&mut camera.sensor_height, 4.0..=60.0));
+                    ui.end_row();
+
+                    ui.label("Shutter (s)");
+                    ui.add(
+                        egui::Slider::new(&mut camera.shutter_speed, 1.0 / 8000.0..=1.0)
+                            .logarithmic(true),
+                    );
+                    ui.end_row();
+
+                    ui.label("Shutter Angle");
+                    ui.add(egui::Slider::new(&mut camera.shutter_angle, 0.0..=360.0));
+                    ui.end_row();
+
+                    ui.label("ISO");
+                    ui.add(egui::Slider::new(&mut camera.iso, 50.0..=6400.0).logarithmic(true));
+                    ui.end_row();
+
+                    ui.label("Physical Exposure");
+                    ui.checkbox(&mut camera.physical_exposure, "");
+                    ui.end_row();
+                });
         });
 
         impl_component_ui!(CameraController, world, id, ui, ui, camera, {
@@ -261,6 +322,62 @@ pub fn world_tree(ui: &mut Ui, id: Entity, world: &mut World) {
             label_value(ui, "Iecay", &mut light.decay);
         });
 
+        impl_component_ui!(RectLight, world, id, ui, ui, light, {
+            egui::Grid::new(format!("RectLight {}", id.index()))
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Shape");
+                    egui::ComboBox::from_id_salt(format!("RectLight Shape {}", id.index()))
+                        .selected_text(format!("{:?}", light.shape))
+                        .show_ui(ui, |ui| {
+                            for shape in [AreaLightShape::Rectangle, AreaLightShape::Disk] {
+                                ui.selectable_value(
+                                    &mut light.shape,
+                                    shape,
+                                    format!("{:?}", shape),
+                                );
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Color");
+                    color_vec4_srgba(ui, &mut light.color);
+                    ui.end_row();
+
+                    ui.label("Intensity");
+                    ui.add(
+                        DragValue::new(&mut light.intensity)
+                            .speed(0.1)
+                            .range(0.0..=f32::MAX),
+                    );
+                    ui.end_row();
+
+                    ui.label("Size");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            DragValue::new(&mut light.width)
+                                .speed(0.05)
+                                .range(0.0..=f32::MAX),
+                        );
+                        ui.add(
+                            DragValue::new(&mut light.height)
+                                .speed(0.05)
+                                .range(0.0..=f32::MAX),
+                        );
+                    });
+                    ui.end_row();
+
+                    ui.label("Two Sided");
+                    ui.checkbox(&mut light.two_sided, "");
+                    ui.end_row();
+
+                    ui.label("Show Geometry");
+                    ui.checkbox(&mut light.visible, "");
+                    ui.end_row();
+                });
+        });
+
         impl_component_ui!(PBRMaterial, world, id, ui, ui, mat, {
             egui::Grid::new(format!("PBR {}", id.index()))
                 .num_columns(2)
@@ -283,6 +400,18 @@ pub fn world_tree(ui: &mut Ui, id: Entity, world: &mut World) {
                         ui.add(egui::Slider::new(it, 0.0f32..=1.0f32));
                     });
                     ui.end_row();
+
+                    ui.label("Emissive");
+                    option_value(ui, &mut mat.emissive, Vec3::zero(), |ui, it| {
+                        color_vec3_linear(ui, it);
+                    });
+                    ui.end_row();
+
+                    ui.label("Emissive Strength");
+                    option_value(ui, &mut mat.emissive_strength, 1.0, |ui, it| {
+                        ui.add(DragValue::new(it).speed(0.1).range(0.0..=f32::MAX));
+                    });
+                    ui.end_row();
                 });
         });
 
@@ -302,6 +431,185 @@ pub fn world_tree(ui: &mut Ui, id: Entity, world: &mut World) {
                     ui.label("Color");
                     color_vec4_srgba(ui, &mut light.color);
                     ui.end_row();
+
+                    ui.label("Shadow Resolution");
+                    egui::ComboBox::from_id_salt(format!(
+                        "ParallelLight Resolution {}",
+                        id.index()
+                    ))
+                    .selected_text(light.shadow_resolution.to_string())
+                    .show_ui(ui, |ui| {
+                        for resolution in [512, 1024, 2048, 4096, 8192] {
+                            ui.selectable_value(
+                                &mut light.shadow_resolution,
+                                resolution,
+                                resolution.to_string(),
+                            );
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Depth Bias");
+                    ui.add(
+                        DragValue::new(&mut light.depth_bias)
+                            .speed(0.001)
+                            .range(0.0..=1.0),
+                    );
+                    ui.end_row();
+
+                    ui.label("Normal Offset");
+                    ui.add(
+                        DragValue::new(&mut light.normal_offset)
+                            .speed(0.05)
+                            .range(0.0..=10.0),
+                    );
+                    ui.end_row();
+
+                    ui.label("Shadow Filter");
+                    egui::ComboBox::from_id_salt(format!("ParallelLight Filter {}", id.index()))
+                        .selected_text(format!("{:?}", light.shadow_filter))
+                        .show_ui(ui, |ui| {
+                            for filter in [
+                                ShadowFilter::Hard,
+                                ShadowFilter::Pcf { radius: 1 },
+                                ShadowFilter::PoissonPcf { radius: 2.0 },
+                                ShadowFilter::Pcss { light_size: 0.05 },
+                                ShadowFilter::Vsm {
+                                    blur_radius: 2,
+                                    light_bleeding_reduction: 0.2,
+                                },
+                                ShadowFilter::Evsm {
+                                    blur_radius: 2,
+                                    exponents: [5.0, 5.0],
+                                    light_bleeding_reduction: 0.2,
+                                },
+                            ] {
+                                let selected = light.shadow_filter.mode() == filter.mode();
+                                if ui
+                                    .selectable_label(selected, format!("{:?}", filter))
+                                    .clicked()
+                                    && !selected
+                                {
+                                    light.shadow_filter = filter;
+                                }
+                            }
+                        });
+                    ui.end_row();
+
+                    match &mut light.shadow_filter {
+                        ShadowFilter::Hard => {}
+                        ShadowFilter::Pcf { radius } => {
+                            ui.label("Kernel Radius");
+                            ui.add(DragValue::new(radius).speed(0.1).range(0..=4));
+                            ui.end_row();
+                        }
+                        ShadowFilter::PoissonPcf { radius } => {
+                            ui.label("Filter Radius");
+                            ui.add(DragValue::new(radius).speed(0.05).range(0.0..=16.0));
+                            ui.end_row();
+                        }
+                        ShadowFilter::Pcss { light_size } => {
+                            ui.label("Light Size");
+                            ui.add(DragValue::new(light_size).speed(0.005).range(0.0..=1.0));
+                            ui.end_row();
+                        }
+                        ShadowFilter::Vsm {
+                            blur_radius,
+                            light_bleeding_reduction,
+                        } => {
+                            ui.label("Blur Radius");
+                            ui.add(DragValue::new(blur_radius).speed(0.1).range(0..=16));
+                            ui.end_row();
+
+                            ui.label("Light Bleeding Reduction");
+                            ui.add(
+                                DragValue::new(light_bleeding_reduction)
+                                    .speed(0.01)
+                                    .range(0.0..=0.99),
+                            );
+                            ui.end_row();
+                        }
+                        ShadowFilter::Evsm {
+                            blur_radius,
+                            exponents,
+                            light_bleeding_reduction,
+                        } => {
+                            ui.label("Blur Radius");
+                            ui.add(DragValue::new(blur_radius).speed(0.1).range(0..=16));
+                            ui.end_row();
+
+                            ui.label("Exponents");
+                            ui.horizontal(|ui| {
+                                for exponent in exponents.iter_mut() {
+                                    ui.add(DragValue::new(exponent).speed(0.05).range(0.0..=5.54));
+                                }
+                            });
+                            ui.end_row();
+
+                            ui.label("Light Bleeding Reduction");
+                            ui.add(
+                                DragValue::new(light_bleeding_reduction)
+                                    .speed(0.01)
+                                    .range(0.0..=0.99),
+                            );
+                            ui.end_row();
+                        }
+                    }
+                });
+        });
+
+        impl_component_ui!(ReflectionProbe, world, id, ui, ui, probe, {
+            egui::Grid::new(format!("ReflectionProbe {}", id.index()))
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Half Extents");
+                    ui.horizontal(|ui| {
+                        ui.add(DragValue::new(&mut probe.half_extents.x).speed(0.1));
+                        ui.add(DragValue::new(&mut probe.half_extents.y).speed(0.1));
+                        ui.add(DragValue::new(&mut probe.half_extents.z).speed(0.1));
+                    });
+                    ui.end_row();
+
+                    ui.label("Blend Distance");
+                    ui.add(egui::Slider::new(&mut probe.blend_distance, 0.0..=5.0));
+                    ui.end_row();
+
+                    ui.label("Far");
+                    ui.add(egui::Slider::new(&mut probe.far, 1.0..=200.0));
+                    ui.end_row();
+                });
+        });
+
+        impl_component_ui!(IrradianceVolume, world, id, ui, ui, volume, {
+            egui::Grid::new(format!("IrradianceVolume {}", id.index()))
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Half Extents");
+                    ui.horizontal(|ui| {
+                        ui.add(DragValue::new(&mut volume.half_extents.x).speed(0.1));
+                        ui.add(DragValue::new(&mut volume.half_extents.y).speed(0.1));
+                        ui.add(DragValue::new(&mut volume.half_extents.z).speed(0.1));
+                    });
+                    ui.end_row();
+
+                    ui.label("Resolution");
+                    ui.horizontal(|ui| {
+                        for (value, max) in volume.resolution.iter_mut().zip(MAX_VOLUME_RESOLUTION)
+                        {
+                            ui.add(DragValue::new(value).range(1..=max));
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Blend Distance");
+                    ui.add(egui::Slider::new(&mut volume.blend_distance, 0.0..=5.0));
+                    ui.end_row();
+
+                    ui.label("Far");
+                    ui.add(egui::Slider::new(&mut volume.far, 1.0..=200.0));
+                    ui.end_row();
                 });
         });
 
@@ -316,3 +624,244 @@ pub fn world_tree(ui: &mut Ui, id: Entity, world: &mut World) {
         }
     });
 }
+
+/// The post effect stack, grouped by [`RenderStage`].
+pub fn post_effects_ui(ui: &mut Ui, world: &mut World) {
+    world.resource_scope(|world, mut manager: Mut<PostProcessingManager>| {
+        let queue = &world.resource::<RenderState>().queue;
+        let mut is_reordered = false;
+
+        for stage in RenderStage::ALL {
+            ui.colored_label(Color32::LIGHT_GRAY, format!("{:?}", stage));
+            for effect in manager.effects.iter_mut().filter(|it| it.stage == stage) {
+                egui::Frame::dark_canvas(ui.style())
+                    .inner_margin(egui::Vec2::new(10., 8.))
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut effect.enabled, effect.label.as_str());
+                            ui.label("Order");
+                            is_reordered |= ui.add(DragValue::new(&mut effect.order)).changed();
+                        });
+                        if let Some(params) = effect.params.as_mut() {
+                            params.ui(ui, queue);
+                        }
+                    });
+            }
+            ui.separator();
+        }
+
+        if is_reordered {
+            manager.sort_effects();
+        }
+    });
+}
+
+/// Scene wide settings that are not attached to an entity.
+pub fn environment_ui(ui: &mut Ui, world: &mut World) {
+    let mut sky = world.resource_mut::<ProceduralSky>();
+    ui.colored_label(Color32::LIGHT_GRAY, "Sky");
+    egui::Grid::new("Sky Settings")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("Procedural");
+            ui.checkbox(&mut sky.enabled, "");
+            ui.end_row();
+
+            ui.label("Turbidity");
+            ui.add(egui::Slider::new(&mut sky.turbidity, 1.7..=10.0));
+            ui.end_row();
+
+            ui.label("Intensity");
+            ui.add(egui::Slider::new(&mut sky.intensity, 0.001..=0.1).logarithmic(true));
+            ui.end_row();
+        });
+    ui.label("The sun follows the direction of the parallel light.");
+    ui.separator();
+
+    let mut probes = world.resource_mut::<ReflectionProbes>();
+    ui.colored_label(Color32::LIGHT_GRAY, "Reflection Probes");
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "{} / {} in use",
+            probes.probe_count(),
+            MAX_REFLECTION_PROBES
+        ));
+        if ui.button("Recapture").clicked() {
+            probes.recapture_all();
+        }
+    });
+
+    let mut volumes = world.resource_mut::<IrradianceVolumes>();
+    ui.colored_label(Color32::LIGHT_GRAY, "Irradiance Volumes");
+    ui.horizontal(|ui| {
+        ui.label(format!(
+            "{} / {} in use",
+            volumes.volume_count(),
+            MAX_IRRADIANCE_VOLUMES
+        ));
+        if ui.button("Rebake").clicked() {
+            volumes.rebake_all();
+        }
+    });
+    ui.separator();
+
+    let mut fog = world.resource_mut::<FogSettings>();
+    ui.colored_label(Color32::LIGHT_GRAY, "Fog");
+    egui::Grid::new("Fog Settings")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("Mode");
+            egui::ComboBox::from_id_salt("Fog Mode")
+                .selected_text(format!("{:?}", fog.mode))
+                .show_ui(ui, |ui| {
+                    for mode in [FogMode::Off, FogMode::Exponential, FogMode::Volumetric] {
+                        ui.selectable_value(&mut fog.mode, mode, format!("{:?}", mode));
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Color");
+            let mut color = [fog.color.x, fog.color.y, fog.color.z];
+            if ui.color_edit_button_rgb(&mut color).changed() {
+                fog.color = Vec3::from(color);
+            }
+            ui.end_row();
+
+            ui.label("Density");
+            ui.add(egui::Slider::new(&mut fog.density, 0.0..=1.0).logarithmic(true));
+            ui.end_row();
+
+            ui.label("Height Falloff");
+            ui.add(egui::Slider::new(&mut fog.height_falloff, 0.0..=2.0));
+            ui.end_row();
+
+            ui.label("Base Height");
+            ui.add(DragValue::new(&mut fog.base_height).speed(0.1));
+            ui.end_row();
+
+            ui.label("Anisotropy");
+            ui.add(egui::Slider::new(&mut fog.anisotropy, -0.9..=0.9));
+            ui.end_row();
+
+            ui.label("Ambient");
+            ui.add(egui::Slider::new(&mut fog.ambient, 0.0..=1.0));
+            ui.end_row();
+
+            ui.label("Max Distance");
+            ui.add(egui::Slider::new(&mut fog.max_distance, 5.0..=200.0));
+            ui.end_row();
+        });
+}
+
+/// Assets and shaders whose last (re)load failed, they keep their previous version.
+pub fn assets_ui(ui: &mut Ui, world: &mut World) {
+    ui.colored_label(Color32::LIGHT_GRAY, "Loaded");
+    egui::Grid::new("Loaded Assets")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("Models");
+            ui.label(world.resource::<Assets<Model>>().len().to_string());
+            ui.end_row();
+
+            ui.label("Meshes");
+            ui.label(world.resource::<Assets<UploadedMesh>>().len().to_string());
+            ui.end_row();
+
+            ui.label("Images");
+            ui.label(
+                world
+                    .resource::<Assets<UploadedImageWithSampler>>()
+                    .len()
+                    .to_string(),
+            );
+            ui.end_row();
+        });
+    ui.separator();
+
+    ui.colored_label(Color32::LIGHT_GRAY, "Shaders");
+    match world.get_resource::<ReloadablePipelines>() {
+        Some(pipelines) if !pipelines.errors.is_empty() => {
+            let mut errors = pipelines.errors.iter().collect::<Vec<_>>();
+            errors.sort();
+            for (name, error) in errors {
+                ui.collapsing(*name, |ui| error_label(ui, error));
+            }
+        }
+        _ => {
+            ui.label("No errors");
+        }
+    }
+    ui.separator();
+
+    ui.colored_label(Color32::LIGHT_GRAY, "Assets");
+    let server = world.resource::<AssetServer>();
+    let mut failures = server.failures().collect::<Vec<_>>();
+    if failures.is_empty() {
+        ui.label("No errors");
+    }
+    failures.sort();
+    for (path, error) in failures {
+        ui.collapsing(path, |ui| error_label(ui, error));
+    }
+    ui.separator();
+    ui.label("Files under assets/ are reloaded when they change.");
+    ui.separator();
+
+    ui.colored_label(Color32::LIGHT_GRAY, "Scene");
+    let scene_path = "scenes/scene.ron";
+    let scene_status_id = ui.id().with("Scene Status");
+    ui.horizontal(|ui| {
+        if ui.button("Save").clicked() {
+            let status = match save_scene(world, &AssetSources::root().join(scene_path)) {
+                Ok(()) => format!("Saved to {}", scene_path),
+                Err(e) => format!("Save failed: {:#}", e),
+            };
+            ui.data_mut(|data| data.insert_temp(scene_status_id, status));
+        }
+        if ui.button("Load").clicked() {
+            let status = match load_scene(world, &AssetPath::Assets(scene_path.to_string())) {
+                Ok(()) => format!("Loaded {}", scene_path),
+                Err(e) => format!("Load failed: {:#}", e),
+            };
+            ui.data_mut(|data| data.insert_temp(scene_status_id, status));
+        }
+    });
+    if let Some(status) = ui.data(|data| data.get_temp::<String>(scene_status_id)) {
+        ui.label(status);
+    }
+    ui.separator();
+
+    ui.colored_label(Color32::LIGHT_GRAY, "Export");
+    let path = AssetSources::root()
+        .with_file_name("exports")
+        .join("scene.glb");
+    let status_id = ui.id().with("Export Status");
+    if ui.button("Export Scene (.glb)").clicked() {
+        let status = match export_scene(world, &path) {
+            Ok(stats) => format!(
+                "Wrote {} nodes, {} meshes, {} materials and {} images to {}",
+                stats.nodes,
+                stats.meshes,
+                stats.materials,
+                stats.images,
+                path.display()
+            ),
+            Err(e) => format!("Export failed: {:#}", e),
+        };
+        ui.data_mut(|data| data.insert_temp(status_id, status));
+    }
+    if let Some(status) = ui.data(|data| data.get_temp::<String>(status_id)) {
+        ui.label(status);
+    }
+}
+
+fn error_label(ui: &mut Ui, error: &str) {
+    ui.label(
+        egui::RichText::new(error)
+            .monospace()
+            .color(Color32::LIGHT_RED),
+    );
+}
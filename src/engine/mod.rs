@@ -1,2 +1,3 @@
 pub mod input;
+pub mod scene;
 pub mod time;
@@ -0,0 +1,881 @@
+//! Scenes saved as RON through `bevy_reflect`.
+//!
+//! Components are written as the `Scene*` descriptions below rather than
+//! reflected directly, since most of them hold cgmath types, handles and
+//! entities. Fields a description doesn't know, e.g. from a newer version, are
+//! skipped with a warning and missing fields take their default.
+
+use std::{any::TypeId, collections::HashMap, fmt, fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use bevy_ecs::prelude::*;
+use bevy_reflect::{
+    serde::{ReflectDeserializerProcessor, TypedReflectDeserializer, TypedReflectSerializer},
+    std_traits::ReflectDefault,
+    DynamicStruct, FromReflect, PartialReflect, Reflect, StructInfo, TypeInfo, TypeRegistration,
+    TypeRegistry,
+};
+use serde::de::{DeserializeSeed, Deserializer, Error, IgnoredAny, MapAccess, Visitor};
+
+use crate::{
+    asset::{server::AssetServer, AssetPath, Assets, Handle},
+    cgmath_ext::{Quat, Vec3},
+    engine_lifetime::{insert_loading_model, LoadingModel, ModelInstance, Name, RotationObject},
+    render::{
+        camera::{Camera, CameraController},
+        gizmos::Gizmos,
+        irradiance_volume::IrradianceVolume,
+        light::{
+            area_light::{AreaLightShape, RectLight},
+            parallel_light::{ParallelLight, ShadowFilter},
+            point_light::PointLight,
+        },
+        material::pbr::PBRMaterial,
+        reflection_probe::ReflectionProbe,
+        shadow_mapping::CastShadow,
+        skybox::procedural::ProceduralSky,
+        transform::Transform,
+        ColorSpace, ImageSettings, MainPassObject, MeshRenderer, Model, PlaceholderMesh,
+        RenderTargetSize, UploadedImageWithSampler,
+    },
+};
+
+/// Written to every scene, bump it when a field changes meaning.
+pub const SCENE_VERSION: u32 = 1;
+
+#[derive(Reflect, Clone)]
+#[reflect(Default)]
+pub struct SceneFile {
+    pub version: u32,
+    pub sky: SceneSky,
+    /// Parents come before their children
+    pub entities: Vec<SceneEntity>,
+}
+
+impl Default for SceneFile {
+    fn default() -> Self {
+        Self {
+            version: SCENE_VERSION,
+            sky: Default::default(),
+            entities: Vec::new(),
+        }
+    }
+}
+
+/// The environment map, the procedural sky follows the parallel light.
+#[derive(Reflect, Clone, Debug, Default, PartialEq)]
+#[reflect(Default)]
+pub enum SceneSky {
+    #[default]
+    Cubemap,
+    Procedural {
+        turbidity: f32,
+        intensity: f32,
+    },
+}
+
+#[derive(Reflect, Clone, Default)]
+#[reflect(Default)]
+pub struct SceneEntity {
+    /// Index into [`SceneFile::entities`]
+    pub parent: Option<usize>,
+    pub name: Option<String>,
+    pub transform: SceneTransform,
+    /// A mesh of a model spawned by the asset server
+    pub mesh: Option<SceneMesh>,
+    /// A model that was still loading, its meshes are spawned as children
+    pub model: Option<String>,
+    pub material: Option<SceneMaterial>,
+    pub camera: Option<Camera>,
+    pub camera_controller: bool,
+    pub point_light: Option<ScenePointLight>,
+    pub parallel_light: Option<SceneParallelLight>,
+    pub rect_light: Option<SceneRectLight>,
+    pub reflection_probe: Option<SceneReflectionProbe>,
+    pub irradiance_volume: Option<SceneIrradianceVolume>,
+    pub cast_shadow: bool,
+    pub main_pass: bool,
+    pub rotation_speed: Option<f32>,
+}
+
+#[derive(Reflect, Clone, Debug)]
+#[reflect(Default)]
+pub struct SceneTransform {
+    pub position: [f32; 3],
+    /// x, y, z, w
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Default for SceneTransform {
+    fn default() -> Self {
+        (&Transform::default()).into()
+    }
+}
+
+impl From<&Transform> for SceneTransform {
+    fn from(value: &Transform) -> Self {
+        let rotation = value.rotation;
+        Self {
+            position: value.position.into(),
+            rotation: [rotation.v.x, rotation.v.y, rotation.v.z, rotation.s],
+            scale: value.scale.into(),
+        }
+    }
+}
+
+impl From<&SceneTransform> for Transform {
+    fn from(value: &SceneTransform) -> Self {
+        let [x, y, z, w] = value.rotation;
+        Self {
+            position: value.position.into(),
+            rotation: Quat::new(w, x, y, z),
+            scale: value.scale.into(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Reflect, Clone, Debug, Default)]
+#[reflect(Default)]
+pub struct SceneMesh {
+    pub model: String,
+    /// Index into the meshes of the model
+    pub index: usize,
+}
+
+/// [`PBRMaterial`] overrides, textures by path.
+#[derive(Reflect, Clone, Debug, Default)]
+#[reflect(Default)]
+pub struct SceneMaterial {
+    pub base_color_texture: Option<String>,
+    pub normal_texture: Option<String>,
+    pub emissive_texture: Option<String>,
+    pub roughness: Option<f32>,
+    pub metallic: Option<f32>,
+    pub reflectance: Option<f32>,
+    pub emissive: Option<[f32; 3]>,
+    pub emissive_strength: Option<f32>,
+}
+
+#[derive(Reflect, Clone, Debug)]
+#[reflect(Default)]
+pub struct ScenePointLight {
+    pub color: [f32; 4],
+    pub intensity: f32,
+    pub distance: Option<f32>,
+    pub decay: f32,
+}
+
+impl Default for ScenePointLight {
+    fn default() -> Self {
+        (&PointLight::default()).into()
+    }
+}
+
+impl From<&PointLight> for ScenePointLight {
+    fn from(value: &PointLight) -> Self {
+        Self {
+            color: value.color.into(),
+            intensity: value.intensity,
+            distance: value.distance,
+            decay: value.decay,
+        }
+    }
+}
+
+impl From<&ScenePointLight> for PointLight {
+    fn from(value: &ScenePointLight) -> Self {
+        Self {
+            color: value.color.into(),
+            intensity: value.intensity,
+            distance: value.distance,
+            decay: value.decay,
+        }
+    }
+}
+
+#[derive(Reflect, Clone, Debug)]
+#[reflect(Default)]
+pub struct SceneParallelLight {
+    pub intensity: f32,
+    pub color: [f32; 4],
+    pub size: f32,
+    pub near: f32,
+    pub far: f32,
+    pub shadow_resolution: u32,
+    pub depth_bias: f32,
+    pub normal_offset: f32,
+    pub shadow_filter: ShadowFilter,
+}
+
+impl Default for SceneParallelLight {
+    fn default() -> Self {
+        (&ParallelLight::default()).into()
+    }
+}
+
+impl From<&ParallelLight> for SceneParallelLight {
+    fn from(value: &ParallelLight) -> Self {
+        Self {
+            intensity: value.intensity,
+            color: value.color.into(),
+            size: value.size,
+            near: value.near,
+            far: value.far,
+            shadow_resolution: value.shadow_resolution,
+            depth_bias: value.depth_bias,
+            normal_offset: value.normal_offset,
+            shadow_filter: value.shadow_filter,
+        }
+    }
+}
+
+impl From<&SceneParallelLight> for ParallelLight {
+    fn from(value: &SceneParallelLight) -> Self {
+        Self {
+            intensity: value.intensity,
+            color: value.color.into(),
+            size: value.size,
+            near: value.near,
+            far: value.far,
+            shadow_resolution: value.shadow_resolution,
+            depth_bias: value.depth_bias,
+            normal_offset: value.normal_offset,
+            shadow_filter: value.shadow_filter,
+        }
+    }
+}
+
+#[derive(Reflect, Clone, Debug)]
+#[reflect(Default)]
+pub struct SceneRectLight {
+    pub color: [f32; 4],
+    pub intensity: f32,
+    pub width: f32,
+    pub height: f32,
+    pub shape: AreaLightShape,
+    pub two_sided: bool,
+    pub visible: bool,
+}
+
+impl Default for SceneRectLight {
+    fn default() -> Self {
+        (&RectLight::default()).into()
+    }
+}
+
+impl From<&RectLight> for SceneRectLight {
+    fn from(value: &RectLight) -> Self {
+        Self {
+            color: value.color.into(),
+            intensity: value.intensity,
+            width: value.width,
+            height: value.height,
+            shape: value.shape,
+            two_sided: value.two_sided,
+            visible: value.visible,
+        }
+    }
+}
+
+impl From<&SceneRectLight> for RectLight {
+    fn from(value: &SceneRectLight) -> Self {
+        Self {
+            color: value.color.into(),
+            intensity: value.intensity,
+            width: value.width,
+            height: value.height,
+            shape: value.shape,
+            two_sided: value.two_sided,
+            visible: value.visible,
+        }
+    }
+}
+
+#[derive(Reflect, Clone, Debug)]
+#[reflect(Default)]
+pub struct SceneReflectionProbe {
+    pub half_extents: [f32; 3],
+    pub blend_distance: f32,
+    pub far: f32,
+}
+
+impl Default for SceneReflectionProbe {
+    fn default() -> Self {
+        (&ReflectionProbe::default()).into()
+    }
+}
+
+impl From<&ReflectionProbe> for SceneReflectionProbe {
+    fn from(value: &ReflectionProbe) -> Self {
+        Self {
+            half_extents: value.half_extents.into(),
+            blend_distance: value.blend_distance,
+            far: value.far,
+        }
+    }
+}
+
+impl From<&SceneReflectionProbe> for ReflectionProbe {
+    fn from(value: &SceneReflectionProbe) -> Self {
+        Self {
+            half_extents: value.half_extents.into(),
+            blend_distance: value.blend_distance,
+            far: value.far,
+        }
+    }
+}
+
+#[derive(Reflect, Clone, Debug)]
+#[reflect(Default)]
+pub struct SceneIrradianceVolume {
+    pub half_extents: [f32; 3],
+    pub resolution: [u32; 3],
+    pub blend_distance: f32,
+    pub far: f32,
+}
+
+impl Default for SceneIrradianceVolume {
+    fn default() -> Self {
+        (&IrradianceVolume::default()).into()
+    }
+}
+
+impl From<&IrradianceVolume> for SceneIrradianceVolume {
+    fn from(value: &IrradianceVolume) -> Self {
+        Self {
+            half_extents: value.half_extents.into(),
+            resolution: value.resolution,
+            blend_distance: value.blend_distance,
+            far: value.far,
+        }
+    }
+}
+
+impl From<&SceneIrradianceVolume> for IrradianceVolume {
+    fn from(value: &SceneIrradianceVolume) -> Self {
+        Self {
+            half_extents: value.half_extents.into(),
+            resolution: value.resolution,
+            blend_distance: value.blend_distance,
+            far: value.far,
+        }
+    }
+}
+
+/// `Assets` paths are written relative to the asset root, others with their scheme.
+fn path_to_string(path: &AssetPath) -> String {
+    match path {
+        AssetPath::Assets(path) => path.clone(),
+        AssetPath::Absolute(_) | AssetPath::Embedded(_) => path.final_path(),
+    }
+}
+
+fn path_from_string(path: &str) -> AssetPath {
+    if let Some(path) = path.strip_prefix("embedded://") {
+        AssetPath::Embedded(path.to_string())
+    } else if Path::new(path).is_absolute() {
+        AssetPath::Absolute(path.into())
+    } else {
+        AssetPath::Assets(path.to_string())
+    }
+}
+
+fn type_registry() -> TypeRegistry {
+    let mut registry = TypeRegistry::new();
+    registry.register::<SceneFile>();
+    registry
+}
+
+impl SceneFile {
+    /// Every entity with a [`Transform`], except gizmos and meshes that weren't loaded from a model file.
+    pub fn capture(world: &mut World) -> SceneFile {
+        let mut entities = world
+            .query::<(Entity, &Transform)>()
+            .iter(world)
+            .map(|(entity, transform)| (entity, transform.parent))
+            .collect::<Vec<_>>();
+        entities.sort_by_key(|(entity, _)| *entity);
+        let mut children = HashMap::<Entity, Vec<Entity>>::new();
+        for (entity, parent) in &entities {
+            if let Some(parent) = parent {
+                children.entry(*parent).or_default().push(*entity);
+            }
+        }
+
+        let sky = match world.get_resource::<ProceduralSky>() {
+            Some(sky) if sky.enabled => SceneSky::Procedural {
+                turbidity: sky.turbidity,
+                intensity: sky.intensity,
+            },
+            _ => SceneSky::Cubemap,
+        };
+        let mut scene = SceneFile {
+            sky,
+            ..Default::default()
+        };
+        for (entity, parent) in &entities {
+            if parent.is_none() {
+                scene.capture_entity(world, *entity, None, &children);
+            }
+        }
+        scene
+    }
+
+    fn capture_entity(
+        &mut self,
+        world: &World,
+        entity: Entity,
+        parent: Option<usize>,
+        children: &HashMap<Entity, Vec<Entity>>,
+    ) {
+        let entity_ref = world.entity(entity);
+        let Some(transform) = entity_ref.get::<Transform>() else {
+            return;
+        };
+        // Placeholders and meshes of models loaded outside the asset server can't be referenced
+        let is_model_mesh = entity_ref.contains::<ModelInstance>();
+        if entity_ref.contains::<Gizmos>()
+            || (entity_ref.contains::<MeshRenderer>() && !is_model_mesh)
+        {
+            return;
+        }
+        let model_path = |handle: &Handle<Model>| {
+            world
+                .get_resource::<Assets<Model>>()
+                .and_then(|models| models.path(handle))
+                .map(path_to_string)
+        };
+        let mesh = entity_ref.get::<ModelInstance>().and_then(|instance| {
+            Some(SceneMesh {
+                model: model_path(&instance.model)?,
+                index: instance.mesh_index,
+            })
+        });
+        let material = entity_ref.get::<PBRMaterial>().map(|material| {
+            let images = world.get_resource::<Assets<UploadedImageWithSampler>>();
+            let texture = |handle: &Option<Handle<UploadedImageWithSampler>>| {
+                let handle = handle.as_ref()?;
+                let path = images.and_then(|images| images.path(handle));
+                if path.is_none() {
+                    log::warn!("Saving {:?} without a texture that has no path", entity);
+                }
+                path.map(path_to_string)
+            };
+            SceneMaterial {
+                base_color_texture: texture(&material.base_color_texture),
+                normal_texture: texture(&material.normal_texture),
+                emissive_texture: texture(&material.emissive_texture),
+                roughness: material.roughness,
+                metallic: material.metallic,
+                reflectance: material.reflectance,
+                emissive: material.emissive.map(Into::into),
+                emissive_strength: material.emissive_strength,
+            }
+        });
+
+        let index = self.entities.len();
+        self.entities.push(SceneEntity {
+            parent,
+            name: entity_ref.get::<Name>().map(|it| it.0.clone()),
+            transform: transform.into(),
+            mesh,
+            model: entity_ref
+                .get::<LoadingModel>()
+                .and_then(|it| model_path(&it.model)),
+            material,
+            camera: entity_ref.get::<Camera>().cloned(),
+            camera_controller: entity_ref.contains::<CameraController>(),
+            point_light: entity_ref.get::<PointLight>().map(Into::into),
+            parallel_light: entity_ref.get::<ParallelLight>().map(Into::into),
+            rect_light: entity_ref.get::<RectLight>().map(Into::into),
+            reflection_probe: entity_ref.get::<ReflectionProbe>().map(Into::into),
+            irradiance_volume: entity_ref.get::<IrradianceVolume>().map(Into::into),
+            cast_shadow: entity_ref.contains::<CastShadow>(),
+            main_pass: entity_ref.contains::<MainPassObject>(),
+            rotation_speed: entity_ref.get::<RotationObject>().map(|it| it.speed),
+        });
+        for child in children.get(&entity).into_iter().flatten() {
+            self.capture_entity(world, *child, Some(index), children);
+        }
+    }
+
+    pub fn to_ron(&self) -> Result<String> {
+        let registry = type_registry();
+        let serializer = TypedReflectSerializer::new(self, &registry);
+        Ok(ron::ser::to_string_pretty(
+            &serializer,
+            ron::ser::PrettyConfig::default(),
+        )?)
+    }
+
+    pub fn from_ron(ron: &str) -> Result<SceneFile> {
+        let registry = type_registry();
+        let registration = registry
+            .get(TypeId::of::<SceneFile>())
+            .ok_or(anyhow!("SceneFile isn't registered"))?;
+        let mut processor = SkipUnknownFields::default();
+        let mut deserializer = ron::Deserializer::from_str(ron)?;
+        let value =
+            TypedReflectDeserializer::with_processor(registration, &registry, &mut processor)
+                .deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        for field in &processor.skipped {
+            log::warn!("Skipped unknown scene field {}", field);
+        }
+        let scene = SceneFile::from_reflect(value.as_ref()).ok_or(anyhow!("Malformed scene"))?;
+        if scene.version > SCENE_VERSION {
+            log::warn!(
+                "Scene version {} is newer than {}, some settings may be lost",
+                scene.version,
+                SCENE_VERSION
+            );
+        }
+        Ok(scene)
+    }
+
+    /// Replaces the scene in `world`, gizmos are kept.
+    pub fn spawn(&self, world: &mut World) {
+        let old = world
+            .query_filtered::<Entity, (With<Transform>, Without<Gizmos>)>()
+            .iter(world)
+            .collect::<Vec<_>>();
+        for entity in old {
+            world.despawn(entity);
+        }
+        if let Some(mut sky) = world.get_resource_mut::<ProceduralSky>() {
+            match self.sky {
+                SceneSky::Cubemap => sky.enabled = false,
+                SceneSky::Procedural {
+                    turbidity,
+                    intensity,
+                } => {
+                    sky.enabled = true;
+                    sky.turbidity = turbidity;
+                    sky.intensity = intensity;
+                }
+            }
+        }
+
+        let aspect = world
+            .get_resource::<RenderTargetSize>()
+            .map(|size| size.height as f32 / size.width as f32);
+        let mut spawned = Vec::with_capacity(self.entities.len());
+        for (index, desc) in self.entities.iter().enumerate() {
+            let parent = match desc.parent {
+                Some(parent) if parent < index => Some(spawned[parent]),
+                Some(parent) => {
+                    log::warn!(
+                        "Entity {} has parent {} after it, made a root",
+                        index,
+                        parent
+                    );
+                    None
+                }
+                None => None,
+            };
+            let transform = Transform {
+                parent,
+                ..(&desc.transform).into()
+            };
+            let entity = world.spawn(transform).id();
+            spawned.push(entity);
+            desc.insert_components(world, entity, aspect);
+        }
+
+        if world.query::<&Camera>().iter(world).next().is_none() {
+            log::warn!("Scene has no camera, adding one");
+            world.spawn((
+                Camera::new(aspect.unwrap_or(1.0)),
+                CameraController::default(),
+                Name("Camera".to_string()),
+            ));
+        }
+    }
+}
+
+impl SceneEntity {
+    fn insert_components(&self, world: &mut World, entity: Entity, aspect: Option<f32>) {
+        if let Some(path) = &self.mesh.as_ref().map(|it| path_from_string(&it.model)) {
+            let model = AssetServer::load::<Model>(world, path.clone(), ());
+            let placeholder = world.resource::<PlaceholderMesh>().0.clone();
+            let mesh_renderer = MeshRenderer::new(placeholder, world);
+            let index = self.mesh.as_ref().unwrap().index;
+            world
+                .entity_mut(entity)
+                .insert((mesh_renderer, ModelInstance::new(model, index)));
+        }
+        if let Some(path) = &self.model {
+            let model = AssetServer::load::<Model>(world, path_from_string(path), ());
+            let child_bundle = (CastShadow, MainPassObject, PBRMaterial::default());
+            insert_loading_model(world, entity, model, child_bundle);
+        }
+        if let Some(material) = &self.material {
+            let mut texture = |path: &Option<String>, color_space| {
+                let settings = ImageSettings {
+                    color_space,
+                    ..Default::default()
+                };
+                path.as_ref().map(|path| {
+                    AssetServer::load::<UploadedImageWithSampler>(
+                        world,
+                        path_from_string(path),
+                        settings,
+                    )
+                })
+            };
+            let material = PBRMaterial {
+                base_color_texture: texture(&material.base_color_texture, ColorSpace::Srgb),
+                normal_texture: texture(&material.normal_texture, ColorSpace::Linear),
+                emissive_texture: texture(&material.emissive_texture, ColorSpace::Srgb),
+                roughness: material.roughness,
+                metallic: material.metallic,
+                reflectance: material.reflectance,
+                emissive: material.emissive.map(Vec3::from),
+                emissive_strength: material.emissive_strength,
+            };
+            world.entity_mut(entity).insert(material);
+        }
+
+        let mut entity = world.entity_mut(entity);
+        if let Some(name) = &self.name {
+            entity.insert(Name(name.clone()));
+        }
+        if let Some(camera) = &self.camera {
+            let mut camera = camera.clone();
+            camera.aspect = aspect.unwrap_or(camera.aspect);
+            entity.insert(camera);
+        }
+        if self.camera_controller {
+            entity.insert(CameraController::default());
+        }
+        if let Some(light) = &self.point_light {
+            entity.insert(PointLight::from(light));
+        }
+        if let Some(light) = &self.parallel_light {
+            entity.insert(ParallelLight::from(light));
+        }
+        if let Some(light) = &self.rect_light {
+            entity.insert(RectLight::from(light));
+        }
+        if let Some(probe) = &self.reflection_probe {
+            entity.insert(ReflectionProbe::from(probe));
+        }
+        if let Some(volume) = &self.irradiance_volume {
+            entity.insert(IrradianceVolume::from(volume));
+        }
+        if self.cast_shadow {
+            entity.insert(CastShadow);
+        }
+        if self.main_pass {
+            entity.insert(MainPassObject);
+        }
+        if let Some(speed) = self.rotation_speed {
+            entity.insert(RotationObject { speed });
+        }
+    }
+}
+
+pub fn save_scene(world: &mut World, path: &Path) -> Result<()> {
+    let ron = SceneFile::capture(world).to_ron()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, ron)?;
+    Ok(())
+}
+
+pub fn load_scene(world: &mut World, path: &AssetPath) -> Result<()> {
+    let scene = SceneFile::from_ron(&path.read_to_string()?)?;
+    scene.spawn(world);
+    Ok(())
+}
+
+/// Deserializes structs field by field, skipping the fields they don't have instead of failing.
+#[derive(Default)]
+struct SkipUnknownFields {
+    /// `Type.field` of every skipped field
+    skipped: Vec<String>,
+}
+
+impl ReflectDeserializerProcessor for SkipUnknownFields {
+    fn try_deserialize<'de, D>(
+        &mut self,
+        registration: &TypeRegistration,
+        registry: &TypeRegistry,
+        deserializer: D,
+    ) -> std::result::Result<std::result::Result<Box<dyn PartialReflect>, D>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let TypeInfo::Struct(info) = registration.type_info() else {
+            return Ok(Err(deserializer));
+        };
+        let visitor = StructVisitor {
+            info,
+            registry,
+            processor: self,
+        };
+        let name = info.type_path_table().ident().unwrap_or_default();
+        let value = deserializer.deserialize_struct(name, info.field_names(), visitor)?;
+        Ok(Ok(Box::new(value)))
+    }
+}
+
+struct StructVisitor<'a> {
+    info: &'static StructInfo,
+    registry: &'a TypeRegistry,
+    processor: &'a mut SkipUnknownFields,
+}
+
+impl<'de> Visitor<'de> for StructVisitor<'_> {
+    type Value = DynamicStruct;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "struct {}", self.info.type_path())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut map: A,
+    ) -> std::result::Result<DynamicStruct, A::Error> {
+        let mut value = DynamicStruct::default();
+        while let Some(FieldName(name)) = map.next_key()? {
+            let Some(field) = self.info.field(&name) else {
+                map.next_value::<IgnoredAny>()?;
+                let type_name = self.info.type_path_table().short_path();
+                self.processor
+                    .skipped
+                    .push(format!("{}.{}", type_name, name));
+                continue;
+            };
+            let registration = self.registry.get(field.type_id()).ok_or_else(|| {
+                A::Error::custom(format!("{} isn't registered", field.type_path()))
+            })?;
+            let field_value = map.next_value_seed(TypedReflectDeserializer::with_processor(
+                registration,
+                self.registry,
+                &mut *self.processor,
+            ))?;
+            value.insert_boxed(&name, field_value);
+        }
+        Ok(value)
+    }
+}
+
+/// Struct keys are identifiers in RON, which it won't deserialize as `String`
+struct FieldName(String);
+
+impl<'de> serde::Deserialize<'de> for FieldName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct FieldNameVisitor;
+
+        impl Visitor<'_> for FieldNameVisitor {
+            type Value = FieldName;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a field name")
+            }
+
+            fn visit_str<E: Error>(self, v: &str) -> std::result::Result<FieldName, E> {
+                Ok(FieldName(v.to_string()))
+            }
+        }
+
+        deserializer.deserialize_identifier(FieldNameVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cgmath_ext::Vec4;
+    use cgmath::{Deg, Rotation3};
+
+    #[test]
+    fn test_round_trip() {
+        let mut world = World::new();
+        let parent = world
+            .spawn((
+                Transform {
+                    rotation: Quat::from_angle_y(Deg(30.0)),
+                    ..Transform::with_position(Vec3::new(1.0, 2.0, 3.0))
+                },
+                Name("Rig".to_string()),
+                ParallelLight {
+                    shadow_filter: ShadowFilter::PoissonPcf { radius: 2.5 },
+                    ..Default::default()
+                },
+            ))
+            .id();
+        world.spawn((
+            Transform {
+                parent: Some(parent),
+                ..Default::default()
+            },
+            Camera {
+                fovy: 60.0,
+                ..Camera::new(0.5)
+            },
+            CameraController::default(),
+        ));
+        world.spawn((
+            Transform::default(),
+            RectLight {
+                shape: AreaLightShape::Disk,
+                color: Vec4::new(0.5, 0.25, 1.0, 1.0),
+                ..Default::default()
+            },
+        ));
+
+        let ron = SceneFile::capture(&mut world).to_ron().unwrap();
+        let scene = SceneFile::from_ron(&ron).unwrap();
+        assert_eq!(scene.entities.len(), 3);
+        assert_eq!(scene.entities[1].parent, Some(0));
+        assert_eq!(scene.entities[1].camera.as_ref().unwrap().fovy, 60.0);
+
+        let mut loaded = World::new();
+        scene.spawn(&mut loaded);
+        assert_eq!(SceneFile::capture(&mut loaded).to_ron().unwrap(), ron);
+        let light = loaded
+            .query::<&ParallelLight>()
+            .single(&loaded)
+            .shadow_filter;
+        assert_eq!(light, ShadowFilter::PoissonPcf { radius: 2.5 });
+    }
+
+    #[test]
+    fn test_unknown_and_missing_fields() {
+        let ron = r#"(
+            version: 2,
+            lens_flares: true,
+            sky: Procedural(turbidity: 3.0, intensity: 1.5),
+            entities: [
+                (
+                    name: Some("Lamp"),
+                    transform: (position: (0.0, 1.0, 0.0), skew: (1.0, 2.0)),
+                    point_light: Some((intensity: 4.0, flicker: Some((speed: 2.0)))),
+                ),
+            ],
+        )"#;
+        let scene = SceneFile::from_ron(ron).unwrap();
+        assert_eq!(scene.version, 2);
+        assert_eq!(
+            scene.sky,
+            SceneSky::Procedural {
+                turbidity: 3.0,
+                intensity: 1.5
+            }
+        );
+        let lamp = &scene.entities[0];
+        assert_eq!(lamp.transform.position, [0.0, 1.0, 0.0]);
+        assert_eq!(lamp.transform.scale, [1.0, 1.0, 1.0]);
+        let light = lamp.point_light.as_ref().unwrap();
+        assert_eq!(light.intensity, 4.0);
+        assert_eq!(light.decay, PointLight::default().decay);
+        assert!(!lamp.cast_shadow);
+
+        assert!(SceneFile::from_ron("(entities: 1)").is_err());
+    }
+}
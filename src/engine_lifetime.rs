@@ -1,4 +1,3 @@
-use std::fs;
 use std::sync::Arc;
 
 use crate::cgmath_ext::{Vec3, Vec4, VectorExt};
@@ -11,7 +10,21 @@ use crate::render::defered_rendering::write_g_buffer_pipeline::{
 };
 use crate::render::defered_rendering::{global_binding::GlobalBindGroup, MainPipeline};
 use crate::render::dfg::DFGTexture;
+use crate::render::fog::{
+    sys_refresh_volumetric_fog_lights, sys_render_volumetric_fog, sys_update_fog_uniform,
+    FogSettings, VolumetricFog,
+};
 use crate::render::gizmos::{Gizmos, GizmosGlobalBindGroup, GizmosMaterial, GizmosPipeline};
+use crate::render::irradiance_volume::{
+    sys_update_irradiance_volumes, IrradianceVolume, IrradianceVolumes,
+};
+use crate::render::light::area_light::{
+    event_on_remove_rect_light, sys_render_area_light_geometry, sys_update_area_lights,
+    AreaLightGeometryPipeline, AreaLightShape, RectLight,
+};
+use crate::render::light::cluster::{
+    sys_render_light_clusters, sys_update_light_clusters, LightClusters,
+};
 use crate::render::light::parallel_light::ParallelLight;
 use crate::render::light::point_light::PointLight;
 use crate::render::light::{
@@ -22,22 +35,39 @@ use crate::render::material::buffer_material::BufferMaterialManager;
 use crate::render::material::pbr::{
     sys_update_override_pbr_material_bind_group, PBRMaterial, PBRMaterialBindGroupLayout,
 };
-use crate::render::mipmap::DefaultMipmapGenShader;
+use crate::render::mipmap::MipmapGenerator;
+use crate::render::post_processing::depth_of_field::{sys_update_depth_of_field, DepthOfField};
+use crate::render::post_processing::motion_blur::{
+    sys_render_motion_blur_tiles, sys_update_motion_blur, MotionBlur,
+};
 use crate::render::post_processing::{PostProcessingManager, RenderStage};
-use crate::render::shader_loader::ShaderLoader;
-use crate::render::shadow_mapping::{CastShadow, ShadowMapGlobalBindGroup, ShadowMappingPipeline};
+use crate::render::reflection_probe::{
+    sys_update_reflection_probes, ReflectionProbe, ReflectionProbes,
+};
+use crate::render::shader_loader::{ReloadPipelines, ReloadablePipelines, ShaderLoader};
+use crate::render::shadow_mapping::{
+    sys_update_shadow_map, CastShadow, ShadowMapGlobalBindGroup, ShadowMappingPipeline,
+};
+use crate::render::shadow_moments::{sys_render_shadow_moments, ShadowMoments};
 use crate::render::skybox::prefiltering::PrefilteringPipeline;
+use crate::render::skybox::procedural::{sys_update_procedural_sky, ProceduralSky};
 use crate::render::skybox::{DefaultSkybox, Skybox, SkyboxPipeline};
 use crate::render::systems::{sys_refersh_global_bind_group, PassRenderContext};
-use crate::render::transform::WorldTransform;
+use crate::render::transform::{PreviousWorldTransform, WorldTransform};
+use crate::render::transmission::{sys_render_transmission, Transmission};
 use crate::render::{
     ColorRenderTarget, DefaultMainPipelineMaterial, DepthRenderTarget, FullScreenVertexShader,
-    MainPassObject, MissingTexture, Model, NormalDefaultTexture, ObjectBindGroupLayout,
-    RenderTargetSize, WhiteTexture,
+    MainPassObject, MeshHandle, MissingTexture, Model, NormalDefaultTexture, ObjectBindGroupLayout,
+    PlaceholderMesh, RenderTargetSize, UploadedImageWithSampler, UploadedMesh, WhiteTexture,
 };
 use crate::MainWindow;
 use crate::{
-    asset::{load::Loadable, AssetPath},
+    asset::{
+        load::Loadable,
+        server::{sys_free_unused_assets, sys_upload_decoded_assets, AssetServer, LoadState},
+        watcher::{sys_reload_changed_assets, AssetWatcher},
+        AssetPath, Assets, Handle,
+    },
     engine::input::Input,
     engine::time::Time,
     render::{
@@ -51,11 +81,12 @@ use crate::{
     RenderState, State,
 };
 use bevy_ecs::bundle::Bundle;
-use bevy_ecs::query::{Changed, Or};
+use bevy_ecs::change_detection::{DetectChanges, DetectChangesMut};
 use bevy_ecs::system::{Commands, ResMut, Resource, Single};
-use bevy_ecs::world::{Command, CommandQueue, FromWorld, Mut, World};
+use bevy_ecs::world::{Command, CommandQueue, FromWorld, Mut, Ref, World};
 use bevy_ecs::{
     component::Component,
+    entity::Entity,
     system::{Query, Res, RunSystemOnce},
 };
 use cgmath::{vec2, Deg, InnerSpace, Quaternion, Rad, Rotation3, Vector3};
@@ -80,16 +111,173 @@ pub struct SpawnModelCmd<PB: Bundle, CB: Bundle + Clone> {
 impl<PB: Bundle, CB: Bundle + Clone> Command for SpawnModelCmd<PB, CB> {
     fn apply(self, world: &mut World) {
         let parent = world.spawn(self.parent_bundle).id();
-        for mesh in self.model.meshes.iter() {
-            let uploaded = Arc::new(mesh.upload(world));
-            world.spawn((
-                MeshRenderer::new(uploaded, world),
-                TransformBuilder::default()
-                    .parent(Some(parent))
-                    .build()
-                    .unwrap(),
-                self.child_bundle.clone(),
-            ));
+        spawn_model_children(world, parent, &self.model, self.child_bundle);
+    }
+}
+
+fn spawn_model_children<CB: Bundle + Clone>(
+    world: &mut World,
+    parent: Entity,
+    model: &Model,
+    child_bundle: CB,
+) -> Vec<Entity> {
+    model
+        .uploaded_meshes
+        .iter()
+        .map(|mesh| {
+            world
+                .spawn((
+                    MeshRenderer::from_handle(mesh, world),
+                    MeshHandle(mesh.clone()),
+                    TransformBuilder::default()
+                        .parent(Some(parent))
+                        .build()
+                        .unwrap(),
+                    child_bundle.clone(),
+                ))
+                .id()
+        })
+        .collect()
+}
+
+/// Like [`SpawnModelCmd`], but the model is loaded by the [`AssetServer`] and a
+/// [`PlaceholderMesh`] stands in for it until then.
+pub struct SpawnModelAsyncCmd<PB: Bundle, CB: Bundle + Clone> {
+    model: Handle<Model>,
+    parent_bundle: PB,
+    child_bundle: CB,
+}
+
+type SpawnChildrenFn = Box<dyn Fn(&mut World, Entity, &Model) -> Vec<Entity> + Send + Sync>;
+
+/// On the parent of a model that is still loading.
+#[derive(Component)]
+pub struct LoadingModel {
+    pub model: Handle<Model>,
+    placeholder: Entity,
+    spawn_children: SpawnChildrenFn,
+}
+
+impl<PB: Bundle, CB: Bundle + Clone> Command for SpawnModelAsyncCmd<PB, CB> {
+    fn apply(self, world: &mut World) {
+        let parent = world.spawn(self.parent_bundle).id();
+        insert_loading_model(world, parent, self.model, self.child_bundle);
+    }
+}
+
+/// Spawns a placeholder child under `parent`, replaced by the meshes of `model` once it is loaded.
+pub fn insert_loading_model<CB: Bundle + Clone>(
+    world: &mut World,
+    parent: Entity,
+    model: Handle<Model>,
+    child_bundle: CB,
+) {
+    let placeholder = Arc::clone(&world.resource::<PlaceholderMesh>().0);
+    let placeholder = world
+        .spawn((
+            MeshRenderer::new(placeholder, world),
+            TransformBuilder::default()
+                .parent(Some(parent))
+                .build()
+                .unwrap(),
+            child_bundle.clone(),
+        ))
+        .id();
+    world.entity_mut(parent).insert(LoadingModel {
+        model,
+        placeholder,
+        spawn_children: Box::new(move |world, parent, model| {
+            spawn_model_children(world, parent, model, child_bundle.clone())
+        }),
+    });
+}
+
+/// A child spawned for a mesh of a loaded model, its mesh follows reloads of the model.
+#[derive(Component)]
+pub struct ModelInstance {
+    pub model: Handle<Model>,
+    pub mesh_index: usize,
+    version: u32,
+}
+
+impl ModelInstance {
+    /// Gets its mesh from [`sys_reload_model_instances`] once the model is loaded.
+    pub fn new(model: Handle<Model>, mesh_index: usize) -> Self {
+        Self {
+            model,
+            mesh_index,
+            version: 0,
+        }
+    }
+}
+
+/// Replaces the placeholders of models that finished loading, failed ones keep theirs.
+fn sys_spawn_loaded_models(world: &mut World) {
+    let loading = world
+        .query::<(Entity, &LoadingModel)>()
+        .iter(world)
+        .map(|(entity, loading)| (entity, loading.model.clone()))
+        .collect::<Vec<_>>();
+    for (parent, handle) in loading {
+        match world.resource::<AssetServer>().state(&handle) {
+            Some(LoadState::Loaded) => {}
+            Some(LoadState::Failed(_)) => {
+                world.entity_mut(parent).remove::<LoadingModel>();
+                continue;
+            }
+            _ => continue,
+        }
+        let Some(model) = world.resource::<Assets<Model>>().get(&handle) else {
+            continue;
+        };
+        let loading = world.entity_mut(parent).take::<LoadingModel>().unwrap();
+        world.despawn(loading.placeholder);
+        if let Some(mut transform) = world.get_mut::<Transform>(parent) {
+            transform.children.retain(|it| *it != loading.placeholder);
+        }
+        let version = world.resource::<AssetServer>().version(&handle);
+        let children = (loading.spawn_children)(world, parent, &model);
+        for (mesh_index, child) in children.into_iter().enumerate() {
+            world.entity_mut(child).insert(ModelInstance {
+                model: handle.clone(),
+                mesh_index,
+                version,
+            });
+        }
+    }
+}
+
+/// Swaps in the meshes of reloaded models, meshes added by a reload show up on the next spawn.
+fn sys_reload_model_instances(world: &mut World) {
+    let outdated = world
+        .query::<(Entity, &ModelInstance)>()
+        .iter(world)
+        .filter_map(|(entity, instance)| {
+            let version = world.resource::<AssetServer>().version(&instance.model);
+            (version != instance.version).then(|| (entity, instance.model.clone(), version))
+        })
+        .collect::<Vec<_>>();
+    for (entity, handle, version) in outdated {
+        let Some(model) = world.resource::<Assets<Model>>().get(&handle) else {
+            continue;
+        };
+        let mut entity = world.entity_mut(entity);
+        let mut instance = entity.get_mut::<ModelInstance>().unwrap();
+        instance.version = version;
+        match model.uploaded_meshes.get(instance.mesh_index) {
+            Some(mesh) => {
+                entity.insert(MeshHandle(mesh.clone()));
+            }
+            None => {
+                entity.remove::<MeshHandle>();
+                if let Some(mut mesh_renderer) = entity.get_mut::<MeshRenderer>() {
+                    mesh_renderer.mesh = None;
+                }
+            }
+        }
+        // Rebuilds the material override from the new mesh
+        if let Some(mut material) = entity.get_mut::<PBRMaterial>() {
+            material.set_changed();
         }
     }
 }
@@ -103,12 +291,28 @@ impl State {
         self.world.insert_resource(r);
     }
 
+    /// Like [`State::insert_resource`], the resource is rebuilt when its shaders change.
+    pub fn insert_reloadable_pipeline<R>(&mut self)
+    where
+        R: Resource + FromWorld,
+    {
+        ReloadablePipelines::insert::<R>(&mut self.world);
+    }
+
+    /// Like [`State::insert_reloadable_pipeline`], only the pipelines of the resource are rebuilt.
+    pub fn insert_reloadable_pipelines_of<R>(&mut self)
+    where
+        R: ReloadPipelines,
+    {
+        ReloadablePipelines::insert_stateful::<R>(&mut self.world);
+    }
+
     fn init_egui(&mut self) {
         let renderer = self.world.resource_mut::<EguiRenderer>();
         let ctx = renderer.context();
-        let font_data =
-            fs::read(AssetPath::Assets("fonts/MiSans-Normal.ttf".to_string()).final_path())
-                .unwrap();
+        let font_data = AssetPath::Assets("fonts/MiSans-Normal.ttf".to_string())
+            .read()
+            .unwrap();
         ctx.add_font(egui::epaint::text::FontInsert::new(
             "MiSans",
             egui::FontData::from_owned(font_data),
@@ -122,10 +326,10 @@ impl State {
     pub fn init(&mut self) {
         self.init_egui();
         self.insert_resource::<ShaderLoader>();
+        self.insert_reloadable_pipeline::<MipmapGenerator>();
         self.insert_resource::<WhiteTexture>();
         self.insert_resource::<NormalDefaultTexture>();
         self.insert_resource::<DFGTexture>();
-        self.insert_resource::<DefaultMipmapGenShader>();
         self.insert_resource::<MissingTexture>();
         self.insert_resource::<BufferMaterialManager>();
         self.insert_resource::<RenderTargetSize>();
@@ -142,9 +346,11 @@ impl State {
         // --- Render resource ---
         self.insert_resource::<CameraBuffer>();
         self.insert_resource::<Skybox>();
+        self.insert_reloadable_pipelines_of::<ProceduralSky>();
         self.world
             .insert_resource(LightUnifromBuffer::new(&self.render_state().device));
         self.insert_resource::<ShadowMap>();
+        self.insert_reloadable_pipelines_of::<ShadowMoments>();
         // self.insert_resource::<ShadowMapEguiTextureId>();
 
         self.insert_resource::<FullScreenVertexShader>();
@@ -160,17 +366,25 @@ impl State {
 
         // 1.5
         self.insert_resource::<GBufferTexturesBindGroup>();
+        self.insert_reloadable_pipelines_of::<ReflectionProbes>();
+        self.insert_reloadable_pipelines_of::<IrradianceVolumes>();
         self.insert_resource::<GlobalBindGroup>();
+        self.insert_reloadable_pipelines_of::<VolumetricFog>();
+        self.insert_reloadable_pipeline::<LightClusters>();
 
         // 2. Pipelines
-        self.insert_resource::<WriteGBufferPipeline>();
-        self.insert_resource::<SkyboxPipeline>();
-        self.insert_resource::<MainPipeline>();
-        self.insert_resource::<ShadowMappingPipeline>();
-        self.insert_resource::<GizmosPipeline>();
+        self.insert_reloadable_pipeline::<WriteGBufferPipeline>();
+        self.insert_reloadable_pipeline::<SkyboxPipeline>();
+        self.insert_reloadable_pipeline::<MainPipeline>();
+        self.insert_reloadable_pipeline::<ShadowMappingPipeline>();
+        self.insert_reloadable_pipelines_of::<GizmosPipeline>();
+        self.insert_reloadable_pipeline::<AreaLightGeometryPipeline>();
+        self.insert_reloadable_pipeline::<Transmission>();
 
         // Post Processing
         self.insert_resource::<PostProcessingManager>();
+        self.insert_reloadable_pipelines_of::<DepthOfField>();
+        self.insert_reloadable_pipelines_of::<MotionBlur>();
 
         // --- Other resources ---
         self.insert_resource::<Input>();
@@ -179,10 +393,19 @@ impl State {
         self.world.insert_resource(Time::default());
         self.world.insert_resource(EguiConfig::default());
         self.world.insert_resource(CameraConfig::default());
+        self.world.insert_resource(FogSettings::default());
         self.insert_resource::<DefaultMainPipelineMaterial>();
+        self.insert_resource::<PlaceholderMesh>();
+        self.insert_resource::<AssetServer>();
+        self.insert_resource::<AssetWatcher>();
+        self.world.insert_resource(Assets::<Model>::new());
+        self.world.insert_resource(Assets::<UploadedMesh>::new());
+        self.world
+            .insert_resource(Assets::<UploadedImageWithSampler>::new());
 
         // Add Events'Observers
         self.world.add_observer(event_on_remove_point_light);
+        self.world.add_observer(event_on_remove_rect_light);
 
         {
             // Set egui visual / style / theme
@@ -259,6 +482,21 @@ impl State {
 
     pub fn pre_update(&mut self) {
         self.world.resource_mut::<Time>().update();
+        self.world
+            .run_system_cached(sys_reload_changed_assets)
+            .unwrap();
+        self.world
+            .run_system_cached(sys_upload_decoded_assets)
+            .unwrap();
+        self.world
+            .run_system_cached(sys_spawn_loaded_models)
+            .unwrap();
+        self.world
+            .run_system_cached(sys_reload_model_instances)
+            .unwrap();
+        self.world
+            .run_system_cached(render::sys_update_mesh_renderers)
+            .unwrap();
         self.world.run_system_cached(Input::sys_pre_update).unwrap();
         self.world
             .run_system_cached(editor::sys_on_resize_render_target)
@@ -279,23 +517,41 @@ impl State {
         self.run_system_once(render::transform::sys_update_world_transform);
         self.run_system_once(render::transform::sys_update_children);
 
-        self.run_system_once(sys_update_transform_buffers);
+        self.run_system_cached(sys_update_transform_buffers);
 
         // Update camera uniform
         self.run_system_cached(sys_update_camera_uniform);
+        self.run_system_cached(sys_update_depth_of_field);
+        self.run_system_cached(sys_update_motion_blur);
+
+        // Sky may change the color of the sun, so before the light uniform
+        self.run_system_cached(sys_update_procedural_sky);
 
         // Update light uniform
+        self.run_system_cached(sys_update_shadow_map);
         self.run_system_cached(render::light::sys_update_light_uniform);
+        self.run_system_cached(sys_update_fog_uniform);
+        // Captures are lit with the light uniform of this frame
+        self.run_system_cached(sys_update_reflection_probes);
+        self.run_system_cached(sys_update_irradiance_volumes);
 
         // Clear Down an Up maps
         self.run_system_cached(Input::sys_post_update);
 
         // Dynamic Lights
         self.run_system_cached(sys_update_dynamic_lights);
+        self.run_system_cached(sys_update_area_lights);
         self.run_system_cached(sys_update_dynamic_lights_bind_group);
+        self.run_system_cached(sys_refresh_volumetric_fog_lights);
+        self.run_system_cached(sys_update_light_clusters);
 
         // Override Material
         self.run_system_cached(sys_update_override_pbr_material_bind_group);
+
+        // Free assets that no entity uses anymore
+        self.run_system_cached(sys_free_unused_assets::<Model>);
+        self.run_system_cached(sys_free_unused_assets::<UploadedMesh>);
+        self.run_system_cached(sys_free_unused_assets::<UploadedImageWithSampler>);
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
@@ -326,6 +582,12 @@ impl State {
         world
             .run_system_cached_with(render::systems::sys_render_shadow_mapping_pass, &mut ctx)
             .unwrap();
+        world
+            .run_system_cached_with(sys_render_shadow_moments, &mut ctx)
+            .unwrap();
+        world
+            .run_system_cached_with(sys_render_volumetric_fog, &mut ctx)
+            .unwrap();
         // --------------------------
 
         ctx.stage = RenderStage::BeforeOpaque;
@@ -333,6 +595,10 @@ impl State {
             .run_system_cached_with(render::systems::sys_render_post_processing, &mut ctx)
             .unwrap();
 
+        world
+            .run_system_cached_with(sys_render_light_clusters, &mut ctx)
+            .unwrap();
+
         // PASS: Main ---------------
         world
             .run_system_cached_with(render::systems::sys_render_write_g_buffer_pass, &mut ctx)
@@ -340,8 +606,14 @@ impl State {
         world
             .run_system_cached_with(render::systems::sys_render_main_pass, &mut ctx)
             .unwrap();
+        world
+            .run_system_cached_with(sys_render_area_light_geometry, &mut ctx)
+            .unwrap();
         // -------------------------
 
+        world
+            .run_system_cached_with(sys_render_motion_blur_tiles, &mut ctx)
+            .unwrap();
         ctx.stage = RenderStage::AfterOpaque;
         world
             .run_system_cached_with(render::systems::sys_render_post_processing, &mut ctx)
@@ -352,6 +624,12 @@ impl State {
             .run_system_cached_with(render::systems::sys_render_post_processing, &mut ctx)
             .unwrap();
 
+        // PASS: Transparent --------
+        world
+            .run_system_cached_with(sys_render_transmission, &mut ctx)
+            .unwrap();
+        // -------------------------
+
         ctx.stage = RenderStage::AfterTransparent;
         world
             .run_system_cached_with(render::systems::sys_render_post_processing, &mut ctx)
@@ -453,19 +731,35 @@ pub fn sys_update_camera(
         * Quaternion::from_angle_x(Deg(controller.yaw));
 }
 
-fn sys_update_transform_buffers(world: &mut World) {
-    world.resource_scope(|world, render_state: Mut<RenderState>| {
-        let mut query =
-            world.query_filtered::<(&WorldTransform, &MeshRenderer), Changed<WorldTransform>>();
-        for (world_trans, mesh_renderer) in query.iter(world) {
-            mesh_renderer.update_transform_buffer(&render_state.queue, world_trans.get_uniform());
+fn sys_update_transform_buffers(
+    mut query: Query<(
+        Ref<WorldTransform>,
+        Ref<MeshRenderer>,
+        &mut PreviousWorldTransform,
+    )>,
+    render_state: Res<RenderState>,
+) {
+    for (world_trans, mesh_renderer, mut previous) in query.iter_mut() {
+        // Objects that stopped moving still need one more upload to clear their velocity,
+        // a new mesh renderer comes with a new buffer
+        if !world_trans.is_changed() && !mesh_renderer.is_changed() && previous.at_rest {
+            continue;
         }
-    });
+        let mut uniform = world_trans.get_uniform();
+        let model = uniform.model.into();
+        if let Some(prev_model) = previous.model {
+            uniform.prev_model = prev_model.into();
+        }
+        previous.at_rest = previous.model == Some(model);
+        previous.model = Some(model);
+        mesh_renderer.update_transform_buffer(&render_state.queue, uniform);
+    }
 }
 
+/// Runs every frame so that the previous view projection stays one frame behind.
 fn sys_update_camera_uniform(
-    render_camera: Res<CameraBuffer>,
-    single: Single<(&Camera, &WorldTransform), Or<(Changed<Camera>, Changed<WorldTransform>)>>,
+    mut render_camera: ResMut<CameraBuffer>,
+    single: Single<(&Camera, &WorldTransform)>,
     rs: Res<RenderState>,
 ) {
     let (camera, transform) = single.into_inner();
@@ -503,16 +797,57 @@ fn sys_startup_scene(world: &mut World) {
         });
     }
 
-    let dragon_model = Arc::new(
-        render::Model::load(
-            AssetPath::Assets("models/DragonAttenuation.glb".to_string()),
-            world,
-        )
-        .unwrap(),
-    );
-    let plane_model = Arc::new(
-        render::Model::load(AssetPath::Assets("models/plane.glb".to_string()), world).unwrap(),
+    let dragon_model = AssetServer::load::<Model>(
+        world,
+        AssetPath::Assets("models/DragonAttenuation.glb".to_string()),
+        (),
     );
+    let plane_model =
+        AssetServer::load::<Model>(world, AssetPath::Assets("models/plane.glb".to_string()), ());
+
+    world.spawn((
+        RectLight {
+            color: Vec4::new(1.0, 0.8, 0.6, 1.0),
+            width: 2.0,
+            height: 1.0,
+            ..Default::default()
+        },
+        Transform {
+            // Facing down
+            rotation: Quaternion::from_angle_x(Deg(-90.0)),
+            ..Transform::with_position(Vec3::new(2.0, 2.5, -1.0))
+        },
+        Name("Rect Light".to_string()),
+    ));
+    world.spawn((
+        RectLight {
+            color: Vec4::new(0.6, 0.8, 1.0, 1.0),
+            shape: AreaLightShape::Disk,
+            ..Default::default()
+        },
+        Transform {
+            // Facing down
+            rotation: Quaternion::from_angle_x(Deg(-90.0)),
+            ..Transform::with_position(Vec3::new(7.0, 2.5, -1.0))
+        },
+        Name("Disk Light".to_string()),
+    ));
+    world.spawn((
+        ReflectionProbe {
+            half_extents: Vec3::new(8.0, 3.0, 6.0),
+            ..Default::default()
+        },
+        Transform::with_position(Vec3::new(4.0, 1.5, 2.0)),
+        Name("Reflection Probe".to_string()),
+    ));
+    world.spawn((
+        IrradianceVolume {
+            half_extents: Vec3::new(8.0, 2.0, 6.0),
+            ..Default::default()
+        },
+        Transform::with_position(Vec3::new(4.0, 1.0, 2.0)),
+        Name("Irradiance Volume".to_string()),
+    ));
 
     let mut queue = CommandQueue::from_world(world);
 
@@ -528,11 +863,10 @@ fn sys_startup_scene(world: &mut World) {
 
     let mut cmd = Commands::new(&mut queue, world);
 
-    for mesh in arrow.meshes {
-        let uploaded = Arc::new(mesh.upload(world));
-
+    for mesh in arrow.uploaded_meshes {
         cmd.spawn((
-            MeshRenderer::new(uploaded, world),
+            MeshRenderer::from_handle(&mesh, world),
+            MeshHandle(mesh),
             {
                 Gizmos {
                     instance: Arc::clone(&instance),
@@ -544,7 +878,7 @@ fn sys_startup_scene(world: &mut World) {
 
     let count = 5;
     for i in 0..5 {
-        cmd.queue(SpawnModelCmd {
+        cmd.queue(SpawnModelAsyncCmd {
             model: dragon_model.clone(),
             parent_bundle: (
                 TransformBuilder::default()
@@ -567,8 +901,8 @@ fn sys_startup_scene(world: &mut World) {
         });
     }
 
-    cmd.queue(SpawnModelCmd {
-        model: plane_model.clone(),
+    cmd.queue(SpawnModelAsyncCmd {
+        model: plane_model,
         parent_bundle: (
             TransformBuilder::default()
                 .position(Vec3::new_y(-1.0))
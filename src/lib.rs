@@ -3,6 +3,7 @@ use bevy_ecs::world::World;
 use bevy_ecs::{change_detection::Mut, system::IntoSystem};
 use egui_tools::EguiRenderer;
 use pollster::block_on;
+use render::defered_rendering::write_g_buffer_pipeline::g_buffer_bytes_per_sample;
 use std::sync::Arc;
 use wgpu::{Features, Instance, Surface};
 use winit::{
@@ -20,14 +21,32 @@ mod macro_utils;
 mod render;
 pub mod wgpu_init;
 
+pub use asset::source::{AssetReader, AssetSources, ZipReader};
+
 lazy_static::lazy_static! {
     pub static ref DEVICE_FEATURES: Arc<Vec<Features>> = Arc::new(vec![
         Features::TIMESTAMP_QUERY
     ]);
+    /// Requested only when the adapter supports them
+    pub static ref OPTIONAL_DEVICE_FEATURES: Arc<Vec<Features>> = Arc::new(vec![
+        Features::TEXTURE_COMPRESSION_BC,
+        Features::TEXTURE_COMPRESSION_ETC2,
+        Features::TEXTURE_COMPRESSION_ASTC,
+    ]);
 }
 
+/// Caches every model under the asset root, see `examples/process_assets.rs`.
+pub fn process_assets() -> usize {
+    asset::processed::process_models()
+}
+
+/// `--assets <dir>` and `--pack <file.zip>` select where the assets are read from,
+/// see [`AssetSources::apply_args`].
 pub async fn run() {
     env_logger::init();
+    if let Err(e) = AssetSources::apply_args(std::env::args().skip(1)) {
+        log::error!("{:#}", e);
+    }
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
     let mut app = App::new();
@@ -216,17 +235,31 @@ impl RenderState {
             for feat in DEVICE_FEATURES.iter() {
                 ret |= *feat;
             }
+            for feat in OPTIONAL_DEVICE_FEATURES.iter() {
+                ret |= *feat & adapter.features();
+            }
             ret
         };
+        let required_limits = wgpu::Limits {
+            max_color_attachment_bytes_per_sample: g_buffer_bytes_per_sample(),
+            ..if cfg!(target_arch = "wasm32") {
+                wgpu::Limits::downlevel_webgl2_defaults()
+            } else {
+                wgpu::Limits::default()
+            }
+        };
+        let allowed = adapter.limits().max_color_attachment_bytes_per_sample;
+        assert!(
+            allowed >= required_limits.max_color_attachment_bytes_per_sample,
+            "The G-buffer needs {} color attachment bytes per sample, the adapter allows {}",
+            required_limits.max_color_attachment_bytes_per_sample,
+            allowed
+        );
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     required_features,
-                    required_limits: if cfg!(target_arch = "wasm32") {
-                        wgpu::Limits::downlevel_webgl2_defaults()
-                    } else {
-                        wgpu::Limits::default()
-                    },
+                    required_limits,
                     label: None,
                     memory_hints: Default::default(),
                 },
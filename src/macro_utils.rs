@@ -1,14 +1,21 @@
 use wgpu::{BindGroupLayoutEntry, BindingType, ShaderStages};
 
 #[allow(unused)]
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub enum BGLEntry {
     UniformBuffer(),
     /// `(is_read_only: bool)`
     StorageBuffer(bool),
     /// `(multisampled: bool, texture_sample_type: wgpu::TextureSampleType)`
     Tex2D(bool, wgpu::TextureSampleType),
+    Tex2DArray(wgpu::TextureSampleType),
     TexCube(bool, wgpu::TextureSampleType),
+    TexCubeArray(wgpu::TextureSampleType),
+    Tex3D(wgpu::TextureSampleType),
+    /// `(access: wgpu::StorageTextureAccess, format: wgpu::TextureFormat)`
+    StorageTex2D(wgpu::StorageTextureAccess, wgpu::TextureFormat),
+    /// `(access: wgpu::StorageTextureAccess, format: wgpu::TextureFormat)`
+    StorageTex3D(wgpu::StorageTextureAccess, wgpu::TextureFormat),
     Sampler(wgpu::SamplerBindingType),
     Raw(BindGroupLayoutEntry),
 }
@@ -42,11 +49,36 @@ impl BGLEntry {
                         view_dimension: wgpu::TextureViewDimension::D2,
                         multisampled,
                     },
+                    BGLEntry::Tex2DArray(texture_sample_type) => BindingType::Texture {
+                        sample_type: texture_sample_type,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
                     BGLEntry::TexCube(multisampled, texture_sample_type) => BindingType::Texture {
                         sample_type: texture_sample_type,
                         view_dimension: wgpu::TextureViewDimension::Cube,
                         multisampled,
                     },
+                    BGLEntry::TexCubeArray(texture_sample_type) => BindingType::Texture {
+                        sample_type: texture_sample_type,
+                        view_dimension: wgpu::TextureViewDimension::CubeArray,
+                        multisampled: false,
+                    },
+                    BGLEntry::Tex3D(texture_sample_type) => BindingType::Texture {
+                        sample_type: texture_sample_type,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    BGLEntry::StorageTex2D(access, format) => BindingType::StorageTexture {
+                        access,
+                        format,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    BGLEntry::StorageTex3D(access, format) => BindingType::StorageTexture {
+                        access,
+                        format,
+                        view_dimension: wgpu::TextureViewDimension::D3,
+                    },
                     BGLEntry::Sampler(sampler_binding_type) => {
                         wgpu::BindingType::Sampler(sampler_binding_type)
                     }
@@ -1,8 +1,11 @@
 use std::sync::Arc;
 
 use bevy_ecs::component::Component;
-use bevy_ecs::{system::Resource, world::FromWorld};
-use bevy_reflect::Reflect;
+use bevy_ecs::{
+    system::Resource,
+    world::{FromWorld, World},
+};
+use bevy_reflect::{std_traits::ReflectDefault, Reflect};
 use cgmath::{perspective, Matrix4};
 use wgpu::BufferDescriptor;
 
@@ -13,9 +16,12 @@ use super::transform::{Transform, WorldTransform};
 #[derive(Resource)]
 pub struct CameraBuffer {
     pub buffer: Arc<wgpu::Buffer>,
+    /// View projection uploaded last frame
+    pub prev_view_proj: Option<Matrix4<f32>>,
 }
 
 #[derive(Component, Clone, Reflect)]
+#[reflect(Default)]
 #[require(Transform)]
 pub struct Camera {
     // Height / Width
@@ -23,6 +29,19 @@ pub struct Camera {
     pub fovy: f32,
     pub znear: f32,
     pub zfar: f32,
+    /// Aperture in f-stops
+    pub aperture: f32,
+    /// Distance from the camera to the focal plane in meters
+    pub focal_distance: f32,
+    /// Sensor height in millimeters, 24mm is the full frame height
+    pub sensor_height: f32,
+    /// Shutter speed in seconds
+    pub shutter_speed: f32,
+    /// Fraction of the frame the shutter is open in degrees, 360 blurs over the whole frame
+    pub shutter_angle: f32,
+    pub iso: f32,
+    /// Derive exposure from aperture, shutter speed and ISO instead of using 1.0
+    pub physical_exposure: bool,
 }
 
 #[derive(Component, Default)]
@@ -38,11 +57,20 @@ impl FromWorld for CameraBuffer {
     }
 }
 
+impl Default for Camera {
+    fn default() -> Self {
+        Camera::new(1.0)
+    }
+}
+
 impl Camera {
     pub fn build_view_projection_matrix(&self, transform: &WorldTransform) -> Matrix4<f32> {
-        let view = transform.view_matrix();
+        self.projection_matrix() * transform.view_matrix()
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
         let proj = perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
-        OPENGL_TO_WGPU_MATRIX * proj * view
+        OPENGL_TO_WGPU_MATRIX * proj
     }
 
     pub fn new(aspect: f32) -> Camera {
@@ -51,16 +79,57 @@ impl Camera {
             fovy: 45.0,
             znear: 0.01,
             zfar: 100.0,
+            aperture: 16.0,
+            focal_distance: 5.0,
+            sensor_height: 24.0,
+            shutter_speed: 1.0 / 125.0,
+            shutter_angle: 180.0,
+            iso: 100.0,
+            physical_exposure: false,
+        }
+    }
+
+    /// The first camera of `world`, the default one before any is spawned.
+    pub fn first_or_default(world: &mut World) -> Camera {
+        let mut q_camera = world.query::<&Camera>();
+        q_camera.iter(world).next().cloned().unwrap_or_default()
+    }
+
+    /// Focal length in meters, derived from the vertical fov and the sensor height.
+    pub fn focal_length(&self) -> f32 {
+        let half_fovy = cgmath::Rad::from(cgmath::Deg(self.fovy * 0.5)).0;
+        self.sensor_height * 0.001 * 0.5 / half_fovy.tan()
+    }
+
+    /// Diameter of the entrance pupil in meters.
+    pub fn aperture_diameter(&self) -> f32 {
+        self.focal_length() / self.aperture.max(0.1)
+    }
+
+    /// Exposure value at ISO 100.
+    pub fn ev100(&self) -> f32 {
+        (self.aperture * self.aperture / self.shutter_speed * 100.0 / self.iso).log2()
+    }
+
+    /// Multiplier applied to the lit color. Saturation based, see "Moving Frostbite to PBR".
+    pub fn exposure(&self) -> f32 {
+        if self.physical_exposure {
+            1.0 / (1.2 * 2f32.powf(self.ev100()))
+        } else {
+            1.0
         }
     }
 
     pub fn get_uniform(&self, transform: &WorldTransform) -> CameraUniform {
         let pos = transform.position;
         let dir = transform.forward();
+        let view_proj = self.build_view_projection_matrix(transform).into();
         CameraUniform {
-            view_proj: self.build_view_projection_matrix(transform).into(),
+            view_proj,
             position: [pos.x, pos.y, pos.z, 1.],
-            direction: [dir.x, dir.y, dir.z, 1.],
+            direction: [dir.x, dir.y, dir.z],
+            exposure: self.exposure(),
+            prev_view_proj: view_proj,
         }
     }
 }
@@ -87,20 +156,22 @@ impl CameraBuffer {
 
         CameraBuffer {
             buffer: Arc::new(camera_buffer),
+            prev_view_proj: None,
         }
     }
 
     pub fn update_uniform2gpu(
-        &self,
+        &mut self,
         camera: &Camera,
         transform: &WorldTransform,
         queue: &wgpu::Queue,
     ) {
-        queue.write_buffer(
-            &self.buffer,
-            0,
-            bytemuck::cast_slice(&[camera.get_uniform(transform)]),
-        );
+        let mut uniform = camera.get_uniform(transform);
+        if let Some(prev) = self.prev_view_proj {
+            uniform.prev_view_proj = prev.into();
+        }
+        self.prev_view_proj = Some(uniform.view_proj.into());
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
     }
 }
 
@@ -109,7 +180,9 @@ impl CameraBuffer {
 pub struct CameraUniform {
     pub view_proj: [[f32; 4]; 4],
     pub position: [f32; 4],
-    pub direction: [f32; 4],
+    pub direction: [f32; 3],
+    pub exposure: f32,
+    pub prev_view_proj: [[f32; 4]; 4],
 }
 
 impl_pod_zeroable!(CameraUniform);
@@ -3,16 +3,20 @@ use std::sync::Arc;
 use wgpu::{BindGroup, BindGroupLayout, BindingResource, ShaderStages, TextureViewDescriptor};
 
 use crate::{
-    asset::{load::Loadable, AssetPath},
+    asset::AssetPath,
     bg_descriptor, bg_layout_descriptor,
     macro_utils::BGLEntry,
-    render::skybox::{DefaultSkybox, Skybox},
+    render::{
+        irradiance_volume::IrradianceVolumes, reflection_probe::ReflectionProbes,
+        shadow_moments::ShadowMoments,
+        skybox::{DefaultSkybox, Skybox},
+    },
     RenderState,
 };
 
 use super::super::{
     camera::CameraBuffer, cubemap::CubemapConverterRgba8unorm, dfg::DFGTexture,
-    light::LightUnifromBuffer, shadow_mapping::ShadowMap, UploadedImageWithSampler,
+    light::LightUnifromBuffer, shadow_mapping::ShadowMap, ImageSettings, UploadedImageWithSampler,
 };
 
 #[derive(Resource)]
@@ -22,9 +26,13 @@ pub struct GlobalBindGroup {
 }
 impl FromWorld for GlobalBindGroup {
     fn from_world(world: &mut World) -> Self {
-        let hdri = UploadedImageWithSampler::load(
+        let hdri = UploadedImageWithSampler::load_with_settings(
             AssetPath::Assets("textures/hdr/qwantani_afternoon_2k.hdr".to_string()),
             world,
+            ImageSettings {
+                mipmaps: false,
+                ..Default::default()
+            },
         )
         .unwrap();
 
@@ -33,6 +41,9 @@ impl FromWorld for GlobalBindGroup {
         let rs = world.resource::<RenderState>();
         let device = &rs.device;
         let shadow_map = world.resource::<ShadowMap>();
+        let probes = world.resource::<ReflectionProbes>();
+        let irradiance_volumes = world.resource::<IrradianceVolumes>();
+        let shadow_moments = world.resource::<ShadowMoments>();
 
         let bind_group_layout_desc = bg_layout_descriptor! {
             ["Main PBR Global Bind Group Layout"]
@@ -43,6 +54,12 @@ impl FromWorld for GlobalBindGroup {
             4: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, wgpu::TextureSampleType::Float { filterable: true }); // DFG
             5: ShaderStages::FRAGMENT => BGLEntry::TexCube(false, wgpu::TextureSampleType::Float { filterable: true }); // Skybox
             6: ShaderStages::FRAGMENT => BGLEntry::Sampler(wgpu::SamplerBindingType::Filtering); // Skybox
+            7: ShaderStages::FRAGMENT => BGLEntry::TexCubeArray(wgpu::TextureSampleType::Float { filterable: true }); // Reflection Probes
+            8: ShaderStages::FRAGMENT => BGLEntry::UniformBuffer(); // Reflection Probes
+            9: ShaderStages::FRAGMENT => BGLEntry::Tex3D(wgpu::TextureSampleType::Float { filterable: true }); // Irradiance Volumes
+            10: ShaderStages::FRAGMENT => BGLEntry::UniformBuffer(); // Irradiance Volumes
+            11: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, wgpu::TextureSampleType::Float { filterable: true }); // Shadow Moments
+            12: ShaderStages::FRAGMENT => BGLEntry::Sampler(wgpu::SamplerBindingType::Filtering); // Shadow Moments
         };
 
         let layout = Arc::new(device.create_bind_group_layout(&bind_group_layout_desc));
@@ -73,6 +90,12 @@ impl FromWorld for GlobalBindGroup {
             4: BindingResource::TextureView(&dfg.texture.view);
             5: BindingResource::TextureView(&view);
             6: BindingResource::Sampler(&dfg.texture.sampler); // todo cubemap sampler
+            7: BindingResource::TextureView(&probes.cubemaps.view);
+            8: probes.uniform_buffer.as_entire_binding();
+            9: BindingResource::TextureView(&irradiance_volumes.texture.view);
+            10: irradiance_volumes.uniform_buffer.as_entire_binding();
+            11: BindingResource::TextureView(&shadow_moments.image().view);
+            12: BindingResource::Sampler(&shadow_moments.image().sampler);
         };
 
         let bind_group = Arc::new(device.create_bind_group(&bind_group_desc));
@@ -99,6 +122,9 @@ fn refresh_global_bind_group(
     light: Res<LightUnifromBuffer>,
     shadow_map: Res<ShadowMap>,
     dfg: Res<DFGTexture>,
+    probes: Res<ReflectionProbes>,
+    irradiance_volumes: Res<IrradianceVolumes>,
+    shadow_moments: Res<ShadowMoments>,
 ) {
     let device = &rs.device;
     let skybox_texture = skybox.texture.as_ref().unwrap_or(&default_skybox.texture);
@@ -112,6 +138,12 @@ fn refresh_global_bind_group(
         4: BindingResource::TextureView(&dfg.texture.view);
         5: BindingResource::TextureView(&skybox_texture.view);
         6: BindingResource::Sampler(&dfg.texture.sampler); // todo cubemap sampler
+        7: BindingResource::TextureView(&probes.cubemaps.view);
+        8: probes.uniform_buffer.as_entire_binding();
+        9: BindingResource::TextureView(&irradiance_volumes.texture.view);
+        10: irradiance_volumes.uniform_buffer.as_entire_binding();
+        11: BindingResource::TextureView(&shadow_moments.image().view);
+        12: BindingResource::Sampler(&shadow_moments.image().sampler);
     };
 
     global_bind_group.bind_group = Arc::new(device.create_bind_group(&bind_group_desc));
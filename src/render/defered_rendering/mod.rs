@@ -7,7 +7,10 @@ use write_g_buffer_pipeline::GBufferTexturesBindGroup;
 
 use crate::{asset::AssetPath, wgpu_init, RenderState};
 
-use super::{light::DynamicLightBindGroup, shader_loader::ShaderLoader, FullScreenVertexShader};
+use super::{
+    fog::VolumetricFog, light::DynamicLightBindGroup, shader_loader::ShaderLoader,
+    FullScreenVertexShader,
+};
 
 pub mod global_binding;
 pub mod write_g_buffer_pipeline;
@@ -38,6 +41,7 @@ impl FromWorld for MainPipeline {
             Arc::clone(&world.resource::<GlobalBindGroup>().layout),
             Arc::clone(&world.resource::<GBufferTexturesBindGroup>().layout),
             Arc::clone(&world.resource::<DynamicLightBindGroup>().layout),
+            Arc::clone(&world.resource::<VolumetricFog>().layout),
         ];
 
         let render_pipeline_layout =
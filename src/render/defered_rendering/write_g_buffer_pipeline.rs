@@ -14,6 +14,23 @@ use crate::{
 
 use super::GlobalBindGroup;
 
+/// Targets written by [`WriteGBufferPipeline`], in location order.
+pub const G_BUFFER_FORMATS: [(&str, TextureFormat); 4] = [
+    ("World Pos", TextureFormat::Rgba16Float),
+    ("G-Buffer", TextureFormat::Rgba32Uint),
+    ("Velocity", TextureFormat::Rg16Float),
+    ("G-Buffer Extra", TextureFormat::Rg32Uint),
+];
+
+/// Color attachment bytes per sample of [`G_BUFFER_FORMATS`], counted as wgpu validates them.
+/// More than the default `max_color_attachment_bytes_per_sample`, so the device asks for it.
+pub fn g_buffer_bytes_per_sample() -> u32 {
+    G_BUFFER_FORMATS.iter().fold(0, |total, (_, format)| {
+        total.next_multiple_of(format.target_component_alignment().unwrap())
+            + format.target_pixel_byte_cost().unwrap()
+    })
+}
+
 #[derive(Resource, Clone)]
 pub struct GBufferTexturesBindGroup {
     pub sampler: Arc<Sampler>,
@@ -55,19 +72,18 @@ impl GBufferTexturesBindGroup {
         sampler: &Sampler,
         layout: &BindGroupLayout,
     ) -> (Vec<GBufferTexture>, Arc<BindGroup>) {
-        let textures: Vec<GBufferTexture> = vec![
-            ("World Pos", TextureFormat::Rgba16Float),
-            ("G-Buffer", TextureFormat::Rgba32Uint),
-        ]
-        .into_iter()
-        .map(|(label, format)| create_g_buffer_image(label, device, size, format))
-        .collect();
+        let textures: Vec<GBufferTexture> = G_BUFFER_FORMATS
+            .into_iter()
+            .map(|(label, format)| create_g_buffer_image(label, device, size, format))
+            .collect();
 
         let bind_group = Arc::new(device.create_bind_group(&bg_descriptor! {
             ["GBuffer Textures"][&layout]
             0: BindingResource::Sampler(sampler);
             1: BindingResource::TextureView(&textures[0].image.view);
             2: BindingResource::TextureView(&textures[1].image.view);
+            3: BindingResource::TextureView(&textures[2].image.view);
+            4: BindingResource::TextureView(&textures[3].image.view);
         }));
 
         (textures, bind_group)
@@ -96,6 +112,8 @@ impl GBufferTexturesBindGroup {
             0: ShaderStages::FRAGMENT => BGLEntry::Sampler(wgpu::SamplerBindingType::NonFiltering); // Universal Sampler
             1: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, wgpu::TextureSampleType::Float { filterable: false }); // World Pos
             2: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, wgpu::TextureSampleType::Uint); // G-Buffer
+            3: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, wgpu::TextureSampleType::Float { filterable: false }); // Velocity
+            4: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, wgpu::TextureSampleType::Uint); // G-Buffer Extra
         }));
         let (textures, bind_group) =
             Self::create_textures_and_bind_groups(device, size, &sampler, &layout);
@@ -175,18 +193,14 @@ impl FromWorld for WriteGBufferPipeline {
                 push_constant_ranges: &[],
             });
 
-        let targets = [
-            // World Position
-            Some(wgpu_init::color_target_replace_write_all(
-                wgpu::TextureFormat::Rgba16Float,
-            )),
-            // G-Buffer
+        // Blending doesn't apply to the integer targets
+        let targets = G_BUFFER_FORMATS.map(|(_, format)| {
             Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Rgba32Uint,
+                format,
                 blend: None,
                 write_mask: ColorWrites::ALL,
-            }),
-        ];
+            })
+        });
 
         let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Write G-Buffer"),
@@ -2,9 +2,7 @@ use std::sync::Arc;
 
 use bevy_ecs::prelude::*;
 
-use crate::asset::load::Loadable;
-
-use super::UploadedImageWithSampler;
+use super::{ColorSpace, ImageSettings, UploadedImageWithSampler};
 
 #[derive(Resource)]
 pub struct DFGTexture {
@@ -13,9 +11,13 @@ pub struct DFGTexture {
 impl FromWorld for DFGTexture {
     fn from_world(world: &mut World) -> Self {
         let texture = Arc::new(
-            UploadedImageWithSampler::load(
+            UploadedImageWithSampler::load_with_settings(
                 crate::asset::AssetPath::Assets("textures/ibl_brdf_lut.png".to_string()),
                 world,
+                ImageSettings {
+                    color_space: ColorSpace::Linear,
+                    mipmaps: false,
+                },
             )
             .unwrap(),
         );
@@ -0,0 +1,386 @@
+use std::sync::Arc;
+
+use bevy_ecs::system::InMut;
+use cgmath::SquareMatrix;
+use wgpu::{
+    BindGroup, BindGroupLayout, BindingResource, BufferDescriptor, BufferUsages, ComputePipeline,
+    Device, ShaderModule, StorageTextureAccess,
+};
+
+use crate::{asset::AssetPath, bg_descriptor, impl_pod_zeroable, macro_utils::BGLEntry};
+
+use super::{
+    camera::Camera,
+    light::{DynamicLightBindGroup, LightUnifromBuffer},
+    prelude::*,
+    shader_loader::{ReloadPipelines, ShaderLoader},
+    shadow_mapping::ShadowMap,
+    systems::PassRenderContext,
+};
+
+/// Froxel grid resolution, x and y follow the screen, z is the distance along the view ray.
+pub const FROXEL_GRID: [u32; 3] = [160, 90, 64];
+const FROXEL_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+const WORKGROUP_SIZE: u32 = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FogMode {
+    Off,
+    /// Analytic height fog, lit by the ambient term and an unshadowed parallel light
+    Exponential,
+    /// Froxel based, with shadowed light shafts from the parallel light and point lights
+    Volumetric,
+}
+
+#[derive(Resource, Clone, Debug)]
+pub struct FogSettings {
+    pub mode: FogMode,
+    /// Scattering albedo
+    pub color: Vec3,
+    /// Extinction per meter at `base_height`
+    pub density: f32,
+    /// How fast the density falls off above `base_height`, per meter
+    pub height_falloff: f32,
+    pub base_height: f32,
+    /// Henyey-Greenstein `g`, positive scatters forward
+    pub anisotropy: f32,
+    /// Light scattered uniformly in all directions, so shadowed fog is not black
+    pub ambient: f32,
+    /// Far end of the froxel grid in meters
+    pub max_distance: f32,
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        Self {
+            mode: FogMode::Off,
+            color: Vec3::new(0.8, 0.85, 0.9),
+            density: 0.05,
+            height_falloff: 0.2,
+            base_height: 0.0,
+            anisotropy: 0.6,
+            ambient: 0.1,
+            max_distance: 50.0,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct FogUniform {
+    pub inv_view_proj: [[f32; 4]; 4],
+    pub camera_position: [f32; 4],
+    pub color: [f32; 4],
+    pub density: f32,
+    pub height_falloff: f32,
+    pub base_height: f32,
+    pub anisotropy: f32,
+    pub max_distance: f32,
+    /// 0: off, 1: exponential, 2: volumetric
+    pub mode: u32,
+    pub ambient: f32,
+    pub padding: f32,
+}
+
+impl_pod_zeroable!(FogUniform);
+
+impl FogSettings {
+    pub fn uniform(&self, camera: &Camera, transform: &WorldTransform) -> FogUniform {
+        let inv_view_proj = camera
+            .build_view_projection_matrix(transform)
+            .invert()
+            .unwrap_or(Mat4::identity());
+        let pos = transform.position;
+        FogUniform {
+            inv_view_proj: inv_view_proj.into(),
+            camera_position: [pos.x, pos.y, pos.z, 1.0],
+            color: [self.color.x, self.color.y, self.color.z, 1.0],
+            density: self.density,
+            height_falloff: self.height_falloff,
+            base_height: self.base_height,
+            anisotropy: self.anisotropy,
+            max_distance: self.max_distance.max(0.1),
+            mode: match self.mode {
+                FogMode::Off => 0,
+                FogMode::Exponential => 1,
+                FogMode::Volumetric => 2,
+            },
+            ambient: self.ambient,
+            padding: 0.0,
+        }
+    }
+}
+
+/// Froxel volumes and the bind group the deferred composite reads the fog from.
+#[derive(Resource)]
+pub struct VolumetricFog {
+    pub uniform_buffer: Arc<wgpu::Buffer>,
+    pub layout: Arc<BindGroupLayout>,
+    pub bind_group: Arc<BindGroup>,
+    inject_pipeline: ComputePipeline,
+    inject_layout: BindGroupLayout,
+    inject_bind_group: BindGroup,
+    integrate_pipeline: ComputePipeline,
+    integrate_layout: BindGroupLayout,
+    integrate_bind_group: BindGroup,
+    #[allow(unused)]
+    scattering: wgpu::Texture,
+    scattering_view: wgpu::TextureView,
+    #[allow(unused)]
+    integrated: wgpu::Texture,
+}
+
+fn create_froxel_texture(label: &str, device: &Device) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: Extent3d {
+            width: FROXEL_GRID[0],
+            height: FROXEL_GRID[1],
+            depth_or_array_layers: FROXEL_GRID[2],
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: FROXEL_FORMAT,
+        usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
+fn create_compute_pipeline(
+    label: &str,
+    layout: &BindGroupLayout,
+    shader: &ShaderModule,
+    device: &Device,
+) -> ComputePipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(label),
+        layout: Some(&pipeline_layout),
+        module: shader,
+        entry_point: Some("cs_main"),
+        compilation_options: Default::default(),
+        cache: None,
+    })
+}
+
+fn create_inject_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    uniform_buffer: &wgpu::Buffer,
+    light: &LightUnifromBuffer,
+    dynamic_lights: &DynamicLightBindGroup,
+    shadow_map: &ShadowMap,
+    scattering_view: &wgpu::TextureView,
+) -> BindGroup {
+    device.create_bind_group(&bg_descriptor! {
+        ["Volumetric Fog Inject"] [layout]
+        0: uniform_buffer.as_entire_binding();
+        1: light.buffer.as_entire_binding();
+        2: dynamic_lights.point_lights_storage_buffer.as_entire_binding();
+        3: BindingResource::TextureView(&shadow_map.image.view);
+        4: BindingResource::Sampler(&shadow_map.image.sampler);
+        5: BindingResource::TextureView(scattering_view);
+    })
+}
+
+impl FromWorld for VolumetricFog {
+    fn from_world(world: &mut World) -> Self {
+        let device = &world.resource::<RenderState>().device;
+        let light = world.resource::<LightUnifromBuffer>();
+        let dynamic_lights = world.resource::<DynamicLightBindGroup>();
+        let shadow_map = world.resource::<ShadowMap>();
+
+        let uniform_buffer = Arc::new(device.create_buffer(&BufferDescriptor {
+            label: Some("Fog Uniform Buffer"),
+            size: size_of::<FogUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+        let scattering = create_froxel_texture("Froxel Scattering", device);
+        let integrated = create_froxel_texture("Froxel Integrated", device);
+        let scattering_view = scattering.create_view(&Default::default());
+        let integrated_view = integrated.create_view(&Default::default());
+
+        let inject_layout = device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Volumetric Fog Inject"]
+            0: ShaderStages::COMPUTE => BGLEntry::UniformBuffer(); // Fog
+            1: ShaderStages::COMPUTE => BGLEntry::UniformBuffer(); // Light
+            2: ShaderStages::COMPUTE => BGLEntry::StorageBuffer(true); // Point Lights
+            3: ShaderStages::COMPUTE => BGLEntry::Tex2D(false, wgpu::TextureSampleType::Depth); // Shadow Map
+            4: ShaderStages::COMPUTE => BGLEntry::Sampler(wgpu::SamplerBindingType::Comparison); // Shadow Map
+            5: ShaderStages::COMPUTE => BGLEntry::StorageTex3D(StorageTextureAccess::WriteOnly, FROXEL_FORMAT);
+        });
+        let integrate_layout = device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Volumetric Fog Integrate"]
+            0: ShaderStages::COMPUTE => BGLEntry::UniformBuffer(); // Fog
+            1: ShaderStages::COMPUTE => BGLEntry::Tex3D(wgpu::TextureSampleType::Float { filterable: false });
+            2: ShaderStages::COMPUTE => BGLEntry::StorageTex3D(StorageTextureAccess::WriteOnly, FROXEL_FORMAT);
+        });
+        let layout = Arc::new(device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Fog"]
+            0: ShaderStages::FRAGMENT => BGLEntry::UniformBuffer();
+            1: ShaderStages::FRAGMENT => BGLEntry::Tex3D(wgpu::TextureSampleType::Float { filterable: true });
+            2: ShaderStages::FRAGMENT => BGLEntry::Sampler(wgpu::SamplerBindingType::Filtering);
+        }));
+
+        let inject_bind_group = create_inject_bind_group(
+            device,
+            &inject_layout,
+            &uniform_buffer,
+            light,
+            dynamic_lights,
+            shadow_map,
+            &scattering_view,
+        );
+        let integrate_bind_group = device.create_bind_group(&bg_descriptor! {
+            ["Volumetric Fog Integrate"] [&integrate_layout]
+            0: uniform_buffer.as_entire_binding();
+            1: BindingResource::TextureView(&scattering_view);
+            2: BindingResource::TextureView(&integrated_view);
+        });
+
+        let sampler = device.create_sampler(&wgpu_init::sampler_desc(
+            Some("Fog Volume"),
+            wgpu::AddressMode::ClampToEdge,
+            wgpu::FilterMode::Linear,
+        ));
+        let bind_group = Arc::new(device.create_bind_group(&bg_descriptor! {
+            ["Fog"] [&layout]
+            0: uniform_buffer.as_entire_binding();
+            1: BindingResource::TextureView(&integrated_view);
+            2: BindingResource::Sampler(&sampler);
+        }));
+
+        let (inject_pipeline, integrate_pipeline) =
+            Self::create_pipelines(world, &inject_layout, &integrate_layout).unwrap();
+
+        Self {
+            uniform_buffer,
+            layout,
+            bind_group,
+            inject_pipeline,
+            inject_layout,
+            inject_bind_group,
+            integrate_pipeline,
+            integrate_layout,
+            integrate_bind_group,
+            scattering,
+            scattering_view,
+            integrated,
+        }
+    }
+}
+
+impl VolumetricFog {
+    fn create_pipelines(
+        world: &mut World,
+        inject_layout: &BindGroupLayout,
+        integrate_layout: &BindGroupLayout,
+    ) -> anyhow::Result<(ComputePipeline, ComputePipeline)> {
+        let inject_shader = ShaderLoader::load_module_by_world(
+            world,
+            AssetPath::new_shader_wgsl("volumetric_fog_inject"),
+        )?;
+        let integrate_shader = ShaderLoader::load_module_by_world(
+            world,
+            AssetPath::new_shader_wgsl("volumetric_fog_integrate"),
+        )?;
+        let device = &world.resource::<RenderState>().device;
+        Ok((
+            create_compute_pipeline(
+                "Volumetric Fog Inject",
+                inject_layout,
+                &inject_shader,
+                device,
+            ),
+            create_compute_pipeline(
+                "Volumetric Fog Integrate",
+                integrate_layout,
+                &integrate_shader,
+                device,
+            ),
+        ))
+    }
+}
+
+impl ReloadPipelines for VolumetricFog {
+    type Pipelines = (ComputePipeline, ComputePipeline);
+
+    fn build_pipelines(&self, world: &mut World) -> anyhow::Result<Self::Pipelines> {
+        Self::create_pipelines(world, &self.inject_layout, &self.integrate_layout)
+    }
+
+    fn set_pipelines(&mut self, pipelines: Self::Pipelines, _world: &mut World) {
+        (self.inject_pipeline, self.integrate_pipeline) = pipelines;
+    }
+}
+
+/// Rebuilds the inject bind group when the point light buffer or the shadow map is reallocated.
+pub fn sys_refresh_volumetric_fog_lights(
+    mut fog: ResMut<VolumetricFog>,
+    dynamic_lights: Res<DynamicLightBindGroup>,
+    light: Res<LightUnifromBuffer>,
+    shadow_map: Res<ShadowMap>,
+    rs: Res<RenderState>,
+) {
+    if !dynamic_lights.is_changed() && !shadow_map.is_changed() {
+        return;
+    }
+    let fog = fog.as_mut();
+    fog.inject_bind_group = create_inject_bind_group(
+        &rs.device,
+        &fog.inject_layout,
+        &fog.uniform_buffer,
+        &light,
+        &dynamic_lights,
+        &shadow_map,
+        &fog.scattering_view,
+    );
+}
+
+pub fn sys_update_fog_uniform(
+    settings: Res<FogSettings>,
+    camera: Single<(&Camera, &WorldTransform)>,
+    fog: Res<VolumetricFog>,
+    rs: Res<RenderState>,
+) {
+    let (camera, transform) = camera.into_inner();
+    rs.queue.write_buffer(
+        &fog.uniform_buffer,
+        0,
+        bytemuck::cast_slice(&[settings.uniform(camera, transform)]),
+    );
+}
+
+/// Inject the scattering of every froxel, then integrate it front to back along the view rays.
+pub fn sys_render_volumetric_fog(
+    InMut(ctx): InMut<PassRenderContext>,
+    settings: Res<FogSettings>,
+    fog: Res<VolumetricFog>,
+) {
+    if settings.mode != FogMode::Volumetric {
+        return;
+    }
+
+    let mut compute_pass = ctx
+        .encoder
+        .begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Volumetric Fog"),
+            timestamp_writes: None,
+        });
+    let groups_x = FROXEL_GRID[0].div_ceil(WORKGROUP_SIZE);
+    let groups_y = FROXEL_GRID[1].div_ceil(WORKGROUP_SIZE);
+
+    compute_pass.set_pipeline(&fog.inject_pipeline);
+    compute_pass.set_bind_group(0, Some(&fog.inject_bind_group), &[]);
+    compute_pass.dispatch_workgroups(groups_x, groups_y, FROXEL_GRID[2]);
+
+    compute_pass.set_pipeline(&fog.integrate_pipeline);
+    compute_pass.set_bind_group(0, Some(&fog.integrate_bind_group), &[]);
+    compute_pass.dispatch_workgroups(groups_x, groups_y, 1);
+}
@@ -15,7 +15,7 @@ use super::{
         register_buffer_material_by_world, BufferMaterialData, UploadedBufferMaterialInstance,
     },
     prelude::*,
-    shader_loader::ShaderLoader,
+    shader_loader::{ReloadPipelines, ShaderLoader},
     ColorRenderTarget,
 };
 
@@ -123,11 +123,6 @@ impl FromWorld for GizmosGlobalBindGroup {
 
 impl FromWorld for GizmosPipeline {
     fn from_world(world: &mut World) -> Self {
-        let shader_source = world
-            .resource_mut::<ShaderLoader>()
-            .load_source(AssetPath::new_shader_wgsl("gizmos"))
-            .unwrap();
-
         let bg_layout_desc = bg_layout_descriptor! {
             ["Gizmos"]
             0: ShaderStages::FRAGMENT => BGLEntry::UniformBuffer();
@@ -150,15 +145,48 @@ impl FromWorld for GizmosPipeline {
             }),
         );
 
+        let pipeline = Self::create_pipeline(world, &layout).unwrap();
+        Self {
+            pipeline,
+            layout,
+            depth_texture,
+        }
+    }
+}
+
+impl ReloadPipelines for GizmosPipeline {
+    type Pipelines = Arc<RenderPipeline>;
+
+    fn build_pipelines(&self, world: &mut World) -> anyhow::Result<Self::Pipelines> {
+        Self::create_pipeline(world, &self.layout)
+    }
+
+    fn set_pipelines(&mut self, pipelines: Self::Pipelines, _world: &mut World) {
+        self.pipeline = pipelines;
+    }
+}
+
+impl GizmosPipeline {
+    fn create_pipeline(
+        world: &mut World,
+        layout: &PipelineLayout,
+    ) -> anyhow::Result<Arc<RenderPipeline>> {
+        let shader_source = world
+            .resource_mut::<ShaderLoader>()
+            .load_source(AssetPath::new_shader_wgsl("gizmos"))?;
+
+        let rs = world.resource::<RenderState>();
+        let device = &rs.device;
+
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Gizmos"),
             source: shader_source,
         });
 
-        let pipeline = Arc::new(
-            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        Ok(Arc::new(device.create_render_pipeline(
+            &wgpu::RenderPipelineDescriptor {
                 label: Some("Gizmos"),
-                layout: Some(&layout),
+                layout: Some(layout),
                 vertex: wgpu_init::vertex_state(&shader, &[Vertex::desc()]),
                 primitive: wgpu_init::primitive_triangle_list_default(),
                 depth_stencil: Some(wgpu::DepthStencilState {
@@ -185,17 +213,10 @@ impl FromWorld for GizmosPipeline {
                 }),
                 multiview: None,
                 cache: None,
-            }),
-        );
-        Self {
-            pipeline,
-            layout,
-            depth_texture,
-        }
+            },
+        )))
     }
-}
 
-impl GizmosPipeline {
     pub fn resize(&mut self, width: u32, height: u32, device: &wgpu::Device) {
         self.depth_texture = Arc::new(create_depth_texture(device, width, height, None));
     }
@@ -0,0 +1,377 @@
+use std::sync::Arc;
+
+use cgmath::ElementWise;
+use wgpu::{
+    BindingResource, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, ComputePipeline,
+    StorageTextureAccess, TextureViewDescriptor,
+};
+
+use crate::{asset::AssetPath, bg_descriptor, impl_pod_zeroable, macro_utils::BGLEntry};
+
+use super::{
+    prelude::*,
+    reflection_probe::{CaptureScene, ReflectionProbes},
+    shader_loader::{ReloadPipelines, ShaderLoader},
+    UploadedImage,
+};
+
+/// Must match the array size in `global_bindings.wgsl`
+pub const MAX_IRRADIANCE_VOLUMES: usize = 4;
+/// Most probes a volume can have along each axis.
+/// The z size must match `IRRADIANCE_SLAB_DEPTH` in `global_bindings.wgsl`
+pub const MAX_VOLUME_RESOLUTION: [u32; 3] = [16, 8, 16];
+const VOLUME_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// Grid of diffuse light probes filling a box around the entity. Each probe
+/// stores the irradiance of the six axis directions (an ambient cube),
+/// captured the same way as a [`super::reflection_probe::ReflectionProbe`].
+///
+/// Baking takes one capture per probe, it runs when the volume is added,
+/// moved or resized, when the sky changes, or by [`IrradianceVolumes::rebake_all`].
+#[derive(Component, Clone, Debug)]
+#[require(Transform)]
+pub struct IrradianceVolume {
+    pub half_extents: Vec3,
+    /// Number of probes along each axis, clamped to [`MAX_VOLUME_RESOLUTION`]
+    pub resolution: [u32; 3],
+    /// Distance inside the box over which the volume fades out
+    pub blend_distance: f32,
+    /// Far plane of the captures
+    pub far: f32,
+}
+
+impl Default for IrradianceVolume {
+    fn default() -> Self {
+        Self {
+            half_extents: Vec3::new(5.0, 2.0, 5.0),
+            resolution: [4, 2, 4],
+            blend_distance: 1.0,
+            far: 50.0,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct RawIrradianceVolume {
+    pub box_min: [f32; 3],
+    pub blend_distance: f32,
+    pub box_max: [f32; 3],
+    /// Which group of six slabs of the volume texture
+    pub slot: u32,
+    pub resolution: [u32; 4],
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct IrradianceVolumesUniform {
+    /// Sorted from the smallest box, so nested volumes win over the ones around them
+    pub volumes: [RawIrradianceVolume; MAX_IRRADIANCE_VOLUMES],
+    pub count: [u32; 4],
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct IrradianceBakeUniform {
+    /// Texel of the +x direction, the other directions follow every `slab_depth` texels along z
+    pub texel: [u32; 3],
+    pub slab_depth: u32,
+}
+
+impl_pod_zeroable!(RawIrradianceVolume);
+impl_pod_zeroable!(IrradianceVolumesUniform);
+impl_pod_zeroable!(IrradianceBakeUniform);
+
+impl IrradianceVolume {
+    pub fn clamped_resolution(&self) -> [u32; 3] {
+        [0, 1, 2].map(|i| self.resolution[i].clamp(1, MAX_VOLUME_RESOLUTION[i]))
+    }
+
+    pub fn raw(&self, transform: &WorldTransform, slot: u32) -> RawIrradianceVolume {
+        let pos = transform.position;
+        let [x, y, z] = self.clamped_resolution();
+        RawIrradianceVolume {
+            box_min: (pos - self.half_extents).into(),
+            blend_distance: self.blend_distance.max(0.0),
+            box_max: (pos + self.half_extents).into(),
+            slot,
+            resolution: [x, y, z, 0],
+        }
+    }
+
+    /// World position of the probe at `index`, the probes span the whole box.
+    pub fn probe_position(&self, transform: &WorldTransform, index: [u32; 3]) -> Vec3 {
+        let resolution = self.clamped_resolution();
+        let t = Vec3::from([0, 1, 2].map(|i| match resolution[i] {
+            1 => 0.5,
+            n => index[i] as f32 / (n - 1) as f32,
+        }));
+        transform.position - self.half_extents + (self.half_extents * 2.0).mul_element_wise(t)
+    }
+
+    fn volume(&self) -> f32 {
+        self.half_extents.x * self.half_extents.y * self.half_extents.z
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct BakeKey {
+    position: Vec3,
+    half_extents: Vec3,
+    resolution: [u32; 3],
+    far: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct VolumeSlot {
+    entity: Entity,
+    /// What the probes were baked with, `None` to rebake
+    baked: Option<BakeKey>,
+}
+
+/// Ambient cubes of every volume in one 3D texture, bound in the global bind group.
+/// Each volume owns six slabs of [`MAX_VOLUME_RESOLUTION`] along z, one per direction.
+#[derive(Resource)]
+pub struct IrradianceVolumes {
+    pub texture: UploadedImage,
+    pub uniform_buffer: Arc<wgpu::Buffer>,
+    slots: [Option<VolumeSlot>; MAX_IRRADIANCE_VOLUMES],
+    bake_layout: BindGroupLayout,
+    bake_uniform_buffer: wgpu::Buffer,
+    bake_pipeline: ComputePipeline,
+}
+
+impl FromWorld for IrradianceVolumes {
+    fn from_world(world: &mut World) -> Self {
+        let device = &world.resource::<RenderState>().device;
+
+        let [width, height, depth] = MAX_VOLUME_RESOLUTION;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Irradiance Volumes"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: depth * 6 * MAX_IRRADIANCE_VOLUMES as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: VOLUME_FORMAT,
+            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        let uniform_buffer = Arc::new(device.create_buffer(&BufferDescriptor {
+            label: Some("Irradiance Volumes"),
+            size: size_of::<IrradianceVolumesUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+        let bake_uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Irradiance Volume Bake"),
+            size: size_of::<IrradianceBakeUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bake_layout = device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Irradiance Volume Bake"]
+            0: ShaderStages::COMPUTE => BGLEntry::UniformBuffer();
+            1: ShaderStages::COMPUTE => BGLEntry::Tex2DArray(TextureSampleType::Float { filterable: false }); // Capture
+            2: ShaderStages::COMPUTE => BGLEntry::StorageTex3D(StorageTextureAccess::WriteOnly, VOLUME_FORMAT);
+        });
+        let bake_pipeline = Self::create_bake_pipeline(world, &bake_layout).unwrap();
+
+        Self {
+            texture: UploadedImage { texture, view },
+            uniform_buffer,
+            slots: [None; MAX_IRRADIANCE_VOLUMES],
+            bake_layout,
+            bake_uniform_buffer,
+            bake_pipeline,
+        }
+    }
+}
+
+impl ReloadPipelines for IrradianceVolumes {
+    type Pipelines = ComputePipeline;
+
+    fn build_pipelines(&self, world: &mut World) -> anyhow::Result<Self::Pipelines> {
+        Self::create_bake_pipeline(world, &self.bake_layout)
+    }
+
+    fn set_pipelines(&mut self, pipelines: Self::Pipelines, _world: &mut World) {
+        self.bake_pipeline = pipelines;
+        self.rebake_all();
+    }
+}
+
+impl IrradianceVolumes {
+    fn create_bake_pipeline(
+        world: &mut World,
+        bake_layout: &BindGroupLayout,
+    ) -> anyhow::Result<ComputePipeline> {
+        let shader = ShaderLoader::load_module_by_world(
+            world,
+            AssetPath::new_shader_wgsl("irradiance_volume_bake"),
+        )?;
+        let device = &world.resource::<RenderState>().device;
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Irradiance Volume Bake"),
+            bind_group_layouts: &[bake_layout],
+            push_constant_ranges: &[],
+        });
+        Ok(
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Irradiance Volume Bake"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some("cs_main"),
+                compilation_options: Default::default(),
+                cache: None,
+            }),
+        )
+    }
+
+    pub fn rebake_all(&mut self) {
+        for slot in self.slots.iter_mut().flatten() {
+            slot.baked = None;
+        }
+    }
+
+    pub fn volume_count(&self) -> usize {
+        self.slots.iter().flatten().count()
+    }
+
+    fn slot_of(&mut self, entity: Entity) -> Option<usize> {
+        if let Some(index) = self
+            .slots
+            .iter()
+            .position(|it| it.is_some_and(|it| it.entity == entity))
+        {
+            return Some(index);
+        }
+        let index = self.slots.iter().position(Option::is_none)?;
+        self.slots[index] = Some(VolumeSlot {
+            entity,
+            baked: None,
+        });
+        Some(index)
+    }
+
+    /// Capture every probe of the volume and integrate its ambient cube into slot `slot`.
+    fn bake(
+        &self,
+        slot: usize,
+        volume: &IrradianceVolume,
+        transform: &WorldTransform,
+        probes: &ReflectionProbes,
+        scene: &CaptureScene,
+        capture_bind_group: &BindGroup,
+    ) {
+        let rs = &scene.rs;
+        let slab_depth = MAX_VOLUME_RESOLUTION[2];
+        let [nx, ny, nz] = volume.clamped_resolution();
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    let position = volume.probe_position(transform, [x, y, z]);
+                    let capture =
+                        probes.render_faces(position, volume.far, scene, capture_bind_group);
+                    let capture_view = capture.create_view(&TextureViewDescriptor {
+                        dimension: Some(wgpu::TextureViewDimension::D2Array),
+                        ..Default::default()
+                    });
+
+                    rs.queue.write_buffer(
+                        &self.bake_uniform_buffer,
+                        0,
+                        bytemuck::cast_slice(&[IrradianceBakeUniform {
+                            texel: [x, y, slot as u32 * 6 * slab_depth + z],
+                            slab_depth,
+                        }]),
+                    );
+                    let bind_group = rs.device.create_bind_group(&bg_descriptor! {
+                        ["Irradiance Volume Bake"] [&self.bake_layout]
+                        0: self.bake_uniform_buffer.as_entire_binding();
+                        1: BindingResource::TextureView(&capture_view);
+                        2: BindingResource::TextureView(&self.texture.view);
+                    });
+                    let mut encoder = rs.device.create_command_encoder(&CommandEncoderDescriptor {
+                        label: Some("Irradiance Volume Bake"),
+                    });
+                    {
+                        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                            label: Some("Irradiance Volume Bake"),
+                            timestamp_writes: None,
+                        });
+                        pass.set_pipeline(&self.bake_pipeline);
+                        pass.set_bind_group(0, &bind_group, &[]);
+                        pass.dispatch_workgroups(1, 1, 1);
+                    }
+                    rs.queue.submit(std::iter::once(encoder.finish()));
+                }
+            }
+        }
+    }
+}
+
+/// Bake the volumes that are new or changed and upload the boxes for the deferred pass.
+pub fn sys_update_irradiance_volumes(
+    mut volumes: ResMut<IrradianceVolumes>,
+    q_volumes: Query<(Entity, &IrradianceVolume, &WorldTransform)>,
+    probes: Res<ReflectionProbes>,
+    scene: CaptureScene,
+) {
+    for slot in volumes.slots.iter_mut() {
+        if slot.is_some_and(|it| !q_volumes.contains(it.entity)) {
+            *slot = None;
+        }
+    }
+    if scene.skybox.is_changed() {
+        volumes.rebake_all();
+    }
+
+    let mut capture_bind_group = None;
+    let mut raw_volumes = Vec::with_capacity(MAX_IRRADIANCE_VOLUMES);
+    for (entity, volume, transform) in q_volumes.iter() {
+        let Some(slot) = volumes.slot_of(entity) else {
+            log::warn!(
+                "More than {} irradiance volumes, {:?} is ignored",
+                MAX_IRRADIANCE_VOLUMES,
+                entity
+            );
+            continue;
+        };
+        raw_volumes.push((volume.volume(), volume.raw(transform, slot as u32)));
+
+        let key = Some(BakeKey {
+            position: transform.position,
+            half_extents: volume.half_extents,
+            resolution: volume.clamped_resolution(),
+            far: volume.far,
+        });
+        if volumes.slots[slot].is_some_and(|it| it.baked == key) {
+            continue;
+        }
+        let capture_bind_group =
+            capture_bind_group.get_or_insert_with(|| probes.capture_bind_group(&scene));
+        volumes.bake(slot, volume, transform, &probes, &scene, capture_bind_group);
+        if let Some(slot) = volumes.slots[slot].as_mut() {
+            slot.baked = key;
+        }
+    }
+
+    raw_volumes.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut uniform = IrradianceVolumesUniform {
+        volumes: [bytemuck::Zeroable::zeroed(); MAX_IRRADIANCE_VOLUMES],
+        count: [raw_volumes.len() as u32, 0, 0, 0],
+    };
+    for (dst, (_, raw)) in uniform.volumes.iter_mut().zip(raw_volumes) {
+        *dst = raw;
+    }
+    scene
+        .rs
+        .queue
+        .write_buffer(&volumes.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+}
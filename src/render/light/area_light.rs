@@ -0,0 +1,264 @@
+use std::sync::Arc;
+
+use bevy_ecs::prelude::*;
+use bevy_reflect::Reflect;
+use wgpu::util::{DeviceExt, TextureDataOrder};
+
+use crate::{
+    asset::AssetPath,
+    impl_pod_zeroable,
+    render::{
+        defered_rendering::global_binding::GlobalBindGroup, prelude::*,
+        shader_loader::ShaderLoader, systems::PassRenderContext, ColorRenderTarget,
+        DepthRenderTarget, UploadedImage,
+    },
+};
+
+use super::{DynamicLightBindGroup, DynamicLights};
+
+const LTC_LUT_SIZE: u32 = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Reflect)]
+pub enum AreaLightShape {
+    Rectangle,
+    /// Ellipse when width and height differ
+    Disk,
+}
+
+/// Rectangle or disk light in the local XY plane, emitting along the forward (-Z) axis.
+#[derive(Component, Clone)]
+#[require(Transform)]
+pub struct RectLight {
+    pub color: Vec4,
+    pub intensity: f32,
+    pub width: f32,
+    pub height: f32,
+    pub shape: AreaLightShape,
+    pub two_sided: bool,
+    /// Draw the emitting surface in the main view
+    pub visible: bool,
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct RawAreaLight {
+    pub position: [f32; 3],
+    pub shape: u32,
+    /// Local X scaled by half of the width
+    pub right: [f32; 3],
+    pub two_sided: u32,
+    /// Local Y scaled by half of the height
+    pub up: [f32; 3],
+    pub visible: u32,
+    pub color: [f32; 4],
+}
+
+impl_pod_zeroable!(RawAreaLight);
+
+impl Default for RectLight {
+    fn default() -> Self {
+        Self {
+            color: Vec4::one(),
+            intensity: 4.0,
+            width: 1.0,
+            height: 1.0,
+            shape: AreaLightShape::Rectangle,
+            two_sided: false,
+            visible: true,
+        }
+    }
+}
+
+impl RectLight {
+    pub fn raw(&self, transform: &WorldTransform) -> RawAreaLight {
+        let right = -transform.left() * (self.width * 0.5);
+        let up = transform.up() * (self.height * 0.5);
+        let radiance = self.color.truncate() * self.intensity;
+        RawAreaLight {
+            position: transform.position.into(),
+            shape: self.shape as u32,
+            right: right.into(),
+            two_sided: self.two_sided as u32,
+            up: up.into(),
+            visible: self.visible as u32,
+            color: [radiance.x, radiance.y, radiance.z, 1.0],
+        }
+    }
+}
+
+/// GGX fitted LTC tables, generated by `examples/fit_ltc.rs`.
+pub struct LtcLookupTables {
+    /// Inverse matrix entries
+    pub ltc_1: UploadedImage,
+    /// Magnitude and Fresnel
+    pub ltc_2: UploadedImage,
+    pub sampler: wgpu::Sampler,
+}
+
+impl LtcLookupTables {
+    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Self> {
+        let load_table = |name: &str| -> anyhow::Result<UploadedImage> {
+            let path = AssetPath::Assets(format!("textures/ltc/{}.bin", name));
+            let data = path.read()?;
+            // Rgba16Float
+            let expected = (LTC_LUT_SIZE * LTC_LUT_SIZE * 8) as usize;
+            anyhow::ensure!(
+                data.len() == expected,
+                "LTC table {} has {} bytes, expected {}",
+                name,
+                data.len(),
+                expected
+            );
+            let texture = device.create_texture_with_data(
+                queue,
+                &wgpu_init::texture_desc_2d_one_mip_sample_level(
+                    Some("LTC Lookup Table"),
+                    Extent3d {
+                        width: LTC_LUT_SIZE,
+                        height: LTC_LUT_SIZE,
+                        depth_or_array_layers: 1,
+                    },
+                    TextureFormat::Rgba16Float,
+                    TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                ),
+                TextureDataOrder::LayerMajor,
+                &data,
+            );
+            let view = texture.create_view(&Default::default());
+            Ok(UploadedImage { texture, view })
+        };
+
+        Ok(Self {
+            ltc_1: load_table("ltc_1")?,
+            ltc_2: load_table("ltc_2")?,
+            sampler: device.create_sampler(&wgpu_init::sampler_desc(
+                Some("LTC Lookup Table"),
+                wgpu::AddressMode::ClampToEdge,
+                wgpu::FilterMode::Linear,
+            )),
+        })
+    }
+}
+
+type RectLightChanged = Or<(Changed<RectLight>, Changed<WorldTransform>)>;
+
+pub fn sys_update_area_lights(
+    mut dynamic_lights: ResMut<DynamicLights>,
+    q_lights: Query<(Entity, &RectLight, &WorldTransform), RectLightChanged>,
+) {
+    for (id, light, transform) in q_lights.iter() {
+        dynamic_lights.area_lights.insert(id, light.raw(transform));
+    }
+}
+
+pub fn event_on_remove_rect_light(
+    trigger: Trigger<OnRemove, RectLight>,
+    mut dynamic_lights: ResMut<DynamicLights>,
+) {
+    dynamic_lights.area_lights.remove(&trigger.entity());
+}
+
+/// Draws the emitting surfaces of area lights on top of the lit scene.
+#[derive(Resource)]
+pub struct AreaLightGeometryPipeline {
+    pub pipeline: Arc<RenderPipeline>,
+}
+
+impl FromWorld for AreaLightGeometryPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let shader =
+            ShaderLoader::load_module_by_world(world, AssetPath::new_shader_wgsl("area_light"))
+                .unwrap();
+        let rs = world.resource::<RenderState>();
+        let device = &rs.device;
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Area Light Geometry"),
+            bind_group_layouts: &[
+                &world.resource::<GlobalBindGroup>().layout,
+                &world.resource::<DynamicLightBindGroup>().layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Area Light Geometry"),
+            layout: Some(&layout),
+            vertex: wgpu_init::vertex_state(&shader, &[]),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: None,
+                ..wgpu_init::primitive_triangle_list_default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: RenderState::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: Default::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu_init::color_target_replace_write_all(
+                    rs.config.format,
+                ))],
+                compilation_options: Default::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline: Arc::new(pipeline),
+        }
+    }
+}
+
+pub fn sys_render_area_light_geometry(
+    InMut(ctx): InMut<PassRenderContext>,
+    color_target: Res<ColorRenderTarget>,
+    depth_target: Res<DepthRenderTarget>,
+    pipeline: Res<AreaLightGeometryPipeline>,
+    global_bind_group: Res<GlobalBindGroup>,
+    dynamic_lights_bind_group: Res<DynamicLightBindGroup>,
+    dynamic_lights: Res<DynamicLights>,
+) {
+    let (Some(color_target), Some(depth_target)) =
+        (color_target.0.as_ref(), depth_target.0.as_ref())
+    else {
+        return;
+    };
+    let count = dynamic_lights.area_lights.len() as u32;
+    if count == 0 {
+        return;
+    }
+
+    let mut render_pass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Area Light Geometry"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: &color_target.view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: &depth_target.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }),
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+
+    render_pass.set_pipeline(&pipeline.pipeline);
+    render_pass.set_bind_group(0, Some(global_bind_group.bind_group.as_ref()), &[]);
+    render_pass.set_bind_group(1, Some(dynamic_lights_bind_group.bind_group.as_ref()), &[]);
+    // A fan of 8 triangles per light, rectangles collapse the last 4
+    render_pass.draw(0..24, 0..count);
+}
@@ -0,0 +1,165 @@
+use bevy_ecs::prelude::*;
+use cgmath::SquareMatrix;
+use wgpu::ComputePipeline;
+
+use crate::{
+    asset::AssetPath,
+    bg_descriptor, impl_pod_zeroable,
+    macro_utils::BGLEntry,
+    render::{camera::Camera, prelude::*, shader_loader::ShaderLoader, systems::PassRenderContext},
+};
+
+use super::{DynamicLightBindGroup, DynamicLights};
+
+/// View space froxels, x and y split the screen, z is split exponentially between near and far.
+/// Must match `light_cluster.wgsl`
+pub const CLUSTER_GRID: [u32; 3] = [16, 9, 24];
+pub const MAX_LIGHTS_PER_CLUSTER: u32 = 64;
+const WORKGROUP_SIZE: [u32; 3] = [4, 3, 4];
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct ClusterUniform {
+    pub view: [[f32; 4]; 4],
+    pub inverse_projection: [[f32; 4]; 4],
+    /// xyz: grid, w: point lights
+    pub grid: [u32; 4],
+    /// near, far, slice scale, slice bias
+    pub depth: [f32; 4],
+}
+
+impl_pod_zeroable!(ClusterUniform);
+
+impl ClusterUniform {
+    pub fn new(camera: &Camera, transform: &WorldTransform, point_lights: u32) -> Self {
+        let near = camera.znear;
+        let far = camera.zfar;
+        // slice = log(depth) * scale - log(near) * scale
+        let scale = CLUSTER_GRID[2] as f32 / (far / near).ln();
+        Self {
+            view: transform.view_matrix().into(),
+            inverse_projection: camera
+                .projection_matrix()
+                .invert()
+                .unwrap_or(Mat4::identity())
+                .into(),
+            grid: [
+                CLUSTER_GRID[0],
+                CLUSTER_GRID[1],
+                CLUSTER_GRID[2],
+                point_lights,
+            ],
+            depth: [near, far, scale, -near.ln() * scale],
+        }
+    }
+}
+
+pub fn cluster_lights_buffer_size() -> u64 {
+    let clusters = CLUSTER_GRID.iter().product::<u32>() as u64;
+    clusters * (1 + MAX_LIGHTS_PER_CLUSTER as u64) * size_of::<u32>() as u64
+}
+
+/// Bins point lights into clusters every frame, the main pass only shades the lights of its cluster.
+#[derive(Resource)]
+pub struct LightClusters {
+    pipeline: ComputePipeline,
+    layout: BindGroupLayout,
+    bind_group: BindGroup,
+}
+
+impl LightClusters {
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &BindGroupLayout,
+        dynamic_lights: &DynamicLightBindGroup,
+    ) -> BindGroup {
+        device.create_bind_group(&bg_descriptor! {
+            ["Light Clusters"] [layout]
+            0: dynamic_lights.cluster_uniform_buffer.as_entire_binding();
+            1: dynamic_lights.point_lights_storage_buffer.as_entire_binding();
+            2: dynamic_lights.cluster_lights_buffer.as_entire_binding();
+        })
+    }
+}
+
+impl FromWorld for LightClusters {
+    fn from_world(world: &mut World) -> Self {
+        let shader =
+            ShaderLoader::load_module_by_world(world, AssetPath::new_shader_wgsl("light_cluster"))
+                .unwrap();
+        let device = &world.resource::<RenderState>().device;
+        let dynamic_lights = world.resource::<DynamicLightBindGroup>();
+
+        let layout = device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Light Clusters"]
+            0: ShaderStages::COMPUTE => BGLEntry::UniformBuffer();
+            1: ShaderStages::COMPUTE => BGLEntry::StorageBuffer(true); // Point Lights
+            2: ShaderStages::COMPUTE => BGLEntry::StorageBuffer(false); // Cluster Lights
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Light Clusters"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Light Clusters"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: Some("cs_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let bind_group = Self::create_bind_group(device, &layout, dynamic_lights);
+
+        Self {
+            pipeline,
+            layout,
+            bind_group,
+        }
+    }
+}
+
+pub fn sys_update_light_clusters(
+    camera: Single<(&Camera, &WorldTransform)>,
+    dynamic_lights: Res<DynamicLights>,
+    dynamic_lights_bind_group: Res<DynamicLightBindGroup>,
+    mut clusters: ResMut<LightClusters>,
+    rs: Res<RenderState>,
+) {
+    if dynamic_lights_bind_group.is_changed() {
+        // The point light buffer may have been reallocated
+        let clusters = clusters.as_mut();
+        clusters.bind_group = LightClusters::create_bind_group(
+            &rs.device,
+            &clusters.layout,
+            &dynamic_lights_bind_group,
+        );
+    }
+
+    let (camera, transform) = camera.into_inner();
+    let uniform = ClusterUniform::new(camera, transform, dynamic_lights.point_lights.len() as u32);
+    rs.queue.write_buffer(
+        &dynamic_lights_bind_group.cluster_uniform_buffer,
+        0,
+        bytemuck::cast_slice(&[uniform]),
+    );
+}
+
+pub fn sys_render_light_clusters(
+    InMut(ctx): InMut<PassRenderContext>,
+    clusters: Res<LightClusters>,
+) {
+    let mut compute_pass = ctx
+        .encoder
+        .begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Light Clusters"),
+            timestamp_writes: None,
+        });
+    compute_pass.set_pipeline(&clusters.pipeline);
+    compute_pass.set_bind_group(0, Some(&clusters.bind_group), &[]);
+    compute_pass.dispatch_workgroups(
+        CLUSTER_GRID[0].div_ceil(WORKGROUP_SIZE[0]),
+        CLUSTER_GRID[1].div_ceil(WORKGROUP_SIZE[1]),
+        CLUSTER_GRID[2].div_ceil(WORKGROUP_SIZE[2]),
+    );
+}
@@ -1,16 +1,21 @@
 use std::{collections::BTreeMap, sync::Arc};
 
+use area_light::{LtcLookupTables, RawAreaLight};
 use bevy_ecs::prelude::*;
+use cluster::ClusterUniform;
 use parallel_light::ParallelLight;
 use point_light::{PointLight, RawPointLight};
 use wgpu::{BindGroup, BindGroupLayout, BufferDescriptor, BufferUsages, ShaderStages};
 
 use crate::{
-    bg_descriptor, bg_layout_descriptor, impl_pod_zeroable, macro_utils::BGLEntry, RenderState,
+    bg_descriptor, bg_layout_descriptor, impl_pod_zeroable, macro_utils::BGLEntry,
+    wgpu_init::DynamicBuffer, RenderState,
 };
 
 use super::transform::{Transform, WorldTransform};
 
+pub mod area_light;
+pub mod cluster;
 pub mod parallel_light;
 pub mod point_light;
 
@@ -29,33 +34,70 @@ pub struct LightUniform {
     pub space_matrix: [[f32; 4]; 4],
     pub intensity: f32,
     pub padding2: [f32; 3],
-    /// x: point_lights, y, z, w
+    /// x: point_lights, y: area_lights, z, w
     pub lights_count: [u32; 4],
+    pub shadow: [f32; 4],
+    pub shadow_filter: [u32; 4],
+    pub shadow_moments: [f32; 4],
 }
 
 /// It manages lights' bind group and buffers that will change.
 /// Dynamically increase or decrease.
 #[derive(Resource)]
 pub struct DynamicLightBindGroup {
-    pub point_lights_storage_buffer: Arc<wgpu::Buffer>,
+    pub point_lights_storage_buffer: DynamicBuffer,
+    pub area_lights_storage_buffer: DynamicBuffer,
+    pub cluster_uniform_buffer: Arc<wgpu::Buffer>,
+    /// Per cluster: light count followed by `MAX_LIGHTS_PER_CLUSTER` light indices
+    pub cluster_lights_buffer: Arc<wgpu::Buffer>,
+    pub ltc: Arc<LtcLookupTables>,
     pub layout: Arc<BindGroupLayout>,
     pub bind_group: Arc<BindGroup>,
 }
 
+const INITIAL_LIGHTS_CAPACITY: usize = 128;
+
 impl FromWorld for DynamicLightBindGroup {
     fn from_world(world: &mut bevy_ecs::world::World) -> Self {
-        let device = &world.resource::<RenderState>().device;
+        let rs = world.resource::<RenderState>();
+        let device = &rs.device;
 
-        let buffer = device.create_buffer(&BufferDescriptor {
-            label: Some("Point Light Storage Buffer"),
-            size: 128 * size_of::<RawPointLight>() as u64,
-            usage: BufferUsages::COPY_DST | BufferUsages::STORAGE,
+        let buffer = DynamicBuffer::new(
+            device,
+            "Point Light Storage Buffer",
+            BufferUsages::STORAGE,
+            (INITIAL_LIGHTS_CAPACITY * size_of::<RawPointLight>()) as u64,
+        );
+        let area_buffer = DynamicBuffer::new(
+            device,
+            "Area Light Storage Buffer",
+            BufferUsages::STORAGE,
+            (INITIAL_LIGHTS_CAPACITY * size_of::<RawAreaLight>()) as u64,
+        );
+        let cluster_uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Light Cluster Uniform Buffer"),
+            size: size_of::<ClusterUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
+        let cluster_lights_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Light Cluster Storage Buffer"),
+            size: cluster::cluster_lights_buffer_size(),
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let ltc = Arc::new(LtcLookupTables::load(device, &rs.queue).unwrap());
 
         let layout_desc = bg_layout_descriptor! {
             ["Dynamic Light"]
             0: ShaderStages::FRAGMENT => BGLEntry::StorageBuffer(true);
+            // Area lights, also read by the vertex stage to draw their geometry
+            1: ShaderStages::VERTEX_FRAGMENT => BGLEntry::StorageBuffer(true);
+            2: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, wgpu::TextureSampleType::Float { filterable: true }); // LTC 1
+            3: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, wgpu::TextureSampleType::Float { filterable: true }); // LTC 2
+            4: ShaderStages::FRAGMENT => BGLEntry::Sampler(wgpu::SamplerBindingType::Filtering); // LTC
+            5: ShaderStages::FRAGMENT => BGLEntry::UniformBuffer(); // Clusters
+            6: ShaderStages::FRAGMENT => BGLEntry::StorageBuffer(true); // Cluster Lights
             // // DFG Sampler
             // 1: ShaderStages::FRAGMENT => BGLEntry::Sampler(wgpu::SamplerBindingType::Filtering);
             // // IBL DFG LUT
@@ -69,15 +111,51 @@ impl FromWorld for DynamicLightBindGroup {
         };
         let layout = Arc::new(device.create_bind_group_layout(&layout_desc));
 
-        let bg_desc = bg_descriptor!(
+        let bind_group = device.create_bind_group(&bg_descriptor!(
                 ["Dynamic Light"][&layout]
                 0: buffer.as_entire_binding();
-        );
-        let bind_group = Arc::new(device.create_bind_group(&bg_desc));
+                1: area_buffer.as_entire_binding();
+                2: wgpu::BindingResource::TextureView(&ltc.ltc_1.view);
+                3: wgpu::BindingResource::TextureView(&ltc.ltc_2.view);
+                4: wgpu::BindingResource::Sampler(&ltc.sampler);
+                5: cluster_uniform_buffer.as_entire_binding();
+                6: cluster_lights_buffer.as_entire_binding();
+        ));
         Self {
-            point_lights_storage_buffer: Arc::new(buffer),
+            point_lights_storage_buffer: buffer,
+            area_lights_storage_buffer: area_buffer,
+            cluster_uniform_buffer: Arc::new(cluster_uniform_buffer),
+            cluster_lights_buffer: Arc::new(cluster_lights_buffer),
+            ltc,
             layout,
-            bind_group,
+            bind_group: Arc::new(bind_group),
+        }
+    }
+}
+
+impl DynamicLightBindGroup {
+    fn create_bind_group(&self, device: &wgpu::Device) -> BindGroup {
+        device.create_bind_group(&bg_descriptor!(
+                ["Dynamic Light"][&self.layout]
+                0: self.point_lights_storage_buffer.as_entire_binding();
+                1: self.area_lights_storage_buffer.as_entire_binding();
+                2: wgpu::BindingResource::TextureView(&self.ltc.ltc_1.view);
+                3: wgpu::BindingResource::TextureView(&self.ltc.ltc_2.view);
+                4: wgpu::BindingResource::Sampler(&self.ltc.sampler);
+                5: self.cluster_uniform_buffer.as_entire_binding();
+                6: self.cluster_lights_buffer.as_entire_binding();
+        ))
+    }
+
+    /// Grows the light buffers to fit, then rebuilds the bind group.
+    /// Bind groups of other passes holding the old buffers have to be rebuilt too.
+    fn reserve(&mut self, device: &wgpu::Device, point_lights: u64, area_lights: u64) {
+        let point_grown = self
+            .point_lights_storage_buffer
+            .reserve(device, point_lights);
+        let area_grown = self.area_lights_storage_buffer.reserve(device, area_lights);
+        if point_grown || area_grown {
+            self.bind_group = Arc::new(self.create_bind_group(device));
         }
     }
 }
@@ -113,7 +191,15 @@ impl LightUniform {
             padding2: [0f32; 3],
             padding1: 0.,
             space_matrix: parallel.light_space_matrix(transform).into(),
-            lights_count: [dynamic.point_lights.len() as u32, 0, 0, 0],
+            lights_count: [
+                dynamic.point_lights.len() as u32,
+                dynamic.area_lights.len() as u32,
+                0,
+                0,
+            ],
+            shadow: parallel.shadow_params(),
+            shadow_filter: parallel.shadow_filter_params(),
+            shadow_moments: parallel.shadow_moment_params(),
         }
     }
 }
@@ -124,6 +210,7 @@ impl_pod_zeroable!(RawPointLight);
 #[derive(Resource, Default)]
 pub struct DynamicLights {
     pub point_lights: BTreeMap<Entity, RawPointLight>,
+    pub area_lights: BTreeMap<Entity, RawAreaLight>,
 }
 
 pub fn sys_update_dynamic_lights(
@@ -150,21 +237,38 @@ pub fn sys_update_dynamic_lights_bind_group(
     dynamic_lights: Res<DynamicLights>,
     light_buffer: Res<LightUnifromBuffer>,
     parallel_light: Single<(&ParallelLight, &WorldTransform)>,
-    bg: Res<DynamicLightBindGroup>,
+    mut bg: ResMut<DynamicLightBindGroup>,
     rs: Res<RenderState>,
 ) {
     if dynamic_lights.is_changed() {
-        rs.queue.write_buffer(
-            &bg.point_lights_storage_buffer,
-            0,
-            bytemuck::cast_slice(
-                &dynamic_lights
-                    .point_lights
-                    .values()
-                    .cloned()
-                    .collect::<Vec<_>>(),
-            ),
-        );
+        let point_lights = dynamic_lights
+            .point_lights
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        let area_lights = dynamic_lights
+            .area_lights
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        let point_lights: &[u8] = bytemuck::cast_slice(&point_lights);
+        let area_lights: &[u8] = bytemuck::cast_slice(&area_lights);
+
+        // Only touch the resource mutably when it grows, dependents rebuild on change
+        if !bg
+            .point_lights_storage_buffer
+            .fits(point_lights.len() as u64)
+            || !bg.area_lights_storage_buffer.fits(area_lights.len() as u64)
+        {
+            bg.reserve(
+                &rs.device,
+                point_lights.len() as u64,
+                area_lights.len() as u64,
+            );
+        }
+        bg.point_lights_storage_buffer
+            .write(&rs.queue, point_lights);
+        bg.area_lights_storage_buffer.write(&rs.queue, area_lights);
         let uniform =
             LightUniform::from_lights(parallel_light.0, &dynamic_lights, parallel_light.1);
         rs.queue
@@ -1,7 +1,61 @@
 use crate::render::{camera::OPENGL_TO_WGPU_MATRIX, prelude::*};
 use bevy_ecs::prelude::*;
+use bevy_reflect::Reflect;
 use cgmath::{Matrix, Matrix4};
 
+/// Must match `SHADOW_FILTER_*` in `shadow.wgsl`
+#[derive(Clone, Copy, PartialEq, Debug, Reflect)]
+pub enum ShadowFilter {
+    Hard,
+    /// Square kernel of `(2 * radius + 1)^2` taps
+    Pcf {
+        radius: u32,
+    },
+    /// Rotated Poisson disk, radius in texels
+    PoissonPcf {
+        radius: f32,
+    },
+    /// Percentage closer soft shadows, the penumbra widens with the distance to the blocker.
+    /// `light_size` is the penumbra width per world unit between blocker and receiver.
+    Pcss {
+        light_size: f32,
+    },
+    /// Variance shadow map, prefiltered by a `blur_radius` texels blur and mipmaps.
+    /// `light_bleeding_reduction` cuts off the low end of the visibility to hide halos.
+    Vsm {
+        blur_radius: u32,
+        light_bleeding_reduction: f32,
+    },
+    /// Variance of exponentially warped depth, bleeds less than [`ShadowFilter::Vsm`].
+    /// Exponents above 5.54 overflow the f16 moments.
+    Evsm {
+        blur_radius: u32,
+        exponents: [f32; 2],
+        light_bleeding_reduction: f32,
+    },
+}
+
+impl ShadowFilter {
+    pub fn mode(&self) -> u32 {
+        match self {
+            ShadowFilter::Hard => 0,
+            ShadowFilter::Pcf { .. } => 1,
+            ShadowFilter::PoissonPcf { .. } => 2,
+            ShadowFilter::Pcss { .. } => 3,
+            ShadowFilter::Vsm { .. } => 4,
+            ShadowFilter::Evsm { .. } => 5,
+        }
+    }
+
+    /// Sampled from the blurred moments instead of the depth map
+    pub fn is_prefiltered(&self) -> bool {
+        matches!(self, ShadowFilter::Vsm { .. } | ShadowFilter::Evsm { .. })
+    }
+}
+
+/// Variance below this is treated as noise of the depth map
+const MIN_VARIANCE: f32 = 1e-5;
+
 #[derive(Component)]
 pub struct ParallelLight {
     pub intensity: f32,
@@ -9,6 +63,13 @@ pub struct ParallelLight {
     pub size: f32,
     pub near: f32,
     pub far: f32,
+    /// Width and height of the shadow map, it is reallocated on change
+    pub shadow_resolution: u32,
+    /// World units along the light direction
+    pub depth_bias: f32,
+    /// Texels along the surface normal, scaled by the angle to the light
+    pub normal_offset: f32,
+    pub shadow_filter: ShadowFilter,
 }
 
 impl Default for ParallelLight {
@@ -19,6 +80,10 @@ impl Default for ParallelLight {
             size: 10.,
             near: 1.,
             far: 20.,
+            shadow_resolution: 2048,
+            depth_bias: 0.02,
+            normal_offset: 1.0,
+            shadow_filter: ShadowFilter::Pcf { radius: 1 },
         }
     }
 }
@@ -30,4 +95,57 @@ impl ParallelLight {
         let view = transform.view_matrix();
         OPENGL_TO_WGPU_MATRIX * proj * view
     }
+
+    /// x: texel size, y: depth bias in NDC, z: normal offset in world units,
+    /// w: filter radius in UV, or UV per NDC depth of penumbra for PCSS
+    pub fn shadow_params(&self) -> [f32; 4] {
+        let texel = 1.0 / self.shadow_resolution as f32;
+        let depth_range = self.far - self.near;
+        let radius = match self.shadow_filter {
+            ShadowFilter::Hard => 0.0,
+            ShadowFilter::Pcf { radius } => radius as f32 * texel,
+            ShadowFilter::PoissonPcf { radius } => radius * texel,
+            ShadowFilter::Pcss { light_size } => light_size * depth_range / self.size,
+            ShadowFilter::Vsm { .. } | ShadowFilter::Evsm { .. } => 0.0,
+        };
+        [
+            texel,
+            self.depth_bias / depth_range,
+            self.normal_offset * self.size * texel,
+            radius,
+        ]
+    }
+
+    /// x: filter mode, y: PCF radius in texels, z: moments blur radius in texels
+    pub fn shadow_filter_params(&self) -> [u32; 4] {
+        let (radius, blur_radius) = match self.shadow_filter {
+            ShadowFilter::Pcf { radius } => (radius, 0),
+            ShadowFilter::Vsm { blur_radius, .. } | ShadowFilter::Evsm { blur_radius, .. } => {
+                (0, blur_radius)
+            }
+            _ => (0, 0),
+        };
+        [self.shadow_filter.mode(), radius, blur_radius, 0]
+    }
+
+    /// x: positive exponent, y: negative exponent, z: light bleeding reduction, w: min variance
+    pub fn shadow_moment_params(&self) -> [f32; 4] {
+        match self.shadow_filter {
+            ShadowFilter::Vsm {
+                light_bleeding_reduction,
+                ..
+            } => [0.0, 0.0, light_bleeding_reduction, MIN_VARIANCE],
+            ShadowFilter::Evsm {
+                exponents,
+                light_bleeding_reduction,
+                ..
+            } => [
+                exponents[0],
+                exponents[1],
+                light_bleeding_reduction,
+                MIN_VARIANCE,
+            ],
+            _ => [0.0, 0.0, 0.0, MIN_VARIANCE],
+        }
+    }
 }
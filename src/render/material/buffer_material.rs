@@ -23,7 +23,7 @@ pub trait BufferMaterialData {
     type Raw: bytemuck::Pod;
 
     fn raw(&self) -> Self::Raw;
-    fn binding_resources<'a>(&self, buffer: &'a Buffer) -> Vec<wgpu::BindingResource<'a>>;
+    fn binding_resources<'a>(&'a self, buffer: &'a Buffer) -> Vec<wgpu::BindingResource<'a>>;
 }
 
 #[derive(Resource, Default)]
@@ -53,6 +53,12 @@ impl BufferMaterialManager {
         Ok(Arc::clone(&self.map.get(&key).unwrap().layout))
     }
 
+    pub fn get_layout<M: BufferMaterialData + 'static>(&self) -> Option<Arc<BindGroupLayout>> {
+        self.map
+            .get(&TypeId::of::<M>())
+            .map(|it| Arc::clone(&it.layout))
+    }
+
     pub fn instantiate_material<M: BufferMaterialData + 'static>(
         &mut self,
         data: M,
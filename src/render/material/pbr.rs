@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use crate::{
+    asset::{Assets, Handle},
     bg_descriptor, impl_pod_zeroable,
     macro_utils::BGLEntry,
     render::{
@@ -28,18 +29,122 @@ impl FromWorld for PBRMaterialBindGroupLayout {
                 2: ShaderStages::FRAGMENT => BGLEntry::Sampler(SamplerBindingType::Filtering);
                 3: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, TextureSampleType::Float { filterable: true }); // Normal Tex
                 4: ShaderStages::FRAGMENT => BGLEntry::Sampler(SamplerBindingType::Filtering);
+                5: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, TextureSampleType::Float { filterable: true }); // Emissive Tex
+                6: ShaderStages::FRAGMENT => BGLEntry::Sampler(SamplerBindingType::Filtering);
             )));
         Self(material_bind_group_layout)
     }
 }
 
+/// Factors of the glTF material extensions beyond metallic-roughness.
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialExtensions {
+    /// `KHR_materials_ior`, also sets [`GltfMaterial::reflectance`] on load
+    pub ior: f32,
+    /// `KHR_materials_specular`, scales the F0 and F90 of dielectrics
+    pub specular: f32,
+    pub specular_color: Vec3,
+    /// `KHR_materials_sheen`
+    pub sheen_color: Vec3,
+    pub sheen_roughness: f32,
+    /// `KHR_materials_transmission`, above zero the material is drawn in the forward transmission pass
+    pub transmission: f32,
+    /// `KHR_materials_volume`, zero thickness is thin walled
+    pub thickness: f32,
+    pub attenuation_distance: f32,
+    pub attenuation_color: Vec3,
+}
+
+impl Default for MaterialExtensions {
+    fn default() -> Self {
+        Self {
+            ior: 1.5,
+            specular: 1.0,
+            specular_color: Vec3::new(1.0, 1.0, 1.0),
+            sheen_color: Vec3::zero(),
+            sheen_roughness: 0.0,
+            transmission: 0.0,
+            thickness: 0.0,
+            attenuation_distance: f32::MAX,
+            attenuation_color: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl MaterialExtensions {
+    pub fn is_transmissive(&self) -> bool {
+        self.transmission > 0.0
+    }
+
+    /// Reflectance giving the F0 of the IOR, `f0 = 0.16 * reflectance^2`
+    pub fn reflectance(&self) -> f32 {
+        let f0_sqrt = ((self.ior - 1.0) / (self.ior + 1.0)).abs();
+        (f0_sqrt / 0.4).clamp(0.0, 1.0)
+    }
+}
+
+/// UV set of a texture slot and its `KHR_texture_transform`
+#[derive(Clone, Copy, Debug)]
+pub struct TextureTransform {
+    /// Index of the UV set, 0 or 1
+    pub tex_coord: u32,
+    pub offset: Vec2,
+    /// Radians, counter-clockwise in UV space
+    pub rotation: f32,
+    pub scale: Vec2,
+}
+
+impl Default for TextureTransform {
+    fn default() -> Self {
+        Self {
+            tex_coord: 0,
+            offset: Vec2::zero(),
+            rotation: 0.0,
+            scale: Vec2::new(1.0, 1.0),
+        }
+    }
+}
+
+/// Rows of the affine `offset * rotation * scale` matrix, must match `TextureTransform` in `pbr_type.wgsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RawTextureTransform {
+    pub u_row: [f32; 3],
+    pub tex_coord: u32,
+    pub v_row: [f32; 3],
+    pub padding: u32,
+}
+impl_pod_zeroable!(RawTextureTransform);
+
+impl From<&TextureTransform> for RawTextureTransform {
+    fn from(value: &TextureTransform) -> Self {
+        let (sin, cos) = value.rotation.sin_cos();
+        let scale = value.scale;
+        Self {
+            u_row: [cos * scale.x, sin * scale.y, value.offset.x],
+            tex_coord: value.tex_coord,
+            v_row: [-sin * scale.x, cos * scale.y, value.offset.y],
+            padding: 0,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct GltfMaterial {
     pub base_color_texture: Option<Arc<UploadedImageWithSampler>>,
     pub normal_texture: Option<Arc<UploadedImageWithSampler>>,
+    pub emissive_texture: Option<Arc<UploadedImageWithSampler>>,
     pub roughness: f32,
     pub metallic: f32,
     pub reflectance: f32,
+    /// Linear color, multiplied by the emissive texture
+    pub emissive: Vec3,
+    /// Scales the emissive color beyond 1 for HDR emission
+    pub emissive_strength: f32,
+    pub base_color_transform: TextureTransform,
+    pub normal_transform: TextureTransform,
+    pub emissive_transform: TextureTransform,
+    pub extensions: MaterialExtensions,
 }
 
 impl Default for GltfMaterial {
@@ -47,9 +152,16 @@ impl Default for GltfMaterial {
         Self {
             base_color_texture: None,
             normal_texture: None,
+            emissive_texture: None,
             roughness: 1.0,
             metallic: 0.0,
             reflectance: 0.5,
+            emissive: Vec3::zero(),
+            emissive_strength: 1.0,
+            base_color_transform: Default::default(),
+            normal_transform: Default::default(),
+            emissive_transform: Default::default(),
+            extensions: Default::default(),
         }
     }
 }
@@ -57,6 +169,8 @@ impl Default for GltfMaterial {
 pub struct UploadedPBRMaterial {
     pub bind_group: Arc<BindGroup>,
     pub pipeline: Arc<RenderPipeline>,
+    /// Skipped by the G-buffer pass and drawn by the transmission pass instead
+    pub transmissive: bool,
 }
 
 impl UploadedPBRMaterial {
@@ -78,6 +192,11 @@ impl UploadedPBRMaterial {
             .as_ref()
             .map(|it| it.as_ref())
             .unwrap_or(normal_texture);
+        let emissive = gltf_material
+            .emissive_texture
+            .as_ref()
+            .map(|it| it.as_ref())
+            .unwrap_or(white_texture);
         let material_bind_group_layout = &layout.0;
 
         let raw = RawPBRMaterial::from(gltf_material);
@@ -95,11 +214,14 @@ impl UploadedPBRMaterial {
             2: BindingResource::Sampler(&base_color.sampler);
             3: BindingResource::TextureView(&normal.view);
             4: BindingResource::Sampler(&normal.sampler);
+            5: BindingResource::TextureView(&emissive.view);
+            6: BindingResource::Sampler(&emissive.sampler);
         )));
 
         Self {
             bind_group,
             pipeline: main_pipeline,
+            transmissive: gltf_material.extensions.is_transmissive(),
         }
     }
 }
@@ -121,27 +243,105 @@ pub struct PBRMaterialOverride {
 #[derive(Component, Clone, Default)]
 #[require(PBRMaterialOverride)]
 pub struct PBRMaterial {
-    pub base_color_texture: Option<Arc<UploadedImageWithSampler>>,
-    pub normal_texture: Option<Arc<UploadedImageWithSampler>>,
+    pub base_color_texture: Option<Handle<UploadedImageWithSampler>>,
+    pub normal_texture: Option<Handle<UploadedImageWithSampler>>,
+    pub emissive_texture: Option<Handle<UploadedImageWithSampler>>,
     pub roughness: Option<f32>,
     pub metallic: Option<f32>,
     pub reflectance: Option<f32>,
+    pub emissive: Option<Vec3>,
+    pub emissive_strength: Option<f32>,
+}
+
+impl PBRMaterial {
+    /// Fills the fields that aren't overridden from `base`, the material of the mesh's first primitive.
+    pub fn merged(
+        &self,
+        base: Option<&GltfMaterial>,
+        images: Option<&Assets<UploadedImageWithSampler>>,
+    ) -> GltfMaterial {
+        let image = |handle: &Option<Handle<UploadedImageWithSampler>>| {
+            handle
+                .as_ref()
+                .zip(images)
+                .and_then(|(handle, images)| images.get(handle))
+        };
+        GltfMaterial {
+            base_color_texture: image(&self.base_color_texture)
+                .or(base.and_then(|it| it.base_color_texture.clone())),
+            normal_texture: image(&self.normal_texture)
+                .or(base.and_then(|it| it.normal_texture.clone())),
+            emissive_texture: image(&self.emissive_texture)
+                .or(base.and_then(|it| it.emissive_texture.clone())),
+            roughness: self
+                .roughness
+                .unwrap_or(base.map(|it| it.roughness).unwrap_or_default()),
+            metallic: self
+                .metallic
+                .unwrap_or(base.map(|it| it.metallic).unwrap_or_default()),
+            reflectance: self
+                .reflectance
+                .unwrap_or(base.map(|it| it.reflectance).unwrap_or_default()),
+            emissive: self
+                .emissive
+                .unwrap_or(base.map(|it| it.emissive).unwrap_or(Vec3::zero())),
+            emissive_strength: self
+                .emissive_strength
+                .unwrap_or(base.map(|it| it.emissive_strength).unwrap_or(1.0)),
+            base_color_transform: base.map(|it| it.base_color_transform).unwrap_or_default(),
+            normal_transform: base.map(|it| it.normal_transform).unwrap_or_default(),
+            emissive_transform: base.map(|it| it.emissive_transform).unwrap_or_default(),
+            extensions: base.map(|it| it.extensions).unwrap_or_default(),
+        }
+    }
 }
 
+#[repr(C, align(16))]
 #[derive(Clone, Copy, Debug)]
 pub struct RawPBRMaterial {
     pub metallic: f32,
     pub roughness: f32,
     pub reflectance: f32,
+    pub emissive_strength: f32,
+    /// w is unused
+    pub emissive: [f32; 4],
+    /// rgb: sheen color, a: sheen roughness
+    pub sheen: [f32; 4],
+    /// rgb: specular color scaled by the specular factor, a: specular factor
+    pub specular: [f32; 4],
+    /// x: transmission, y: thickness, z: attenuation distance, w: ior
+    pub transmission: [f32; 4],
+    /// w is unused
+    pub attenuation_color: [f32; 4],
+    pub base_color_transform: RawTextureTransform,
+    pub normal_transform: RawTextureTransform,
+    pub emissive_transform: RawTextureTransform,
 }
 impl_pod_zeroable!(RawPBRMaterial);
 
 impl From<&GltfMaterial> for RawPBRMaterial {
     fn from(value: &GltfMaterial) -> Self {
+        let ext = &value.extensions;
         Self {
             metallic: value.metallic,
             roughness: value.roughness,
             reflectance: value.reflectance,
+            emissive_strength: value.emissive_strength,
+            emissive: value.emissive.extend(0.0).into(),
+            sheen: ext.sheen_color.extend(ext.sheen_roughness).into(),
+            specular: (ext.specular_color * ext.specular)
+                .extend(ext.specular)
+                .into(),
+            transmission: [
+                ext.transmission,
+                ext.thickness,
+                ext.attenuation_distance,
+                ext.ior,
+            ],
+            attenuation_color: ext.attenuation_color.extend(0.0).into(),
+            base_color_transform: (&value.base_color_transform).into(),
+            normal_transform: (&value.normal_transform).into(),
+            emissive_transform: (&value.emissive_transform).into(),
         }
     }
 }
@@ -152,6 +352,7 @@ pub fn sys_update_override_pbr_material_bind_group(
     white: Res<WhiteTexture>,
     normal_default: Res<NormalDefaultTexture>,
     layout: Res<PBRMaterialBindGroupLayout>,
+    images: Option<Res<Assets<UploadedImageWithSampler>>>,
     mut pbr_mats: Query<
         (&MeshRenderer, &PBRMaterial, &mut PBRMaterialOverride),
         Changed<PBRMaterial>,
@@ -168,25 +369,7 @@ pub fn sys_update_override_pbr_material_bind_group(
                     .map(|primitive| primitive.material.as_ref())
             })
             .flatten();
-        let mat = GltfMaterial {
-            base_color_texture: ove_mat.base_color_texture.clone().or(raw_mat
-                .as_ref()
-                .and_then(|it| it.base_color_texture.clone())),
-            normal_texture: ove_mat.normal_texture.clone().or(raw_mat
-                .as_ref()
-                .and_then(|it| it.normal_texture.clone())),
-            roughness: ove_mat
-                .roughness
-                .unwrap_or(raw_mat.map(|it| it.roughness).unwrap_or(Default::default())),
-            metallic: ove_mat
-                .metallic
-                .unwrap_or(raw_mat.map(|it| it.metallic).unwrap_or(Default::default())),
-            reflectance: ove_mat.reflectance.unwrap_or(
-                raw_mat
-                    .map(|it| it.reflectance)
-                    .unwrap_or(Default::default()),
-            ),
-        };
+        let mat = ove_mat.merged(raw_mat.map(|it| it.as_ref()), images.as_deref());
         ove.material = Some(Arc::new(UploadedPBRMaterial::from_gltf(
             &rs.device,
             &layout,
@@ -1,25 +1,128 @@
-use std::sync::Arc;
+use std::collections::HashMap;
 
 use bevy_ecs::{system::Resource, world::FromWorld};
-use wgpu::{ShaderModule, TextureFormat};
+use wgpu::{BindGroupLayout, RenderPipeline, Sampler, ShaderModule, TextureFormat};
 
-use crate::asset::load::Loadable;
+use crate::{
+    asset::AssetPath,
+    bg_descriptor, bg_layout_descriptor,
+    macro_utils::BGLEntry,
+    render::{prelude::*, shader_loader::ShaderLoader},
+};
 
-#[derive(Clone, Resource)]
-pub struct DefaultMipmapGenShader {
-    shader: Arc<ShaderModule>,
+/// Fills the mip chains of textures by blitting each level into the next.
+/// The blit pipelines are created once per texture format.
+#[derive(Resource)]
+pub struct MipmapGenerator {
+    shader: ShaderModule,
+    layout: BindGroupLayout,
+    sampler: Sampler,
+    pipelines: HashMap<TextureFormat, RenderPipeline>,
 }
 
-impl FromWorld for DefaultMipmapGenShader {
+impl FromWorld for MipmapGenerator {
     fn from_world(world: &mut bevy_ecs::world::World) -> Self {
+        let shader =
+            ShaderLoader::load_module_by_world(world, AssetPath::new_shader_wgsl("blit")).unwrap();
+        let device = &world.resource::<RenderState>().device;
+        let layout = device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Mipmap Blit"]
+            0: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, TextureSampleType::Float { filterable: true });
+            1: ShaderStages::FRAGMENT => BGLEntry::Sampler(SamplerBindingType::Filtering);
+        });
+        let sampler = device.create_sampler(&wgpu_init::sampler_desc(
+            Some("Mipmap Blit"),
+            wgpu::AddressMode::ClampToEdge,
+            wgpu::FilterMode::Linear,
+        ));
         Self {
-            shader: Arc::new(
-                ShaderModule::load(
-                    crate::asset::AssetPath::Assets("shaders/blit.wgsl".to_string()),
-                    world,
-                )
-                .unwrap(),
-            ),
+            shader,
+            layout,
+            sampler,
+            pipelines: HashMap::new(),
+        }
+    }
+}
+
+impl MipmapGenerator {
+    fn pipeline(&mut self, device: &wgpu::Device, format: TextureFormat) -> &RenderPipeline {
+        self.pipelines.entry(format).or_insert_with(|| {
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Mipmap Blit"),
+                bind_group_layouts: &[&self.layout],
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Mipmap Blit"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &self.shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(format.into())],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+                cache: None,
+            })
+        })
+    }
+
+    /// Generates every level after the first, `texture` needs `RENDER_ATTACHMENT` usage.
+    pub fn generate(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        device: &wgpu::Device,
+        texture: &wgpu::Texture,
+    ) {
+        let mip_count = texture.mip_level_count();
+        if mip_count <= 1 {
+            return;
+        }
+        let views = (0..mip_count)
+            .map(|level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("mip"),
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let pipeline = self.pipeline(device, texture.format()).clone();
+        for target_mip in 1..mip_count as usize {
+            let bind_group = device.create_bind_group(&bg_descriptor! {
+                ["Mipmap Blit"] [&self.layout]
+                0: wgpu::BindingResource::TextureView(&views[target_mip - 1]);
+                1: wgpu::BindingResource::Sampler(&self.sampler);
+            });
+
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mipmap Blit"),
+                color_attachments: &[Some(wgpu_init::render_pass_color_attachment(
+                    &views[target_mip],
+                    None,
+                    true,
+                ))],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            rpass.set_pipeline(&pipeline);
+            rpass.set_bind_group(0, &bind_group, &[]);
+            rpass.draw(0..4, 0..1);
         }
     }
 }
@@ -33,100 +136,3 @@ pub fn calculate_mip_level_count<const N: usize>(tex_size: &[u32; N]) -> u32 {
         })
         .unwrap_or(1u32)
 }
-
-pub fn generate_mip_map(
-    encoder: &mut wgpu::CommandEncoder,
-    device: &wgpu::Device,
-    texture: &wgpu::Texture,
-    format: TextureFormat,
-    shader: &wgpu::ShaderModule,
-    mip_count: u32,
-) {
-    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("blit"),
-        layout: None,
-        vertex: wgpu::VertexState {
-            module: shader,
-            entry_point: Some("vs_main"),
-            buffers: &[],
-            compilation_options: Default::default(),
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: shader,
-            entry_point: Some("fs_main"),
-            targets: &[Some(format.into())],
-            compilation_options: Default::default(),
-        }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleStrip,
-            ..Default::default()
-        },
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
-        cache: None,
-    });
-    let bind_group_layout = pipeline.get_bind_group_layout(0);
-
-    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-        label: Some("mip"),
-        address_mode_u: wgpu::AddressMode::ClampToEdge,
-        address_mode_v: wgpu::AddressMode::ClampToEdge,
-        address_mode_w: wgpu::AddressMode::ClampToEdge,
-        mag_filter: wgpu::FilterMode::Linear,
-        min_filter: wgpu::FilterMode::Nearest,
-        mipmap_filter: wgpu::FilterMode::Nearest,
-        ..Default::default()
-    });
-
-    let views = (0..mip_count)
-        .map(|level| {
-            texture.create_view(&wgpu::TextureViewDescriptor {
-                label: Some("mip"),
-                format: None,
-                dimension: None,
-                aspect: wgpu::TextureAspect::All,
-                base_mip_level: level,
-                mip_level_count: None,
-                base_array_layer: 0,
-                array_layer_count: None,
-                usage: None,
-            })
-        })
-        .collect::<Vec<_>>();
-
-    for target_mip in 1..mip_count as usize {
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&views[target_mip - 1]),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-            ],
-            label: None,
-        });
-
-        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &views[target_mip],
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
-        rpass.set_pipeline(&pipeline);
-        rpass.set_bind_group(0, &bind_group, &[]);
-        rpass.draw(0..4, 0..1);
-    }
-}
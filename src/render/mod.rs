@@ -1,17 +1,20 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use bevy_ecs::{
     component::Component,
-    system::Resource,
+    query::{Changed, With},
+    system::{Query, Res, Resource},
     world::{FromWorld, World},
 };
+use cgmath::InnerSpace;
 use defered_rendering::MainPipeline;
 use material::{
-    pbr::{GltfMaterial, PBRMaterialBindGroupLayout, UploadedPBRMaterial},
+    pbr::{GltfMaterial, PBRMaterialBindGroupLayout, PBRMaterialOverride, UploadedPBRMaterial},
     UploadedMaterial,
 };
+use mipmap::{calculate_mip_level_count, MipmapGenerator};
 use shader_loader::ShaderLoader;
-use transform::TransformUniform;
+use transform::{PreviousWorldTransform, TransformUniform};
 use wgpu::{
     util::DeviceExt, BindGroup, BindGroupLayout, Buffer, BufferDescriptor, BufferUsages, Extent3d,
     RenderPass, Sampler, ShaderModule, ShaderStages, Texture, TextureDescriptor, TextureDimension,
@@ -19,8 +22,10 @@ use wgpu::{
 };
 
 use crate::{
-    asset::{load::Loadable, AssetPath},
-    bg_descriptor, bg_layout_descriptor, impl_pod_zeroable,
+    asset::{compressed::CompressedImage, load::Loadable, AssetPath, Assets, Handle},
+    bg_descriptor, bg_layout_descriptor,
+    cgmath_ext::{Vec3, VectorExt},
+    impl_pod_zeroable,
     macro_utils::BGLEntry,
     wgpu_init, RenderState,
 };
@@ -29,17 +34,22 @@ pub mod camera;
 pub mod cubemap;
 pub mod defered_rendering;
 pub mod dfg;
+pub mod fog;
 pub mod gizmos;
+pub mod irradiance_volume;
 pub mod light;
 pub mod material;
 pub mod mipmap;
 pub mod post_processing;
 pub mod prelude;
+pub mod reflection_probe;
 pub mod shader_loader;
 pub mod shadow_mapping;
+pub mod shadow_moments;
 pub mod skybox;
 pub mod systems;
 pub mod transform;
+pub mod transmission;
 pub mod utils;
 
 #[derive(Resource)]
@@ -107,7 +117,7 @@ pub fn create_color_render_target_image(
     let texture = device.create_texture(&desc);
     let view = texture.create_view(&TextureViewDescriptor::default());
 
-    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+    let sampler_desc = wgpu::SamplerDescriptor {
         // 4.
         address_mode_u: wgpu::AddressMode::ClampToEdge,
         address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -119,13 +129,15 @@ pub fn create_color_render_target_image(
         lod_min_clamp: 0.0,
         lod_max_clamp: 100.0,
         ..Default::default()
-    });
+    };
+    let sampler = device.create_sampler(&sampler_desc);
 
     UploadedImageWithSampler {
         size,
         texture,
         view,
         sampler,
+        sampler_desc,
     }
 }
 
@@ -152,17 +164,18 @@ pub fn create_depth_texture(
     };
     let texture = device.create_texture(&desc);
     let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler = device.create_sampler(&{
-        let mut desc = wgpu_init::sampler_desc_no_filter();
-        desc.compare = compare;
-        desc
-    });
+    let sampler_desc = wgpu::SamplerDescriptor {
+        compare,
+        ..wgpu_init::sampler_desc_no_filter()
+    };
+    let sampler = device.create_sampler(&sampler_desc);
 
     UploadedImageWithSampler {
         size,
         texture,
         view,
         sampler,
+        sampler_desc,
     }
 }
 
@@ -212,17 +225,66 @@ impl FromWorld for DepthRenderTarget {
 }
 
 #[derive(Component, Clone)]
+#[require(PreviousWorldTransform)]
 pub struct MeshRenderer {
     pub mesh: Option<Arc<UploadedMesh>>,
     pub object_bind_group: Arc<BindGroup>,
     pub transform_buffer: Arc<Buffer>,
 }
 
+/// The mesh of an entity, [`sys_update_mesh_renderers`] puts it into its [`MeshRenderer`].
+#[derive(Component, Clone)]
+pub struct MeshHandle(pub Handle<UploadedMesh>);
+
+pub fn sys_update_mesh_renderers(
+    meshes: Res<Assets<UploadedMesh>>,
+    mut query: Query<(&MeshHandle, &mut MeshRenderer), Changed<MeshHandle>>,
+) {
+    for (handle, mut renderer) in query.iter_mut() {
+        renderer.mesh = meshes.get(&handle.0);
+    }
+}
+
 #[derive(Component, Clone)]
 pub struct MainPassObject;
 
+/// Meshes drawn by the main pass, with their material overrides
+pub type MainPassMeshes<'w, 's> = Query<
+    'w,
+    's,
+    (&'static MeshRenderer, Option<&'static PBRMaterialOverride>),
+    (With<transform::Transform>, With<MainPassObject>),
+>;
+
+/// Which primitives [`MeshRenderer::draw_main`] draws, by the transmission of their material
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PrimitiveFilter {
+    All,
+    Opaque,
+    Transmissive,
+}
+
+impl PrimitiveFilter {
+    fn accepts(&self, material: &UploadedPBRMaterial) -> bool {
+        match self {
+            PrimitiveFilter::All => true,
+            PrimitiveFilter::Opaque => !material.transmissive,
+            PrimitiveFilter::Transmissive => material.transmissive,
+        }
+    }
+}
+
 impl MeshRenderer {
     pub fn new(mesh: Arc<UploadedMesh>, world: &World) -> Self {
+        Self::with_mesh(Some(mesh), world)
+    }
+
+    /// For entities with a [`MeshHandle`] to `handle`
+    pub fn from_handle(handle: &Handle<UploadedMesh>, world: &World) -> Self {
+        Self::with_mesh(world.resource::<Assets<UploadedMesh>>().get(handle), world)
+    }
+
+    fn with_mesh(mesh: Option<Arc<UploadedMesh>>, world: &World) -> Self {
         let device = &world.resource::<RenderState>().device;
         let layout = &world.resource::<ObjectBindGroupLayout>().0;
 
@@ -237,7 +299,7 @@ impl MeshRenderer {
             0: buffer.as_entire_binding();
         ));
         Self {
-            mesh: Some(mesh),
+            mesh,
             object_bind_group: Arc::new(object_bind_group),
             transform_buffer: Arc::new(buffer),
         }
@@ -268,10 +330,14 @@ impl MeshRenderer {
         render_pass: &mut RenderPass,
         default_material: Arc<UploadedPBRMaterial>,
         override_material: Option<&UploadedPBRMaterial>,
+        filter: PrimitiveFilter,
     ) {
         let Some(mesh) = self.mesh.as_ref() else {
             return;
         };
+        if override_material.is_some_and(|it| !filter.accepts(it)) {
+            return;
+        }
 
         render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
         render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
@@ -288,8 +354,11 @@ impl MeshRenderer {
                     Some(a) => a,
                     None => &default_material,
                 };
+                if !filter.accepts(material_instance) {
+                    continue;
+                }
                 if last_material.is_none()
-                    || Arc::ptr_eq(last_material.as_ref().unwrap(), material_instance)
+                    || !Arc::ptr_eq(last_material.as_ref().unwrap(), material_instance)
                 {
                     last_material = Some(Arc::clone(material_instance));
                     render_pass.set_bind_group(1, material_instance.get_bind_group(), &[]);
@@ -326,18 +395,21 @@ pub struct Vertex {
     pub tangent: [f32; 3],
     pub color: [f32; 4],
     pub tex_coord: [f32; 2],
+    /// Second UV set, for lightmaps and AO maps
+    pub tex_coord1: [f32; 2],
 }
 
 impl_pod_zeroable!(Vertex);
 
 impl Vertex {
     #[rustfmt::skip]
-    const ATTRIBS: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+    const ATTRIBS: [wgpu::VertexAttribute; 6] = wgpu::vertex_attr_array![
         0 => Float32x3, // Position
         1 => Float32x3, // Normal
         2 => Float32x3, // Tangent
         3 => Float32x4, // Color
         4 => Float32x2, // UV0
+        5 => Float32x2, // UV1
     ];
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
@@ -361,6 +433,41 @@ pub struct UploadedPrimitive {
     pub material: Option<Arc<GltfMaterial>>,
 }
 
+/// How the texels of an uploaded image are decoded when sampled.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum ColorSpace {
+    /// Colors, e.g. base color and emissive textures
+    #[default]
+    Srgb,
+    /// Data, e.g. normal, metallic-roughness and lookup textures
+    Linear,
+}
+
+impl ColorSpace {
+    pub fn rgba8_format(self) -> wgpu::TextureFormat {
+        match self {
+            ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ImageSettings {
+    pub color_space: ColorSpace,
+    /// Generate the full mip chain on upload
+    pub mipmaps: bool,
+}
+
+impl Default for ImageSettings {
+    fn default() -> Self {
+        Self {
+            color_space: ColorSpace::Srgb,
+            mipmaps: true,
+        }
+    }
+}
+
 pub struct UploadedImageWithSampler {
     #[allow(unused)]
     pub size: wgpu::Extent3d,
@@ -368,6 +475,8 @@ pub struct UploadedImageWithSampler {
     pub texture: Texture,
     pub view: TextureView,
     pub sampler: Sampler,
+    /// What `sampler` was created from, written out by the glTF export
+    pub sampler_desc: wgpu::SamplerDescriptor<'static>,
 }
 
 pub struct UploadedImage {
@@ -378,14 +487,37 @@ pub struct UploadedImage {
 
 pub struct Model {
     pub meshes: Vec<Mesh>,
+    /// `meshes` on the GPU, shared by every entity spawned from the model
+    pub uploaded_meshes: Vec<Handle<UploadedMesh>>,
+}
+
+impl Model {
+    /// Uploads `meshes` into `Assets<UploadedMesh>`.
+    pub fn new(meshes: Vec<Mesh>, world: &mut World) -> Self {
+        let uploaded_meshes = meshes
+            .iter()
+            .map(|mesh| {
+                let uploaded = Arc::new(mesh.upload(world));
+                world
+                    .get_resource_or_insert_with(Assets::<UploadedMesh>::new)
+                    .push(uploaded)
+            })
+            .collect();
+        Self {
+            meshes,
+            uploaded_meshes,
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
     pub primitives: Vec<Primitive>,
 }
 
+#[derive(Clone)]
 pub struct Primitive {
     pub indices_start: u32,
     pub indices_num: u32,
@@ -393,6 +525,86 @@ pub struct Primitive {
 }
 
 impl Mesh {
+    /// Min and max corners of the vertex positions, `None` without vertices
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        let first = Vec3::from(self.vertices.first()?.position);
+        Some(self.vertices.iter().fold((first, first), |(min, max), v| {
+            let p = Vec3::from(v.position);
+            (
+                Vec3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                Vec3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+            )
+        }))
+    }
+
+    /// Fills zero normals with the area weighted normals of the triangles around the position,
+    /// so that vertices split by UV seams stay smooth.
+    pub fn generate_normals(&mut self) {
+        let key = |v: &Vertex| v.position.map(f32::to_bits);
+        let mut normals = HashMap::<[u32; 3], Vec3>::new();
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] =
+                [0, 1, 2].map(|i| Vec3::from(self.vertices[triangle[i] as usize].position));
+            // Twice the area long
+            let normal = (b - a).cross(c - a);
+            for &i in triangle {
+                *normals
+                    .entry(key(&self.vertices[i as usize]))
+                    .or_insert(Vec3::zero()) += normal;
+            }
+        }
+        for v in self.vertices.iter_mut().filter(|v| v.normal == [0.0; 3]) {
+            if let Some(normal) = normals.get(&key(v)).filter(|it| it.magnitude2() > 0.0) {
+                v.normal = normal.normalize().into();
+            }
+        }
+    }
+
+    /// Fills zero tangents from the UV directions of the triangles, perpendicular to the normal.
+    pub fn generate_tangents(&mut self) {
+        let mut tangents = vec![Vec3::zero(); self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &self.vertices[triangle[i] as usize]);
+            let edge1 = Vec3::from(b.position) - Vec3::from(a.position);
+            let edge2 = Vec3::from(c.position) - Vec3::from(a.position);
+            let (du1, dv1) = (
+                b.tex_coord[0] - a.tex_coord[0],
+                b.tex_coord[1] - a.tex_coord[1],
+            );
+            let (du2, dv2) = (
+                c.tex_coord[0] - a.tex_coord[0],
+                c.tex_coord[1] - a.tex_coord[1],
+            );
+            let det = du1 * dv2 - du2 * dv1;
+            if det.abs() < f32::EPSILON {
+                continue;
+            }
+            let tangent = (edge1 * dv2 - edge2 * dv1) / det;
+            for &i in triangle {
+                tangents[i as usize] += tangent;
+            }
+        }
+        for (v, tangent) in self.vertices.iter_mut().zip(tangents) {
+            if v.tangent != [0.0; 3] {
+                continue;
+            }
+            let normal = Vec3::from(v.normal);
+            let mut tangent = tangent - normal * normal.dot(tangent);
+            // No usable UVs, any direction on the surface will do
+            if tangent.magnitude2() < f32::EPSILON {
+                let axis = if normal.x.abs() < 0.9 {
+                    Vec3::unit_x()
+                } else {
+                    Vec3::unit_y()
+                };
+                tangent = normal.cross(axis).cross(normal);
+            }
+            if tangent.magnitude2() > 0.0 {
+                v.tangent = tangent.normalize().into();
+            }
+        }
+    }
+
     pub fn upload(&self, world: &World) -> UploadedMesh {
         let rs = world.resource::<RenderState>();
         let device = &rs.device;
@@ -468,47 +680,48 @@ impl UploadedImageWithSampler {
         }
     }
 
-    pub fn from_glb_data(
-        data: &gltf::image::Data,
-        #[allow(unused)] gltf_sampler: &gltf::texture::Sampler,
+    /// Uploads an RGBA8 image, its mip chain is generated when `mipmaps` is given.
+    pub fn from_rgba8(
+        image: &image::RgbaImage,
+        color_space: ColorSpace,
+        sampler_desc: &wgpu::SamplerDescriptor,
         device: &wgpu::Device,
         queue: &wgpu::Queue,
+        mipmaps: Option<&mut MipmapGenerator>,
     ) -> Self {
+        let (width, height) = image.dimensions();
         let size = wgpu::Extent3d {
-            width: data.width,
-            height: data.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
+        let (mip_level_count, usage) = match mipmaps {
+            Some(_) => (
+                calculate_mip_level_count(&[width, height]),
+                wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            ),
+            None => (
+                1,
+                wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::COPY_SRC,
+            ),
+        };
 
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            format: color_space.rgba8_format(),
+            usage,
             view_formats: &[],
         });
 
-        let pixels = match data.format {
-            gltf::image::Format::R8G8B8 => {
-                let new_len = data.pixels.len() / 3 * 4;
-                let mut ret = vec![0u8; new_len];
-                for i in 0..new_len {
-                    let divide = i / 4;
-                    let modulo = i % 4;
-                    ret[i] = if modulo != 3 {
-                        *data.pixels.get(divide * 3 + modulo).unwrap()
-                    } else {
-                        0u8
-                    };
-                }
-                ret
-            }
-            _ => data.pixels.clone(),
-        };
-
         queue.write_texture(
             wgpu::TexelCopyTextureInfoBase {
                 texture: &texture,
@@ -516,22 +729,213 @@ impl UploadedImageWithSampler {
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            &pixels,
-            UploadedImageWithSampler::image_data_layout(data.width, data.height, 4, 0),
+            image,
+            UploadedImageWithSampler::image_data_layout(width, height, 4, 0),
             size,
         );
 
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        if let Some(mipmaps) = mipmaps {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Mipmap Generation"),
+            });
+            mipmaps.generate(&mut encoder, device, &texture);
+            queue.submit(Some(encoder.finish()));
+        }
 
-        // todo
-        let sampler = device.create_sampler(&UploadedImageWithSampler::default_sampler_desc());
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(sampler_desc);
 
         Self {
             size,
             texture,
             view,
             sampler,
+            sampler_desc: owned_sampler_desc(sampler_desc),
+        }
+    }
+
+    /// Copies mip 0 of an image uploaded by [`Self::from_rgba8`] back to the CPU, blocking until done.
+    pub fn read_rgba8(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> anyhow::Result<image::RgbaImage> {
+        let format = self.texture.format();
+        if !matches!(
+            format,
+            wgpu::TextureFormat::Rgba8UnormSrgb | wgpu::TextureFormat::Rgba8Unorm
+        ) {
+            anyhow::bail!("Can't read back {:?} textures", format);
+        }
+        if !self.texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            anyhow::bail!("Texture wasn't created with COPY_SRC");
+        }
+        let wgpu::Extent3d { width, height, .. } = self.texture.size();
+        let row_bytes = width * 4;
+        let padded_row_bytes = row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Image Readback"),
+            size: (padded_row_bytes * height) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Image Readback"),
+        });
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: Some(height),
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()??;
+        let pixels = slice
+            .get_mapped_range()
+            .chunks(padded_row_bytes as usize)
+            .flat_map(|row| &row[..row_bytes as usize])
+            .copied()
+            .collect();
+        buffer.unmap();
+        image::RgbaImage::from_raw(width, height, pixels)
+            .ok_or(anyhow::anyhow!("Readback size mismatch"))
+    }
+
+    /// Uploads a 2D `.ktx2` or `.dds` image with the mip levels it ships with.
+    pub fn from_compressed(
+        image: &CompressedImage,
+        color_space: ColorSpace,
+        sampler_desc: &wgpu::SamplerDescriptor,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> anyhow::Result<Self> {
+        if image.is_cubemap || image.layers != 1 {
+            anyhow::bail!("Expected a 2D image, found {} layers", image.layers);
+        }
+        let texture = image.upload(None, color_space, device, queue)?;
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(sampler_desc);
+        Ok(Self {
+            size: texture.size(),
+            texture,
+            view,
+            sampler,
+            sampler_desc: owned_sampler_desc(sampler_desc),
+        })
+    }
+}
+
+/// `desc` without its label, so that it can be kept next to the sampler.
+fn owned_sampler_desc(desc: &wgpu::SamplerDescriptor) -> wgpu::SamplerDescriptor<'static> {
+    wgpu::SamplerDescriptor {
+        label: None,
+        address_mode_u: desc.address_mode_u,
+        address_mode_v: desc.address_mode_v,
+        address_mode_w: desc.address_mode_w,
+        mag_filter: desc.mag_filter,
+        min_filter: desc.min_filter,
+        mipmap_filter: desc.mipmap_filter,
+        lod_min_clamp: desc.lod_min_clamp,
+        lod_max_clamp: desc.lod_max_clamp,
+        compare: desc.compare,
+        anisotropy_clamp: desc.anisotropy_clamp,
+        border_color: desc.border_color,
+    }
+}
+
+/// Expands glTF images to RGBA8, 16 bit and float channels are narrowed to 8 bits.
+pub fn gltf_image_to_rgba8(data: gltf::image::Data) -> anyhow::Result<image::RgbaImage> {
+    use gltf::image::Format;
+
+    let (channels, channel_size) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    // The gltf crate keeps wider channels in native byte order
+    let narrow = |bytes: &[u8]| match bytes.len() {
+        2 => ((u16::from_ne_bytes([bytes[0], bytes[1]]) as u32 * 255 + 32767) / 65535) as u8,
+        4 => {
+            let value = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            (value.clamp(0.0, 1.0) * 255.0).round() as u8
         }
+        _ => bytes[0],
+    };
+    let pixels = match (channels, channel_size) {
+        (4, 1) => data.pixels,
+        _ => data
+            .pixels
+            .chunks_exact(channels * channel_size)
+            .flat_map(|pixel| {
+                let c = |i: usize| narrow(&pixel[i * channel_size..(i + 1) * channel_size]);
+                match channels {
+                    1 => [c(0), c(0), c(0), u8::MAX],
+                    2 => [c(0), c(1), 0, u8::MAX],
+                    3 => [c(0), c(1), c(2), u8::MAX],
+                    _ => [c(0), c(1), c(2), c(3)],
+                }
+            })
+            .collect(),
+    };
+    image::RgbaImage::from_raw(data.width, data.height, pixels)
+        .ok_or_else(|| anyhow::anyhow!("glTF image size doesn't match its pixels"))
+}
+
+/// Wrap modes and filters of a glTF sampler, unspecified filters are linear.
+pub fn gltf_sampler_desc(sampler: &gltf::texture::Sampler) -> wgpu::SamplerDescriptor<'static> {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+    use wgpu::{AddressMode, FilterMode};
+
+    let address_mode = |mode: WrappingMode| match mode {
+        WrappingMode::ClampToEdge => AddressMode::ClampToEdge,
+        WrappingMode::MirroredRepeat => AddressMode::MirrorRepeat,
+        WrappingMode::Repeat => AddressMode::Repeat,
+    };
+    let mag_filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => FilterMode::Nearest,
+        _ => FilterMode::Linear,
+    };
+    let (min_filter, mipmap_filter) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) | Some(MinFilter::NearestMipmapNearest) => {
+            (FilterMode::Nearest, FilterMode::Nearest)
+        }
+        Some(MinFilter::NearestMipmapLinear) => (FilterMode::Nearest, FilterMode::Linear),
+        Some(MinFilter::LinearMipmapNearest) => (FilterMode::Linear, FilterMode::Nearest),
+        _ => (FilterMode::Linear, FilterMode::Linear),
+    };
+    wgpu::SamplerDescriptor {
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+        address_mode_w: AddressMode::ClampToEdge,
+        mag_filter,
+        min_filter,
+        mipmap_filter,
+        ..Default::default()
     }
 }
 
@@ -567,7 +971,7 @@ impl FromWorld for WhiteTexture {
     fn from_world(world: &mut World) -> Self {
         Self(Arc::new(
             UploadedImageWithSampler::load(
-                AssetPath::Assets("textures/white.png".to_string()),
+                AssetPath::Embedded("textures/white.png".to_string()),
                 world,
             )
             .unwrap(),
@@ -578,9 +982,13 @@ impl FromWorld for WhiteTexture {
 impl FromWorld for NormalDefaultTexture {
     fn from_world(world: &mut World) -> Self {
         Self(Arc::new(
-            UploadedImageWithSampler::load(
-                AssetPath::Assets("textures/normal_default.png".to_string()),
+            UploadedImageWithSampler::load_with_settings(
+                AssetPath::Embedded("textures/normal_default.png".to_string()),
                 world,
+                ImageSettings {
+                    color_space: ColorSpace::Linear,
+                    ..Default::default()
+                },
             )
             .unwrap(),
         ))
@@ -591,7 +999,7 @@ impl FromWorld for MissingTexture {
     fn from_world(world: &mut World) -> Self {
         Self(Arc::new(
             UploadedImageWithSampler::load(
-                AssetPath::Assets("textures/missing.png".to_string()),
+                AssetPath::Embedded("textures/missing.png".to_string()),
                 world,
             )
             .unwrap(),
@@ -622,3 +1030,50 @@ impl FromWorld for DefaultMainPipelineMaterial {
         Self(Arc::new(mat))
     }
 }
+
+/// Unit cube drawn with [`DefaultMainPipelineMaterial`], shown while a model is loading.
+#[derive(Resource, Clone)]
+pub struct PlaceholderMesh(pub Arc<UploadedMesh>);
+
+impl FromWorld for PlaceholderMesh {
+    fn from_world(world: &mut World) -> Self {
+        let faces = [
+            ([1.0, 0.0, 0.0], [0.0, 0.0, -1.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0]),
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+            ([0.0, 0.0, -1.0], [-1.0, 0.0, 0.0]),
+        ];
+        let mut vertices = Vec::with_capacity(24);
+        let mut indices = Vec::with_capacity(36);
+        for (normal, tangent) in faces {
+            let n = Vec3::from(normal);
+            let t = Vec3::from(tangent);
+            let b = n.cross(t);
+            let base = vertices.len() as u32;
+            for (s, r) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                vertices.push(Vertex {
+                    position: ((n + t * s + b * r) * 0.5).into(),
+                    normal,
+                    tangent,
+                    color: [1.0; 4],
+                    tex_coord: [(s + 1.0) * 0.5, (1.0 - r) * 0.5],
+                    tex_coord1: [0.0; 2],
+                });
+            }
+            indices.extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+
+        let mesh = Mesh {
+            primitives: vec![Primitive {
+                indices_start: 0,
+                indices_num: indices.len() as u32,
+                material: None,
+            }],
+            vertices,
+            indices,
+        };
+        Self(Arc::new(mesh.upload(world)))
+    }
+}
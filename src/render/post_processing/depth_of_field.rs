@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use bevy_ecs::prelude::*;
+use egui::Widget;
+use wgpu::RenderPipeline;
+
+use crate::{
+    asset::AssetPath,
+    impl_pod_zeroable,
+    render::{
+        camera::Camera,
+        material::buffer_material::BufferMaterialData,
+        post_processing::{
+            add_post_effect_by_world, create_effect_pipeline_by_world,
+            effect::{PostEffectData, PostEffectDescriptor, PostEffectId},
+            PostProcessingManager, RenderStage,
+        },
+        shader_loader::ReloadPipelines,
+    },
+    RenderState,
+};
+
+/// Bokeh depth of field, the circle of confusion comes from the physical properties of [`Camera`].
+#[derive(Resource)]
+pub struct DepthOfField {
+    pub effect: PostEffectId,
+}
+
+#[derive(Debug, Clone)]
+pub struct DepthOfFieldParams {
+    /// Largest circle of confusion radius in pixels
+    pub max_coc_radius: f32,
+    /// Distance in pixels between the rings of the gathering spiral, smaller is smoother and slower
+    pub radius_scale: f32,
+    pub focal_distance: f32,
+    pub focal_length: f32,
+    pub aperture_diameter: f32,
+    pub sensor_height: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct RawDepthOfFieldParams {
+    pub focal_distance: f32,
+    pub focal_length: f32,
+    pub aperture_diameter: f32,
+    pub sensor_height: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    pub max_coc_radius: f32,
+    pub radius_scale: f32,
+}
+
+impl_pod_zeroable!(RawDepthOfFieldParams);
+
+impl DepthOfFieldParams {
+    pub fn from_camera(camera: &Camera) -> Self {
+        let mut ret = Self {
+            max_coc_radius: 12.0,
+            radius_scale: 0.5,
+            focal_distance: 0.0,
+            focal_length: 0.0,
+            aperture_diameter: 0.0,
+            sensor_height: 0.0,
+            znear: 0.0,
+            zfar: 0.0,
+        };
+        ret.apply_camera(camera);
+        ret
+    }
+
+    pub fn apply_camera(&mut self, camera: &Camera) {
+        self.focal_distance = camera.focal_distance;
+        self.focal_length = camera.focal_length();
+        self.aperture_diameter = camera.aperture_diameter();
+        self.sensor_height = camera.sensor_height * 0.001;
+        self.znear = camera.znear;
+        self.zfar = camera.zfar;
+    }
+}
+
+impl BufferMaterialData for DepthOfFieldParams {
+    type Raw = RawDepthOfFieldParams;
+
+    fn raw(&self) -> Self::Raw {
+        Self::Raw {
+            focal_distance: self.focal_distance,
+            focal_length: self.focal_length,
+            aperture_diameter: self.aperture_diameter,
+            sensor_height: self.sensor_height,
+            znear: self.znear,
+            zfar: self.zfar,
+            max_coc_radius: self.max_coc_radius,
+            radius_scale: self.radius_scale,
+        }
+    }
+
+    fn binding_resources<'a>(&self, buffer: &'a wgpu::Buffer) -> Vec<wgpu::BindingResource<'a>> {
+        vec![buffer.as_entire_binding()]
+    }
+}
+
+impl PostEffectData for DepthOfFieldParams {
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Max CoC");
+            changed |= egui::Slider::new(&mut self.max_coc_radius, 1.0..=32.0)
+                .ui(ui)
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Radius Scale");
+            changed |= egui::Slider::new(&mut self.radius_scale, 0.2..=2.0)
+                .ui(ui)
+                .changed();
+        });
+        ui.label("Aperture, focus and sensor are set on the camera.");
+        changed
+    }
+}
+
+impl DepthOfField {
+    pub fn descriptor() -> PostEffectDescriptor<'static> {
+        PostEffectDescriptor {
+            read_depth: true,
+            // Expensive, turned on from the post processing panel
+            enabled: false,
+            ..PostEffectDescriptor::new("Depth of Field", RenderStage::AfterOpaque)
+        }
+    }
+}
+
+impl FromWorld for DepthOfField {
+    fn from_world(world: &mut World) -> Self {
+        let params = DepthOfFieldParams::from_camera(&Camera::first_or_default(world));
+        let effect = add_post_effect_by_world(
+            world,
+            &Self::descriptor(),
+            AssetPath::new_shader_wgsl("depth_of_field"),
+            params,
+        )
+        .unwrap();
+        Self { effect }
+    }
+}
+
+impl ReloadPipelines for DepthOfField {
+    type Pipelines = RenderPipeline;
+
+    fn build_pipelines(&self, world: &mut World) -> anyhow::Result<Self::Pipelines> {
+        create_effect_pipeline_by_world(
+            world,
+            self.effect,
+            AssetPath::new_shader_wgsl("depth_of_field"),
+        )
+    }
+
+    fn set_pipelines(&mut self, pipelines: Self::Pipelines, world: &mut World) {
+        if let Some(effect) = world
+            .resource_mut::<PostProcessingManager>()
+            .effect_mut(self.effect)
+        {
+            effect.pipeline = Arc::new(pipelines);
+        }
+    }
+}
+
+pub fn sys_update_depth_of_field(
+    camera: Option<Single<&Camera, Changed<Camera>>>,
+    dof: Res<DepthOfField>,
+    rs: Res<RenderState>,
+    mut manager: ResMut<PostProcessingManager>,
+) {
+    let Some(camera) = camera else {
+        return;
+    };
+    let Some(params) = manager
+        .effect_mut(dof.effect)
+        .and_then(|it| it.params_mut::<DepthOfFieldParams>())
+    else {
+        return;
+    };
+    params.data.apply_camera(&camera);
+    params.update_buffer(&rs.queue);
+}
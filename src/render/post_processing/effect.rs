@@ -0,0 +1,128 @@
+use std::{any::Any, sync::Arc};
+
+use wgpu::{BindGroup, PipelineLayout, RenderPipeline};
+
+use crate::render::{
+    material::buffer_material::{BufferMaterialData, UploadedBufferMaterialInstance},
+    BGLEntry,
+};
+
+use super::RenderStage;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub struct PostEffectId(uuid::Uuid);
+
+impl PostEffectId {
+    pub fn new() -> Self {
+        Self(uuid::Uuid::new_v4())
+    }
+}
+
+/// Parameters of a post effect. The raw struct is uploaded as a uniform buffer
+/// at `@binding(0)` of the effect's parameter bind group.
+pub trait PostEffectData: BufferMaterialData + Send + Sync + 'static {
+    /// Draw the inspector of the parameters, return `true` if anything changed.
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool;
+}
+
+/// Type erased [`UploadedBufferMaterialInstance`] of a [`PostEffectData`].
+pub trait PostEffectParams: Send + Sync {
+    fn bind_group(&self) -> &BindGroup;
+    fn ui(&mut self, ui: &mut egui::Ui, queue: &wgpu::Queue);
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<M: PostEffectData> PostEffectParams for UploadedBufferMaterialInstance<M> {
+    fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, queue: &wgpu::Queue) {
+        if self.data.ui(ui) {
+            self.update_buffer(queue);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Bind groups an effect reads besides the source texture.
+/// Group 0 is always the source texture, the others follow in this order.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PostEffectBinding {
+    /// `0: uniform`, followed by [`PostEffectDescriptor::params_entries`]
+    Params,
+    /// `0: texture_depth_2d, 1: sampler`
+    Depth,
+    /// Same layout as `GBufferTexturesBindGroup`
+    GBuffer,
+}
+
+#[derive(Clone, Debug)]
+pub struct PostEffectDescriptor<'a> {
+    pub label: &'a str,
+    pub stage: RenderStage,
+    pub order: i32,
+    pub enabled: bool,
+    pub read_depth: bool,
+    pub read_g_buffer: bool,
+    /// Resources of the effect itself, bound in the params group after the uniform from binding 1.
+    /// They share the layout registered for the params type, so keep them the same for every effect of it.
+    pub params_entries: &'a [BGLEntry],
+}
+
+impl<'a> PostEffectDescriptor<'a> {
+    pub fn new(label: &'a str, stage: RenderStage) -> Self {
+        Self {
+            label,
+            stage,
+            order: 0,
+            enabled: true,
+            read_depth: false,
+            read_g_buffer: false,
+            params_entries: &[],
+        }
+    }
+
+    /// Groups after the source texture, in the order they are bound.
+    pub fn bindings(&self, with_params: bool) -> Vec<PostEffectBinding> {
+        let mut bindings = vec![];
+        if with_params {
+            bindings.push(PostEffectBinding::Params);
+        }
+        if self.read_depth {
+            bindings.push(PostEffectBinding::Depth);
+        }
+        if self.read_g_buffer {
+            bindings.push(PostEffectBinding::GBuffer);
+        }
+        bindings
+    }
+}
+
+pub struct PostEffect {
+    pub id: PostEffectId,
+    pub label: String,
+    pub stage: RenderStage,
+    /// Effects of the same stage run in ascending order.
+    pub order: i32,
+    pub enabled: bool,
+    pub pipeline: Arc<RenderPipeline>,
+    /// Kept to rebuild `pipeline` when its shader changes
+    pub(super) pipeline_layout: PipelineLayout,
+    pub params: Option<Box<dyn PostEffectParams>>,
+    pub bindings: Vec<PostEffectBinding>,
+}
+
+impl PostEffect {
+    pub fn params_mut<M: PostEffectData>(
+        &mut self,
+    ) -> Option<&mut UploadedBufferMaterialInstance<M>> {
+        self.params.as_mut().and_then(|it| {
+            it.as_any_mut()
+                .downcast_mut::<UploadedBufferMaterialInstance<M>>()
+        })
+    }
+}
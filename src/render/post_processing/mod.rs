@@ -1,18 +1,31 @@
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
+use anyhow::{anyhow, Result};
 use bevy_ecs::prelude::*;
+use effect::{
+    PostEffect, PostEffectBinding, PostEffectData, PostEffectDescriptor, PostEffectId,
+    PostEffectParams,
+};
 use wgpu::{
-    BindGroup, BindGroupLayout, BindingResource, Device, PipelineLayout, PipelineLayoutDescriptor,
-    RenderPipeline, ShaderModule, ShaderStages, SurfaceConfiguration,
+    BindGroup, BindGroupLayout, BindGroupLayoutDescriptor, BindingResource, Device, PipelineLayout,
+    PipelineLayoutDescriptor, RenderPipeline, ShaderModule, ShaderStages, SurfaceConfiguration,
 };
 
-use crate::{bg_descriptor, bg_layout_descriptor, render::BGLEntry, wgpu_init};
+use crate::{
+    asset::AssetPath, bg_descriptor, bg_layout_descriptor, render::BGLEntry, wgpu_init, RenderState,
+};
 
 use super::{
-    create_color_render_target_image, FullScreenVertexShader, RenderTargetSize,
-    UploadedImageWithSampler,
+    create_color_render_target_image,
+    defered_rendering::write_g_buffer_pipeline::GBufferTexturesBindGroup,
+    material::buffer_material::BufferMaterialManager, shader_loader::ShaderLoader,
+    DepthRenderTarget, FullScreenVertexShader, RenderTargetSize, UploadedImageWithSampler,
 };
 
+pub mod depth_of_field;
+pub mod effect;
+pub mod motion_blur;
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum RenderStage {
     BeforeOpaque,
@@ -21,12 +34,22 @@ pub enum RenderStage {
     AfterTransparent,
 }
 
+impl RenderStage {
+    pub const ALL: [RenderStage; 4] = [
+        RenderStage::BeforeOpaque,
+        RenderStage::AfterOpaque,
+        RenderStage::BeforeTransparent,
+        RenderStage::AfterTransparent,
+    ];
+}
+
 #[derive(Resource)]
 pub struct PostProcessingManager {
-    pub pipelines: HashMap<RenderStage, Vec<PostProcessingPipeline>>,
-    pub pipeline_layout: Arc<PipelineLayout>,
-    #[allow(unused)]
+    pub effects: Vec<PostEffect>,
     pub bind_group_layout: Arc<BindGroupLayout>,
+    pub depth_bind_group_layout: Arc<BindGroupLayout>,
+    pub depth_bind_group: Arc<BindGroup>,
+    pub g_buffer_bind_group_layout: Arc<BindGroupLayout>,
     bind_group_0: Arc<BindGroup>,
     bind_group_1: Arc<BindGroup>,
     temp_texture_0: Arc<UploadedImageWithSampler>,
@@ -56,30 +79,143 @@ impl PostProcessingManager {
         self.temp_texture_index = (self.temp_texture_index + 1) % 2;
         ret
     }
-    pub fn add_pipeline_from_shader(
+
+    /// Indices of the enabled effects of `stage`, in running order.
+    pub fn enabled_effects(&self, stage: RenderStage) -> Vec<usize> {
+        self.effects
+            .iter()
+            .enumerate()
+            .filter(|(_, it)| it.stage == stage && it.enabled)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    pub fn effect(&self, id: PostEffectId) -> Option<&PostEffect> {
+        self.effects.iter().find(|it| it.id == id)
+    }
+
+    pub fn effect_mut(&mut self, id: PostEffectId) -> Option<&mut PostEffect> {
+        self.effects.iter_mut().find(|it| it.id == id)
+    }
+
+    /// Call it after changing `order` of any effect.
+    pub fn sort_effects(&mut self) {
+        self.effects.sort_by_key(|it| it.order);
+    }
+
+    pub fn add_effect_with_params<M: PostEffectData>(
+        &mut self,
+        desc: &PostEffectDescriptor,
+        fs_shader: &ShaderModule,
+        params: M,
+        material_manager: &mut BufferMaterialManager,
+        device: &Device,
+        config: &SurfaceConfiguration,
+    ) -> Result<PostEffectId> {
+        let layout = match material_manager.get_layout::<M>() {
+            Some(layout) => layout,
+            None => {
+                let entries: Vec<_> = std::iter::once(BGLEntry::UniformBuffer())
+                    .chain(desc.params_entries.iter().cloned())
+                    .enumerate()
+                    .map(|(binding, entry)| {
+                        entry.into_bgl_entry(binding as u32, ShaderStages::FRAGMENT)
+                    })
+                    .collect();
+                material_manager.register::<M>(
+                    device,
+                    &BindGroupLayoutDescriptor {
+                        label: Some("Post Effect Params"),
+                        entries: &entries,
+                    },
+                )?
+            }
+        };
+        let instance = material_manager.instantiate_material(params, device)?;
+        Ok(self.push_effect(
+            desc,
+            fs_shader,
+            Some((Box::new(instance), layout)),
+            device,
+            config,
+        ))
+    }
+
+    fn push_effect(
         &mut self,
-        label: Option<&str>,
-        stage: RenderStage,
-        fs_shader: ShaderModule,
+        desc: &PostEffectDescriptor,
+        fs_shader: &ShaderModule,
+        params: Option<(Box<dyn PostEffectParams>, Arc<BindGroupLayout>)>,
         device: &Device,
         config: &SurfaceConfiguration,
-    ) {
-        let pipeline = device.create_render_pipeline(&wgpu_init::full_screen_pipeline_desc(
-            label,
-            &self.pipeline_layout,
+    ) -> PostEffectId {
+        let bindings = desc.bindings(params.is_some());
+        let mut layouts: Vec<&BindGroupLayout> = vec![&self.bind_group_layout];
+        layouts.extend(bindings.iter().map(|binding| match binding {
+            PostEffectBinding::Params => params.as_ref().unwrap().1.as_ref(),
+            PostEffectBinding::Depth => self.depth_bind_group_layout.as_ref(),
+            PostEffectBinding::GBuffer => self.g_buffer_bind_group_layout.as_ref(),
+        }));
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some(desc.label),
+            bind_group_layouts: &layouts,
+            push_constant_ranges: &[],
+        });
+        let pipeline =
+            self.create_pipeline(desc.label, &pipeline_layout, fs_shader, device, config);
+
+        let id = PostEffectId::new();
+        self.effects.push(PostEffect {
+            id,
+            label: desc.label.to_string(),
+            stage: desc.stage,
+            order: desc.order,
+            enabled: desc.enabled,
+            pipeline: Arc::new(pipeline),
+            pipeline_layout,
+            params: params.map(|(params, _)| params),
+            bindings,
+        });
+        self.sort_effects();
+        id
+    }
+
+    fn create_pipeline(
+        &self,
+        label: &str,
+        layout: &PipelineLayout,
+        fs_shader: &ShaderModule,
+        device: &Device,
+        config: &SurfaceConfiguration,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&wgpu_init::full_screen_pipeline_desc(
+            Some(label),
+            layout,
             &self.vs_shader,
-            &fs_shader,
+            fs_shader,
             &[Some(wgpu_init::color_target_replace_write_all(
                 config.format,
             ))],
-        ));
+        ))
+    }
 
-        self.pipelines
-            .entry(stage)
-            .or_default()
-            .push(PostProcessingPipeline {
-                pipeline: Arc::new(pipeline),
-            });
+    /// Builds the pipeline of an existing effect with a new fragment shader.
+    pub fn create_effect_pipeline(
+        &self,
+        id: PostEffectId,
+        fs_shader: &ShaderModule,
+        device: &Device,
+        config: &SurfaceConfiguration,
+    ) -> Option<RenderPipeline> {
+        let effect = self.effect(id)?;
+        Some(self.create_pipeline(
+            &effect.label,
+            &effect.pipeline_layout,
+            fs_shader,
+            device,
+            config,
+        ))
     }
 
     pub fn resize(
@@ -88,6 +224,7 @@ impl PostProcessingManager {
         height: u32,
         device: &Device,
         config: &SurfaceConfiguration,
+        depth: &UploadedImageWithSampler,
     ) {
         let bind_group_layout = &self.bind_group_layout;
         self.temp_texture_0 = Arc::new(create_color_render_target_image(
@@ -107,6 +244,11 @@ impl PostProcessingManager {
             0: BindingResource::TextureView(&self.temp_texture_1.view);
             1: BindingResource::Sampler(&self.temp_texture_1.sampler);
         }));
+        self.depth_bind_group = Arc::new(device.create_bind_group(&bg_descriptor! {
+            ["Post Processing Depth"] [&self.depth_bind_group_layout]
+            0: BindingResource::TextureView(&depth.view);
+            1: BindingResource::Sampler(&depth.sampler);
+        }));
     }
 }
 
@@ -121,6 +263,13 @@ impl FromWorld for PostProcessingManager {
             1: ShaderStages::FRAGMENT => BGLEntry::Sampler(wgpu::SamplerBindingType::Filtering);
         };
         let bind_group_layout = rs.device.create_bind_group_layout(&descriptor);
+        let depth_bind_group_layout = rs.device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Post Processing Depth"]
+            0: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, wgpu::TextureSampleType::Depth);
+            1: ShaderStages::FRAGMENT => BGLEntry::Sampler(wgpu::SamplerBindingType::NonFiltering);
+        });
+        let g_buffer_bind_group_layout =
+            Arc::clone(&world.resource::<GBufferTexturesBindGroup>().layout);
 
         let size = world.resource::<RenderTargetSize>();
         let temp_texture_0 = Arc::new(create_color_render_target_image(
@@ -147,17 +296,21 @@ impl FromWorld for PostProcessingManager {
             1: BindingResource::Sampler(&temp_texture_1.sampler);
         }));
 
-        let pipeline_layout = rs.device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: Some("Post Processing"),
-            bind_group_layouts: &[&bind_group_layout],
-            push_constant_ranges: &[],
-        });
+        let depth = world.resource::<DepthRenderTarget>().0.as_ref().unwrap();
+        let depth_bind_group = Arc::new(rs.device.create_bind_group(&bg_descriptor! {
+            ["Post Processing Depth"] [&depth_bind_group_layout]
+            0: BindingResource::TextureView(&depth.view);
+            1: BindingResource::Sampler(&depth.sampler);
+        }));
+
         Self {
-            pipelines: HashMap::new(),
+            effects: vec![],
             bind_group_layout: Arc::new(bind_group_layout),
+            depth_bind_group_layout: Arc::new(depth_bind_group_layout),
+            depth_bind_group,
+            g_buffer_bind_group_layout,
             bind_group_0,
             bind_group_1,
-            pipeline_layout: Arc::new(pipeline_layout),
             temp_texture_0,
             temp_texture_1,
             vs_shader,
@@ -166,7 +319,97 @@ impl FromWorld for PostProcessingManager {
     }
 }
 
-#[derive(Clone)]
-pub struct PostProcessingPipeline {
-    pub pipeline: Arc<RenderPipeline>,
+/// Load the fragment shader from `shader` and add a parameterised effect to [`PostProcessingManager`].
+pub fn add_post_effect_by_world<M: PostEffectData>(
+    world: &mut World,
+    desc: &PostEffectDescriptor,
+    shader: AssetPath,
+    params: M,
+) -> Result<PostEffectId> {
+    let fs_shader = ShaderLoader::load_module_by_world(world, shader)?;
+    world.resource_scope(|world, rs: Mut<RenderState>| {
+        world.resource_scope(|world, mut manager: Mut<PostProcessingManager>| {
+            manager.add_effect_with_params(
+                desc,
+                &fs_shader,
+                params,
+                &mut world.resource_mut::<BufferMaterialManager>(),
+                &rs.device,
+                &rs.config,
+            )
+        })
+    })
+}
+
+/// Load the fragment shader from `shader` and build a new pipeline for effect `id`,
+/// the owner of the effect swaps it in.
+pub fn create_effect_pipeline_by_world(
+    world: &mut World,
+    id: PostEffectId,
+    shader: AssetPath,
+) -> Result<RenderPipeline> {
+    let fs_shader = ShaderLoader::load_module_by_world(world, shader)?;
+    let rs = world.resource::<RenderState>();
+    world
+        .resource::<PostProcessingManager>()
+        .create_effect_pipeline(id, &fs_shader, &rs.device, &rs.config)
+        .ok_or_else(|| anyhow!("Post effect {:?} not found", id))
+}
+
+#[cfg(test)]
+mod test {
+    use wgpu::naga::{
+        self,
+        valid::{Capabilities, ValidationFlags, Validator},
+    };
+
+    use super::{depth_of_field::DepthOfField, motion_blur::MotionBlur, *};
+
+    /// Compose and validate the shader of an effect as `push_effect` would lay it out,
+    /// without a device.
+    fn assert_effect_layout(desc: &PostEffectDescriptor, shader: &str) {
+        let bindings = desc.bindings(true);
+        let group_count = 1 + bindings.len() as u32;
+        assert!(
+            group_count <= wgpu::Limits::default().max_bind_groups,
+            "{} binds {} groups",
+            desc.label,
+            group_count
+        );
+
+        let mut shader_loader = ShaderLoader::from_world(&mut World::new());
+        let Ok(wgpu::ShaderSource::Naga(module)) =
+            shader_loader.load_source(AssetPath::new_shader_wgsl(shader))
+        else {
+            panic!("Failed to compose {}", shader);
+        };
+        Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .unwrap();
+
+        for (_, global) in module.global_variables.iter() {
+            let Some(naga::ResourceBinding { group, binding }) = global.binding else {
+                continue;
+            };
+            assert!(group < group_count, "{} reads group {}", desc.label, group);
+            if group > 0 && bindings[group as usize - 1] == PostEffectBinding::Params {
+                assert!(
+                    binding as usize <= desc.params_entries.len(),
+                    "{} reads params binding {}",
+                    desc.label,
+                    binding
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_depth_of_field_layout() {
+        assert_effect_layout(&DepthOfField::descriptor(), "depth_of_field");
+    }
+
+    #[test]
+    fn test_motion_blur_layout() {
+        assert_effect_layout(&MotionBlur::descriptor(), "motion_blur");
+    }
 }
@@ -0,0 +1,377 @@
+use std::sync::Arc;
+
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::InMut;
+use egui::Widget;
+use wgpu::{
+    BindGroup, BindGroupLayout, BindingResource, Device, Extent3d, PipelineLayoutDescriptor,
+    RenderPipeline, ShaderModule, ShaderStages, TextureFormat, TextureUsages, TextureView,
+};
+
+use crate::{
+    asset::AssetPath,
+    bg_descriptor, bg_layout_descriptor, impl_pod_zeroable,
+    render::{
+        camera::Camera,
+        defered_rendering::write_g_buffer_pipeline::GBufferTexturesBindGroup,
+        material::buffer_material::{BufferMaterialData, BufferMaterialManager},
+        post_processing::{
+            add_post_effect_by_world, create_effect_pipeline_by_world,
+            effect::{PostEffectData, PostEffectDescriptor, PostEffectId},
+            PostProcessingManager, RenderStage,
+        },
+        shader_loader::{ReloadPipelines, ShaderLoader},
+        systems::PassRenderContext,
+        BGLEntry, FullScreenVertexShader, RenderTargetSize, UploadedImage,
+    },
+    wgpu_init, RenderState,
+};
+
+/// Size in pixels of the tiles the velocity is dilated over, also the largest blur radius.
+pub const TILE_SIZE: u32 = 16;
+const TILE_FORMAT: TextureFormat = TextureFormat::Rg16Float;
+
+/// Per-pixel motion blur from the velocity G-buffer, see McGuire et al.
+/// "A Reconstruction Filter for Plausible Motion Blur".
+#[derive(Resource)]
+pub struct MotionBlur {
+    pub effect: PostEffectId,
+    pub tile_layout: Arc<BindGroupLayout>,
+    tile_max_pipeline: RenderPipeline,
+    neighbor_max_pipeline: RenderPipeline,
+    tile_max: UploadedImage,
+    neighbor_max: UploadedImage,
+    tile_max_bind_group: Arc<BindGroup>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MotionBlurParams {
+    pub sample_count: u32,
+    /// In degrees, copied from [`Camera::shutter_angle`]
+    pub shutter_angle: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    /// Bound after the uniform, replaced on resize
+    pub neighbor_max: TextureView,
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct RawMotionBlurParams {
+    pub shutter_fraction: f32,
+    pub sample_count: u32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl_pod_zeroable!(RawMotionBlurParams);
+
+impl MotionBlurParams {
+    pub fn from_camera(camera: &Camera, neighbor_max: TextureView) -> Self {
+        let mut ret = Self {
+            sample_count: 15,
+            shutter_angle: 0.0,
+            znear: 0.0,
+            zfar: 0.0,
+            neighbor_max,
+        };
+        ret.apply_camera(camera);
+        ret
+    }
+
+    pub fn apply_camera(&mut self, camera: &Camera) {
+        self.shutter_angle = camera.shutter_angle;
+        self.znear = camera.znear;
+        self.zfar = camera.zfar;
+    }
+}
+
+impl BufferMaterialData for MotionBlurParams {
+    type Raw = RawMotionBlurParams;
+
+    fn raw(&self) -> Self::Raw {
+        Self::Raw {
+            shutter_fraction: self.shutter_angle / 360.0,
+            sample_count: self.sample_count,
+            znear: self.znear,
+            zfar: self.zfar,
+        }
+    }
+
+    fn binding_resources<'a>(&'a self, buffer: &'a wgpu::Buffer) -> Vec<wgpu::BindingResource<'a>> {
+        vec![
+            buffer.as_entire_binding(),
+            BindingResource::TextureView(&self.neighbor_max),
+        ]
+    }
+}
+
+impl PostEffectData for MotionBlurParams {
+    fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Samples");
+            changed |= egui::Slider::new(&mut self.sample_count, 3..=31)
+                .ui(ui)
+                .changed();
+        });
+        ui.label("Shutter angle is set on the camera.");
+        changed
+    }
+}
+
+fn create_tile_image(label: &str, device: &Device, width: u32, height: u32) -> UploadedImage {
+    let size = Extent3d {
+        width: width.div_ceil(TILE_SIZE).max(1),
+        height: height.div_ceil(TILE_SIZE).max(1),
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu_init::texture_desc_2d_one_mip_sample_level(
+        Some(label),
+        size,
+        TILE_FORMAT,
+        TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+    ));
+    let view = texture.create_view(&Default::default());
+    UploadedImage { texture, view }
+}
+
+fn create_tile_pipeline(
+    label: &str,
+    layout: &BindGroupLayout,
+    vs_shader: &ShaderModule,
+    fs_shader: &ShaderModule,
+    device: &Device,
+) -> RenderPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: &[layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu_init::full_screen_pipeline_desc(
+        Some(label),
+        &pipeline_layout,
+        vs_shader,
+        fs_shader,
+        &[Some(wgpu_init::color_target_replace_write_all(TILE_FORMAT))],
+    ))
+}
+
+impl MotionBlur {
+    pub fn descriptor() -> PostEffectDescriptor<'static> {
+        PostEffectDescriptor {
+            order: 10,
+            read_depth: true,
+            read_g_buffer: true,
+            params_entries: &[BGLEntry::Tex2D(
+                false,
+                wgpu::TextureSampleType::Float { filterable: false },
+            )],
+            ..PostEffectDescriptor::new("Motion Blur", RenderStage::AfterOpaque)
+        }
+    }
+
+    /// Returns the tile max and neighbor max pipelines.
+    fn create_tile_pipelines(
+        world: &mut World,
+        tile_layout: &BindGroupLayout,
+    ) -> anyhow::Result<(RenderPipeline, RenderPipeline)> {
+        let tile_max_shader = ShaderLoader::load_module_by_world(
+            world,
+            AssetPath::new_shader_wgsl("motion_blur_tile_max"),
+        )?;
+        let neighbor_max_shader = ShaderLoader::load_module_by_world(
+            world,
+            AssetPath::new_shader_wgsl("motion_blur_neighbor_max"),
+        )?;
+        let device = &world.resource::<RenderState>().device;
+        let vs_shader = &world.resource::<FullScreenVertexShader>().module;
+        let g_buffer_layout = &world.resource::<GBufferTexturesBindGroup>().layout;
+        Ok((
+            create_tile_pipeline(
+                "Motion Blur Tile Max",
+                g_buffer_layout,
+                vs_shader,
+                &tile_max_shader,
+                device,
+            ),
+            create_tile_pipeline(
+                "Motion Blur Neighbor Max",
+                tile_layout,
+                vs_shader,
+                &neighbor_max_shader,
+                device,
+            ),
+        ))
+    }
+
+    /// Returns the tile max and neighbor max images with the bind group of the tile max.
+    fn create_tiles(
+        device: &Device,
+        layout: &BindGroupLayout,
+        width: u32,
+        height: u32,
+    ) -> (UploadedImage, UploadedImage, Arc<BindGroup>) {
+        let tile_max = create_tile_image("Motion Blur Tile Max", device, width, height);
+        let neighbor_max = create_tile_image("Motion Blur Neighbor Max", device, width, height);
+        let tile_max_bind_group = Arc::new(device.create_bind_group(&bg_descriptor! {
+            ["Motion Blur Tile Max"] [layout]
+            0: BindingResource::TextureView(&tile_max.view);
+        }));
+        (tile_max, neighbor_max, tile_max_bind_group)
+    }
+
+    pub fn resize(
+        &mut self,
+        width: u32,
+        height: u32,
+        device: &Device,
+        manager: &mut PostProcessingManager,
+        material_manager: &mut BufferMaterialManager,
+    ) {
+        (self.tile_max, self.neighbor_max, self.tile_max_bind_group) =
+            Self::create_tiles(device, &self.tile_layout, width, height);
+        if let Some(params) = manager
+            .effect_mut(self.effect)
+            .and_then(|it| it.params_mut::<MotionBlurParams>())
+        {
+            params.data.neighbor_max = self.neighbor_max.view.clone();
+            if let Err(err) = material_manager.update_bind_group(params, device) {
+                log::error!("Failed to rebind the motion blur neighbor max: {:#}", err);
+            }
+        }
+    }
+}
+
+impl FromWorld for MotionBlur {
+    fn from_world(world: &mut World) -> Self {
+        let (tile_layout, tile_max, neighbor_max, tile_max_bind_group) = {
+            let device = &world.resource::<RenderState>().device;
+            let size = world.resource::<RenderTargetSize>();
+
+            let tile_layout = Arc::new(device.create_bind_group_layout(&bg_layout_descriptor! {
+                ["Motion Blur Tiles"]
+                0: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, wgpu::TextureSampleType::Float { filterable: false });
+            }));
+            let (tile_max, neighbor_max, tile_max_bind_group) =
+                Self::create_tiles(device, &tile_layout, size.width, size.height);
+            (tile_layout, tile_max, neighbor_max, tile_max_bind_group)
+        };
+        let (tile_max_pipeline, neighbor_max_pipeline) =
+            Self::create_tile_pipelines(world, &tile_layout).unwrap();
+
+        let params = MotionBlurParams::from_camera(
+            &Camera::first_or_default(world),
+            neighbor_max.view.clone(),
+        );
+        let effect = add_post_effect_by_world(
+            world,
+            &Self::descriptor(),
+            AssetPath::new_shader_wgsl("motion_blur"),
+            params,
+        )
+        .unwrap();
+
+        Self {
+            effect,
+            tile_layout,
+            tile_max_pipeline,
+            neighbor_max_pipeline,
+            tile_max,
+            neighbor_max,
+            tile_max_bind_group,
+        }
+    }
+}
+
+impl ReloadPipelines for MotionBlur {
+    /// The effect, tile max and neighbor max pipelines
+    type Pipelines = (RenderPipeline, RenderPipeline, RenderPipeline);
+
+    fn build_pipelines(&self, world: &mut World) -> anyhow::Result<Self::Pipelines> {
+        let effect = create_effect_pipeline_by_world(
+            world,
+            self.effect,
+            AssetPath::new_shader_wgsl("motion_blur"),
+        )?;
+        let (tile_max, neighbor_max) = Self::create_tile_pipelines(world, &self.tile_layout)?;
+        Ok((effect, tile_max, neighbor_max))
+    }
+
+    fn set_pipelines(&mut self, pipelines: Self::Pipelines, world: &mut World) {
+        let (effect_pipeline, tile_max_pipeline, neighbor_max_pipeline) = pipelines;
+        self.tile_max_pipeline = tile_max_pipeline;
+        self.neighbor_max_pipeline = neighbor_max_pipeline;
+        if let Some(effect) = world
+            .resource_mut::<PostProcessingManager>()
+            .effect_mut(self.effect)
+        {
+            effect.pipeline = Arc::new(effect_pipeline);
+        }
+    }
+}
+
+pub fn sys_update_motion_blur(
+    camera: Option<Single<&Camera, Changed<Camera>>>,
+    motion_blur: Res<MotionBlur>,
+    rs: Res<RenderState>,
+    mut manager: ResMut<PostProcessingManager>,
+) {
+    let Some(camera) = camera else {
+        return;
+    };
+    let Some(params) = manager
+        .effect_mut(motion_blur.effect)
+        .and_then(|it| it.params_mut::<MotionBlurParams>())
+    else {
+        return;
+    };
+    params.data.apply_camera(&camera);
+    params.update_buffer(&rs.queue);
+}
+
+/// Reduce the velocity G-buffer to the dominant velocity of each tile and its neighbors.
+pub fn sys_render_motion_blur_tiles(
+    InMut(ctx): InMut<PassRenderContext>,
+    motion_blur: Res<MotionBlur>,
+    manager: Res<PostProcessingManager>,
+    g_buffer_bind_group: Res<GBufferTexturesBindGroup>,
+) {
+    if !manager
+        .effect(motion_blur.effect)
+        .is_some_and(|it| it.enabled)
+    {
+        return;
+    }
+
+    let passes = [
+        (
+            "Motion Blur Tile Max",
+            &motion_blur.tile_max_pipeline,
+            &g_buffer_bind_group.bind_group,
+            &motion_blur.tile_max,
+        ),
+        (
+            "Motion Blur Neighbor Max",
+            &motion_blur.neighbor_max_pipeline,
+            &motion_blur.tile_max_bind_group,
+            &motion_blur.neighbor_max,
+        ),
+    ];
+    for (label, pipeline, bind_group, target) in passes {
+        let mut render_pass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu_init::render_pass_color_attachment(
+                &target.view,
+                Some(wgpu::Color::TRANSPARENT),
+                true,
+            ))],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, Some(bind_group.as_ref()), &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
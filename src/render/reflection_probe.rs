@@ -0,0 +1,633 @@
+use std::sync::Arc;
+
+use bevy_ecs::system::SystemParam;
+use wgpu::{
+    BindingResource, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device,
+    TextureViewDescriptor,
+};
+
+use crate::{asset::AssetPath, bg_descriptor, impl_pod_zeroable, macro_utils::BGLEntry};
+
+use super::{
+    cubemap::{CubemapMatrixBindGroups, CubemapVertexShader},
+    irradiance_volume::IrradianceVolumes,
+    light::LightUnifromBuffer,
+    material::pbr::PBRMaterialBindGroupLayout,
+    prelude::*,
+    shader_loader::{ReloadPipelines, ShaderLoader},
+    skybox::{
+        prefiltering::{self, PrefilteringPipeline},
+        DefaultSkybox, Skybox,
+    },
+    utils::cube::{cube_vertex_layout, CubeVerticesBuffer},
+    DefaultMainPipelineMaterial, MainPassMeshes, PrimitiveFilter, UploadedImage,
+};
+
+/// Must match the array size in `global_bindings.wgsl`
+pub const MAX_REFLECTION_PROBES: usize = 8;
+pub const PROBE_RESOLUTION: u32 = 128;
+const PROBE_MIP_LEVELS: u32 = 5;
+const PROBE_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+/// Far plane of the matrices in [`CubemapMatrixBindGroups`]
+const CUBEMAP_MATRIX_FAR: f32 = 10.0;
+
+/// Local specular environment, captured from the position of the entity.
+/// Surfaces inside the box use it instead of the sky, with the reflection ray
+/// projected onto the box so the reflections line up with the walls of a room.
+///
+/// Probes are static, they are recaptured when they move, when the sky changes
+/// or by [`ReflectionProbes::recapture_all`].
+#[derive(Component, Clone, Debug)]
+#[require(Transform)]
+pub struct ReflectionProbe {
+    /// Half size of the axis aligned box around the probe, both the influence and the parallax volume
+    pub half_extents: Vec3,
+    /// Distance inside the box over which the probe fades into its surroundings
+    pub blend_distance: f32,
+    /// Far plane of the capture
+    pub far: f32,
+}
+
+impl Default for ReflectionProbe {
+    fn default() -> Self {
+        Self {
+            half_extents: Vec3::new(5.0, 3.0, 5.0),
+            blend_distance: 1.0,
+            far: 50.0,
+        }
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct RawReflectionProbe {
+    pub position: [f32; 3],
+    pub blend_distance: f32,
+    pub box_min: [f32; 3],
+    /// Cubemap index in the probe array
+    pub layer: u32,
+    pub box_max: [f32; 3],
+    pub padding: f32,
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct ReflectionProbesUniform {
+    /// Sorted from the smallest box, so nested probes win over the ones around them
+    pub probes: [RawReflectionProbe; MAX_REFLECTION_PROBES],
+    pub count: [u32; 4],
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct ProbeCaptureUniform {
+    pub position: [f32; 3],
+    /// Fits the capture range into the near and far plane of the cubemap matrices
+    pub depth_scale: f32,
+}
+
+impl_pod_zeroable!(RawReflectionProbe);
+impl_pod_zeroable!(ReflectionProbesUniform);
+impl_pod_zeroable!(ProbeCaptureUniform);
+
+impl ReflectionProbe {
+    pub fn raw(&self, transform: &WorldTransform, layer: u32) -> RawReflectionProbe {
+        let pos = transform.position;
+        let min = pos - self.half_extents;
+        let max = pos + self.half_extents;
+        RawReflectionProbe {
+            position: pos.into(),
+            blend_distance: self.blend_distance.max(0.0),
+            box_min: min.into(),
+            layer,
+            box_max: max.into(),
+            padding: 0.0,
+        }
+    }
+
+    fn volume(&self) -> f32 {
+        self.half_extents.x * self.half_extents.y * self.half_extents.z
+    }
+}
+
+/// Everything a capture draws, shared with the irradiance volume bake.
+#[derive(SystemParam)]
+pub struct CaptureScene<'w, 's> {
+    mesh_renderers: MainPassMeshes<'w, 's>,
+    default_material: Res<'w, DefaultMainPipelineMaterial>,
+    pub skybox: Res<'w, Skybox>,
+    default_skybox: Res<'w, DefaultSkybox>,
+    light: Res<'w, LightUnifromBuffer>,
+    pub rs: Res<'w, RenderState>,
+    pub matrix_bind_groups: Res<'w, CubemapMatrixBindGroups>,
+    pub cube_vertex_buffer: Res<'w, CubeVerticesBuffer>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct ProbeSlot {
+    entity: Entity,
+    /// What the cubemap was captured with, `None` to recapture
+    captured: Option<(Vec3, f32)>,
+}
+
+/// Cubemap array of all the probes, bound in the global bind group.
+#[derive(Resource)]
+pub struct ReflectionProbes {
+    pub cubemaps: UploadedImage,
+    pub uniform_buffer: Arc<wgpu::Buffer>,
+    slots: [Option<ProbeSlot>; MAX_REFLECTION_PROBES],
+    capture_layout: BindGroupLayout,
+    capture_uniform_buffer: wgpu::Buffer,
+    capture_pipeline: RenderPipeline,
+    sky_pipeline: RenderPipeline,
+    depth: UploadedImage,
+}
+
+fn capture_pipeline_desc<'a>(
+    label: &'a str,
+    layout: &'a PipelineLayout,
+    vertex: wgpu::VertexState<'a>,
+    fragment: wgpu::FragmentState<'a>,
+    depth_write: bool,
+    cull_mode: Option<wgpu::Face>,
+) -> wgpu::RenderPipelineDescriptor<'a> {
+    wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex,
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: RenderState::DEPTH_FORMAT,
+            depth_write_enabled: depth_write,
+            depth_compare: if depth_write {
+                wgpu::CompareFunction::Less
+            } else {
+                wgpu::CompareFunction::Always
+            },
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        fragment: Some(fragment),
+        multiview: None,
+        cache: None,
+    }
+}
+
+fn create_cubemap_texture(
+    label: &str,
+    device: &Device,
+    layers: u32,
+    mip_level_count: u32,
+    usage: TextureUsages,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: Extent3d {
+            width: PROBE_RESOLUTION,
+            height: PROBE_RESOLUTION,
+            depth_or_array_layers: layers,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: PROBE_FORMAT,
+        usage,
+        view_formats: &[],
+    })
+}
+
+impl FromWorld for ReflectionProbes {
+    fn from_world(world: &mut World) -> Self {
+        let device = &world.resource::<RenderState>().device;
+
+        let texture = create_cubemap_texture(
+            "Reflection Probes",
+            device,
+            6 * MAX_REFLECTION_PROBES as u32,
+            PROBE_MIP_LEVELS,
+            TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        );
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::CubeArray),
+            ..Default::default()
+        });
+        let uniform_buffer = Arc::new(device.create_buffer(&BufferDescriptor {
+            label: Some("Reflection Probes"),
+            size: size_of::<ReflectionProbesUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+        let capture_uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Reflection Probe Capture"),
+            size: size_of::<ProbeCaptureUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let capture_layout = device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Reflection Probe Capture"]
+            0: ShaderStages::VERTEX_FRAGMENT => BGLEntry::UniformBuffer(); // Probe
+            1: ShaderStages::FRAGMENT => BGLEntry::UniformBuffer(); // Light
+            2: ShaderStages::FRAGMENT => BGLEntry::TexCube(false, TextureSampleType::Float { filterable: true }); // Skybox
+            3: ShaderStages::FRAGMENT => BGLEntry::Sampler(SamplerBindingType::Filtering); // Skybox
+        });
+
+        let depth = device.create_texture(&wgpu_init::texture_desc_2d_one_mip_sample_level(
+            Some("Reflection Probe Depth"),
+            Extent3d {
+                width: PROBE_RESOLUTION,
+                height: PROBE_RESOLUTION,
+                depth_or_array_layers: 1,
+            },
+            RenderState::DEPTH_FORMAT,
+            TextureUsages::RENDER_ATTACHMENT,
+        ));
+        let depth_view = depth.create_view(&Default::default());
+
+        let (capture_pipeline, sky_pipeline) =
+            Self::create_pipelines(world, &capture_layout).unwrap();
+
+        Self {
+            cubemaps: UploadedImage { texture, view },
+            uniform_buffer,
+            slots: [None; MAX_REFLECTION_PROBES],
+            capture_layout,
+            capture_uniform_buffer,
+            capture_pipeline,
+            sky_pipeline,
+            depth: UploadedImage {
+                texture: depth,
+                view: depth_view,
+            },
+        }
+    }
+}
+
+impl ReloadPipelines for ReflectionProbes {
+    type Pipelines = (RenderPipeline, RenderPipeline);
+
+    fn build_pipelines(&self, world: &mut World) -> anyhow::Result<Self::Pipelines> {
+        Self::create_pipelines(world, &self.capture_layout)
+    }
+
+    fn set_pipelines(&mut self, pipelines: Self::Pipelines, world: &mut World) {
+        (self.capture_pipeline, self.sky_pipeline) = pipelines;
+        self.recapture_all();
+        // The volumes are baked from the same captures
+        if let Some(mut volumes) = world.get_resource_mut::<IrradianceVolumes>() {
+            volumes.rebake_all();
+        }
+    }
+}
+
+impl ReflectionProbes {
+    fn create_pipelines(
+        world: &mut World,
+        capture_layout: &BindGroupLayout,
+    ) -> anyhow::Result<(RenderPipeline, RenderPipeline)> {
+        let shader = ShaderLoader::load_module_by_world(
+            world,
+            AssetPath::new_shader_wgsl("reflection_probe_capture"),
+        )?;
+        let sky_shader = ShaderLoader::load_module_by_world(
+            world,
+            AssetPath::new_shader_wgsl("reflection_probe_sky"),
+        )?;
+        let device = &world.resource::<RenderState>().device;
+        let matrix_bind_groups = world.resource::<CubemapMatrixBindGroups>();
+        let vert_shader = world.resource::<CubemapVertexShader>();
+        let material_layout = &world.resource::<PBRMaterialBindGroupLayout>().0;
+        let object_layout = &world.resource::<ObjectBindGroupLayout>().0;
+
+        // The meshes keep their material and object groups at 1 and 2
+        let capture_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Reflection Probe Capture"),
+                bind_group_layouts: &[
+                    capture_layout,
+                    material_layout,
+                    object_layout,
+                    &matrix_bind_groups.layout,
+                ],
+                push_constant_ranges: &[],
+            });
+        let capture_pipeline = device.create_render_pipeline(&capture_pipeline_desc(
+            "Reflection Probe Capture",
+            &capture_pipeline_layout,
+            wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[Vertex::desc()],
+            },
+            wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(PROBE_FORMAT.into())],
+            },
+            true,
+            // The y flip of the cubemap faces reverses the winding
+            None,
+        ));
+
+        let sky_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Reflection Probe Sky"),
+            bind_group_layouts: &[&matrix_bind_groups.layout, capture_layout],
+            push_constant_ranges: &[],
+        });
+        let sky_pipeline = device.create_render_pipeline(&capture_pipeline_desc(
+            "Reflection Probe Sky",
+            &sky_pipeline_layout,
+            wgpu::VertexState {
+                module: &vert_shader.module,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[cube_vertex_layout()],
+            },
+            wgpu::FragmentState {
+                module: &sky_shader,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(PROBE_FORMAT.into())],
+            },
+            false,
+            Some(wgpu::Face::Front),
+        ));
+
+        Ok((capture_pipeline, sky_pipeline))
+    }
+
+    /// Recapture every probe, e.g. after the scene around them changed.
+    pub fn recapture_all(&mut self) {
+        for slot in self.slots.iter_mut().flatten() {
+            slot.captured = None;
+        }
+    }
+
+    pub fn probe_count(&self) -> usize {
+        self.slots.iter().flatten().count()
+    }
+
+    fn slot_of(&mut self, entity: Entity) -> Option<usize> {
+        if let Some(index) = self
+            .slots
+            .iter()
+            .position(|it| it.is_some_and(|it| it.entity == entity))
+        {
+            return Some(index);
+        }
+        let index = self.slots.iter().position(Option::is_none)?;
+        self.slots[index] = Some(ProbeSlot {
+            entity,
+            captured: None,
+        });
+        Some(index)
+    }
+
+    pub fn capture_bind_group(&self, scene: &CaptureScene) -> BindGroup {
+        let skybox_texture = scene
+            .skybox
+            .texture
+            .as_ref()
+            .unwrap_or(&scene.default_skybox.texture);
+        let sampler = scene.rs.device.create_sampler(&wgpu_init::sampler_desc(
+            Some("Reflection Probe Capture"),
+            wgpu::AddressMode::ClampToEdge,
+            wgpu::FilterMode::Linear,
+        ));
+        scene.rs.device.create_bind_group(&bg_descriptor! {
+            ["Reflection Probe Capture"] [&self.capture_layout]
+            0: self.capture_uniform_buffer.as_entire_binding();
+            1: scene.light.buffer.as_entire_binding();
+            2: BindingResource::TextureView(&skybox_texture.view);
+            3: BindingResource::Sampler(&sampler);
+        })
+    }
+
+    /// Render the sky and the main pass objects around `position` into the six
+    /// layers of a new [`PROBE_RESOLUTION`] texture.
+    pub fn render_faces(
+        &self,
+        position: Vec3,
+        far: f32,
+        scene: &CaptureScene,
+        capture_bind_group: &BindGroup,
+    ) -> wgpu::Texture {
+        let rs = &scene.rs;
+        let device = &rs.device;
+        rs.queue.write_buffer(
+            &self.capture_uniform_buffer,
+            0,
+            bytemuck::cast_slice(&[ProbeCaptureUniform {
+                position: position.into(),
+                depth_scale: CUBEMAP_MATRIX_FAR / far.max(0.1),
+            }]),
+        );
+
+        let source = create_cubemap_texture(
+            "Reflection Probe Capture",
+            device,
+            6,
+            1,
+            TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC,
+        );
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Reflection Probe Capture"),
+        });
+        for face in 0..6 {
+            let target = source.create_view(&TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: face,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+            let matrix = &scene.matrix_bind_groups.bind_groups[face as usize];
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Reflection Probe Capture"),
+                color_attachments: &[Some(wgpu_init::render_pass_color_attachment(
+                    &target,
+                    Some(wgpu::Color::BLACK),
+                    true,
+                ))],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            pass.set_pipeline(&self.sky_pipeline);
+            pass.set_vertex_buffer(0, scene.cube_vertex_buffer.vertices_buffer.slice(..));
+            pass.set_bind_group(0, matrix, &[]);
+            pass.set_bind_group(1, capture_bind_group, &[]);
+            pass.draw(0..36, 0..1);
+
+            pass.set_pipeline(&self.capture_pipeline);
+            pass.set_bind_group(0, capture_bind_group, &[]);
+            pass.set_bind_group(3, matrix, &[]);
+            for (mesh_renderer, override_mat) in scene.mesh_renderers.iter() {
+                mesh_renderer.draw_main(
+                    &mut pass,
+                    scene.default_material.0.clone(),
+                    override_mat.and_then(|it| it.material.as_deref()),
+                    PrimitiveFilter::All,
+                );
+            }
+        }
+        rs.queue.submit(std::iter::once(encoder.finish()));
+        source
+    }
+
+    /// Capture the faces around `position`, then prefilter them into the mip
+    /// chain of cubemap `layer`.
+    fn capture(
+        &self,
+        layer: usize,
+        position: Vec3,
+        far: f32,
+        scene: &CaptureScene,
+        capture_bind_group: &BindGroup,
+        prefiltering_pipeline: &PrefilteringPipeline,
+    ) -> anyhow::Result<()> {
+        let rs = &scene.rs;
+        let device = &rs.device;
+        let source = self.render_faces(position, far, scene, capture_bind_group);
+
+        let source_view = source.create_view(&TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let prefiltered = prefiltering::prefilter(
+            Some("Reflection Probe"),
+            device,
+            &rs.queue,
+            &source,
+            &source_view,
+            PROBE_MIP_LEVELS,
+            256,
+            prefiltering_pipeline,
+            &scene.matrix_bind_groups,
+            &scene.cube_vertex_buffer,
+        )?;
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Reflection Probe Copy"),
+        });
+        for level in 0..PROBE_MIP_LEVELS {
+            let size = (PROBE_RESOLUTION >> level).max(1);
+            encoder.copy_texture_to_texture(
+                wgpu::TexelCopyTextureInfoBase {
+                    texture: &prefiltered.texture,
+                    mip_level: level,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::TexelCopyTextureInfoBase {
+                    texture: &self.cubemaps.texture,
+                    mip_level: level,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: 6 * layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: 6,
+                },
+            );
+        }
+        rs.queue.submit(std::iter::once(encoder.finish()));
+        Ok(())
+    }
+}
+
+/// Assign the probes to cubemaps, capture the ones that are new or moved and
+/// upload the boxes for the deferred pass.
+pub fn sys_update_reflection_probes(
+    mut probes: ResMut<ReflectionProbes>,
+    q_probes: Query<(Entity, &ReflectionProbe, &WorldTransform)>,
+    scene: CaptureScene,
+    prefiltering_pipeline: Res<PrefilteringPipeline>,
+) {
+    for slot in probes.slots.iter_mut() {
+        if slot.is_some_and(|it| !q_probes.contains(it.entity)) {
+            *slot = None;
+        }
+    }
+    if scene.skybox.is_changed() {
+        probes.recapture_all();
+    }
+
+    let mut capture_bind_group = None;
+    let mut raw_probes = Vec::with_capacity(MAX_REFLECTION_PROBES);
+    for (entity, probe, transform) in q_probes.iter() {
+        let Some(layer) = probes.slot_of(entity) else {
+            log::warn!(
+                "More than {} reflection probes, {:?} is ignored",
+                MAX_REFLECTION_PROBES,
+                entity
+            );
+            continue;
+        };
+        raw_probes.push((probe.volume(), probe.raw(transform, layer as u32)));
+
+        let key = Some((transform.position, probe.far));
+        if probes.slots[layer].is_some_and(|it| it.captured == key) {
+            continue;
+        }
+        let capture_bind_group =
+            capture_bind_group.get_or_insert_with(|| probes.capture_bind_group(&scene));
+        match probes.capture(
+            layer,
+            transform.position,
+            probe.far,
+            &scene,
+            capture_bind_group,
+            &prefiltering_pipeline,
+        ) {
+            Ok(()) => {
+                if let Some(slot) = probes.slots[layer].as_mut() {
+                    slot.captured = key;
+                }
+            }
+            Err(e) => log::error!("Failed to capture reflection probe {:?}: {e}", entity),
+        }
+    }
+
+    raw_probes.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let mut uniform = ReflectionProbesUniform {
+        probes: [bytemuck::Zeroable::zeroed(); MAX_REFLECTION_PROBES],
+        count: [raw_probes.len() as u32, 0, 0, 0],
+    };
+    for (dst, (_, raw)) in uniform.probes.iter_mut().zip(raw_probes) {
+        *dst = raw;
+    }
+    scene
+        .rs
+        .queue
+        .write_buffer(&probes.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+}
@@ -1,32 +1,40 @@
-use std::{borrow::Cow, fs};
+use std::{
+    any::Any,
+    borrow::Cow,
+    collections::HashMap,
+    mem,
+    panic::{self, AssertUnwindSafe},
+};
 
+use anyhow::{anyhow, Context};
 use bevy_ecs::prelude::*;
 use naga_oil::compose::Composer;
 use wgpu::ShaderSource;
 
-use crate::asset::AssetPath;
+use crate::{asset::AssetPath, RenderState};
 
 #[derive(Resource)]
 pub struct ShaderLoader {
     pub composer: Composer,
+    /// Shaders loaded since the last [`ShaderLoader::take_loaded`]
+    loaded: Vec<AssetPath>,
 }
 
 impl ShaderLoader {
     pub fn load_source(&mut self, path: AssetPath) -> anyhow::Result<wgpu::ShaderSource<'static>> {
         let final_path = path.final_path();
-        let string = match fs::read_to_string(&final_path) {
-            Ok(s) => s,
-            Err(e) => {
-                panic!("Load Shader Failed: {} \n Err: {}", &final_path, e)
-            }
-        };
+        let string = path
+            .read_to_string()
+            .with_context(|| format!("Load Shader Failed: {}", &final_path))?;
+        self.loaded.push(path);
         let source = self
             .composer
             .make_naga_module(naga_oil::compose::NagaModuleDescriptor {
                 source: &string,
                 file_path: &final_path,
                 ..Default::default()
-            })?;
+            })
+            .map_err(|e| anyhow!(e.emit_to_string(&self.composer)))?;
         Ok(ShaderSource::Naga(Cow::Owned(source)))
     }
 
@@ -47,25 +55,229 @@ impl ShaderLoader {
 
         Ok(shader)
     }
+
+    fn take_loaded(&mut self) -> Vec<AssetPath> {
+        let mut loaded = mem::take(&mut self.loaded);
+        loaded.sort_by_key(|it| it.final_path());
+        loaded.dedup_by_key(|it| it.final_path());
+        loaded
+    }
+
+    pub fn libs_dir() -> String {
+        AssetPath::Assets("shaders/libs/".to_string()).final_path()
+    }
+
+    /// Re-reads every lib, modules that fail to compose are left out.
+    ///
+    /// The libs are kept as they were if the directory can't be read.
+    pub fn reload_libs(&mut self) -> anyhow::Result<()> {
+        let (composer, errors) = compose_libs()?;
+        self.composer = composer;
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(errors.join("\n")))
+        }
+    }
 }
 
-impl FromWorld for ShaderLoader {
-    fn from_world(_world: &mut World) -> Self {
-        let mut composer = Composer::default();
-        let paths =
-            fs::read_dir(AssetPath::Assets("shaders/libs/".to_string()).final_path()).unwrap();
-        for path in paths {
-            let path = &path.unwrap().path();
-            let shader_string = fs::read_to_string(path).unwrap();
-            match composer.add_composable_module(naga_oil::compose::ComposableModuleDescriptor {
-                source: &shader_string,
-                file_path: path.to_str().unwrap(),
-                ..Default::default()
-            }) {
-                Ok(_) => {}
-                Err(e) => println!("? -> {e:#?}"),
+/// Returns the composer with every lib that composed and the errors of the others.
+fn compose_libs() -> anyhow::Result<(Composer, Vec<String>)> {
+    let mut composer = Composer::default();
+    let mut errors = Vec::new();
+    let paths = AssetPath::Assets("shaders/libs/".to_string())
+        .read_dir()
+        .with_context(|| format!("Read Shader Libs Failed: {}", ShaderLoader::libs_dir()))?;
+    for path in paths {
+        let shader_string = match path.read_to_string() {
+            Ok(s) => s,
+            Err(e) => {
+                errors.push(format!("{}: {}", path.final_path(), e));
+                continue;
             }
+        };
+        match composer.add_composable_module(naga_oil::compose::ComposableModuleDescriptor {
+            source: &shader_string,
+            file_path: &path.final_path(),
+            ..Default::default()
+        }) {
+            Ok(_) => {}
+            Err(e) => errors.push(e.emit_to_string(&composer)),
+        }
+    }
+    Ok((composer, errors))
+}
+
+impl FromWorld for ShaderLoader {
+    fn from_world(world: &mut World) -> Self {
+        let (composer, errors) =
+            compose_libs().unwrap_or_else(|e| (Composer::default(), vec![format!("{:#}", e)]));
+        for e in &errors {
+            log::error!("Failed to compose shader lib: {}", e);
+        }
+        if !errors.is_empty() {
+            world
+                .get_resource_or_insert_with(ReloadablePipelines::default)
+                .errors
+                .insert(SHADER_LIBS, errors.join("\n"));
+        }
+        Self {
+            composer,
+            loaded: Vec::new(),
         }
-        Self { composer }
     }
 }
+
+/// Key of the lib errors in [`ReloadablePipelines::errors`].
+const SHADER_LIBS: &str = "Shader Libs";
+
+struct ReloadablePipeline {
+    name: &'static str,
+    shaders: Vec<AssetPath>,
+    rebuild: fn(&mut World) -> anyhow::Result<()>,
+}
+
+/// Pipeline resources that are rebuilt when one of their shaders changes on disk.
+///
+/// A rebuild that fails keeps the old pipeline, the error is kept in `errors`
+/// until the next successful rebuild.
+#[derive(Resource, Default)]
+pub struct ReloadablePipelines {
+    pipelines: Vec<ReloadablePipeline>,
+    pub errors: HashMap<&'static str, String>,
+}
+
+impl ReloadablePipelines {
+    /// Builds `R` and inserts it, remembering the shaders it loaded.
+    pub fn insert<R: Resource + FromWorld>(world: &mut World) {
+        Self::register::<R>(world, rebuild::<R>);
+    }
+
+    /// Like [`ReloadablePipelines::insert`], but a shader change only rebuilds the
+    /// pipelines of `R` and keeps the rest of it.
+    pub fn insert_stateful<R: ReloadPipelines>(world: &mut World) {
+        Self::register::<R>(world, rebuild_pipelines::<R>);
+    }
+
+    fn register<R: Resource + FromWorld>(
+        world: &mut World,
+        rebuild: fn(&mut World) -> anyhow::Result<()>,
+    ) {
+        world.resource_mut::<ShaderLoader>().take_loaded();
+        let resource = R::from_world(world);
+        world.insert_resource(resource);
+        let shaders = world.resource_mut::<ShaderLoader>().take_loaded();
+        world
+            .get_resource_or_insert_with(ReloadablePipelines::default)
+            .pipelines
+            .push(ReloadablePipeline {
+                name: short_type_name::<R>(),
+                shaders,
+                rebuild,
+            });
+    }
+
+    /// `changed` are final paths, a changed lib rebuilds every pipeline.
+    pub fn reload_shaders(world: &mut World, changed: &[String]) {
+        let libs_dir = ShaderLoader::libs_dir();
+        let libs_changed = changed.iter().any(|it| it.starts_with(&libs_dir));
+        world.resource_scope(|world, mut reloadable: Mut<ReloadablePipelines>| {
+            if libs_changed {
+                let result = build_checked(world, |world| {
+                    world.resource_mut::<ShaderLoader>().reload_libs()
+                });
+                match result.and_then(|it| it) {
+                    Ok(()) => {
+                        reloadable.errors.remove(SHADER_LIBS);
+                    }
+                    Err(e) => {
+                        log::error!("Failed to reload shader libs: {:#}", e);
+                        reloadable.errors.insert(SHADER_LIBS, format!("{:#}", e));
+                    }
+                };
+            }
+            let ReloadablePipelines { pipelines, errors } = reloadable.as_mut();
+            for pipeline in pipelines.iter_mut() {
+                let affected = libs_changed
+                    || pipeline
+                        .shaders
+                        .iter()
+                        .any(|it| changed.contains(&it.final_path()));
+                if !affected {
+                    continue;
+                }
+                world.resource_mut::<ShaderLoader>().take_loaded();
+                let result = (pipeline.rebuild)(world);
+                let shaders = world.resource_mut::<ShaderLoader>().take_loaded();
+                match result {
+                    Ok(()) => {
+                        log::info!("Reloaded {}", pipeline.name);
+                        errors.remove(pipeline.name);
+                        pipeline.shaders = shaders;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to reload {}: {}", pipeline.name, e);
+                        errors.insert(pipeline.name, format!("{}", e));
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// A resource that owns more than its pipelines, e.g. render targets bound elsewhere,
+/// and so can't simply be rebuilt from the world when its shaders change.
+pub trait ReloadPipelines: Resource + FromWorld {
+    type Pipelines;
+
+    /// Loads the shaders and builds the pipelines, reusing the layouts of `self`.
+    fn build_pipelines(&self, world: &mut World) -> anyhow::Result<Self::Pipelines>;
+
+    fn set_pipelines(&mut self, pipelines: Self::Pipelines, world: &mut World);
+}
+
+/// Replaces `R` only if building it neither panics nor raises a validation error.
+fn rebuild<R: Resource + FromWorld>(world: &mut World) -> anyhow::Result<()> {
+    let built = build_checked(world, R::from_world)?;
+    world.insert_resource(built);
+    Ok(())
+}
+
+/// Replaces the pipelines of `R` only if building them succeeds.
+fn rebuild_pipelines<R: ReloadPipelines>(world: &mut World) -> anyhow::Result<()> {
+    world.resource_scope(|world, mut resource: Mut<R>| {
+        let pipelines = build_checked(world, |world| resource.build_pipelines(world))??;
+        resource.set_pipelines(pipelines, world);
+        Ok(())
+    })
+}
+
+/// Runs `build`, turning a panic or a validation error into an error.
+fn build_checked<T>(world: &mut World, build: impl FnOnce(&mut World) -> T) -> anyhow::Result<T> {
+    world
+        .resource::<RenderState>()
+        .device
+        .push_error_scope(wgpu::ErrorFilter::Validation);
+    let built = panic::catch_unwind(AssertUnwindSafe(|| build(world)));
+    let error = pollster::block_on(world.resource::<RenderState>().device.pop_error_scope());
+    let built = built.map_err(|payload| anyhow!(panic_message(payload.as_ref())))?;
+    if let Some(error) = error {
+        return Err(anyhow!("{}", error));
+    }
+    Ok(built)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Unknown panic".to_string()
+    }
+}
+
+fn short_type_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    name.rsplit("::").next().unwrap_or(name)
+}
@@ -1,9 +1,8 @@
 use std::sync::Arc;
 
 use bevy_ecs::{
-    component::Component,
-    system::Resource,
-    world::{self, FromWorld, Mut},
+    prelude::*,
+    world::{self, Mut},
 };
 use wgpu::{BindGroup, BindGroupLayout, PipelineLayout, RenderPipeline, ShaderStages};
 
@@ -12,10 +11,15 @@ use crate::{
 };
 
 use super::{
-    light::LightUnifromBuffer, shader_loader::ShaderLoader, ObjectBindGroupLayout,
-    UploadedImageWithSampler, Vertex,
+    defered_rendering::global_binding::RefreshGlobalBindGroupCmd,
+    light::{parallel_light::ParallelLight, LightUnifromBuffer},
+    shader_loader::ShaderLoader,
+    shadow_moments::ShadowMoments,
+    ObjectBindGroupLayout, UploadedImageWithSampler, Vertex,
 };
 
+const MIN_SHADOW_RESOLUTION: u32 = 256;
+
 #[derive(Resource)]
 pub struct ShadowMap {
     // For shadow map rendering pass
@@ -109,11 +113,8 @@ impl FromWorld for ShadowMappingPipeline {
                     depth_write_enabled: true,
                     depth_compare: wgpu::CompareFunction::Less,
                     stencil: Default::default(),
-                    bias: wgpu::DepthBiasState {
-                        constant: 2,
-                        slope_scale: 2.0,
-                        clamp: 0.0,
-                    },
+                    // Biased when sampled, see `ParallelLight::depth_bias`
+                    bias: Default::default(),
                 }),
                 multisample: wgpu::MultisampleState {
                     count: 1,
@@ -129,17 +130,51 @@ impl FromWorld for ShadowMappingPipeline {
     }
 }
 
+impl ShadowMap {
+    pub fn new(device: &wgpu::Device, resolution: u32) -> Self {
+        let image = crate::render::create_depth_texture(
+            device,
+            resolution,
+            resolution,
+            Some(wgpu::CompareFunction::LessEqual),
+        );
+        Self { image }
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.image.size.width
+    }
+}
+
 impl FromWorld for ShadowMap {
     fn from_world(world: &mut world::World) -> Self {
         world.resource_scope(|_, render_state: Mut<RenderState>| {
-            let image = crate::render::create_depth_texture(
+            Self::new(
                 &render_state.device,
-                2048,
-                2048,
-                Some(wgpu::CompareFunction::LessEqual),
-            );
-
-            Self { image }
+                ParallelLight::default().shadow_resolution,
+            )
         })
     }
 }
+
+/// Reallocates the shadow map when the resolution of the [`ParallelLight`] changes.
+pub fn sys_update_shadow_map(
+    light: Option<Single<&ParallelLight, Changed<ParallelLight>>>,
+    mut shadow_map: ResMut<ShadowMap>,
+    mut moments: ResMut<ShadowMoments>,
+    light_buffer: Res<LightUnifromBuffer>,
+    rs: Res<RenderState>,
+    mut commands: Commands,
+) {
+    let Some(light) = light else {
+        return;
+    };
+    let max = rs.device.limits().max_texture_dimension_2d;
+    let resolution = light.shadow_resolution.clamp(MIN_SHADOW_RESOLUTION, max);
+    if resolution == shadow_map.resolution() {
+        return;
+    }
+    *shadow_map = ShadowMap::new(&rs.device, resolution);
+    moments.resize(&rs.device, &shadow_map, &light_buffer);
+    commands.queue(RefreshGlobalBindGroupCmd);
+}
@@ -0,0 +1,288 @@
+use bevy_ecs::prelude::*;
+use wgpu::{
+    BindGroup, BindGroupLayout, BindingResource, ComputePipeline, StorageTextureAccess,
+    TextureSampleType, TextureViewDescriptor,
+};
+
+use crate::{
+    asset::AssetPath,
+    bg_descriptor, bg_layout_descriptor,
+    macro_utils::BGLEntry,
+    render::{
+        light::{parallel_light::ParallelLight, LightUnifromBuffer},
+        mipmap::calculate_mip_level_count,
+        prelude::*,
+        shader_loader::{ReloadPipelines, ShaderLoader},
+        shadow_mapping::ShadowMap,
+        systems::PassRenderContext,
+        UploadedImageWithSampler,
+    },
+};
+
+/// Must match `shadow_moments.wgsl`
+pub const MOMENTS_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Textures at the resolution of the shadow map, recreated when it is reallocated.
+struct MomentTargets {
+    /// Blurred moments with the full mip chain
+    image: UploadedImageWithSampler,
+    horizontal_bind_group: BindGroup,
+    vertical_bind_group: BindGroup,
+    /// One per mip after the first
+    downsample_bind_groups: Vec<(BindGroup, u32)>,
+}
+
+/// Prefiltered moments of the shadow map for [`ShadowFilter::Vsm`] and [`ShadowFilter::Evsm`].
+///
+/// [`ShadowFilter::Vsm`]: crate::render::light::parallel_light::ShadowFilter::Vsm
+/// [`ShadowFilter::Evsm`]: crate::render::light::parallel_light::ShadowFilter::Evsm
+#[derive(Resource)]
+pub struct ShadowMoments {
+    horizontal_layout: BindGroupLayout,
+    filter_layout: BindGroupLayout,
+    horizontal_pipeline: ComputePipeline,
+    vertical_pipeline: ComputePipeline,
+    downsample_pipeline: ComputePipeline,
+    targets: MomentTargets,
+}
+
+impl ShadowMoments {
+    pub fn image(&self) -> &UploadedImageWithSampler {
+        &self.targets.image
+    }
+
+    /// Returns the horizontal blur, vertical blur and downsample pipelines.
+    fn create_pipelines(
+        world: &mut World,
+        horizontal_layout: &BindGroupLayout,
+        filter_layout: &BindGroupLayout,
+    ) -> anyhow::Result<(ComputePipeline, ComputePipeline, ComputePipeline)> {
+        let shader = ShaderLoader::load_module_by_world(
+            world,
+            AssetPath::new_shader_wgsl("shadow_moments"),
+        )?;
+        let device = &world.resource::<RenderState>().device;
+
+        let create_pipeline = |layout: &BindGroupLayout, entry_point: &str| {
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Shadow Moments"),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Shadow Moments"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+        Ok((
+            create_pipeline(horizontal_layout, "cs_blur_horizontal"),
+            create_pipeline(filter_layout, "cs_blur_vertical"),
+            create_pipeline(filter_layout, "cs_downsample"),
+        ))
+    }
+
+    /// Recreates the targets when the shadow map is reallocated.
+    pub fn resize(
+        &mut self,
+        device: &wgpu::Device,
+        shadow_map: &ShadowMap,
+        light: &LightUnifromBuffer,
+    ) {
+        self.targets = MomentTargets::new(
+            device,
+            &self.horizontal_layout,
+            &self.filter_layout,
+            shadow_map,
+            light,
+        );
+    }
+}
+
+impl MomentTargets {
+    fn new(
+        device: &wgpu::Device,
+        horizontal_layout: &BindGroupLayout,
+        filter_layout: &BindGroupLayout,
+        shadow_map: &ShadowMap,
+        light: &LightUnifromBuffer,
+    ) -> Self {
+        let resolution = shadow_map.resolution();
+        let size = Extent3d {
+            width: resolution,
+            height: resolution,
+            depth_or_array_layers: 1,
+        };
+        let mip_count = calculate_mip_level_count(&[resolution, resolution]);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Moments"),
+            size,
+            mip_level_count: mip_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: MOMENTS_FORMAT,
+            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        let sampler_desc = wgpu::SamplerDescriptor {
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy_clamp: 8,
+            ..wgpu_init::sampler_desc(
+                Some("Shadow Moments"),
+                wgpu::AddressMode::ClampToEdge,
+                wgpu::FilterMode::Linear,
+            )
+        };
+        let sampler = device.create_sampler(&sampler_desc);
+        let mip_views = (0..mip_count)
+            .map(|level| {
+                texture.create_view(&TextureViewDescriptor {
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let blur_texture = device.create_texture(&wgpu_init::texture_desc_2d_one_mip_sample_level(
+            Some("Shadow Moments Blur"),
+            size,
+            MOMENTS_FORMAT,
+            TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+        ));
+        // Moments blurred along x only, kept alive by the bind groups
+        let blur_view = blur_texture.create_view(&Default::default());
+
+        let horizontal_bind_group = device.create_bind_group(&bg_descriptor! {
+            ["Shadow Moments Horizontal"] [horizontal_layout]
+            0: light.buffer.as_entire_binding();
+            1: BindingResource::TextureView(&shadow_map.image.view);
+            2: BindingResource::TextureView(&blur_view);
+        });
+        let vertical_bind_group = device.create_bind_group(&bg_descriptor! {
+            ["Shadow Moments Vertical"] [filter_layout]
+            0: light.buffer.as_entire_binding();
+            2: BindingResource::TextureView(&mip_views[0]);
+            3: BindingResource::TextureView(&blur_view);
+        });
+        let downsample_bind_groups = (1..mip_count as usize)
+            .map(|level| {
+                let bind_group = device.create_bind_group(&bg_descriptor! {
+                    ["Shadow Moments Downsample"] [filter_layout]
+                    0: light.buffer.as_entire_binding();
+                    2: BindingResource::TextureView(&mip_views[level]);
+                    3: BindingResource::TextureView(&mip_views[level - 1]);
+                });
+                (bind_group, (resolution >> level).max(1))
+            })
+            .collect();
+
+        Self {
+            image: UploadedImageWithSampler {
+                size,
+                texture,
+                view,
+                sampler,
+                sampler_desc,
+            },
+            horizontal_bind_group,
+            vertical_bind_group,
+            downsample_bind_groups,
+        }
+    }
+}
+
+impl FromWorld for ShadowMoments {
+    fn from_world(world: &mut World) -> Self {
+        let device = &world.resource::<RenderState>().device;
+
+        let horizontal_layout = device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Shadow Moments Horizontal"]
+            0: ShaderStages::COMPUTE => BGLEntry::UniformBuffer(); // Light
+            1: ShaderStages::COMPUTE => BGLEntry::Tex2D(false, TextureSampleType::Depth); // Shadow Map
+            2: ShaderStages::COMPUTE => BGLEntry::StorageTex2D(StorageTextureAccess::WriteOnly, MOMENTS_FORMAT);
+        });
+        let filter_layout = device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Shadow Moments Filter"]
+            0: ShaderStages::COMPUTE => BGLEntry::UniformBuffer(); // Light
+            2: ShaderStages::COMPUTE => BGLEntry::StorageTex2D(StorageTextureAccess::WriteOnly, MOMENTS_FORMAT);
+            3: ShaderStages::COMPUTE => BGLEntry::Tex2D(false, TextureSampleType::Float { filterable: false });
+        });
+
+        let targets = MomentTargets::new(
+            device,
+            &horizontal_layout,
+            &filter_layout,
+            world.resource::<ShadowMap>(),
+            world.resource::<LightUnifromBuffer>(),
+        );
+
+        let (horizontal_pipeline, vertical_pipeline, downsample_pipeline) =
+            Self::create_pipelines(world, &horizontal_layout, &filter_layout).unwrap();
+
+        Self {
+            horizontal_layout,
+            filter_layout,
+            horizontal_pipeline,
+            vertical_pipeline,
+            downsample_pipeline,
+            targets,
+        }
+    }
+}
+
+impl ReloadPipelines for ShadowMoments {
+    type Pipelines = (ComputePipeline, ComputePipeline, ComputePipeline);
+
+    fn build_pipelines(&self, world: &mut World) -> anyhow::Result<Self::Pipelines> {
+        Self::create_pipelines(world, &self.horizontal_layout, &self.filter_layout)
+    }
+
+    fn set_pipelines(&mut self, pipelines: Self::Pipelines, _world: &mut World) {
+        (
+            self.horizontal_pipeline,
+            self.vertical_pipeline,
+            self.downsample_pipeline,
+        ) = pipelines;
+    }
+}
+
+/// Encodes, blurs and mipmaps the moments after the shadow map pass.
+pub fn sys_render_shadow_moments(
+    InMut(ctx): InMut<PassRenderContext>,
+    light: Single<&ParallelLight>,
+    moments: Res<ShadowMoments>,
+) {
+    if !light.shadow_filter.is_prefiltered() {
+        return;
+    }
+    let targets = &moments.targets;
+    let resolution = targets.image.size.width;
+    let groups = resolution.div_ceil(WORKGROUP_SIZE);
+
+    let mut compute_pass = ctx
+        .encoder
+        .begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Shadow Moments"),
+            timestamp_writes: None,
+        });
+    compute_pass.set_pipeline(&moments.horizontal_pipeline);
+    compute_pass.set_bind_group(0, &targets.horizontal_bind_group, &[]);
+    compute_pass.dispatch_workgroups(groups, groups, 1);
+
+    compute_pass.set_pipeline(&moments.vertical_pipeline);
+    compute_pass.set_bind_group(0, &targets.vertical_bind_group, &[]);
+    compute_pass.dispatch_workgroups(groups, groups, 1);
+
+    compute_pass.set_pipeline(&moments.downsample_pipeline);
+    for (bind_group, size) in targets.downsample_bind_groups.iter() {
+        let groups = size.div_ceil(WORKGROUP_SIZE);
+        compute_pass.set_bind_group(0, bind_group, &[]);
+        compute_pass.dispatch_workgroups(groups, groups, 1);
+    }
+}
@@ -4,7 +4,7 @@ use bevy_ecs::prelude::*;
 use bevy_ecs::world::FromWorld;
 use wgpu::{PipelineLayout, RenderPipeline};
 
-use crate::asset::cubemap::load_cubemap_sliced;
+use crate::asset::cubemap::{load_cubemap_compressed, load_cubemap_sliced};
 use crate::{asset::AssetPath, RenderState};
 
 use super::cubemap::CubemapMatrixBindGroups;
@@ -13,6 +13,7 @@ use super::utils::cube::CubeVerticesBuffer;
 use super::{shader_loader::ShaderLoader, UploadedImage};
 
 pub mod prefiltering;
+pub mod procedural;
 
 #[derive(Resource)]
 pub struct SkyboxPipeline {
@@ -34,12 +35,27 @@ pub struct DefaultSkybox {
 impl FromWorld for DefaultSkybox {
     fn from_world(world: &mut World) -> Self {
         let rs = world.resource::<RenderState>();
+        // `prefilter` can't render to a compressed format, so the cubemap must come prefiltered
+        let compressed = AssetPath::Assets("textures/cubemap/skybox.ktx2".to_string());
+        if compressed.exists() {
+            match load_cubemap_compressed(&compressed, &rs.device, &rs.queue) {
+                Ok(texture) if texture.texture.mip_level_count() > 1 => return Self { texture },
+                Ok(_) => log::warn!(
+                    "{} has a single mip level and can't be prefiltered, using the sliced faces",
+                    compressed.final_path()
+                ),
+                Err(err) => log::error!(
+                    "Failed to load {}, using the sliced faces: {:#}",
+                    compressed.final_path(),
+                    err
+                ),
+            }
+        }
         let paths = ["posx", "negx", "posy", "negy", "posz", "negz"]
             // .map(|it| AssetPath::Assets(format!("textures/cubemap/test_{}.png", it)));
             .map(|it| AssetPath::Assets(format!("textures/cubemap/{}.jpg", it)));
         let source_texture = load_cubemap_sliced(&paths, &rs.device, &rs.queue).unwrap();
 
-        let rs = world.resource::<RenderState>();
         let pipeline = world.resource::<prefiltering::PrefilteringPipeline>();
         let matrix_bind_groups = world.resource::<CubemapMatrixBindGroups>();
         let cube_vertex = world.resource::<CubeVerticesBuffer>();
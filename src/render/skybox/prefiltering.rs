@@ -22,10 +22,14 @@ use crate::{
 };
 
 const LABEL: Option<&'static str> = Some("Prefiltering Env Map");
+/// Formats [`prefilter`] can render the mip chain in, the cubemap keeps the format of its source
+pub const PREFILTER_FORMATS: [TextureFormat; 2] =
+    [TextureFormat::Rgba8UnormSrgb, TextureFormat::Rgba16Float];
 
 #[derive(Resource)]
 pub struct PrefilteringPipeline {
-    pub pipeline: Arc<RenderPipeline>,
+    /// One for each of [`PREFILTER_FORMATS`]
+    pub pipelines: Vec<Arc<RenderPipeline>>,
     pub layout: Arc<PipelineLayout>,
     pub uniform_bind_group_layout: Arc<BindGroupLayout>,
 }
@@ -38,6 +42,13 @@ pub struct PrefilteringEnvironmentUniform {
 
 impl_pod_zeroable!(PrefilteringEnvironmentUniform);
 
+impl PrefilteringPipeline {
+    pub fn pipeline(&self, format: TextureFormat) -> Option<&RenderPipeline> {
+        let index = PREFILTER_FORMATS.iter().position(|it| *it == format)?;
+        Some(&self.pipelines[index])
+    }
+}
+
 impl FromWorld for PrefilteringPipeline {
     fn from_world(world: &mut World) -> Self {
         let shader = ShaderLoader::load_module_by_world(
@@ -66,42 +77,46 @@ impl FromWorld for PrefilteringPipeline {
 
         let vert_shader = world.resource::<CubemapVertexShader>();
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: LABEL,
-            layout: Some(&layout),
-            vertex: wgpu::VertexState {
-                module: &vert_shader.module,
-                entry_point: Some("vs_main"),
-                compilation_options: Default::default(),
-                buffers: &[render::utils::cube::cube_vertex_layout()],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Front),
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: 0,
-                alpha_to_coverage_enabled: false,
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                compilation_options: Default::default(),
-                targets: &[Some(TextureFormat::Rgba8UnormSrgb.into())],
-            }),
-            multiview: None,
-            cache: None,
-        });
+        let create_pipeline = |format: TextureFormat| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: LABEL,
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &vert_shader.module,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[render::utils::cube::cube_vertex_layout()],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Front),
+                    unclipped_depth: false,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    conservative: false,
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: 0,
+                    alpha_to_coverage_enabled: false,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(format.into())],
+                }),
+                multiview: None,
+                cache: None,
+            })
+        };
 
         Self {
-            pipeline: Arc::new(pipeline),
+            pipelines: PREFILTER_FORMATS
+                .map(|format| Arc::new(create_pipeline(format)))
+                .to_vec(),
             layout: Arc::new(layout),
             uniform_bind_group_layout: Arc::new(bg_layout),
         }
@@ -154,6 +169,9 @@ pub fn prefilter(
     if size.depth_or_array_layers != 6 {
         return Err(anyhow::anyhow!("Not a cubemap!"));
     }
+    let render_pipeline = pipeline
+        .pipeline(source_texture.format())
+        .ok_or_else(|| anyhow::anyhow!("Can't prefilter into {:?}", source_texture.format()))?;
     let texture = device.create_texture(&wgpu::TextureDescriptor {
         label,
         size,
@@ -239,7 +257,7 @@ pub fn prefilter(
                 occlusion_query_set: None,
             });
 
-            pass.set_pipeline(&pipeline.pipeline);
+            pass.set_pipeline(render_pipeline);
             pass.set_vertex_buffer(0, cube_vertex_buffer.vertices_buffer.slice(..));
             pass.set_bind_group(
                 0,
@@ -0,0 +1,374 @@
+use bevy_ecs::prelude::*;
+use cgmath::{ElementWise, InnerSpace};
+use wgpu::{
+    BindGroup, BindGroupLayout, Buffer, BufferDescriptor, BufferUsages, CommandEncoderDescriptor,
+    RenderPipeline, ShaderStages, TextureFormat, TextureUsages,
+};
+
+use crate::{
+    asset::AssetPath,
+    bg_descriptor, bg_layout_descriptor,
+    cgmath_ext::{Vec3, Vec4},
+    impl_pod_zeroable,
+    macro_utils::BGLEntry,
+    render::{
+        cubemap::{CubemapMatrixBindGroups, CubemapVertexShader},
+        light::parallel_light::ParallelLight,
+        shader_loader::{ReloadPipelines, ShaderLoader},
+        transform::WorldTransform,
+        utils::cube::{cube_vertex_layout, CubeVerticesBuffer},
+    },
+    RenderState,
+};
+
+use super::{prefiltering, prefiltering::PrefilteringPipeline, Skybox};
+
+/// Keeps the sun and a bright sky unclipped, also the format of the prefiltered cubemap
+const SKY_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// Illuminance in klx of a [`ParallelLight`] with an intensity of 1, the clear-sky sun at noon
+const SUN_ILLUMINANCE_KLX: f32 = 100.0;
+/// Rebake once the sun has moved more than about 0.25 degrees
+const SUN_MOVE_THRESHOLD: f32 = 0.99999;
+const RAYLEIGH_OPTICAL_DEPTH: [f32; 3] = [0.0464, 0.108, 0.265];
+const MIE_OPTICAL_DEPTH_PER_TURBIDITY: f32 = 0.05;
+
+/// Preetham analytic sky, see "A Practical Analytic Model for Daylight".
+/// Baked into the environment cubemap whenever the [`ParallelLight`] moves,
+/// which also takes over the color of the light.
+///
+/// Opt-in: it starts disabled, leaving the [`DefaultSkybox`](super::DefaultSkybox)
+/// and the light color untouched until `enabled` is set.
+#[derive(Resource)]
+pub struct ProceduralSky {
+    pub enabled: bool,
+    /// Haziness of the atmosphere, 2 is a clear day
+    pub turbidity: f32,
+    /// Scale from the kcd/m² of the model to the radiance of the environment map,
+    /// physically matched to the sun at `1 / SUN_ILLUMINANCE_KLX`
+    pub intensity: f32,
+    pub resolution: u32,
+    baked: Option<SkyBakeKey>,
+    pipeline: RenderPipeline,
+    layout: BindGroupLayout,
+    uniform_buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct SkyBakeKey {
+    sun_direction: Vec3,
+    turbidity: f32,
+    intensity: f32,
+}
+
+impl SkyBakeKey {
+    fn is_close(&self, other: &Self) -> bool {
+        self.sun_direction.dot(other.sun_direction) > SUN_MOVE_THRESHOLD
+            && self.turbidity == other.turbidity
+            && self.intensity == other.intensity
+    }
+}
+
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct SkyUniform {
+    /// xyz: direction to the sun, w: intensity
+    pub sun_direction: [f32; 4],
+    /// xyz: zenith Y, x, y, w: cosine of the sun's angular radius
+    pub zenith: [f32; 4],
+    /// Perez A to E of Y, x, y
+    pub perez: [[f32; 4]; 5],
+    /// rgb: transmittance towards the sun, w: night fade
+    pub sun_color: [f32; 4],
+}
+
+impl_pod_zeroable!(SkyUniform);
+
+/// Fraction of the sunlight reaching the ground, from the Kasten-Young air mass.
+pub fn sun_transmittance(sun_direction: Vec3, turbidity: f32) -> Vec3 {
+    let cos_zenith = sun_direction.y.clamp(0.0, 1.0);
+    let zenith_deg = cos_zenith.acos().to_degrees();
+    let air_mass = 1.0 / (cos_zenith + 0.50572 * (96.07995 - zenith_deg).powf(-1.6364));
+    let mie = MIE_OPTICAL_DEPTH_PER_TURBIDITY * turbidity;
+    let optical_depth = Vec3::from(RAYLEIGH_OPTICAL_DEPTH).add_element_wise(mie) * air_mass;
+    optical_depth.map(|it| (-it).exp())
+}
+
+/// Sky and sun fade out as the sun goes under the horizon.
+pub fn night_fade(sun_direction: Vec3) -> f32 {
+    let t = ((sun_direction.y + 0.1) / 0.2).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl SkyUniform {
+    pub fn new(sun_direction: Vec3, turbidity: f32, intensity: f32) -> Self {
+        let t = turbidity;
+        // The model is only valid with the sun above the horizon
+        let theta = sun_direction.y.clamp(0.01, 1.0).acos();
+        let (theta2, theta3) = (theta * theta, theta * theta * theta);
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_x = t * t * (0.00166 * theta3 - 0.00375 * theta2 + 0.00209 * theta)
+            + t * (-0.02903 * theta3 + 0.06377 * theta2 - 0.03202 * theta + 0.00394)
+            + (0.11693 * theta3 - 0.21196 * theta2 + 0.06052 * theta + 0.25886);
+        let zenith_y = t * t * (0.00275 * theta3 - 0.00610 * theta2 + 0.00317 * theta)
+            + t * (-0.04214 * theta3 + 0.08970 * theta2 - 0.04153 * theta + 0.00516)
+            + (0.15346 * theta3 - 0.26756 * theta2 + 0.06670 * theta + 0.26688);
+
+        #[rustfmt::skip]
+        let perez = [
+            [ 0.1787 * t - 1.4630, -0.0193 * t - 0.2592, -0.0167 * t - 0.2608, 0.0],
+            [-0.3554 * t + 0.4275, -0.0665 * t + 0.0008, -0.0950 * t + 0.0092, 0.0],
+            [-0.0227 * t + 5.3251, -0.0004 * t + 0.2125, -0.0079 * t + 0.2102, 0.0],
+            [ 0.1206 * t - 2.5771, -0.0641 * t - 0.8989, -0.0441 * t - 1.6537, 0.0],
+            [-0.0670 * t + 0.3703, -0.0033 * t + 0.0452, -0.0109 * t + 0.0529, 0.0],
+        ];
+
+        let sun_color = sun_transmittance(sun_direction, turbidity);
+        Self {
+            sun_direction: [sun_direction.x, sun_direction.y, sun_direction.z, intensity],
+            zenith: [
+                zenith_luminance,
+                zenith_x,
+                zenith_y,
+                0.53f32.to_radians().cos(),
+            ],
+            perez,
+            sun_color: [
+                sun_color.x,
+                sun_color.y,
+                sun_color.z,
+                night_fade(sun_direction),
+            ],
+        }
+    }
+}
+
+impl FromWorld for ProceduralSky {
+    fn from_world(world: &mut World) -> Self {
+        let device = &world.resource::<RenderState>().device;
+
+        let uniform_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Procedural Sky"),
+            size: size_of::<SkyUniform>() as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layout = device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Procedural Sky"]
+            0: ShaderStages::FRAGMENT => BGLEntry::UniformBuffer();
+        });
+        let bind_group = device.create_bind_group(&bg_descriptor! {
+            ["Procedural Sky"] [&layout]
+            0: uniform_buffer.as_entire_binding();
+        });
+
+        let pipeline = Self::create_pipeline(world, &layout).unwrap();
+
+        Self {
+            enabled: false,
+            turbidity: 2.5,
+            intensity: 1.0 / SUN_ILLUMINANCE_KLX,
+            resolution: 256,
+            baked: None,
+            pipeline,
+            layout,
+            uniform_buffer,
+            bind_group,
+        }
+    }
+}
+
+impl ReloadPipelines for ProceduralSky {
+    type Pipelines = RenderPipeline;
+
+    fn build_pipelines(&self, world: &mut World) -> anyhow::Result<Self::Pipelines> {
+        Self::create_pipeline(world, &self.layout)
+    }
+
+    fn set_pipelines(&mut self, pipelines: Self::Pipelines, _world: &mut World) {
+        self.pipeline = pipelines;
+        // Rebaked by `sys_update_procedural_sky` if enabled
+        self.baked = None;
+    }
+}
+
+impl ProceduralSky {
+    fn create_pipeline(
+        world: &mut World,
+        layout: &BindGroupLayout,
+    ) -> anyhow::Result<RenderPipeline> {
+        let shader = ShaderLoader::load_module_by_world(
+            world,
+            AssetPath::new_shader_wgsl("procedural_sky"),
+        )?;
+        let device = &world.resource::<RenderState>().device;
+        let matrix_bind_groups = world.resource::<CubemapMatrixBindGroups>();
+        let vert_shader = world.resource::<CubemapVertexShader>();
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Procedural Sky"),
+            bind_group_layouts: &[&matrix_bind_groups.layout, layout],
+            push_constant_ranges: &[],
+        });
+        Ok(
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("Procedural Sky"),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &vert_shader.module,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[cube_vertex_layout()],
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: Some(wgpu::Face::Front),
+                    unclipped_depth: false,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    conservative: false,
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(SKY_FORMAT.into())],
+                }),
+                multiview: None,
+                cache: None,
+            }),
+        )
+    }
+
+    /// Render the sky into a new cubemap, ready for [`prefiltering::prefilter`].
+    fn render_cubemap(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        matrix_bind_groups: &CubemapMatrixBindGroups,
+        cube_vertex_buffer: &CubeVerticesBuffer,
+    ) -> wgpu::Texture {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Procedural Sky"),
+            size: wgpu::Extent3d {
+                width: self.resolution,
+                height: self.resolution,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SKY_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Procedural Sky"),
+        });
+        for face in 0..6 {
+            let target = texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: face,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Procedural Sky"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_vertex_buffer(0, cube_vertex_buffer.vertices_buffer.slice(..));
+            pass.set_bind_group(0, &matrix_bind_groups.bind_groups[face as usize], &[]);
+            pass.set_bind_group(1, &self.bind_group, &[]);
+            pass.draw(0..36, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        texture
+    }
+}
+
+/// Rebake the sky, the prefiltered environment map and the sun color when the sun moves.
+pub fn sys_update_procedural_sky(
+    mut sky: ResMut<ProceduralSky>,
+    mut skybox: ResMut<Skybox>,
+    sun: Option<Single<(&mut ParallelLight, &WorldTransform)>>,
+    rs: Res<RenderState>,
+    prefiltering_pipeline: Res<PrefilteringPipeline>,
+    matrix_bind_groups: Res<CubemapMatrixBindGroups>,
+    cube_vertex_buffer: Res<CubeVerticesBuffer>,
+) {
+    if !sky.enabled {
+        if sky.baked.take().is_some() {
+            skybox.texture = None;
+        }
+        return;
+    }
+    let Some(sun) = sun else {
+        return;
+    };
+    let (mut light, transform) = sun.into_inner();
+
+    let key = SkyBakeKey {
+        sun_direction: -transform.forward().normalize(),
+        turbidity: sky.turbidity,
+        intensity: sky.intensity,
+    };
+    if sky.baked.is_some_and(|it| it.is_close(&key)) {
+        return;
+    }
+
+    let uniform = SkyUniform::new(key.sun_direction, key.turbidity, key.intensity);
+    rs.queue
+        .write_buffer(&sky.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    let source = sky.render_cubemap(
+        &rs.device,
+        &rs.queue,
+        &matrix_bind_groups,
+        &cube_vertex_buffer,
+    );
+    let source_view = source.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::Cube),
+        ..Default::default()
+    });
+    match prefiltering::prefilter(
+        Some("Procedural Sky"),
+        &rs.device,
+        &rs.queue,
+        &source,
+        &source_view,
+        5,
+        256,
+        &prefiltering_pipeline,
+        &matrix_bind_groups,
+        &cube_vertex_buffer,
+    ) {
+        Ok(texture) => skybox.texture = Some(texture),
+        Err(e) => log::error!("Failed to prefilter the procedural sky: {e}"),
+    }
+
+    let [r, g, b, fade] = uniform.sun_color;
+    light.color = Vec4::new(r * fade, g * fade, b * fade, 1.0);
+    sky.baked = Some(key);
+}
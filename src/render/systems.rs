@@ -6,14 +6,13 @@ use super::{
         write_g_buffer_pipeline::{GBufferTexturesBindGroup, WriteGBufferPipeline},
         MainPipeline,
     },
+    fog::VolumetricFog,
     gizmos::{Gizmos, GizmosGlobalBindGroup, GizmosPipeline},
     light::DynamicLightBindGroup,
-    material::pbr::PBRMaterialOverride,
     prelude::*,
     skybox::{Skybox, SkyboxPipeline},
-    transform::Transform,
     utils::cube::CubeVerticesBuffer,
-    MainPassObject,
+    MainPassMeshes,
 };
 use egui_wgpu::ScreenDescriptor;
 use wgpu::{CommandEncoder, TextureView};
@@ -26,9 +25,10 @@ use crate::{
 };
 
 use super::{
-    post_processing::{PostProcessingManager, RenderStage},
+    post_processing::{effect::PostEffectBinding, PostProcessingManager, RenderStage},
     shadow_mapping::{CastShadow, ShadowMap, ShadowMapGlobalBindGroup, ShadowMappingPipeline},
     ColorRenderTarget, DefaultMainPipelineMaterial, DepthRenderTarget, MeshRenderer,
+    PrimitiveFilter,
 };
 
 const BACKGROUND_COLOR: wgpu::Color = wgpu::Color {
@@ -89,10 +89,7 @@ pub fn sys_render_write_g_buffer_pass(
     main_pipeline: Res<WriteGBufferPipeline>,
     global_bind_group: Res<GlobalBindGroup>,
     default_material: Res<DefaultMainPipelineMaterial>,
-    mesh_renderers: Query<
-        (&MeshRenderer, Option<&PBRMaterialOverride>),
-        (With<Transform>, With<MainPassObject>),
-    >,
+    mesh_renderers: MainPassMeshes,
 ) {
     let Some(depth_image) = depth_target.0.as_ref() else {
         return;
@@ -124,6 +121,7 @@ pub fn sys_render_write_g_buffer_pass(
             default_material.0.clone(),
             override_mat
                 .and_then(|it| it.material.as_ref().map(|it| it.as_ref())),
+            PrimitiveFilter::Opaque,
         );
     }
 }
@@ -135,6 +133,7 @@ pub fn sys_render_main_pass(
     g_buffer_bind_group: Res<GBufferTexturesBindGroup>,
     main_global_bind_group: Res<GlobalBindGroup>,
     dynamic_lights_bind_group: Res<DynamicLightBindGroup>,
+    fog: Res<VolumetricFog>,
     skybox_pipeline: Res<SkyboxPipeline>,
     cube_vertex_buffer: Res<CubeVerticesBuffer>,
 ) {
@@ -167,6 +166,7 @@ pub fn sys_render_main_pass(
     render_pass.set_pipeline(&main_pipeline.pipeline);
     render_pass.set_bind_group(1, Some(g_buffer_bind_group.bind_group.as_ref()), &[]);
     render_pass.set_bind_group(2, Some(dynamic_lights_bind_group.bind_group.as_ref()), &[]);
+    render_pass.set_bind_group(3, Some(fog.bind_group.as_ref()), &[]);
     render_pass.draw(0..3, 0..1);
 }
 
@@ -196,13 +196,14 @@ pub fn sys_render_post_processing(
     InMut(ctx): InMut<PassRenderContext>,
     mut manager: ResMut<PostProcessingManager>,
     color_target: Res<ColorRenderTarget>,
+    g_buffer_bind_group: Res<GBufferTexturesBindGroup>,
 ) {
     let Some(color_target) = color_target.0.as_ref() else {
         return;
     };
     let stage = ctx.stage;
-    let pi = manager.pipelines.get(&stage);
-    if pi.is_none() || pi.unwrap().len() <= 0 {
+    let effects = manager.enabled_effects(stage);
+    if effects.is_empty() {
         return;
     }
 
@@ -215,30 +216,36 @@ pub fn sys_render_post_processing(
         color_target.size,
     );
 
-    let pipelines = manager.pipelines.get(&stage).cloned();
-    pipelines.inspect(|it| {
-        for pipeline in it.iter() {
-            let (source, target) = manager.next_source_and_target();
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &target.view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
+    for index in effects.into_iter() {
+        let (source, target) = manager.next_source_and_target();
+        let effect = &manager.effects[index];
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(&effect.label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
 
-            render_pass.set_pipeline(&pipeline.pipeline);
-            render_pass.set_bind_group(0, Some(source.as_ref()), &[]);
-            render_pass.draw(0..3, 0..1);
+        render_pass.set_pipeline(&effect.pipeline);
+        render_pass.set_bind_group(0, Some(source.as_ref()), &[]);
+        for (i, binding) in effect.bindings.iter().enumerate() {
+            let bind_group = match binding {
+                PostEffectBinding::Params => effect.params.as_ref().unwrap().bind_group(),
+                PostEffectBinding::Depth => manager.depth_bind_group.as_ref(),
+                PostEffectBinding::GBuffer => g_buffer_bind_group.bind_group.as_ref(),
+            };
+            render_pass.set_bind_group(i as u32 + 1, Some(bind_group), &[]);
         }
-    });
+        render_pass.draw(0..3, 0..1);
+    }
 
     copy_texture(
         encoder,
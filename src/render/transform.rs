@@ -1,4 +1,4 @@
-use bevy_ecs::prelude::Query;
+use bevy_ecs::prelude::{DetectChangesMut, Query};
 use bevy_ecs::{component::Component, entity::Entity};
 use cgmath::{ElementWise, Matrix3, Matrix4, Rotation, SquareMatrix, Vector3};
 use derive_builder::Builder;
@@ -22,18 +22,27 @@ pub struct Transform {
     pub scale: Vec3,
 }
 
-#[derive(Component, Clone)]
+#[derive(Component, Clone, PartialEq)]
 pub struct WorldTransform {
     pub position: Vec3,
     pub rotation: Quat,
     pub scale: Vec3,
 }
 
+/// Model matrix uploaded last frame, used to derive per-object velocity.
+#[derive(Component, Clone, Default)]
+pub struct PreviousWorldTransform {
+    pub model: Option<Mat4>,
+    /// The last upload had no velocity
+    pub at_rest: bool,
+}
+
 #[repr(C, align(16))]
 #[derive(Clone, Copy, Debug)]
 pub struct TransformUniform {
     pub model: [[f32; 4]; 4],
     pub normal: [[f32; 4]; 3],
+    pub prev_model: [[f32; 4]; 4],
 }
 
 unsafe impl bytemuck::Pod for TransformUniform {}
@@ -61,7 +70,8 @@ pub fn sys_update_world_transform(
         .collect::<Vec<_>>();
     vec.into_iter().for_each(|(id, world_transform)| {
         let (_, _, mut to_modified) = q_transform.get_mut(id).unwrap();
-        *to_modified = world_transform;
+        // Keeps `Changed<WorldTransform>` to the entities that actually moved
+        to_modified.set_if_neq(world_transform);
     });
 }
 
@@ -118,6 +128,7 @@ impl WorldTransform {
                 normal.y.with_w(0.).into(),
                 normal.z.with_w(0.).into(),
             ],
+            prev_model: model.into(),
         }
     }
 
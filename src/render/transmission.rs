@@ -0,0 +1,346 @@
+use std::sync::Arc;
+
+use bevy_ecs::prelude::*;
+use wgpu::{BindingResource, Sampler, TextureView, TextureViewDescriptor};
+
+use crate::{
+    asset::AssetPath,
+    bg_descriptor,
+    macro_utils::BGLEntry,
+    render::{
+        defered_rendering::global_binding::GlobalBindGroup,
+        material::pbr::{PBRMaterialBindGroupLayout, PBRMaterialOverride},
+        mipmap::calculate_mip_level_count,
+        prelude::*,
+        shader_loader::ShaderLoader,
+        systems::PassRenderContext,
+        ColorRenderTarget, DefaultMainPipelineMaterial, DepthRenderTarget, MainPassMeshes,
+        MeshRenderer, PrimitiveFilter,
+    },
+};
+
+/// Copy of the opaque scene color, each mip a blurrier version of the previous one.
+struct SceneColor {
+    image: UploadedImageWithSampler,
+    bind_group: BindGroup,
+    mip_views: Vec<TextureView>,
+    /// Reads the previous mip, one per mip after the first
+    downsample_bind_groups: Vec<BindGroup>,
+}
+
+impl SceneColor {
+    fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: TextureFormat,
+        layout: &BindGroupLayout,
+        blit_layout: &BindGroupLayout,
+        blit_sampler: &Sampler,
+    ) -> Self {
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let mip_count = calculate_mip_level_count(&[width, height]);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Transmission Scene Color"),
+            size,
+            mip_level_count: mip_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        let sampler_desc = wgpu::SamplerDescriptor {
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..wgpu_init::sampler_desc(
+                Some("Transmission Scene Color"),
+                wgpu::AddressMode::ClampToEdge,
+                wgpu::FilterMode::Linear,
+            )
+        };
+        let sampler = device.create_sampler(&sampler_desc);
+        let mip_views = (0..mip_count)
+            .map(|level| {
+                texture.create_view(&TextureViewDescriptor {
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let bind_group = device.create_bind_group(&bg_descriptor! {
+            ["Transmission Scene Color"] [layout]
+            0: BindingResource::TextureView(&view);
+            1: BindingResource::Sampler(&sampler);
+        });
+        let downsample_bind_groups = (1..mip_count as usize)
+            .map(|level| {
+                device.create_bind_group(&bg_descriptor! {
+                    ["Transmission Scene Color Downsample"] [blit_layout]
+                    0: BindingResource::TextureView(&mip_views[level - 1]);
+                    1: BindingResource::Sampler(blit_sampler);
+                })
+            })
+            .collect();
+
+        Self {
+            image: UploadedImageWithSampler {
+                size,
+                texture,
+                view,
+                sampler,
+                sampler_desc,
+            },
+            bind_group,
+            mip_views,
+            downsample_bind_groups,
+        }
+    }
+}
+
+/// Forward pass of the `KHR_materials_transmission` and `KHR_materials_volume` materials,
+/// drawn over the opaque result they refract.
+#[derive(Resource)]
+pub struct Transmission {
+    pipeline: RenderPipeline,
+    blit_pipeline: RenderPipeline,
+    layout: BindGroupLayout,
+    blit_layout: BindGroupLayout,
+    blit_sampler: Sampler,
+    format: TextureFormat,
+    scene_color: SceneColor,
+}
+
+impl Transmission {
+    pub fn resize(&mut self, width: u32, height: u32, device: &wgpu::Device) {
+        self.scene_color = SceneColor::new(
+            device,
+            width,
+            height,
+            self.format,
+            &self.layout,
+            &self.blit_layout,
+            &self.blit_sampler,
+        );
+    }
+}
+
+impl FromWorld for Transmission {
+    fn from_world(world: &mut World) -> Self {
+        let shader =
+            ShaderLoader::load_module_by_world(world, AssetPath::new_shader_wgsl("transmission"))
+                .unwrap();
+        let blit_shader =
+            ShaderLoader::load_module_by_world(world, AssetPath::new_shader_wgsl("blit")).unwrap();
+        let rs = world.resource::<RenderState>();
+        let device = &rs.device;
+        let format = rs.config.format;
+        let size = world.resource::<RenderTargetSize>();
+
+        let layout = device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Transmission Scene Color"]
+            0: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, TextureSampleType::Float { filterable: true });
+            1: ShaderStages::FRAGMENT => BGLEntry::Sampler(SamplerBindingType::Filtering);
+        });
+        let blit_layout = device.create_bind_group_layout(&bg_layout_descriptor! {
+            ["Transmission Scene Color Downsample"]
+            0: ShaderStages::FRAGMENT => BGLEntry::Tex2D(false, TextureSampleType::Float { filterable: true });
+            1: ShaderStages::FRAGMENT => BGLEntry::Sampler(SamplerBindingType::Filtering);
+        });
+        let blit_sampler = device.create_sampler(&wgpu_init::sampler_desc(
+            Some("Transmission Scene Color Downsample"),
+            wgpu::AddressMode::ClampToEdge,
+            wgpu::FilterMode::Linear,
+        ));
+
+        let global_layout = Arc::clone(&world.resource::<GlobalBindGroup>().layout);
+        let material_layout = Arc::clone(&world.resource::<PBRMaterialBindGroupLayout>().0);
+        let object_layout = Arc::clone(&world.resource::<ObjectBindGroupLayout>().0);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Transmission"),
+            bind_group_layouts: &[&global_layout, &material_layout, &object_layout, &layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Transmission"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[Vertex::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu_init::color_target_replace_write_all(format))],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: RenderState::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let blit_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Transmission Scene Color Downsample"),
+            bind_group_layouts: &[&blit_layout],
+            push_constant_ranges: &[],
+        });
+        let blit_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Transmission Scene Color Downsample"),
+            layout: Some(&blit_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &blit_shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &blit_shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu_init::color_target_replace_write_all(format))],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let scene_color = SceneColor::new(
+            device,
+            size.width,
+            size.height,
+            format,
+            &layout,
+            &blit_layout,
+            &blit_sampler,
+        );
+
+        Self {
+            pipeline,
+            blit_pipeline,
+            layout,
+            blit_layout,
+            blit_sampler,
+            format,
+            scene_color,
+        }
+    }
+}
+
+fn has_transmissive(
+    mesh_renderer: &MeshRenderer,
+    override_mat: Option<&PBRMaterialOverride>,
+) -> bool {
+    if let Some(material) = override_mat.and_then(|it| it.material.as_ref()) {
+        return material.transmissive;
+    }
+    mesh_renderer.mesh.as_ref().is_some_and(|mesh| {
+        mesh.primitives.iter().any(|primitive| {
+            primitive
+                .uploaded_material
+                .as_ref()
+                .is_some_and(|it| it.transmissive)
+        })
+    })
+}
+
+/// Copies and blurs the opaque scene color, then draws the transmissive primitives over it.
+pub fn sys_render_transmission(
+    InMut(ctx): InMut<PassRenderContext>,
+    color_target: Res<ColorRenderTarget>,
+    depth_target: Res<DepthRenderTarget>,
+    transmission: Res<Transmission>,
+    global_bind_group: Res<GlobalBindGroup>,
+    default_material: Res<DefaultMainPipelineMaterial>,
+    mesh_renderers: MainPassMeshes,
+) {
+    let (Some(color_image), Some(depth_image)) = (color_target.0.as_ref(), depth_target.0.as_ref())
+    else {
+        return;
+    };
+    if !mesh_renderers
+        .iter()
+        .any(|(mesh_renderer, override_mat)| has_transmissive(mesh_renderer, override_mat))
+    {
+        return;
+    }
+
+    let scene_color = &transmission.scene_color;
+    let encoder = &mut ctx.encoder;
+    wgpu_init::copy_texture(
+        encoder,
+        &color_image.texture,
+        &scene_color.image.texture,
+        color_image.size,
+    );
+    let targets = scene_color.mip_views.iter().skip(1);
+    for (bind_group, target) in scene_color.downsample_bind_groups.iter().zip(targets) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Transmission Scene Color Downsample"),
+            color_attachments: &[Some(wgpu_init::render_pass_color_attachment(
+                target, None, true,
+            ))],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&transmission.blit_pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..4, 0..1);
+    }
+
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Transmission Pass"),
+        color_attachments: &[Some(wgpu_init::render_pass_color_attachment(
+            &color_image.view,
+            None,
+            true,
+        ))],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: &depth_image.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Load,
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }),
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    });
+    render_pass.set_pipeline(&transmission.pipeline);
+    render_pass.set_bind_group(0, Some(global_bind_group.bind_group.as_ref()), &[]);
+    render_pass.set_bind_group(3, &scene_color.bind_group, &[]);
+    for (mesh_renderer, override_mat) in mesh_renderers.iter() {
+        mesh_renderer.draw_main(
+            &mut render_pass,
+            default_material.0.clone(),
+            override_mat.and_then(|it| it.material.as_deref()),
+            PrimitiveFilter::Transmissive,
+        );
+    }
+}
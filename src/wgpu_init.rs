@@ -1,50 +1,99 @@
+use std::sync::Arc;
+
 use wgpu::{
     util::{DeviceExt, TextureDataOrder},
-    BindGroupLayoutEntry, BindingType, ColorTargetState, Extent3d, Origin3d,
-    PipelineCompilationOptions, PipelineLayout, RenderPassColorAttachment,
+    BindGroupLayoutEntry, BindingType, BufferDescriptor, BufferUsages, ColorTargetState, Extent3d,
+    Origin3d, PipelineCompilationOptions, PipelineLayout, RenderPassColorAttachment,
     RenderPipelineDescriptor, SamplerDescriptor, ShaderModule, ShaderStages, TextureDescriptor,
     TextureFormat, TextureUsages, TextureView, VertexBufferLayout, VertexState,
 };
 
 use crate::{cgmath_ext::Vec4, render::UploadedImageWithSampler};
 
-// pub struct DynamicBuffer<'a> {
-//     pub size: u64,
-//     pub buffer: Arc<wgpu::Buffer>,
-//     pub desc: BufferDescriptor<'a>,
-// }
+/// GPU buffer for per-frame arrays, reallocated with doubled capacity when the data outgrows it.
+/// Bind groups holding the old buffer have to be rebuilt after it grows.
+pub struct DynamicBuffer {
+    label: &'static str,
+    usage: BufferUsages,
+    capacity: u64,
+    buffer: Arc<wgpu::Buffer>,
+}
+
+impl DynamicBuffer {
+    /// Smallest allocation, bindings can't be empty.
+    pub const MIN_CAPACITY: u64 = 256;
+
+    pub fn new(device: &wgpu::Device, label: &'static str, usage: BufferUsages, size: u64) -> Self {
+        let capacity = Self::grown_capacity(0, size);
+        Self {
+            label,
+            usage: usage | BufferUsages::COPY_DST,
+            capacity,
+            buffer: Arc::new(Self::create_buffer(device, label, usage, capacity)),
+        }
+    }
 
-// impl<'a> DynamicBuffer<'a> {
-//     pub fn new(device: &wgpu::Device, desc: BufferDescriptor<'a>) -> Self {
-//         let size = desc.size;
-//         let buffer = Arc::new(device.create_buffer(&desc));
-//         Self { size, buffer, desc }
-//     }
+    fn create_buffer(
+        device: &wgpu::Device,
+        label: &str,
+        usage: BufferUsages,
+        capacity: u64,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size: capacity,
+            usage: usage | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
 
-//     pub fn write_buffer(
-//         &self,
-//         queue: wgpu::Queue,
-//         device: wgpu::Device,
-//         offset: u64,
-//         data: &[u8],
-//     ) -> Option<Arc<Buffer>> {
-//         let required_size = size_of_val(data) as u64 + offset;
-//         let is_oversize = required_size > self.size;
-//         if is_oversize {
-//             let buffer = device.create_buffer(&self.desc);
-//             let desc = self.desc.clone();
-//             desc.size = queue.write_buffer(buffer, offset, data)
-//         } else {
-//             queue.write_buffer(&self.buffer, offset, data);
-//         };
-//         None
-//     }
+    /// Capacity in bytes after growing `capacity` until `required` bytes fit.
+    pub fn grown_capacity(capacity: u64, required: u64) -> u64 {
+        let mut capacity = capacity.max(Self::MIN_CAPACITY);
+        while capacity < required {
+            capacity *= 2;
+        }
+        capacity.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+    }
 
-//     pub fn calculate_new_size(&self, target_size: u64) {
-//         let mut size = self.size;
-//         while size < target_size {}
-//     }
-// }
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn buffer(&self) -> &Arc<wgpu::Buffer> {
+        &self.buffer
+    }
+
+    pub fn as_entire_binding(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+
+    pub fn fits(&self, size: u64) -> bool {
+        size <= self.capacity
+    }
+
+    /// Reallocates when `size` bytes don't fit, returns whether the buffer was replaced.
+    /// The old content is not kept.
+    pub fn reserve(&mut self, device: &wgpu::Device, size: u64) -> bool {
+        if self.fits(size) {
+            return false;
+        }
+        self.capacity = Self::grown_capacity(self.capacity, size);
+        self.buffer = Arc::new(Self::create_buffer(
+            device,
+            self.label,
+            self.usage,
+            self.capacity,
+        ));
+        true
+    }
+
+    /// Writes from the start of the buffer, `reserve` first.
+    pub fn write(&self, queue: &wgpu::Queue, data: &[u8]) {
+        debug_assert!(self.fits(data.len() as u64), "{} overflowed", self.label);
+        queue.write_buffer(&self.buffer, 0, data);
+    }
+}
 
 pub const fn bind_group_layout_entry_shader(binding: u32, ty: BindingType) -> BindGroupLayoutEntry {
     BindGroupLayoutEntry {
@@ -272,11 +321,8 @@ pub fn create_pure_color_texture(
             (color.w * 255.) as u8,
         ],
     );
-    let sampler = device.create_sampler(&sampler_desc(
-        None,
-        wgpu::AddressMode::Repeat,
-        wgpu::FilterMode::Linear,
-    ));
+    let sampler_desc = sampler_desc(None, wgpu::AddressMode::Repeat, wgpu::FilterMode::Linear);
+    let sampler = device.create_sampler(&sampler_desc);
     let view = texture.create_view(&Default::default());
 
     UploadedImageWithSampler {
@@ -284,6 +330,7 @@ pub fn create_pure_color_texture(
         view,
         size,
         sampler,
+        sampler_desc,
     }
 }
 
@@ -309,3 +356,42 @@ pub fn copy_texture(
         size,
     );
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dynamic_buffer_fits_without_growing() {
+        assert_eq!(DynamicBuffer::grown_capacity(1024, 0), 1024);
+        assert_eq!(DynamicBuffer::grown_capacity(1024, 1024), 1024);
+    }
+
+    #[test]
+    fn test_dynamic_buffer_doubles() {
+        assert_eq!(DynamicBuffer::grown_capacity(1024, 1025), 2048);
+        assert_eq!(DynamicBuffer::grown_capacity(1024, 5000), 8192);
+    }
+
+    #[test]
+    fn test_dynamic_buffer_min_capacity() {
+        assert_eq!(
+            DynamicBuffer::grown_capacity(0, 0),
+            DynamicBuffer::MIN_CAPACITY
+        );
+        assert_eq!(
+            DynamicBuffer::grown_capacity(0, 1),
+            DynamicBuffer::MIN_CAPACITY
+        );
+        assert_eq!(
+            DynamicBuffer::grown_capacity(0, DynamicBuffer::MIN_CAPACITY + 1),
+            DynamicBuffer::MIN_CAPACITY * 2
+        );
+    }
+
+    #[test]
+    fn test_dynamic_buffer_copy_alignment() {
+        // Capacities that aren't a multiple of 4 still have to be copyable
+        assert_eq!(DynamicBuffer::grown_capacity(257, 300), 516);
+    }
+}